@@ -0,0 +1,539 @@
+//! Cooks the assets of a directory into the form they are loaded in at runtime
+//! Collada and glTF models are converted to binary meshes, images are decoded as described by their spec with a
+//! full mip chain, or as is for KTX2 and DDS files, and GLSL
+//! shaders are compiled to SPIR-V for every set of defines a pipeline uses them with
+//! Material, pipeline, renderpass, render graph, texture spec and layered texture JSON is validated
+//! The output is either a directory holding only the cooked files, to be mounted over the
+//! sources, or with --pack, a pack of everything needed at runtime
+//! A manifest of the written files is written to the output as manifest.json
+//! Files that fail to cook or validate fail the cook, in which case no manifest and no pack is
+//! written
+mod output;
+
+use log::{error, info};
+use output::Output;
+use serde::Serialize;
+use sprocket::graphics::vulkan::gltf;
+use sprocket::graphics::vulkan::shader::{self, CookedShader};
+use sprocket::graphics::vulkan::{
+    cooked, normalize, CookedKind, LayeredTextureSpec, MaterialSpec, ModelData, PipelineSpec,
+    RenderGraphSpec, RenderPassSpec, TextureData, TextureSpec,
+};
+use sprocket::logger;
+use sprocket::vfs::{self, PackWriter};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::{env, fs, io, process};
+
+const USAGE: &str = "Usage: sprocket-cook [--mount <point>] [--pack] <input dir> <output>
+    --mount <point>  The mount point asset paths refer to, defaults to assets
+    --pack           Write a pack of all assets instead of a directory of the cooked files";
+
+/// The model formats meshes are loaded from
+const MODEL_EXTENSIONS: &[&str] = &["dae", "gltf", "glb"];
+
+/// The image formats textures are loaded from
+const IMAGE_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "tga", "bmp", "psd", "pic", "pnm", "hdr", "exr", "ktx2", "dds",
+];
+
+struct Options {
+    mount: String,
+    pack: bool,
+    input: PathBuf,
+    output: PathBuf,
+}
+
+/// Describes what was written to the output
+#[derive(Serialize)]
+struct Manifest {
+    /// The version of the cooked formats
+    version: u32,
+    mount: String,
+    entries: Vec<ManifestEntry>,
+    /// The JSON files that were validated
+    validated: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ManifestEntry {
+    /// The virtual path of the source file
+    source: String,
+    /// The path of the written file relative to the output
+    output: String,
+    /// None if the file was written as is
+    kind: Option<CookedKind>,
+}
+
+fn main() {
+    logger::init(log::LevelFilter::Info);
+
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
+    match cook(&options) {
+        Ok(0) => info!("Cooked '{}'", options.input.display()),
+        Ok(errors) => {
+            error!("Cooking failed with {} errors", errors);
+            process::exit(1);
+        }
+        Err(e) => {
+            error!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut mount = "assets".to_owned();
+    let mut pack = false;
+    let mut paths = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--mount" => mount = args.next().ok_or("--mount requires a mount point")?,
+            "--pack" => pack = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if arg.starts_with("--") => return Err(format!("Unknown option '{}'", arg)),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    match (paths.pop(), paths.pop(), paths.is_empty()) {
+        (Some(output), Some(input), true) => Ok(Options {
+            mount,
+            pack,
+            input,
+            output,
+        }),
+        _ => Err("Expected an input directory and an output".to_owned()),
+    }
+}
+
+/// Cooks every file of the input and returns the number of files that failed
+fn cook(options: &Options) -> Result<usize, String> {
+    let mut files = Vec::new();
+    list_files(&options.input, Path::new(""), &mut files)
+        .map_err(|e| format!("Failed to read '{}' {}", options.input.display(), e))?;
+    files.sort();
+
+    let output = if options.pack {
+        Output::Pack(PackWriter::create(&options.output).map_err(|e| e.to_string())?)
+    } else {
+        Output::Dir(options.output.clone())
+    };
+
+    vfs::mount_dir(&options.mount, &options.input);
+
+    let mut cook = Cook::new(&options.mount, output);
+
+    // Validated first to know which defines the shaders are used with
+    let mut invalid = HashSet::new();
+    for file in files.iter().filter(|file| file.ends_with(".json")) {
+        if let Err(e) = cook.validate(file) {
+            cook.fail(file, e);
+            invalid.insert(file);
+        }
+    }
+
+    for file in files.iter().filter(|file| !invalid.contains(file)) {
+        if let Err(e) = cook.cook_file(file) {
+            cook.fail(file, e);
+        }
+    }
+
+    if cook.errors > 0 {
+        // A pack missing files would fail at runtime instead
+        drop(cook.output);
+        if options.pack {
+            fs::remove_file(&options.output)
+                .map_err(|e| format!("Failed to remove '{}' {}", options.output.display(), e))?;
+        }
+        return Ok(cook.errors);
+    }
+
+    let manifest = serde_json::to_vec_pretty(&cook.manifest).map_err(|e| e.to_string())?;
+    cook.output.write("manifest.json", &manifest)?;
+    cook.output.finish()?;
+
+    Ok(cook.errors)
+}
+
+/// Appends the paths of all files in root/dir relative to root
+fn list_files(root: &Path, dir: &Path, files: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(root.join(dir))? {
+        let entry = entry?;
+        let path = dir.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            list_files(root, &path, files)?;
+        } else {
+            files.push(path.to_string_lossy().replace('\\', "/"));
+        }
+    }
+    Ok(())
+}
+
+struct Cook {
+    mount: String,
+    output: Output,
+    manifest: Manifest,
+    /// The sets of defines each GLSL shader is used with by pipelines
+    variants: HashMap<String, Vec<HashMap<String, String>>>,
+    errors: usize,
+}
+
+impl Cook {
+    fn new(mount: &str, output: Output) -> Self {
+        Cook {
+            mount: mount.to_owned(),
+            output,
+            manifest: Manifest {
+                version: cooked::VERSION,
+                mount: mount.to_owned(),
+                entries: Vec::new(),
+                validated: Vec::new(),
+            },
+            variants: HashMap::new(),
+            errors: 0,
+        }
+    }
+
+    /// Returns the path file is loaded as at runtime
+    fn virtual_path(&self, file: &str) -> String {
+        format!("{}://{}", self.mount, file)
+    }
+
+    fn fail(&mut self, file: &str, reason: String) {
+        error!("'{}': {}", self.virtual_path(file), reason);
+        self.errors += 1;
+    }
+
+    /// Checks that a JSON file describes a valid spec for the directory it is in and that the
+    /// files it references exist
+    /// Other JSON in the textures directory is validated as layered textures, and files outside
+    /// the materials, pipelines, renderpasses, rendergraphs and textures directories that aren't
+    /// texture specs are ignored
+    fn validate(&mut self, file: &str) -> Result<(), String> {
+        let path = self.virtual_path(file);
+        let json = vfs::read_to_string(&path).map_err(|e| e.to_string())?;
+
+        let references = match file.split('/').next() {
+            Some("materials") => {
+                let spec: MaterialSpec = parse(&json)?;
+                let mut references = vec![spec.pipeline().to_owned()];
+                references.extend(spec.textures().iter().cloned());
+                references
+            }
+            Some("pipelines") => {
+                let spec: PipelineSpec = parse(&json)?;
+                spec.validate().map_err(|e| e.to_string())?;
+
+                let shaders = [
+                    &spec.vertex_shader,
+                    &spec.fragment_shader,
+                    &spec.geometry_shader,
+                ];
+                let shaders: Vec<String> = shaders
+                    .iter()
+                    .filter(|shader| !shader.is_empty())
+                    .map(|shader| shader.to_string())
+                    .collect();
+
+                for shader in shaders.iter().filter(|shader| shader::is_glsl(shader)) {
+                    let variants = self.variants.entry(normalize(shader)).or_default();
+                    if !variants.contains(&spec.defines) {
+                        variants.push(spec.defines.clone());
+                    }
+                }
+                shaders
+            }
+            Some("renderpasses") => {
+                parse::<RenderPassSpec>(&json)?;
+                Vec::new()
+            }
+            Some("rendergraphs") => {
+                parse::<RenderGraphSpec>(&json)?;
+                Vec::new()
+            }
+            _ if is_image(file.trim_end_matches(".json")) => {
+                parse::<TextureSpec>(&json)?;
+                Vec::new()
+            }
+            Some("textures") => parse::<LayeredTextureSpec>(&json)?.images(),
+            _ => return Ok(()),
+        };
+
+        let missing: Vec<String> = references
+            .iter()
+            .filter(|reference| !vfs::exists(reference))
+            .map(|reference| format!("'{}'", reference))
+            .collect();
+
+        if !missing.is_empty() {
+            return Err(format!("References missing files {}", missing.join(", ")));
+        }
+
+        self.manifest.validated.push(path);
+        Ok(())
+    }
+
+    /// Writes the cooked version of file to the output
+    /// Files that aren't cooked are written as is if the output is complete
+    fn cook_file(&mut self, file: &str) -> Result<(), String> {
+        let path = self.virtual_path(file);
+        let kind = if MODEL_EXTENSIONS.contains(&&extension(file)[..]) {
+            CookedKind::Model
+        } else if is_image(file) {
+            CookedKind::Texture
+        } else if shader::is_glsl(file) {
+            CookedKind::Shader
+        } else {
+            if self.output.is_complete() {
+                let data = vfs::read(&path).map_err(|e| e.to_string())?;
+                self.output.write(file, &data)?;
+                self.manifest.entries.push(ManifestEntry {
+                    source: path,
+                    output: file.to_owned(),
+                    kind: None,
+                });
+            }
+            return Ok(());
+        };
+
+        let data = match kind {
+            CookedKind::Model => {
+                ModelData::from_file(&path).and_then(|model| cooked::encode(&path, &model))
+            }
+            CookedKind::Texture => TextureData::decode(&path).and_then(|mut texture| {
+                texture.generate_mips();
+                cooked::encode(&path, &texture)
+            }),
+            CookedKind::Shader => {
+                // Shaders no pipeline uses are compiled without defines
+                let unused = vec![HashMap::new()];
+                self.variants
+                    .get(&normalize(&path))
+                    .unwrap_or(&unused)
+                    .iter()
+                    .map(|defines| {
+                        shader::compile(&path, defines)
+                            .map(|compiled| CookedShader::new(compiled, defines))
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+                    .and_then(|variants| cooked::encode(&path, &variants))
+            }
+        }
+        .map_err(|e| e.to_string())?;
+
+        // The scenes, materials and embedded images of glTF files are still read from the file
+        if self.output.is_complete() && gltf::is_gltf(file) {
+            let source = vfs::read(&path).map_err(|e| e.to_string())?;
+            self.output.write(file, &source)?;
+            self.manifest.entries.push(ManifestEntry {
+                source: path.clone(),
+                output: file.to_owned(),
+                kind: None,
+            });
+        }
+
+        let output = kind.path(file);
+        self.output.write(&output, &data)?;
+        info!("Cooked '{}' to '{}'", path, output);
+
+        self.manifest.entries.push(ManifestEntry {
+            source: path,
+            output,
+            kind: Some(kind),
+        });
+        Ok(())
+    }
+}
+
+fn extension(file: &str) -> String {
+    Path::new(file)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/// Returns true if file is an image that is cooked to a texture
+fn is_image(file: &str) -> bool {
+    IMAGE_EXTENSIONS.contains(&&extension(file)[..])
+}
+
+fn parse<'a, T: serde::Deserialize<'a>>(json: &'a str) -> Result<T, String> {
+    serde_json::from_str(json).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sprocket::graphics::Image;
+
+    fn args(args: &[&str]) -> Result<Options, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse_options() {
+        let options = args(&["in", "out"]).unwrap();
+        assert_eq!(options.mount, "assets");
+        assert!(!options.pack);
+        assert_eq!(options.input, Path::new("in"));
+        assert_eq!(options.output, Path::new("out"));
+
+        let options = args(&["--pack", "in", "--mount", "data", "out.pak"]).unwrap();
+        assert_eq!(options.mount, "data");
+        assert!(options.pack);
+        assert_eq!(options.input, Path::new("in"));
+        assert_eq!(options.output, Path::new("out.pak"));
+
+        assert!(args(&["in"]).is_err());
+        assert!(args(&["in", "out", "extra"]).is_err());
+        assert!(args(&["--unknown", "in", "out"]).is_err());
+        assert!(args(&["in", "out", "--mount"]).is_err());
+    }
+
+    /// Creates an empty directory for a test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("sprocket-cook-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(dir: &Path, file: &str, contents: &str) {
+        let path = dir.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    /// Creates an input directory with a texture, a render graph and a file used as is
+    fn assets(name: &str) -> PathBuf {
+        let dir = test_dir(name);
+        fs::create_dir_all(dir.join("textures")).unwrap();
+        Image::new(2, 2, vec![255; 16])
+            .save_png(dir.join("textures/white.png"))
+            .unwrap();
+        write(
+            &dir,
+            "rendergraphs/default.json",
+            r#"{ "resources": [], "passes": [{ "name": "scene", "kind": "Scene", "colors": ["output"] }] }"#,
+        );
+        write(&dir, "notes.txt", "not cooked");
+        dir
+    }
+
+    #[test]
+    fn validate_references() {
+        let mount = "cook-validate";
+        let dir = test_dir(mount);
+        write(&dir, "shaders/default.vert", "");
+        write(
+            &dir,
+            "pipelines/default.json",
+            r#"{ "vertex_shader": "cook-validate://shaders/default.vert", "fragment_shader": "cook-validate://shaders/default.frag" }"#,
+        );
+        write(
+            &dir,
+            "materials/default.json",
+            r#"{ "pipeline": "cook-validate://pipelines/default.json", "textures": [] }"#,
+        );
+        write(
+            &dir,
+            "materials/missing.json",
+            r#"{ "pipeline": "cook-validate://pipelines/missing.json", "textures": [] }"#,
+        );
+        write(&dir, "renderpasses/broken.json", "{");
+        vfs::mount_dir(mount, &dir);
+
+        let mut cook = Cook::new(mount, Output::Dir(dir.join("out")));
+        assert!(cook.validate("materials/default.json").is_ok());
+        assert!(cook
+            .validate("materials/missing.json")
+            .unwrap_err()
+            .contains("pipelines/missing.json"));
+        assert!(cook
+            .validate("pipelines/default.json")
+            .unwrap_err()
+            .contains("shaders/default.frag"));
+        assert!(cook.validate("renderpasses/broken.json").is_err());
+        assert_eq!(
+            cook.manifest.validated,
+            ["cook-validate://materials/default.json"]
+        );
+
+        vfs::unmount(mount);
+    }
+
+    #[test]
+    fn cook_dir() {
+        let input = assets("dir-input");
+        let output = test_dir("dir-output");
+        let options = Options {
+            mount: "cook-dir".to_owned(),
+            pack: false,
+            input,
+            output: output.clone(),
+        };
+        assert_eq!(cook(&options), Ok(0));
+        vfs::unmount(&options.mount);
+
+        // Only the cooked files are written
+        assert!(output.join("manifest.json").exists());
+        assert!(output
+            .join(CookedKind::Texture.path("textures/white.png"))
+            .exists());
+        assert!(!output.join("textures/white.png").exists());
+        assert!(!output.join("notes.txt").exists());
+        assert!(!output.join("rendergraphs/default.json").exists());
+    }
+
+    #[test]
+    fn cook_pack() {
+        let input = assets("pack-input");
+        let output = test_dir("pack-output").join("assets.pak");
+        let options = Options {
+            mount: "cook-pack".to_owned(),
+            pack: true,
+            input,
+            output: output.clone(),
+        };
+        assert_eq!(cook(&options), Ok(0));
+        vfs::unmount(&options.mount);
+
+        // The pack holds everything needed at runtime
+        vfs::mount_pack("cook-pack-output", &output).unwrap();
+        let read = |file: &str| vfs::read(&format!("cook-pack-output://{}", file));
+        assert_eq!(read("notes.txt").unwrap(), b"not cooked");
+        assert!(read("rendergraphs/default.json").is_ok());
+        assert!(read(&CookedKind::Texture.path("textures/white.png")).is_ok());
+        assert!(read("manifest.json").is_ok());
+        vfs::unmount("cook-pack-output");
+    }
+
+    #[test]
+    fn invalid_json_fails_pack() {
+        let input = assets("invalid-input");
+        write(&input, "rendergraphs/broken.json", r#"{ "passes": [] }"#);
+        let output = test_dir("invalid-output").join("assets.pak");
+        let options = Options {
+            mount: "cook-invalid".to_owned(),
+            pack: true,
+            input,
+            output: output.clone(),
+        };
+        assert_eq!(cook(&options), Ok(1));
+        vfs::unmount(&options.mount);
+        assert!(!output.exists());
+    }
+}
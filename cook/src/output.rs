@@ -0,0 +1,46 @@
+use sprocket::vfs::{Compression, PackWriter};
+use std::fs;
+use std::path::PathBuf;
+
+/// Where cooked files are written
+pub enum Output {
+    /// Files are written relative to a directory
+    Dir(PathBuf),
+    /// Files are added to a pack
+    Pack(PackWriter),
+}
+
+impl Output {
+    /// Writes data as the file path relative to the output
+    pub fn write(&mut self, path: &str, data: &[u8]) -> Result<(), String> {
+        match self {
+            Output::Dir(dir) => {
+                let path = dir.join(path);
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)
+                        .map_err(|e| format!("Failed to create '{}' {}", parent.display(), e))?;
+                }
+                fs::write(&path, data)
+                    .map_err(|e| format!("Failed to write '{}' {}", path.display(), e))
+            }
+            Output::Pack(pack) => pack
+                .add(path, data, Compression::Deflate)
+                .map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Returns true if files that are used as is should be written as well
+    /// A directory only holds the cooked files as it is mounted over the sources, while a pack is
+    /// self contained
+    pub fn is_complete(&self) -> bool {
+        matches!(self, Output::Pack(_))
+    }
+
+    /// Completes the output
+    pub fn finish(self) -> Result<(), String> {
+        match self {
+            Output::Dir(_) => Ok(()),
+            Output::Pack(pack) => pack.finish().map_err(|e| e.to_string()),
+        }
+    }
+}
@@ -1,12 +1,19 @@
 #version 450
 #extension GL_ARB_separate_shader_objects : enable
+#extension GL_GOOGLE_include_directive : enable
+
+#include "include/global.glsl"
+#include "include/lighting.glsl"
 
 layout(location = 0) in vec2 fragTexCoord;
+layout(location = 1) in vec3 fragNormal;
+layout(location = 2) in vec3 fragPosition;
 
 layout(location = 0) out vec4 outColor;
 
 layout(binding = 0, set = 1) uniform sampler2D texSampler;
 
 void main() {
-    outColor = texture(texSampler, fragTexCoord);
+    vec4 albedo = texture(texSampler, fragTexCoord);
+    outColor = vec4(shade_blinn_phong(albedo.rgb, fragPosition, fragNormal), albedo.a);
 }
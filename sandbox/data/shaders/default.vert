@@ -1,22 +1,26 @@
 #version 450
 #extension GL_ARB_separate_shader_objects : enable
+#extension GL_GOOGLE_include_directive : enable
 
-/* layout(binding = 0) uniform UniformBufferObject { */
-/*     mat4 model; */
-/*     mat4 view; */
-/*     mat4 proj; */
-/* } ubo; */
+#include "include/global.glsl"
 
 layout(push_constant) uniform Transform {
-        mat4 mvp;
+        mat4 model;
 } transform;
 
 layout(location = 0) in vec3 inPosition;
-layout(location = 1) in vec2 inTexCoord;
+layout(location = 1) in vec3 inNormal;
+layout(location = 2) in vec2 inTexCoord;
 
 layout(location = 0) out vec2 fragTexCoord;
+layout(location = 1) out vec3 fragNormal;
+layout(location = 2) out vec3 fragPosition;
 
 void main() {
-    gl_Position = transform.mvp * vec4(inPosition, 1.0);
+    vec4 position = transform.model * vec4(inPosition, 1.0);
+    gl_Position = globals.viewproj * position;
     fragTexCoord = inTexCoord;
+    // Inverse transpose to keep normals perpendicular under non uniform scale
+    fragNormal = transpose(inverse(mat3(transform.model))) * inNormal;
+    fragPosition = position.xyz;
 }
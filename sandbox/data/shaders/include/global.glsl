@@ -0,0 +1,47 @@
+// Per frame data shared by all pipelines
+// Written by the renderer once for each camera and bound at set 0, binding 0
+// Must match GlobalUniforms in sprocket/src/graphics/vulkan/uniformbuffer.rs
+
+#define MAX_LIGHTS 16
+#define MAX_SHADOW_VIEWS 16
+
+#define LIGHT_DIRECTIONAL 0
+#define LIGHT_POINT 1
+#define LIGHT_SPOT 2
+
+struct LightData {
+    // xyz: world position, w: range
+    vec4 position;
+    // xyz: direction the light is pointing, w: light type
+    vec4 direction;
+    // rgb: color, a: intensity
+    vec4 color;
+    // x: cosine of the inner cone angle, y: cosine of the outer cone angle
+    // z: index of the first shadow view, w: number of shadow views or 0 without shadows
+    vec4 params;
+};
+
+layout(set = 0, binding = 0) uniform GlobalUniforms {
+    mat4 view;
+    mat4 proj;
+    mat4 viewproj;
+    // xyz: camera world position
+    vec4 camera_pos;
+    // x: elapsed seconds, y: delta seconds, z: frame count
+    vec4 time;
+    // xy: viewport size in pixels, zw: reciprocal of viewport size
+    vec4 resolution;
+    // rgb: ambient light color
+    vec4 ambient;
+    uint light_count;
+    LightData lights[MAX_LIGHTS];
+    // The view projection of each view in the shadow atlas
+    mat4 shadow_matrices[MAX_SHADOW_VIEWS];
+    // The tile of each view in the shadow atlas, xy: offset, zw: scale
+    vec4 shadow_rects[MAX_SHADOW_VIEWS];
+    // x: reciprocal of the shadow atlas size, y: depth bias, z: normal offset in world units
+    vec4 shadow_params;
+    // x: intensity of the environment lighting or 0 without an environment
+    // y: mip level of the specular environment map for fully rough surfaces
+    vec4 environment;
+} globals;
@@ -0,0 +1,165 @@
+// Blinn-Phong shading from the lights and environment in the global uniforms
+// Requires include/global.glsl to be included first
+
+#define SPECULAR_STRENGTH 0.5
+#define SHININESS 32.0
+
+// The roughness the environment is reflected with, about as blurry as the highlights of SHININESS
+#define ENVIRONMENT_ROUGHNESS 0.25
+// The reflectance of dielectric surfaces facing the camera
+#define ENVIRONMENT_F0 0.04
+
+// The depth of all shadow casting lights, written by the renderer each frame
+layout(set = 0, binding = 1) uniform sampler2DShadow shadow_atlas;
+
+// The diffuse light reaching a surface facing each direction from the environment
+layout(set = 0, binding = 2) uniform samplerCube irradiance_map;
+// The environment reflected in each direction, blurrier for rougher surfaces at higher mip levels
+layout(set = 0, binding = 3) uniform samplerCube specular_map;
+
+// Returns the fraction of light reaching position through the shadow view at index
+// Filters the 3x3 texels around the position in the atlas
+// Returns -1 if position is outside the view
+float sample_shadow_view(uint index, vec3 position) {
+    vec4 clip = globals.shadow_matrices[index] * vec4(position, 1.0);
+    vec3 ndc = clip.xyz / clip.w;
+    vec2 uv = ndc.xy * 0.5 + 0.5;
+
+    if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0))) || ndc.z < 0.0 || ndc.z > 1.0) {
+        return -1.0;
+    }
+
+    vec4 rect = globals.shadow_rects[index];
+    float texel = globals.shadow_params.x;
+    // Keep the filter from reaching into neighbouring tiles
+    vec2 lower = rect.xy + texel * 1.5;
+    vec2 upper = rect.xy + rect.zw - texel * 1.5;
+    vec2 center = rect.xy + uv * rect.zw;
+    float depth = ndc.z - globals.shadow_params.y;
+
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            vec2 coord = clamp(center + vec2(x, y) * texel, lower, upper);
+            lit += texture(shadow_atlas, vec3(coord, depth));
+        }
+    }
+
+    return lit / 9.0;
+}
+
+// Returns the fraction of light reaching position with normal that isn't occluded
+float light_shadow(LightData light, vec3 position, vec3 normal) {
+    uint count = uint(light.params.w);
+    if (count == 0) {
+        return 1.0;
+    }
+
+    uint first = uint(light.params.z);
+    uint type = uint(light.direction.w);
+    position += normal * globals.shadow_params.z;
+
+    if (type == LIGHT_POINT) {
+        // The faces are ordered +x, -x, +y, -y, +z, -z
+        vec3 offset = position - light.position.xyz;
+        vec3 size = abs(offset);
+        uint face;
+        if (size.x >= size.y && size.x >= size.z) {
+            face = offset.x > 0.0 ? 0u : 1u;
+        } else if (size.y >= size.z) {
+            face = offset.y > 0.0 ? 2u : 3u;
+        } else {
+            face = offset.z > 0.0 ? 4u : 5u;
+        }
+
+        float lit = sample_shadow_view(first + face, position);
+        return lit < 0.0 ? 1.0 : lit;
+    }
+
+    // Directional cascades are ordered outwards from the camera so the first one containing the
+    // position has the highest resolution
+    for (uint i = 0; i < count; i++) {
+        float lit = sample_shadow_view(first + i, position);
+        if (lit >= 0.0) {
+            return lit;
+        }
+    }
+
+    return 1.0;
+}
+
+// Returns the fraction of light reaching position and writes the direction towards the light
+float light_attenuation(LightData light, vec3 position, out vec3 to_light) {
+    uint type = uint(light.direction.w);
+
+    if (type == LIGHT_DIRECTIONAL) {
+        to_light = -normalize(light.direction.xyz);
+        return 1.0;
+    }
+
+    vec3 offset = light.position.xyz - position;
+    float dist = length(offset);
+    to_light = offset / max(dist, 0.0001);
+
+    // Inverse square falloff windowed to reach zero at the range
+    float range = light.position.w;
+    float window = clamp(1.0 - pow(dist / range, 4.0), 0.0, 1.0);
+    float attenuation = window * window / (dist * dist + 1.0);
+
+    if (type == LIGHT_SPOT) {
+        float theta = dot(-to_light, normalize(light.direction.xyz));
+        attenuation *= smoothstep(light.params.y, light.params.x, theta);
+    }
+
+    return attenuation;
+}
+
+// Returns the fraction of the environment reflected by a surface with normal n, Schlick's
+// approximation
+float environment_fresnel(vec3 n, vec3 to_camera) {
+    return ENVIRONMENT_F0 + (1.0 - ENVIRONMENT_F0) * pow(1.0 - max(dot(n, to_camera), 0.0), 5.0);
+}
+
+// Returns the light from the environment reflected towards the camera by a surface with albedo
+// and normal n
+vec3 environment_light(vec3 albedo, vec3 n, vec3 to_camera) {
+    vec3 diffuse = texture(irradiance_map, n).rgb * albedo;
+    float level = ENVIRONMENT_ROUGHNESS * globals.environment.y;
+    vec3 specular = textureLod(specular_map, reflect(-to_camera, n), level).rgb;
+
+    return (diffuse + specular * environment_fresnel(n, to_camera)) * globals.environment.x;
+}
+
+// Returns the lit color of a surface with albedo at position with normal
+// The environment replaces the ambient light when there is one
+vec3 shade_blinn_phong(vec3 albedo, vec3 position, vec3 normal) {
+    vec3 n = normalize(normal);
+    vec3 to_camera = normalize(globals.camera_pos.xyz - position);
+
+    vec3 color = globals.environment.x > 0.0
+        ? environment_light(albedo, n, to_camera)
+        : globals.ambient.rgb * albedo;
+
+    for (uint i = 0; i < globals.light_count; i++) {
+        LightData light = globals.lights[i];
+
+        vec3 to_light;
+        float attenuation = light_attenuation(light, position, to_light);
+        if (attenuation <= 0.0) {
+            continue;
+        }
+
+        attenuation *= light_shadow(light, position, n);
+
+        vec3 radiance = light.color.rgb * light.color.a * attenuation;
+
+        float diffuse = max(dot(n, to_light), 0.0);
+
+        vec3 halfway = normalize(to_light + to_camera);
+        float specular = diffuse > 0.0 ? pow(max(dot(n, halfway), 0.0), SHININESS) : 0.0;
+
+        color += radiance * (albedo * diffuse + SPECULAR_STRENGTH * specular);
+    }
+
+    return color;
+}
@@ -0,0 +1,18 @@
+// The joint matrices of the skinned meshes drawn in a frame
+// Written by the renderer once for each frame and bound at set 0, binding 4
+// Must match JointUniforms in sprocket/src/graphics/vulkan/uniformbuffer.rs
+
+#define MAX_JOINTS 256
+
+layout(set = 0, binding = 4) uniform JointUniforms {
+    // The inverse bind matrix of each joint combined with the world matrix of the joint
+    mat4 joints[MAX_JOINTS];
+} joints;
+
+// Returns the matrix placing a vertex in world space by the weighted joints, starting at offset
+mat4 skin_matrix(uint offset, vec4 indices, vec4 weights) {
+    return weights.x * joints.joints[offset + uint(indices.x)]
+        + weights.y * joints.joints[offset + uint(indices.y)]
+        + weights.z * joints.joints[offset + uint(indices.z)]
+        + weights.w * joints.joints[offset + uint(indices.w)];
+}
@@ -0,0 +1,32 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_GOOGLE_include_directive : enable
+
+#include "include/global.glsl"
+#include "include/skinning.glsl"
+
+layout(push_constant) uniform Transform {
+        mat4 model;
+        // The index of the first joint of the mesh
+        uint joint_offset;
+} transform;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+layout(location = 2) in vec2 inTexCoord;
+layout(location = 3) in vec4 inJoints;
+layout(location = 4) in vec4 inWeights;
+
+layout(location = 0) out vec2 fragTexCoord;
+layout(location = 1) out vec3 fragNormal;
+layout(location = 2) out vec3 fragPosition;
+
+void main() {
+    mat4 world = transform.model * skin_matrix(transform.joint_offset, inJoints, inWeights);
+    vec4 position = world * vec4(inPosition, 1.0);
+    gl_Position = globals.viewproj * position;
+    fragTexCoord = inTexCoord;
+    // Inverse transpose to keep normals perpendicular under non uniform scale
+    fragNormal = transpose(inverse(mat3(world))) * inNormal;
+    fragPosition = position.xyz;
+}
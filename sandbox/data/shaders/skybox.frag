@@ -0,0 +1,12 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 fragDirection;
+
+layout(location = 0) out vec4 outColor;
+
+layout(binding = 0, set = 1) uniform samplerCube skybox;
+
+void main() {
+    outColor = vec4(texture(skybox, fragDirection).rgb, 1.0);
+}
@@ -0,0 +1,16 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+#extension GL_GOOGLE_include_directive : enable
+
+#include "include/global.glsl"
+
+layout(location = 0) out vec3 fragDirection;
+
+void main() {
+    // A triangle covering the viewport at the far plane
+    vec2 ndc = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0;
+    gl_Position = vec4(ndc, 1.0, 1.0);
+
+    vec4 far = inverse(globals.viewproj) * vec4(ndc, 1.0, 1.0);
+    fragDirection = far.xyz / far.w - globals.camera_pos.xyz;
+}
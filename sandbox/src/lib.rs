@@ -0,0 +1,215 @@
+use sprocket::ecs::Entity;
+use sprocket::graphics::{
+    scene, Camera, EnvironmentComponent, Light, MaterialComponent, MeshComponent,
+};
+use sprocket::math::Quaternion;
+use sprocket::physics::Transform;
+use sprocket::systems::animation::Animator;
+use sprocket::*;
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// A named scene that can be loaded into an application
+/// Used by both the sandbox executable and the golden image tests
+pub struct Scene {
+    pub name: &'static str,
+    /// Pushes the layers of the scene
+    pub load: fn(&mut Application),
+}
+
+/// Overrides the directory the sandbox assets are read from
+pub const ASSETS_VAR: &str = "SANDBOX_ASSETS";
+
+/// Returns the directory containing the sandbox assets
+/// The directory named by SANDBOX_ASSETS if set, otherwise the directory of the executable or the
+/// working directory, whichever contains data.pack or a data directory
+fn assets_root() -> PathBuf {
+    if let Some(root) = env::var_os(ASSETS_VAR) {
+        return PathBuf::from(root);
+    }
+
+    let exe_dir = env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_owned));
+    let working_dir = env::current_dir().unwrap_or_default();
+
+    let has_assets = |dir: &Path| dir.join("data.pack").exists() || dir.join("data").is_dir();
+    match exe_dir.filter(|dir| has_assets(dir)) {
+        Some(dir) => dir,
+        None => {
+            if !has_assets(&working_dir) {
+                warn!(
+                    "No sandbox assets next to the executable or in the working directory, set {} to their directory",
+                    ASSETS_VAR
+                );
+            }
+            working_dir
+        }
+    }
+}
+
+/// Mounts the sandbox assets at assets://
+/// Reads from data.pack if it exists, otherwise from the data directory with the assets cooked
+/// by sprocket-cook into the cooked directory, if any, mounted over it
+/// Files in the mods directory, if any, overlay the assets
+/// The directories are looked up in the directory returned by assets_root
+pub fn mount_assets() {
+    let root = assets_root();
+
+    let pack = root.join("data.pack");
+    if pack.exists() {
+        if let Err(e) = vfs::mount_pack("assets", &pack) {
+            error!("Failed to mount asset pack '{}'", e);
+        }
+    } else {
+        vfs::mount_dir("assets", root.join("data"));
+
+        let cooked = root.join("cooked");
+        if cooked.exists() {
+            vfs::mount_dir("assets", cooked);
+        }
+    }
+
+    let mods = root.join("mods");
+    if mods.exists() {
+        vfs::mount_dir("assets", mods);
+    }
+}
+
+/// Returns all sandbox scenes
+pub fn scenes() -> Vec<Scene> {
+    vec![Scene {
+        name: "default",
+        load: |application| application.push_layer(SandboxLayer::new()),
+    }]
+}
+
+/// Spawns a few entities and moves them around
+pub struct SandboxLayer {
+    entities: Vec<Entity>,
+}
+
+impl SandboxLayer {
+    pub fn new() -> Self {
+        SandboxLayer {
+            entities: Vec::new(),
+        }
+    }
+}
+
+impl Default for SandboxLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Layer for SandboxLayer {
+    fn name(&self) -> &str {
+        "Sandbox"
+    }
+
+    fn on_start(&mut self, ctx: &mut Context) {
+        let camera = ctx.create_entity();
+        ctx.component_manager
+            .insert_component(camera, Transform::new(Vec3::new(0.0, 0.0, 5.0)));
+        ctx.component_manager
+            .insert_component(camera, Camera::perspective(1.0, 0.1, 100.0));
+
+        // A sun shining down at an angle and a red point light behind the models, both casting
+        // shadows onto the ground
+        let sun = ctx.create_entity();
+        ctx.component_manager.insert_component(
+            sun,
+            Transform::with_rotation_scale(
+                Vec3::zero(),
+                Quaternion::from_euler(-0.8, -0.5, 0.0),
+                Vec3::one(),
+            ),
+        );
+        ctx.component_manager
+            .insert_component(sun, Light::directional(Vec3::one(), 1.0).with_shadows());
+
+        let lamp = ctx.create_entity();
+        ctx.component_manager
+            .insert_component(lamp, Transform::new(Vec3::new(0.0, 1.0, -2.0)));
+        ctx.component_manager.insert_component(
+            lamp,
+            Light::point(Vec3::new(1.0, 0.2, 0.1), 8.0, 10.0).with_shadows(),
+        );
+
+        // The sky lights the scene and is drawn behind it
+        let sky = ctx.create_entity();
+        ctx.component_manager.insert_component(
+            sky,
+            EnvironmentComponent::with_skybox(
+                "assets://textures/sky.json",
+                1.0,
+                "assets://materials/skybox.json",
+            ),
+        );
+
+        let ground = ctx.create_entity();
+        ctx.component_manager.insert_component(
+            ground,
+            Transform::with_rotation_scale(
+                Vec3::new(0.0, -2.0, 0.0),
+                Quaternion::identity(),
+                Vec3::new(10.0, 0.1, 10.0),
+            ),
+        );
+        ctx.component_manager
+            .insert_component(ground, MeshComponent::model("assets://models/cube.dae"));
+        ctx.component_manager.insert_component(
+            ground,
+            MaterialComponent::new("assets://materials/default.json"),
+        );
+
+        let models = ["assets://models/suzanne.dae", "assets://models/cube.dae"];
+        for model in &models {
+            let entity = ctx.create_entity();
+            ctx.component_manager
+                .insert_component(entity, Transform::new(Vec3::zero()));
+            ctx.component_manager
+                .insert_component(entity, MeshComponent::model(model));
+            ctx.component_manager.insert_component(
+                entity,
+                MaterialComponent::new("assets://materials/default.json"),
+            );
+            self.entities.push(entity);
+        }
+
+        // A skinned column swaying on the ground
+        match scene::spawn_gltf(ctx, "assets://models/column.gltf") {
+            Ok(column) => {
+                if let Some(transform) =
+                    ctx.component_manager.get_component_mut::<Transform>(column)
+                {
+                    transform.position = Vec3::new(-2.5, -1.9, -1.0);
+                }
+                if let Some(animator) = ctx.component_manager.get_component_mut::<Animator>(column)
+                {
+                    animator.play("Sway");
+                }
+            }
+            Err(e) => error!("Failed to spawn column '{}'", e),
+        }
+    }
+
+    fn on_update(&mut self, ctx: &mut Context) {
+        // Scaled so that the scene can be frozen by setting the time scale to zero
+        let elapsed = ctx.time.scaled_elapsed_f32();
+        let positions = [
+            Vec3::new(0.0, elapsed.sin(), 0.0),
+            Vec3::new(elapsed.sin() * 3.0, 2.0, -4.0),
+        ];
+
+        for (entity, position) in self.entities.iter().zip(positions.iter()) {
+            if let Some(transform) = ctx
+                .component_manager
+                .get_component_mut::<Transform>(*entity)
+            {
+                transform.position = *position;
+            }
+        }
+    }
+}
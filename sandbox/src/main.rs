@@ -1,26 +1,25 @@
 use sprocket::*;
 use std::env;
-fn main() {
-    utils::normalize_working_dir();
-    env::set_current_dir("../../sandbox").expect("Failed to set working directory");
 
+fn main() {
     let mut application = Application::new("Sandbox");
     info!("Created application {}", application.name());
 
     logger::init(log::LevelFilter::Trace);
 
+    sandbox::mount_assets();
+
     application.add_window("Sandbox", 800, 600, WindowMode::Windowed);
 
     application.init_graphics();
+
+    // Load the scene named by the first argument
+    let name = env::args().nth(1).unwrap_or_else(|| "default".to_owned());
+    match sandbox::scenes().iter().find(|scene| scene.name == name) {
+        Some(scene) => (scene.load)(&mut application),
+        None => error!("No scene named '{}'", name),
+    }
     application.run();
 
     info!("Terminating application");
-
-    // let mut window =
-    //     Window::new("Sandbox", 800, 600, WindowMode::FullScreen).expect("Failed to create window");
-
-    // loop {
-    //     thread::sleep(Duration::from_millis(500));
-    //     window.poll_events();
-    // }
 }
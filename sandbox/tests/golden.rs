@@ -0,0 +1,139 @@
+//! Renders the sandbox scenes headlessly and compares them to the golden images in tests/golden
+//! Needs a vulkan device and is therefore ignored by default, run it with
+//! `cargo test -p sandbox --test golden -- --ignored`
+//! A missing golden image fails the test, set UPDATE_GOLDEN to write all golden images from the
+//! current output
+//! See tests/golden/README.md for generating and reviewing the golden images
+use sprocket::graphics::Image;
+use sprocket::*;
+use std::env;
+use std::path::{Path, PathBuf};
+
+const WIDTH: u32 = 256;
+const HEIGHT: u32 = 256;
+
+/// The number of frames rendered before capturing once no resources are loading in the background
+/// Gives materials created with placeholder textures time to be replaced
+const FRAMES: usize = 3;
+
+/// The maximum difference of any channel for a pixel to be considered equal
+const TOLERANCE: u8 = 8;
+
+/// The fraction of pixels allowed to exceed the tolerance
+/// Allows for small rasterization differences between drivers
+const MAX_MISMATCHED: f32 = 0.001;
+
+/// Requests to quit after a number of frames have been rendered with all resources loaded
+struct QuitAfter {
+    frames: usize,
+}
+
+impl Layer for QuitAfter {
+    fn name(&self) -> &str {
+        "QuitAfter"
+    }
+
+    fn on_update(&mut self, ctx: &mut Context) {
+        if ctx
+            .resource_manager
+            .is_some_and(|resource_manager| resource_manager.is_loading())
+        {
+            return;
+        }
+
+        self.frames -= 1;
+        if self.frames == 0 {
+            ctx.quit();
+        }
+    }
+}
+
+/// Renders a scene and returns the last frame
+/// Returns None if no renderer could be created
+fn render(scene: &sandbox::Scene) -> Option<Image> {
+    let mut application = Application::new(scene.name);
+    application.init_headless_graphics(WIDTH, HEIGHT, false);
+    application.renderer()?;
+
+    // Freeze the scene at the start
+    application.time().set_scale(0.0);
+    (scene.load)(&mut application);
+    application.push_overlay(QuitAfter { frames: FRAMES });
+    application.run();
+
+    let image = application
+        .renderer()?
+        .capture_frame()
+        .expect("Failed to capture frame");
+    Some(image)
+}
+
+fn output_dir() -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    std::fs::create_dir_all(&dir).expect("Failed to create output directory");
+    dir
+}
+
+#[test]
+#[ignore = "requires a vulkan device"]
+fn golden_images() {
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    sandbox::mount_assets();
+    let mut failures = Vec::new();
+
+    for scene in sandbox::scenes() {
+        let actual = render(&scene).expect("No vulkan device available to render golden images");
+
+        let golden_path = Path::new("tests/golden").join(format!("{}.png", scene.name));
+        if update {
+            std::fs::create_dir_all("tests/golden").expect("Failed to create golden directory");
+            actual
+                .save_png(&golden_path)
+                .expect("Failed to write golden image");
+            eprintln!("Wrote golden image {:?}", golden_path);
+            continue;
+        }
+
+        if !golden_path.exists() {
+            let actual_path = output_dir().join(format!("{}.actual.png", scene.name));
+            actual.save_png(&actual_path).unwrap();
+            failures.push(format!(
+                "{}: missing golden image {:?}, see {:?} and run with UPDATE_GOLDEN=1 to accept it",
+                scene.name, golden_path, actual_path
+            ));
+            continue;
+        }
+
+        let expected = Image::load_png(&golden_path).expect("Failed to read golden image");
+        let actual_path = output_dir().join(format!("{}.actual.png", scene.name));
+
+        let diff = match actual.compare(&expected, TOLERANCE) {
+            Some(diff) => diff,
+            None => {
+                actual.save_png(&actual_path).unwrap();
+                failures.push(format!(
+                    "{}: size {}x{} does not match golden {}x{}",
+                    scene.name,
+                    actual.width(),
+                    actual.height(),
+                    expected.width(),
+                    expected.height()
+                ));
+                continue;
+            }
+        };
+
+        let pixel_count = (actual.width() * actual.height()) as f32;
+        if diff.mismatched as f32 > pixel_count * MAX_MISMATCHED {
+            let diff_path = output_dir().join(format!("{}.diff.png", scene.name));
+            actual.save_png(&actual_path).unwrap();
+            diff.image.save_png(&diff_path).unwrap();
+            failures.push(format!(
+                "{}: {} pixels differ by up to {}, see {:?}",
+                scene.name, diff.mismatched, diff.max_difference, diff_path
+            ));
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
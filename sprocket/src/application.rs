@@ -1,10 +1,14 @@
 use crate::ecs::*;
-use crate::math::*;
-use crate::physics::Transform;
+use crate::graphics::{
+    Camera, EnvironmentComponent, Light, MaterialComponent, MeshComponent, SkinComponent,
+};
+use crate::layer::{self, Context, Layer, LayerStack};
+use crate::physics::{Parent, Transform};
+use crate::systems::animation::{self, Animator};
 use crate::{event::Event, graphics};
 use crate::{
     graphics::window::{Window, WindowMode},
-    Time, Timer,
+    Scheduler, Time, Timer,
 };
 
 use graphics::vulkan::{renderer::Renderer, ResourceManager};
@@ -15,6 +19,13 @@ use std::{
     time,
 };
 
+/// The default duration of each fixed update step
+const FIXED_TIMESTEP: time::Duration = time::Duration::from_micros(16_667);
+
+/// The maximum number of fixed updates run in a single frame
+/// Prevents the fixed updates from spiraling when a frame takes longer than the steps it simulates
+const MAX_FIXED_STEPS: u32 = 8;
+
 pub struct Application {
     name: String,
     windows: Vec<Window>,
@@ -23,9 +34,14 @@ pub struct Application {
     renderer: Option<Renderer>,
     graphics_context: Option<graphics::GraphicsContext>,
     resource_manager: Option<Arc<ResourceManager>>,
+    /// The extent rendered at when running without a window
+    headless_extent: Option<graphics::Extent2D>,
     component_manager: ComponentManager,
     entity_manager: EntityManager,
+    scheduler: Scheduler,
+    layers: LayerStack,
     time: Time,
+    fixed_timestep: time::Duration,
 }
 
 impl Application {
@@ -34,6 +50,18 @@ impl Application {
         let (event_sender, event_receiver) = mpsc::channel::<Event>();
 
         Window::init_glfw();
+
+        let mut component_manager = ComponentManager::new();
+        component_manager.register_component::<Transform>();
+        component_manager.register_component::<Parent>();
+        component_manager.register_component::<Camera>();
+        component_manager.register_component::<Light>();
+        component_manager.register_component::<MeshComponent>();
+        component_manager.register_component::<MaterialComponent>();
+        component_manager.register_component::<EnvironmentComponent>();
+        component_manager.register_component::<SkinComponent>();
+        component_manager.register_component::<Animator>();
+
         Application {
             name: String::from(name),
             windows: Vec::new(),
@@ -42,9 +70,13 @@ impl Application {
             renderer: None,
             graphics_context: None,
             resource_manager: None,
-            component_manager: ComponentManager::new(),
+            headless_extent: None,
+            component_manager,
             entity_manager: EntityManager::new(),
+            scheduler: Scheduler::new(),
+            layers: LayerStack::new(),
             time: Time::new(),
+            fixed_timestep: FIXED_TIMESTEP,
         }
     }
 
@@ -60,7 +92,13 @@ impl Application {
         // Create vulkan renderer if vulkan
         if let graphics::GraphicsContext::Vulkan(context) = self.graphics_context.as_ref().unwrap()
         {
-            self.resource_manager = Some(Arc::new(ResourceManager::new(Arc::clone(context))));
+            self.resource_manager = match ResourceManager::new(Arc::clone(context)) {
+                Ok(resource_manager) => Some(Arc::new(resource_manager)),
+                Err(e) => {
+                    error!("Failed to create resource manager '{}'", e);
+                    return;
+                }
+            };
             self.renderer = match Renderer::new(
                 Arc::clone(context),
                 &self.windows[0],
@@ -76,35 +114,88 @@ impl Application {
         }
     }
 
+    /// Initializes graphics without a window and renders offscreen at width and height
+    /// The application keeps running without windows until a layer requests to quit
+    /// Validation layers are only required if validation is true
+    pub fn init_headless_graphics(&mut self, width: u32, height: u32, validation: bool) {
+        self.graphics_context = match graphics::init_headless(graphics::Api::Vulkan, validation) {
+            Ok(context) => Some(context),
+            Err(msg) => {
+                error!("Failed to initialize headless graphics '{}'", msg);
+                return;
+            }
+        };
+
+        let extent = graphics::Extent2D::new(width, height);
+        self.headless_extent = Some(extent);
+
+        if let graphics::GraphicsContext::Vulkan(context) = self.graphics_context.as_ref().unwrap()
+        {
+            self.resource_manager = match ResourceManager::new(Arc::clone(context)) {
+                Ok(resource_manager) => Some(Arc::new(resource_manager)),
+                Err(e) => {
+                    error!("Failed to create resource manager '{}'", e);
+                    return;
+                }
+            };
+            self.renderer = match Renderer::new_headless(
+                Arc::clone(context),
+                extent,
+                Arc::clone(self.resource_manager.as_ref().unwrap()),
+            ) {
+                Ok(renderer) => Some(renderer),
+                Err(e) => {
+                    error!("Failed to create headless renderer '{}'", e);
+                    None
+                }
+            };
+        }
+    }
+
     pub fn add_window(&mut self, title: &str, width: i32, height: i32, mode: WindowMode) {
         let window = Window::new(title, width, height, mode, self.event_sender.clone());
         self.windows.push(window);
     }
 
+    /// Pushes a layer on top of all other layers but below overlays
+    pub fn push_layer<L: Layer + 'static>(&mut self, layer: L) {
+        info!("Pushing layer '{}'", layer.name());
+        self.layers.push_layer(Box::new(layer));
+    }
+
+    /// Pushes an overlay on top of all layers and overlays
+    pub fn push_overlay<L: Layer + 'static>(&mut self, overlay: L) {
+        info!("Pushing overlay '{}'", overlay.name());
+        self.layers.push_overlay(Box::new(overlay));
+    }
+
+    /// Sets the duration of each fixed update step
+    pub fn set_fixed_timestep(&mut self, timestep: time::Duration) {
+        self.fixed_timestep = timestep;
+    }
+
     pub fn run(&mut self) {
         let mut garbage_timer = Timer::with_target(time::Duration::from_secs(2));
         let mut timer = Timer::with_target(time::Duration::from_secs(5));
+        let mut fixed_accumulator = time::Duration::from_secs(0);
 
-        // Create some entities
-        let entity = self.entity_manager.create_entity();
-        let entity2 = self.entity_manager.create_entity();
-        self.component_manager
-            .insert_component(entity, Transform::new(Vec3::zero()));
-
-        let renderer = self.renderer.as_mut().unwrap();
-
-        while !self.windows.is_empty() {
-            renderer.insert_entity(
-                entity,
-                Transform::new(Vec3::new(0.0, self.time.elapsed_f32().sin(), 0.0)),
-            );
-            renderer.insert_entity(
-                entity2,
-                Transform::new(Vec3::new(self.time.elapsed_f32().sin() * 3.0, 2.0, -4.0)),
-            );
+        let mut ctx = Context::new(
+            &self.time,
+            self.fixed_timestep,
+            &mut self.component_manager,
+            &mut self.entity_manager,
+            &mut self.scheduler,
+            self.resource_manager.as_ref(),
+            &self.windows,
+        );
+        self.layers.start(&mut ctx);
+        let mut quit = ctx.quit_requested();
 
+        while (!self.windows.is_empty() || self.headless_extent.is_some()) && !quit {
             if garbage_timer.signaled() {
-                self.resource_manager.as_ref().unwrap().collect_garbage(5); // Change to swapchain.image_count() in renderer system
+                if let Some(resource_manager) = &self.resource_manager {
+                    resource_manager.collect_garbage(5); // Change to swapchain.image_count() in renderer system
+                }
                 garbage_timer.restart();
             }
             if timer.signaled() {
@@ -116,10 +207,9 @@ impl Application {
                     self.time.framerate(),
                     self.time.delta_us(),
                 );
-                info!(
-                    "Resources: {:?}",
-                    self.resource_manager.as_ref().unwrap().info()
-                );
+                if let Some(resource_manager) = &self.resource_manager {
+                    info!("Resources: {:?}", resource_manager.info());
+                }
                 timer.restart();
             }
             // Process each window for events
@@ -127,23 +217,175 @@ impl Application {
                 .iter()
                 .for_each(|window| window.process_events());
 
-            renderer.draw_frame(&self.windows[0], &self.time);
+            let mut ctx = Context::new(
+                &self.time,
+                self.fixed_timestep,
+                &mut self.component_manager,
+                &mut self.entity_manager,
+                &mut self.scheduler,
+                self.resource_manager.as_ref(),
+                &self.windows,
+            );
 
-            // Receive and handle events
+            // Receive and dispatch events to the layers
             while let Ok(event) = self.event_receiver.try_recv() {
-                if let Event::MousePosition(_, _) = event {
-                } else {
-                    info!("Event: {:?}", event);
+                if !self.layers.event(&mut ctx, &event) {
+                    if let Event::MousePosition(_, _) = event {
+                    } else {
+                        info!("Event: {:?}", event);
+                    }
                 }
             }
+
+            // Run as many fixed steps as fit in the scaled time passed
+            fixed_accumulator += self.time.scaled_delta_raw();
+            let mut steps = 0;
+            while fixed_accumulator >= self.fixed_timestep && steps < MAX_FIXED_STEPS {
+                self.layers.fixed_update(&mut ctx);
+                fixed_accumulator -= self.fixed_timestep;
+                steps += 1;
+            }
+            if steps == MAX_FIXED_STEPS {
+                fixed_accumulator = time::Duration::from_secs(0);
+            }
+
+            self.layers.update(&mut ctx);
+            animation::update(ctx.component_manager, ctx.time.scaled_delta_f32());
+            Scheduler::update(&mut ctx);
+            quit = ctx.quit_requested();
+
+            self.sync_renderer();
+
+            let extent = match self.headless_extent {
+                Some(extent) => Some(extent),
+                None => self.windows.first().map(|window| window.extent()),
+            };
+            if let (Some(renderer), Some(extent)) = (self.renderer.as_mut(), extent) {
+                renderer.draw_frame(extent, &self.time);
+            }
+
             self.windows.retain(|window| !window.should_close());
             self.time.update();
         }
+
+        let mut ctx = Context::new(
+            &self.time,
+            self.fixed_timestep,
+            &mut self.component_manager,
+            &mut self.entity_manager,
+            &mut self.scheduler,
+            self.resource_manager.as_ref(),
+            &self.windows,
+        );
+        self.layers.shutdown(&mut ctx);
+    }
+
+    /// Sends all components changed this frame to the renderer
+    fn sync_renderer(&mut self) {
+        let renderer = match self.renderer.as_mut() {
+            Some(renderer) => renderer,
+            None => return,
+        };
+
+        let component_manager = &mut self.component_manager;
+        sync_component(
+            component_manager,
+            renderer,
+            Renderer::insert_entity,
+            Renderer::remove_entity,
+        );
+        sync_component(
+            component_manager,
+            renderer,
+            Renderer::insert_parent,
+            Renderer::remove_parent,
+        );
+        sync_component(
+            component_manager,
+            renderer,
+            Renderer::insert_camera,
+            Renderer::remove_camera,
+        );
+        sync_component(
+            component_manager,
+            renderer,
+            Renderer::insert_light,
+            Renderer::remove_light,
+        );
+        sync_component(
+            component_manager,
+            renderer,
+            Renderer::insert_mesh,
+            Renderer::remove_mesh,
+        );
+        sync_component(
+            component_manager,
+            renderer,
+            Renderer::insert_material,
+            Renderer::remove_material,
+        );
+        sync_component(
+            component_manager,
+            renderer,
+            Renderer::insert_environment,
+            Renderer::remove_environment,
+        );
+        sync_component(
+            component_manager,
+            renderer,
+            Renderer::insert_skin,
+            Renderer::remove_skin,
+        );
     }
 
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    /// Returns the renderer if graphics have been successfully initialized
+    pub fn renderer(&self) -> Option<&Renderer> {
+        self.renderer.as_ref()
+    }
+
+    /// Returns the scheduler used to run delayed and repeated callbacks on the main loop
+    pub fn scheduler(&mut self) -> &mut Scheduler {
+        &mut self.scheduler
+    }
+
+    /// Returns the main loop time
+    /// Set the time scale to slow down or pause scheduled callbacks
+    pub fn time(&mut self) -> &mut Time {
+        &mut self.time
+    }
+
+    /// Destroys an entity along with all its components and scheduled callbacks
+    /// The entities placed under it with a Parent are destroyed as well, recursively
+    pub fn destroy_entity(&mut self, entity: Entity) {
+        layer::destroy_entity(
+            &mut self.component_manager,
+            &mut self.entity_manager,
+            &mut self.scheduler,
+            entity,
+        );
+    }
+}
+
+/// Sends the removed and changed components of type T to the renderer
+fn sync_component<T: Clone + 'static>(
+    component_manager: &mut ComponentManager,
+    renderer: &mut Renderer,
+    insert: fn(&mut Renderer, Entity, T),
+    remove: fn(&mut Renderer, Entity),
+) {
+    for entity in component_manager.take_removed::<T>() {
+        remove(renderer, entity);
+    }
+
+    for entity in component_manager.take_changed::<T>() {
+        if let Some(component) = component_manager.get_component::<T>(entity) {
+            insert(renderer, entity, component.clone());
+        }
+    }
 }
 
 impl Drop for Application {
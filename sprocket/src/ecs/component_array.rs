@@ -1,10 +1,12 @@
 use super::component::ComponentType;
 use super::entity::Entity;
-use std::{collections::HashMap, ops::Deref, ops::DerefMut};
+use std::{collections::HashMap, collections::HashSet, ops::Deref, ops::DerefMut};
 
 /// Interface for the generic concrete ComponentArray
 pub trait IComponentArray {
     fn component_type(&self) -> ComponentType;
+    /// Removes the component associated to entity if any
+    fn remove_entity(&mut self, entity: Entity);
 }
 
 /// Represents an array that holds a components of type T associated to entities
@@ -17,6 +19,14 @@ pub struct ComponentArray<T: 'static> {
     entity_map: HashMap<Entity, usize>,
     /// A non-sparse list of components, index does not map to entity id
     components: Vec<T>,
+    /// The entity owning the component at the same index in components
+    entities: Vec<Entity>,
+    /// Entities whose component has been inserted or mutably borrowed since the last take_changed
+    changed: HashSet<Entity>,
+    /// Entities whose component has been removed since the last take_removed
+    removed: Vec<Entity>,
+    /// If false, changed and removed are not recorded
+    track_changes: bool,
 }
 
 impl<T: 'static> ComponentArray<T> {
@@ -25,6 +35,20 @@ impl<T: 'static> ComponentArray<T> {
         Self {
             entity_map: HashMap::new(),
             components: Vec::new(),
+            entities: Vec::new(),
+            changed: HashSet::new(),
+            removed: Vec::new(),
+            track_changes: true,
+        }
+    }
+
+    /// Creates a new empty component array that doesn't record changed and removed components
+    /// Used for arrays that are never drained with take_changed and take_removed, E.g; the copies
+    /// of components kept by specialized systems
+    pub fn untracked() -> Self {
+        Self {
+            track_changes: false,
+            ..Self::new()
         }
     }
 
@@ -33,13 +57,21 @@ impl<T: 'static> ComponentArray<T> {
         Self {
             entity_map: HashMap::with_capacity(capacity),
             components: Vec::with_capacity(capacity),
+            entities: Vec::with_capacity(capacity),
+            changed: HashSet::new(),
+            removed: Vec::new(),
+            track_changes: true,
         }
     }
 
     /// Returns a mutable component for an entity
     /// Returns None if component doesn't exist for entity
+    /// Marks the component as changed
     pub fn get_component_mut(&mut self, entity: Entity) -> Option<&mut T> {
         let index = self.entity_map.get(&entity)?;
+        if self.track_changes {
+            self.changed.insert(entity);
+        }
         Some(&mut self.components[*index])
     }
 
@@ -53,6 +85,9 @@ impl<T: 'static> ComponentArray<T> {
     /// Inserts a component for entity
     /// If a component already exists for the entity, it is replaced and returned
     pub fn insert_component(&mut self, entity: Entity, component: T) -> Option<T> {
+        if self.track_changes {
+            self.changed.insert(entity);
+        }
         // Component already exists; replace
         if let Some(index) = self.entity_map.get(&entity) {
             Some(std::mem::replace(&mut self.components[*index], component))
@@ -61,6 +96,7 @@ impl<T: 'static> ComponentArray<T> {
         else {
             let component_index = self.components.len();
             self.components.push(component);
+            self.entities.push(entity);
             self.entity_map.insert(entity, component_index);
             None
         }
@@ -76,12 +112,59 @@ impl<T: 'static> ComponentArray<T> {
 
     /// Removes and returns (if any) a component associated to entity
     /// Returns None if component doesn't exist for entity
+    /// The last component is moved into the hole to keep the array non-sparse
     pub fn remove_component(&mut self, entity: Entity) -> Option<T> {
-        if let Some(index) = self.entity_map.remove(&entity) {
-            Some(self.components.remove(index))
-        } else {
-            None
+        let index = self.entity_map.remove(&entity)?;
+        let component = self.components.swap_remove(index);
+        self.entities.swap_remove(index);
+
+        // Update the index of the moved component
+        if let Some(moved) = self.entities.get(index) {
+            self.entity_map.insert(*moved, index);
         }
+
+        if self.track_changes {
+            self.changed.remove(&entity);
+            self.removed.push(entity);
+        }
+        Some(component)
+    }
+
+    /// Returns true if entity has a component in the array
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.entity_map.contains_key(&entity)
+    }
+
+    /// Iterates all components along with the entity they belong to
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.entities.iter().copied().zip(self.components.iter())
+    }
+
+    /// Iterates all components mutably along with the entity they belong to
+    /// Marks each component as changed when it is yielded
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        let changed = &mut self.changed;
+        let track_changes = self.track_changes;
+        self.entities
+            .iter()
+            .copied()
+            .zip(self.components.iter_mut())
+            .inspect(move |(entity, _)| {
+                if track_changes {
+                    changed.insert(*entity);
+                }
+            })
+    }
+
+    /// Returns and clears the entities whose component has been inserted or mutably borrowed
+    /// since last call
+    pub fn take_changed(&mut self) -> Vec<Entity> {
+        self.changed.drain().collect()
+    }
+
+    /// Returns and clears the entities whose component has been removed since last call
+    pub fn take_removed(&mut self) -> Vec<Entity> {
+        std::mem::take(&mut self.removed)
     }
 }
 
@@ -113,4 +196,45 @@ impl<T> IComponentArray for ComponentArray<T> {
     fn component_type(&self) -> ComponentType {
         ComponentType::get::<T>()
     }
+
+    fn remove_entity(&mut self, entity: Entity) {
+        self.remove_component(entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::EntityManager;
+
+    #[test]
+    fn iter_mut_marks_changed() {
+        let mut entities = EntityManager::new();
+        let (a, b) = (entities.create_entity(), entities.create_entity());
+        let mut array = ComponentArray::new();
+        array.insert_component(a, 1);
+        array.insert_component(b, 2);
+        array.take_changed();
+
+        // Only the yielded components are marked
+        for (_, component) in array.iter_mut().take(1) {
+            *component += 1;
+        }
+        assert_eq!(array.take_changed(), vec![a]);
+
+        array.iter_mut().for_each(|(_, component)| *component += 1);
+        let mut changed = array.take_changed();
+        changed.sort_by_key(|entity| usize::from(*entity));
+        assert_eq!(changed, vec![a, b]);
+        assert_eq!(array.get_component(a), Some(&3));
+    }
+
+    #[test]
+    fn untracked_iter_mut_marks_nothing() {
+        let entity = EntityManager::new().create_entity();
+        let mut array = ComponentArray::untracked();
+        array.insert_component(entity, 1);
+        array.iter_mut().for_each(|(_, component)| *component += 1);
+        assert!(array.take_changed().is_empty());
+    }
 }
@@ -51,7 +51,11 @@ impl ComponentManager {
         component_array.get_component_mut(entity)
     }
 
+    /// Inserts a component for entity
+    /// Registers T if it is not already registered
+    /// If a component already exists for the entity, it is replaced and returned
     pub fn insert_component<T: 'static>(&mut self, entity: Entity, component: T) -> Option<T> {
+        self.register_component::<T>();
         let component_array = self.component_array_mut::<T>()?;
         component_array.insert_component(entity, component)
     }
@@ -61,6 +65,36 @@ impl ComponentManager {
         component_array.remove_component(entity)
     }
 
+    /// Removes all components of all types associated to entity
+    pub fn remove_entity(&mut self, entity: Entity) {
+        self.component_arrays
+            .values_mut()
+            .for_each(|array| array.remove_entity(entity));
+    }
+
+    /// Iterates all components of type T along with the entity they belong to
+    /// Returns an empty iterator if T is not registered
+    pub fn iter<T: 'static>(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.component_array::<T>()
+            .into_iter()
+            .flat_map(|component_array| component_array.iter())
+    }
+
+    /// Returns and clears the entities whose component of type T has been inserted or mutably
+    /// borrowed since last call
+    pub fn take_changed<T: 'static>(&mut self) -> Vec<Entity> {
+        self.component_array_mut::<T>()
+            .map(|component_array| component_array.take_changed())
+            .unwrap_or_default()
+    }
+
+    /// Returns and clears the entities whose component of type T has been removed since last call
+    pub fn take_removed<T: 'static>(&mut self) -> Vec<Entity> {
+        self.component_array_mut::<T>()
+            .map(|component_array| component_array.take_removed())
+            .unwrap_or_default()
+    }
+
     //     /// Processes the events that have happened since last time, like mutation, insertion, and
     //     /// removal
     //     /// Generates a list contaning a list of changed components for each component type
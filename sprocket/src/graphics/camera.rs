@@ -0,0 +1,209 @@
+use super::Extent2D;
+use crate::math::{Mat4, Vec4};
+use serde::{Deserialize, Serialize};
+
+/// Describes how a camera projects the scene
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    /// Perspective projection with a vertical field of view in radians
+    Perspective { fov: f32 },
+    /// Orthographic projection where size is the visible height in world units
+    /// The width is derived from the aspect ratio of the viewport
+    Orthographic { size: f32 },
+}
+
+/// Describes where a camera renders to
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum CameraTarget {
+    /// Renders to the main window
+    Window,
+    /// Renders to an offscreen texture
+    /// The texture is registered in the resource manager under name the first time the camera
+    /// is rendered and can then be used by materials like any other texture
+    Texture {
+        name: String,
+        width: u32,
+        height: u32,
+    },
+}
+
+/// A rectangle in normalized coordinates where 0, 0 is the top left and 1, 1 is the bottom right
+/// of the render target
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Viewport {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Viewport {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Returns a viewport covering the whole render target
+    pub fn full() -> Self {
+        Viewport::new(0.0, 0.0, 1.0, 1.0)
+    }
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self::full()
+    }
+}
+
+/// A component that renders the scene from the Transform of its entity
+/// The camera looks down the negative z axis of the transform
+/// Cameras are rendered in order of ascending priority, with cameras rendering to textures being
+/// rendered before the cameras rendering to the window
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Camera {
+    pub projection: Projection,
+    pub near: f32,
+    pub far: f32,
+    /// The part of the target the camera renders to
+    pub viewport: Viewport,
+    /// The color to clear the viewport with before rendering
+    /// If None the color from previous cameras is kept and only depth is cleared
+    pub clear_color: Option<Vec4>,
+    pub priority: i32,
+    pub target: CameraTarget,
+}
+
+impl Camera {
+    /// Creates a perspective camera rendering to the whole window
+    /// fov is the vertical field of view in radians
+    pub fn perspective(fov: f32, near: f32, far: f32) -> Self {
+        Camera {
+            projection: Projection::Perspective { fov },
+            near,
+            far,
+            viewport: Viewport::full(),
+            clear_color: Some(Vec4::new(0.0, 0.0, 0.01, 1.0)),
+            priority: 0,
+            target: CameraTarget::Window,
+        }
+    }
+
+    /// Creates an orthographic camera rendering to the whole window
+    /// size is the visible height in world units
+    pub fn orthographic(size: f32, near: f32, far: f32) -> Self {
+        Camera {
+            projection: Projection::Orthographic { size },
+            ..Self::perspective(1.0, near, far)
+        }
+    }
+
+    /// Returns the projection matrix for a viewport with aspect ratio aspect
+    pub fn projection_matrix(&self, aspect: f32) -> Mat4 {
+        match self.projection {
+            Projection::Perspective { fov } => Mat4::perspective(aspect, fov, self.near, self.far),
+            Projection::Orthographic { size } => {
+                Mat4::ortho(size * aspect, size, self.near, self.far)
+            }
+        }
+    }
+
+    /// Returns the viewport in pixels as x, y, width, height for a target of extent
+    /// The viewport is clamped to be at least one pixel
+    pub fn pixel_viewport(&self, extent: Extent2D) -> (i32, i32, u32, u32) {
+        let x = (self.viewport.x * extent.width as f32) as i32;
+        let y = (self.viewport.y * extent.height as f32) as i32;
+        let width = ((self.viewport.width * extent.width as f32) as u32).max(1);
+        let height = ((self.viewport.height * extent.height as f32) as u32).max(1);
+        (x, y, width, height)
+    }
+
+    /// Returns the part of the pixel viewport inside a target of extent as x, y, width, height
+    /// The viewport may extend past the target while the scissor and cleared area may not
+    /// Returns None if the viewport lies entirely outside the target
+    pub fn pixel_scissor(&self, extent: Extent2D) -> Option<(i32, i32, u32, u32)> {
+        let (x, y, width, height) = self.pixel_viewport(extent);
+        let left = (x as i64).max(0);
+        let top = (y as i64).max(0);
+        let right = (x as i64 + width as i64).min(extent.width as i64);
+        let bottom = (y as i64 + height as i64).min(extent.height as i64);
+        if right <= left || bottom <= top {
+            return None;
+        }
+
+        Some((
+            left as i32,
+            top as i32,
+            (right - left) as u32,
+            (bottom - top) as u32,
+        ))
+    }
+
+    /// Returns the aspect ratio of the viewport on a target of extent
+    pub fn aspect(&self, extent: Extent2D) -> f32 {
+        let (_, _, width, height) = self.pixel_viewport(extent);
+        width as f32 / height as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera(viewport: Viewport) -> Camera {
+        Camera {
+            viewport,
+            ..Camera::perspective(1.0, 0.1, 100.0)
+        }
+    }
+
+    #[test]
+    fn pixel_viewport() {
+        let extent = Extent2D::new(800, 600);
+        assert_eq!(
+            camera(Viewport::full()).pixel_viewport(extent),
+            (0, 0, 800, 600)
+        );
+        assert_eq!(
+            camera(Viewport::new(0.5, 0.25, 0.5, 0.5)).pixel_viewport(extent),
+            (400, 150, 400, 300)
+        );
+        // At least one pixel
+        assert_eq!(
+            camera(Viewport::new(0.0, 0.0, 0.0, 0.0)).pixel_viewport(extent),
+            (0, 0, 1, 1)
+        );
+        assert_eq!(
+            camera(Viewport::new(-0.25, 0.0, 0.5, 1.0)).pixel_viewport(extent),
+            (-200, 0, 400, 600)
+        );
+    }
+
+    #[test]
+    fn pixel_scissor_is_clamped_to_the_target() {
+        let extent = Extent2D::new(800, 600);
+        assert_eq!(
+            camera(Viewport::full()).pixel_scissor(extent),
+            Some((0, 0, 800, 600))
+        );
+        assert_eq!(
+            camera(Viewport::new(-0.25, -0.5, 0.5, 1.0)).pixel_scissor(extent),
+            Some((0, 0, 200, 300))
+        );
+        assert_eq!(
+            camera(Viewport::new(0.75, 0.5, 0.5, 1.0)).pixel_scissor(extent),
+            Some((600, 300, 200, 300))
+        );
+        assert_eq!(
+            camera(Viewport::new(-1.0, 0.0, 0.5, 1.0)).pixel_scissor(extent),
+            None
+        );
+        assert_eq!(
+            camera(Viewport::new(0.0, 1.0, 1.0, 0.5)).pixel_scissor(extent),
+            None
+        );
+    }
+}
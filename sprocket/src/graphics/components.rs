@@ -0,0 +1,110 @@
+//! Serializable components describing what an entity renders
+//! The components only hold descriptions of the graphics resources, E.g; paths
+//! They are resolved into actual meshes and materials by the renderer which also owns the
+//! references to the loaded resources
+use super::vulkan::Vertex;
+use crate::ecs::Entity;
+use crate::math::Mat4;
+use serde::{Deserialize, Serialize};
+
+/// Describes the mesh an entity renders
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum MeshComponent {
+    /// A mesh in a model file
+    /// If mesh is None the first mesh in the model is used
+    Model { path: String, mesh: Option<String> },
+    /// A mesh created from inline vertex and index data
+    /// The mesh is owned by the entity and not shared
+    Data {
+        vertices: Vec<Vertex>,
+        indices: Vec<u32>,
+    },
+}
+
+impl MeshComponent {
+    /// Creates a mesh component using the first mesh in the model at path
+    pub fn model(path: &str) -> Self {
+        MeshComponent::Model {
+            path: path.to_owned(),
+            mesh: None,
+        }
+    }
+
+    /// Creates a mesh component using the mesh with name in the model at path
+    pub fn model_mesh(path: &str, mesh: &str) -> Self {
+        MeshComponent::Model {
+            path: path.to_owned(),
+            mesh: Some(mesh.to_owned()),
+        }
+    }
+
+    /// Creates a mesh component from vertices and indices
+    pub fn data(vertices: Vec<Vertex>, indices: Vec<u32>) -> Self {
+        MeshComponent::Data { vertices, indices }
+    }
+}
+
+/// Describes the material an entity is rendered with by the path to the material
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MaterialComponent {
+    pub path: String,
+}
+
+impl MaterialComponent {
+    pub fn new(path: &str) -> Self {
+        MaterialComponent {
+            path: path.to_owned(),
+        }
+    }
+}
+
+/// Lights the scene with a cube map of its surroundings
+/// The irradiance and reflections of the cube map are generated when it is loaded and replace the
+/// ambient light of lit materials
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EnvironmentComponent {
+    /// The path to a cube map texture, E.g; a layered texture description
+    pub cubemap: String,
+    /// Scales the light from the environment
+    pub intensity: f32,
+    /// The material drawn behind everything by the cameras with a clear color
+    pub skybox: Option<String>,
+}
+
+impl EnvironmentComponent {
+    /// Creates an environment lighting the scene with the cube map at path
+    pub fn new(cubemap: &str, intensity: f32) -> Self {
+        EnvironmentComponent {
+            cubemap: cubemap.to_owned(),
+            intensity,
+            skybox: None,
+        }
+    }
+
+    /// Creates an environment that also draws the skybox material at path
+    pub fn with_skybox(cubemap: &str, intensity: f32, skybox: &str) -> Self {
+        EnvironmentComponent {
+            skybox: Some(skybox.to_owned()),
+            ..Self::new(cubemap, intensity)
+        }
+    }
+}
+
+/// Deforms the skinned mesh of an entity by the transforms of the joint entities
+/// The joint indices of the vertices index into joints
+/// Entities are not serializable so the component is created when a scene is spawned
+#[derive(Clone, Debug, PartialEq)]
+pub struct SkinComponent {
+    pub joints: Vec<Entity>,
+    /// The matrix transforming each joint from the space of the mesh to its bind pose
+    pub inverse_bind_matrices: Vec<Mat4>,
+}
+
+impl SkinComponent {
+    pub fn new(joints: Vec<Entity>, inverse_bind_matrices: Vec<Mat4>) -> Self {
+        SkinComponent {
+            joints,
+            inverse_bind_matrices,
+        }
+    }
+}
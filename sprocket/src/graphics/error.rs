@@ -26,6 +26,22 @@ pub enum Error {
     ParseError,
     UnimplementedFeature(&'static str),
     MissingDescriptorSet(u32),
+    MissingMesh(String, String),
+    MissingSurface,
+    MissingFrame,
+    UnsupportedFormat(vk::Format),
+    PNGDecodeError(png::DecodingError),
+    PNGEncodeError(png::EncodingError),
+    InvalidRenderGraph(String),
+    InvalidPipelineSpec(String),
+    ShaderReflection(String),
+    ShaderMismatch(String, String),
+    ShaderCompile(String, String),
+    AsyncLoad(String, String),
+    VfsError(crate::vfs::Error),
+    InvalidCooked(String, String),
+    InvalidImage(String, String),
+    InvalidGltf(String, String),
 }
 
 impl From<vk::Result> for Error {
@@ -58,6 +74,24 @@ impl From<simple_xml::Error> for Error {
     }
 }
 
+impl From<png::DecodingError> for Error {
+    fn from(error: png::DecodingError) -> Self {
+        Error::PNGDecodeError(error)
+    }
+}
+
+impl From<png::EncodingError> for Error {
+    fn from(error: png::EncodingError) -> Self {
+        Error::PNGEncodeError(error)
+    }
+}
+
+impl From<crate::vfs::Error> for Error {
+    fn from(error: crate::vfs::Error) -> Self {
+        Error::VfsError(error)
+    }
+}
+
 impl From<serde_json::Error> for Error {
     fn from(error: serde_json::Error) -> Self {
         Error::JSONError(error)
@@ -95,6 +129,22 @@ impl std::fmt::Display for Error {
             Error::ParseError => write!(f, "Failed to parse string into a type"),
             Error::UnimplementedFeature(e) => write!(f, "Feature {} is not yet implemented", e),
             Error::MissingDescriptorSet(set_index) => write!(f, "Missing required descriptor set {}", set_index),
+            Error::MissingMesh(model, mesh) => write!(f, "Model {} does not contain a mesh {}", model, mesh),
+            Error::MissingSurface => write!(f, "Cannot present from a headless context"),
+            Error::MissingFrame => write!(f, "No frame has been rendered yet"),
+            Error::UnsupportedFormat(format) => write!(f, "The format {:?} is not supported", format),
+            Error::PNGDecodeError(e) => write!(f, "Failed to decode png {:?}", e),
+            Error::PNGEncodeError(e) => write!(f, "Failed to encode png {:?}", e),
+            Error::InvalidRenderGraph(reason) => write!(f, "Invalid render graph: {}", reason),
+            Error::InvalidPipelineSpec(reason) => write!(f, "Invalid pipeline spec: {}", reason),
+            Error::ShaderReflection(reason) => write!(f, "Failed to reflect SPIR-V: {}", reason),
+            Error::ShaderMismatch(path, reason) => write!(f, "Shader '{}' does not match the pipeline spec: {}", path, reason),
+            Error::ShaderCompile(path, log) => write!(f, "Failed to compile shader '{}'\n{}", path, log),
+            Error::AsyncLoad(path, reason) => write!(f, "Failed to load '{}' in the background: {}", path, reason),
+            Error::VfsError(e) => write!(f, "{}", e),
+            Error::InvalidCooked(path, reason) => write!(f, "Invalid cooked asset '{}': {}", path, reason),
+            Error::InvalidImage(path, reason) => write!(f, "Invalid image '{}': {}", path, reason),
+            Error::InvalidGltf(path, reason) => write!(f, "Invalid glTF file '{}': {}", path, reason),
         }
     }
 }
@@ -0,0 +1,193 @@
+use super::{Error, Result};
+use ex::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// An 8 bit RGBA image in host memory
+/// Used for captured frames and comparing them against reference images
+#[derive(Clone, PartialEq, Debug)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    /// Tightly packed rows of RGBA pixels
+    pixels: Vec<u8>,
+}
+
+/// The result of comparing two images
+pub struct ImageDiff {
+    /// The number of pixels with any channel differing more than the tolerance
+    pub mismatched: usize,
+    /// The largest difference of any channel
+    pub max_difference: u8,
+    /// Marks mismatched pixels red on top of a dimmed version of the expected image
+    pub image: Image,
+}
+
+impl Image {
+    /// Creates an image from tightly packed RGBA pixels
+    /// # Panics
+    /// If the length of pixels does not match the width and height
+    pub fn new(width: u32, height: u32, pixels: Vec<u8>) -> Self {
+        assert_eq!(pixels.len(), (width * height * 4) as usize);
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Loads an image from a PNG file
+    /// Images without an alpha channel are expanded to RGBA
+    pub fn load_png<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+
+        let mut decoder = png::Decoder::new(file);
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let (info, mut reader) = decoder.read_info()?;
+        let mut buf = vec![0; info.buffer_size()];
+        reader.next_frame(&mut buf)?;
+
+        let pixels = match info.color_type {
+            png::ColorType::RGBA => buf,
+            png::ColorType::RGB => buf
+                .chunks_exact(3)
+                .flat_map(|p| [p[0], p[1], p[2], 255].to_vec())
+                .collect(),
+            png::ColorType::GrayscaleAlpha => buf
+                .chunks_exact(2)
+                .flat_map(|p| [p[0], p[0], p[0], p[1]].to_vec())
+                .collect(),
+            png::ColorType::Grayscale => buf
+                .iter()
+                .flat_map(|p| [*p, *p, *p, 255].to_vec())
+                .collect(),
+            png::ColorType::Indexed => {
+                return Err(Error::ImageReadError(path.to_string_lossy().into_owned()))
+            }
+        };
+
+        Ok(Image::new(info.width, info.height, pixels))
+    }
+
+    /// Writes the image to a PNG file
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let file = File::create(path)?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        Ok(())
+    }
+
+    /// Compares self to the expected image pixel by pixel
+    /// Pixels with any channel differing more than tolerance are counted as mismatched
+    /// Returns None if the sizes differ
+    pub fn compare(&self, expected: &Image, tolerance: u8) -> Option<ImageDiff> {
+        if self.width != expected.width || self.height != expected.height {
+            return None;
+        }
+
+        let mut mismatched = 0;
+        let mut max_difference = 0;
+        let mut diff = Vec::with_capacity(self.pixels.len());
+
+        for (actual, expected) in self
+            .pixels
+            .chunks_exact(4)
+            .zip(expected.pixels.chunks_exact(4))
+        {
+            let difference = actual
+                .iter()
+                .zip(expected)
+                .map(|(a, b)| a.abs_diff(*b))
+                .max()
+                .unwrap_or(0);
+
+            max_difference = max_difference.max(difference);
+
+            if difference > tolerance {
+                mismatched += 1;
+                diff.extend_from_slice(&[255, 0, 0, 255]);
+            } else {
+                let luma = (expected[0] as u32 + expected[1] as u32 + expected[2] as u32) / 12;
+                diff.extend_from_slice(&[luma as u8, luma as u8, luma as u8, 255]);
+            }
+        }
+
+        Some(ImageDiff {
+            mismatched,
+            max_difference,
+            image: Image::new(self.width, self.height, diff),
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the tightly packed RGBA pixels
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, color: [u8; 4]) -> Image {
+        let pixels = (0..width * height).flat_map(|_| color.to_vec()).collect();
+        Image::new(width, height, pixels)
+    }
+
+    #[test]
+    fn compare_equal() {
+        let image = solid(4, 4, [10, 20, 30, 255]);
+        let diff = image.compare(&image, 0).unwrap();
+        assert_eq!(diff.mismatched, 0);
+        assert_eq!(diff.max_difference, 0);
+    }
+
+    #[test]
+    fn compare_tolerance() {
+        let expected = solid(2, 2, [100, 100, 100, 255]);
+        let mut pixels = expected.pixels().to_vec();
+        // First pixel within the tolerance, last pixel outside
+        pixels[0] = 104;
+        pixels[14] = 120;
+        let actual = Image::new(2, 2, pixels);
+
+        let diff = actual.compare(&expected, 4).unwrap();
+        assert_eq!(diff.mismatched, 1);
+        assert_eq!(diff.max_difference, 20);
+        assert_eq!(&diff.image.pixels()[12..], &[255, 0, 0, 255]);
+        assert_ne!(&diff.image.pixels()[..4], &[255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn compare_size_mismatch() {
+        let actual = solid(2, 4, [0, 0, 0, 255]);
+        let expected = solid(4, 2, [0, 0, 0, 255]);
+        assert!(actual.compare(&expected, 255).is_none());
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let pixels = (0..3 * 2 * 4).map(|i| (i * 10) as u8).collect();
+        let image = Image::new(3, 2, pixels);
+
+        let path = std::env::temp_dir().join("sprocket-image-round-trip.png");
+        image.save_png(&path).unwrap();
+        let loaded = Image::load_png(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, image);
+    }
+}
@@ -0,0 +1,124 @@
+use super::vulkan::LightData;
+use crate::math::{Vec3, Vec4};
+use crate::physics::Transform;
+use serde::{Deserialize, Serialize};
+
+/// The light types as written to LightData
+/// Must match the LIGHT_* defines in data/shaders/include/global.glsl
+pub const LIGHT_DIRECTIONAL: u32 = 0;
+pub const LIGHT_POINT: u32 = 1;
+pub const LIGHT_SPOT: u32 = 2;
+
+/// Describes how a light is emitted
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    /// Lights the whole scene from the direction of the transform, like the sun
+    Directional,
+    /// Emits in all directions from the position of the transform
+    /// Fades out to nothing at range
+    Point { range: f32 },
+    /// Emits in a cone along the direction of the transform
+    /// The light fades from full intensity at the inner angle to nothing at the outer angle
+    /// The angles are in radians from the center of the cone
+    Spot {
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+/// A component that lights the scene from the Transform of its entity
+/// Directional and spot lights point down the negative z axis of the transform
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: Vec3,
+    pub intensity: f32,
+    /// Renders the light into the shadow atlas so that it is occluded by meshes
+    /// The atlas has room for a limited number of lights, see SHADOW_ATLAS_TILES
+    #[serde(default)]
+    pub casts_shadows: bool,
+}
+
+impl Light {
+    pub fn directional(color: Vec3, intensity: f32) -> Self {
+        Light {
+            kind: LightKind::Directional,
+            color,
+            intensity,
+            casts_shadows: false,
+        }
+    }
+
+    pub fn point(color: Vec3, intensity: f32, range: f32) -> Self {
+        Light {
+            kind: LightKind::Point { range },
+            color,
+            intensity,
+            casts_shadows: false,
+        }
+    }
+
+    pub fn spot(
+        color: Vec3,
+        intensity: f32,
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Self {
+        Light {
+            kind: LightKind::Spot {
+                range,
+                inner_angle,
+                outer_angle,
+            },
+            color,
+            intensity,
+            casts_shadows: false,
+        }
+    }
+
+    /// Makes the light cast shadows
+    pub fn with_shadows(mut self) -> Self {
+        self.casts_shadows = true;
+        self
+    }
+
+    /// Returns the range of the light
+    /// None for directional lights that reach everything
+    pub fn range(&self) -> Option<f32> {
+        match self.kind {
+            LightKind::Directional => None,
+            LightKind::Point { range } | LightKind::Spot { range, .. } => Some(range),
+        }
+    }
+
+    /// Returns the light as laid out in the global uniforms when placed at transform
+    /// The shadow views are filled in by the renderer
+    pub fn data(&self, transform: &Transform) -> LightData {
+        let (ty, (inner, outer)) = match self.kind {
+            LightKind::Directional => (LIGHT_DIRECTIONAL, (0.0, 0.0)),
+            LightKind::Point { .. } => (LIGHT_POINT, (0.0, 0.0)),
+            LightKind::Spot {
+                inner_angle,
+                outer_angle,
+                ..
+            } => (LIGHT_SPOT, (inner_angle.cos(), outer_angle.cos())),
+        };
+
+        let position = transform.position;
+        let direction = transform.forward();
+
+        LightData {
+            position: Vec4::new(
+                position.x,
+                position.y,
+                position.z,
+                self.range().unwrap_or(0.0),
+            ),
+            direction: Vec4::new(direction.x, direction.y, direction.z, ty as f32),
+            color: Vec4::new(self.color.x, self.color.y, self.color.z, self.intensity),
+            params: Vec4::new(inner, outer, 0.0, 0.0),
+        }
+    }
+}
@@ -1,13 +1,23 @@
 mod glfw;
 pub use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use window::Window;
 
+pub mod camera;
+pub mod components;
 pub mod error;
+pub mod image;
+pub mod light;
+pub mod scene;
 pub mod vulkan;
 pub mod window;
 
+pub use camera::{Camera, CameraTarget, Projection, Viewport};
+pub use components::{EnvironmentComponent, MaterialComponent, MeshComponent, SkinComponent};
 pub use error::{Error, Result};
+pub use image::{Image, ImageDiff};
+pub use light::{Light, LightKind};
 
 pub enum GraphicsContext {
     Vulkan(Arc<vulkan::VulkanContext>),
@@ -32,6 +42,20 @@ pub fn init(api: Api, window: &Window) -> Result<GraphicsContext> {
     }
 }
 
+/// Initializes the graphics api without a window and returns a context
+/// The context can only be used for offscreen rendering
+/// Validation layers are only required if validation is true
+pub fn init_headless(api: Api, validation: bool) -> Result<GraphicsContext> {
+    match api {
+        Api::Vulkan => match vulkan::init_headless(validation) {
+            Ok(context) => Ok(GraphicsContext::Vulkan(Arc::new(context))),
+            Err(f) => Err(f),
+        },
+        Api::OpenGL => Err(Error::UnsupportedAPI(api)),
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct Extent2D {
     width: u32,
     height: u32,
@@ -0,0 +1,165 @@
+//! Spawning the scenes of model files as entities
+use super::vulkan::gltf::{Gltf, GltfScene};
+use super::{MaterialComponent, MeshComponent, Result, SkinComponent};
+use crate::ecs::Entity;
+use crate::layer::Context;
+use crate::math::Vec3;
+use crate::physics::{Parent, Transform};
+use crate::systems::animation::{AnimationChannel, AnimationClip, Animator};
+
+/// Spawns the default scene of the glTF file at path
+/// Each node becomes an entity with a Transform and a Parent, placed under a root entity which
+/// is returned
+/// Nodes with a mesh of several primitives get a child entity for each primitive
+/// The animations are added to an Animator on the root which doesn't play any of them
+/// Destroying the root with Context::destroy_entity destroys the whole scene
+pub fn spawn_gltf(ctx: &mut Context, path: &str) -> Result<Entity> {
+    let scene = Gltf::open_shared(path)?.scene()?;
+
+    let root = ctx.create_entity();
+    ctx.component_manager
+        .insert_component(root, Transform::new(Vec3::zero()));
+
+    // Spawn the nodes reachable from the roots of the scene, parents before children
+    let mut entities: Vec<Option<Entity>> = vec![None; scene.nodes.len()];
+    let mut primitives: Vec<Vec<Entity>> = vec![Vec::new(); scene.nodes.len()];
+    let mut stack: Vec<(usize, Entity)> =
+        scene.roots.iter().rev().map(|node| (*node, root)).collect();
+    while let Some((node, parent)) = stack.pop() {
+        // Nodes may only have one parent, so a node already spawned is part of a cycle
+        if entities[node].is_some() {
+            log::warn!(
+                "Skipping node {} in '{}' which has several parents",
+                node,
+                path
+            );
+            continue;
+        }
+
+        let (entity, node_primitives) = spawn_node(ctx, &scene, path, node, parent);
+        entities[node] = Some(entity);
+        primitives[node] = node_primitives;
+        stack.extend(
+            scene.nodes[node]
+                .children
+                .iter()
+                .rev()
+                .map(|child| (*child, entity)),
+        );
+    }
+
+    add_skins(ctx, &scene, path, &entities, &primitives);
+
+    let clips: Vec<AnimationClip> = scene
+        .animations
+        .into_iter()
+        .map(|animation| AnimationClip {
+            name: animation.name,
+            // Channels of nodes outside the scene are skipped
+            channels: animation
+                .channels
+                .into_iter()
+                .filter_map(|channel| {
+                    Some(AnimationChannel {
+                        target: entities[channel.node]?,
+                        property: channel.property,
+                        interpolation: channel.interpolation,
+                        times: channel.times,
+                        values: channel.values,
+                    })
+                })
+                .collect(),
+        })
+        .collect();
+
+    if !clips.is_empty() {
+        ctx.component_manager
+            .insert_component(root, Animator::new(clips));
+    }
+
+    Ok(root)
+}
+
+/// Spawns the entity of node under parent along with the entities of its primitives
+/// Returns the entity of the node and the entities rendering its primitives
+fn spawn_node(
+    ctx: &mut Context,
+    scene: &GltfScene,
+    path: &str,
+    node: usize,
+    parent: Entity,
+) -> (Entity, Vec<Entity>) {
+    let node = &scene.nodes[node];
+    let entity = ctx.create_entity();
+    let component_manager = &mut ctx.component_manager;
+    component_manager.insert_component(entity, node.transform.clone());
+    component_manager.insert_component(entity, Parent::new(parent));
+
+    if let Some(camera) = &node.camera {
+        component_manager.insert_component(entity, camera.clone());
+    }
+    if let Some(light) = &node.light {
+        component_manager.insert_component(entity, light.clone());
+    }
+
+    match &node.primitives[..] {
+        [] => (entity, Vec::new()),
+        [(mesh, material)] => {
+            component_manager.insert_component(entity, MeshComponent::model_mesh(path, mesh));
+            component_manager.insert_component(entity, MaterialComponent::new(material));
+            (entity, vec![entity])
+        }
+        primitives => {
+            let primitives = primitives
+                .iter()
+                .map(|(mesh, material)| {
+                    let primitive = ctx.create_entity();
+                    let component_manager = &mut ctx.component_manager;
+                    component_manager.insert_component(primitive, Transform::new(Vec3::zero()));
+                    component_manager.insert_component(primitive, Parent::new(entity));
+                    component_manager
+                        .insert_component(primitive, MeshComponent::model_mesh(path, mesh));
+                    component_manager.insert_component(primitive, MaterialComponent::new(material));
+                    primitive
+                })
+                .collect();
+            (entity, primitives)
+        }
+    }
+}
+
+/// Adds a SkinComponent to the entities rendering the primitives of skinned nodes
+/// Skins with joints outside the scene are skipped
+fn add_skins(
+    ctx: &mut Context,
+    scene: &GltfScene,
+    path: &str,
+    entities: &[Option<Entity>],
+    primitives: &[Vec<Entity>],
+) {
+    for (node, primitives) in scene.nodes.iter().zip(primitives) {
+        let skin = match node.skin {
+            Some(skin) if !primitives.is_empty() => &scene.skins[skin],
+            _ => continue,
+        };
+
+        let joints = match skin
+            .joints
+            .iter()
+            .map(|joint| entities[*joint])
+            .collect::<Option<Vec<Entity>>>()
+        {
+            Some(joints) => joints,
+            None => {
+                log::warn!("Skipping skin in '{}' with joints outside the scene", path);
+                continue;
+            }
+        };
+
+        let component = SkinComponent::new(joints, skin.inverse_bind_matrices.clone());
+        for primitive in primitives {
+            ctx.component_manager
+                .insert_component(*primitive, component.clone());
+        }
+    }
+}
@@ -13,7 +13,8 @@ pub fn create_staging(
     size: u64,
 ) -> Result<(vk::Buffer, vk_mem::Allocation, vk_mem::AllocationInfo)> {
     allocator
-        .borrow()
+        .read()
+        .unwrap()
         .create_buffer(
             &vk::BufferCreateInfo::builder()
                 .size(size)
@@ -29,6 +30,30 @@ pub fn create_staging(
         .map_err(|e| e.into())
 }
 
+// Creates a staging buffer that the GPU can copy into and the host read from
+// Buffer is already mapped on creation
+pub fn create_readback(
+    allocator: &VkAllocator,
+    size: u64,
+) -> Result<(vk::Buffer, vk_mem::Allocation, vk_mem::AllocationInfo)> {
+    allocator
+        .read()
+        .unwrap()
+        .create_buffer(
+            &vk::BufferCreateInfo::builder()
+                .size(size)
+                .usage(vk::BufferUsageFlags::TRANSFER_DST)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                .build(),
+            &vk_mem::AllocationCreateInfo {
+                usage: vk_mem::MemoryUsage::GpuToCpu,
+                flags: vk_mem::AllocationCreateFlags::MAPPED,
+                ..Default::default()
+            },
+        )
+        .map_err(|e| e.into())
+}
+
 /// Copies the contents of one buffer to another
 pub fn copy(
     device: &ash::Device,
@@ -64,15 +89,32 @@ pub fn copy(
     unsafe { device.queue_wait_idle(queue).map_err(|e| e.into()) }
 }
 
-pub fn copy_to_image(
+/// An image and the part of it read by copy_from_image
+pub struct ImageRegion {
+    pub image: vk::Image,
+    /// The layout the image is in before and after the copy
+    pub layout: vk::ImageLayout,
+    pub extent: Extent2D,
+    pub aspect: vk::ImageAspectFlags,
+}
+
+/// Copies the contents of the first mip level and layer of an image to a buffer
+/// The image is transitioned to transfer src for the copy and then back to its layout
+/// Waits for the copy to complete
+pub fn copy_from_image(
     device: &ash::Device,
     queue: vk::Queue,
     commandpool: &CommandPool,
-    src_buffer: vk::Buffer,
-    dst_image: vk::Image,
-    extent: Extent2D,
-    aspect: vk::ImageAspectFlags,
+    src: &ImageRegion,
+    dst_buffer: vk::Buffer,
 ) -> Result<()> {
+    let ImageRegion {
+        image: src_image,
+        layout,
+        extent,
+        aspect,
+    } = *src;
+
     let region = vk::BufferImageCopy {
         buffer_offset: 0,
         buffer_row_length: 0,
@@ -90,18 +132,74 @@ pub fn copy_to_image(
             depth: 1,
         },
     };
+
+    let barrier = |old_layout, new_layout, src_access_mask, dst_access_mask| {
+        vk::ImageMemoryBarrier::builder()
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_access_mask(src_access_mask)
+            .dst_access_mask(dst_access_mask)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(src_image)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: aspect,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .build()
+    };
+
     let commandbuffer = &mut CommandBuffer::new_primary(device, commandpool, 1)?[0];
 
     commandbuffer.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
 
+    let transition = layout != vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
+
     unsafe {
-        device.cmd_copy_buffer_to_image(
+        if transition {
+            device.cmd_pipeline_barrier(
+                commandbuffer.vk(),
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::default(),
+                &[],
+                &[],
+                &[barrier(
+                    layout,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::AccessFlags::MEMORY_WRITE,
+                    vk::AccessFlags::TRANSFER_READ,
+                )],
+            );
+        }
+
+        device.cmd_copy_image_to_buffer(
             commandbuffer.vk(),
-            src_buffer,
-            dst_image,
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            src_image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            dst_buffer,
             &[region],
-        )
+        );
+
+        if transition {
+            device.cmd_pipeline_barrier(
+                commandbuffer.vk(),
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::DependencyFlags::default(),
+                &[],
+                &[],
+                &[barrier(
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    layout,
+                    vk::AccessFlags::TRANSFER_READ,
+                    vk::AccessFlags::MEMORY_READ,
+                )],
+            );
+        }
     }
 
     commandbuffer.end()?;
@@ -1,5 +1,6 @@
 use super::{
-    DescriptorSet, Framebuffer, IndexBuffer, Material, Mesh, Pipeline, RenderPass, VertexBuffer,
+    DescriptorSet, Framebuffer, ImageFormat, IndexBuffer, Material, Mesh, Pipeline,
+    PipelineVariant, RenderPass, VertexBuffer,
 };
 
 use ash::version::DeviceV1_0;
@@ -49,6 +50,12 @@ impl CommandPool {
     }
 }
 
+impl CommandPool {
+    pub fn vk(&self) -> vk::CommandPool {
+        self.pool
+    }
+}
+
 impl Drop for CommandPool {
     fn drop(&mut self) {
         unsafe { self.device.destroy_command_pool(self.pool, None) }
@@ -137,24 +144,30 @@ impl CommandBuffer {
     }
 
     pub fn begin_renderpass(
-        &mut self,
+        &self,
         renderpass: &RenderPass,
         framebuffer: &Framebuffer,
         clear_color: crate::math::Vec4,
     ) {
-        let clear_values = [
-            vk::ClearValue {
-                color: vk::ClearColorValue {
-                    float32: [clear_color.x, clear_color.y, clear_color.z, clear_color.w],
+        // One clear value for each attachment in order
+        let clear_values: Vec<_> = renderpass
+            .spec()
+            .attachments
+            .iter()
+            .map(|attachment| match attachment.format {
+                ImageFormat::Depth => vk::ClearValue {
+                    depth_stencil: vk::ClearDepthStencilValue {
+                        depth: 1.0,
+                        stencil: 0,
+                    },
                 },
-            },
-            vk::ClearValue {
-                depth_stencil: vk::ClearDepthStencilValue {
-                    depth: 1.0,
-                    stencil: 0,
+                _ => vk::ClearValue {
+                    color: vk::ClearColorValue {
+                        float32: [clear_color.x, clear_color.y, clear_color.z, clear_color.w],
+                    },
                 },
-            },
-        ];
+            })
+            .collect();
 
         let renderpass_info = vk::RenderPassBeginInfo::builder()
             .render_pass(renderpass.vk())
@@ -179,7 +192,7 @@ impl CommandBuffer {
         unsafe { self.device.cmd_end_render_pass(self.commandbuffer) };
     }
 
-    pub fn bind_pipeline(&self, pipeline: &Pipeline) {
+    pub fn bind_pipeline(&self, pipeline: &PipelineVariant) {
         unsafe {
             self.device.cmd_bind_pipeline(
                 self.commandbuffer,
@@ -220,15 +233,22 @@ impl CommandBuffer {
         self.bind_indexbuffer(mesh.indexbuffer());
     }
 
-    /// Binds a material and the relevant descriptor sets
+    /// Binds a material with the variant of its pipeline for the current renderpass and the relevant
+    /// descriptor sets
     /// Since binding a material most likely will change the pipeline, the global set (set=0) mut
     /// be provided and bound again
     /// Parameter image_index tells which descriptor set in the material to use since there is one
     /// for each swapchain image
-    pub fn bind_material(&self, material: &Material, global_set: &DescriptorSet, image_index: u32) {
-        self.bind_pipeline(material.pipeline());
+    pub fn bind_material(
+        &self,
+        material: &Material,
+        variant: &PipelineVariant,
+        global_set: &DescriptorSet,
+        image_index: u32,
+    ) {
+        self.bind_pipeline(variant);
         self.bind_descriptorsets(
-            &material.pipeline(),
+            material.pipeline(),
             &[
                 global_set,
                 &material.descriptor_sets()[image_index as usize],
@@ -271,6 +291,67 @@ impl CommandBuffer {
         }
     }
 
+    /// Sets the dynamic viewport of the bound pipeline
+    /// The viewport covers rect with the full depth range
+    pub fn set_viewport(&self, rect: vk::Rect2D) {
+        let viewport = vk::Viewport {
+            x: rect.offset.x as f32,
+            y: rect.offset.y as f32,
+            width: rect.extent.width as f32,
+            height: rect.extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        };
+        unsafe {
+            self.device
+                .cmd_set_viewport(self.commandbuffer, 0, &[viewport])
+        }
+    }
+
+    /// Sets the dynamic scissor of the bound pipeline
+    pub fn set_scissor(&self, rect: vk::Rect2D) {
+        unsafe { self.device.cmd_set_scissor(self.commandbuffer, 0, &[rect]) }
+    }
+
+    /// Clears a region of the attachments in the current renderpass
+    /// Clears the first color attachment to clear_color if Some, and the depth attachment to 1.0
+    pub fn clear_attachments(&self, rect: vk::Rect2D, clear_color: Option<crate::math::Vec4>) {
+        let mut attachments = Vec::with_capacity(2);
+        if let Some(clear_color) = clear_color {
+            attachments.push(vk::ClearAttachment {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                color_attachment: 0,
+                clear_value: vk::ClearValue {
+                    color: vk::ClearColorValue {
+                        float32: [clear_color.x, clear_color.y, clear_color.z, clear_color.w],
+                    },
+                },
+            });
+        }
+
+        attachments.push(vk::ClearAttachment {
+            aspect_mask: vk::ImageAspectFlags::DEPTH,
+            color_attachment: 0,
+            clear_value: vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            },
+        });
+
+        let clear_rect = vk::ClearRect {
+            rect,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+
+        unsafe {
+            self.device
+                .cmd_clear_attachments(self.commandbuffer, &attachments, &[clear_rect])
+        }
+    }
+
     pub fn draw(&self) {
         unsafe {
             self.device.cmd_draw(self.commandbuffer, 3, 1, 0, 0);
@@ -287,7 +368,10 @@ impl CommandBuffer {
     /// Resets/Clears the commandbuffer allowing you to once again record commands
     // Normal comment
     pub fn reset(&self) -> Result<()> {
-        Ok(unsafe { self.device.reset_command_buffer(self.commandbuffer, Default::default()) }?)
+        Ok(unsafe {
+            self.device
+                .reset_command_buffer(self.commandbuffer, Default::default())
+        }?)
     }
 
     pub fn vk(&self) -> vk::CommandBuffer {
@@ -0,0 +1,98 @@
+//! Cooked assets are resources converted ahead of time by sprocket-cook into the form they are
+//! uploaded in
+//! The cooked version of a source file is stored next to it as <source>.<extension>, E.g;
+//! assets://models/cube.dae.mesh, so that a directory of cooked assets can be mounted over the
+//! sources
+use super::{Error, Result};
+use crate::vfs;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::time::SystemTime;
+
+/// Identifies a cooked file
+const MAGIC: &[u8; 4] = b"SCKD";
+/// Incremented when the layout of any cooked format changes
+/// Cooked files of other versions are ignored
+pub const VERSION: u32 = 4;
+
+/// The kinds of assets that are cooked
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CookedKind {
+    /// The meshes of a model file as ModelData
+    Model,
+    /// The decoded pixels, format and mip levels of an image file as TextureData
+    Texture,
+    /// The SPIR-V of a GLSL shader compiled with each set of defines it is used with
+    Shader,
+}
+
+impl CookedKind {
+    pub fn extension(self) -> &'static str {
+        match self {
+            CookedKind::Model => "mesh",
+            CookedKind::Texture => "tex",
+            CookedKind::Shader => "spv",
+        }
+    }
+
+    /// Returns the path the cooked version of source is stored at
+    pub fn path(self, source: &str) -> String {
+        format!("{}.{}", source, self.extension())
+    }
+}
+
+/// Serializes the data cooked for path prefixed by the magic and version
+pub fn encode<T: Serialize>(path: &str, data: &T) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bincode::serialize_into(&mut bytes, data)
+        .map_err(|e| Error::InvalidCooked(path.to_owned(), e.to_string()))?;
+    Ok(bytes)
+}
+
+/// Reads and deserializes the cooked file at path
+pub fn read<T: DeserializeOwned>(path: &str) -> Result<T> {
+    let bytes = vfs::read(path)?;
+    let invalid = |reason: String| Error::InvalidCooked(path.to_owned(), reason);
+
+    if bytes.len() < 8 || &bytes[0..4] != MAGIC {
+        return Err(invalid("not a cooked file".to_owned()));
+    }
+
+    let version = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+    if version != VERSION {
+        return Err(invalid(format!("cooked with version {}", version)));
+    }
+
+    bincode::deserialize(&bytes[8..]).map_err(|e| invalid(e.to_string()))
+}
+
+/// Returns the path of the cooked version of source if there is one and source hasn't been
+/// modified since it was cooked
+pub fn find(kind: CookedKind, source: &str) -> Option<String> {
+    let path = kind.path(source);
+    if vfs::exists(&path) && !is_stale(&path, &[source]) {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+/// Returns true if any of sources have been modified after the cooked file at path
+/// Sources that don't exist, E.g; because only the cooked assets are shipped, are ignored
+pub fn is_stale<S: AsRef<str>>(path: &str, sources: &[S]) -> bool {
+    let cooked = match modified(path) {
+        Some(cooked) => cooked,
+        None => return false,
+    };
+
+    sources
+        .iter()
+        .filter_map(|source| modified(source.as_ref()))
+        .any(|source| source > cooked)
+}
+
+/// Returns the time the file path is read from was last modified
+pub(super) fn modified(path: &str) -> Option<SystemTime> {
+    vfs::source_path(path)?.metadata().ok()?.modified().ok()
+}
@@ -0,0 +1,410 @@
+//! Cube maps filtered on the CPU for image based lighting
+//! Faces are in the order +X, -X, +Y, -Y, +Z, -Z and oriented as Vulkan samples them
+use super::enums::TextureKind;
+use super::{formats, Error, Result, TextureData};
+use crate::graphics::Extent2D;
+use crate::math::Vec3;
+use ash::vk;
+use std::f32::consts::PI;
+
+/// The number of faces of a cube map
+pub const FACES: usize = 6;
+
+/// The format of generated cube maps
+pub const FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+/// The number of samples per texel when prefiltering for specular reflections
+const PREFILTER_SAMPLES: u32 = 64;
+
+/// The source level is the largest no wider than this when convolving irradiance
+const IRRADIANCE_SOURCE_SIZE: u32 = 16;
+
+/// Returns the normalized direction through s, t in -1..1 on face
+fn face_direction(face: usize, s: f32, t: f32) -> Vec3 {
+    match face {
+        0 => Vec3::new(1.0, -t, -s),
+        1 => Vec3::new(-1.0, -t, s),
+        2 => Vec3::new(s, 1.0, t),
+        3 => Vec3::new(s, -1.0, -t),
+        4 => Vec3::new(s, -t, 1.0),
+        _ => Vec3::new(-s, -t, -1.0),
+    }
+    .norm()
+}
+
+/// Returns the face a direction points to and the coordinates in 0..1 it hits the face at
+fn direction_face(direction: Vec3) -> (usize, f32, f32) {
+    let Vec3 { x, y, z } = direction;
+    let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+
+    let (face, sc, tc, ma) = if ax >= ay && ax >= az {
+        if x > 0.0 {
+            (0, -z, -y, ax)
+        } else {
+            (1, z, -y, ax)
+        }
+    } else if ay >= az {
+        if y > 0.0 {
+            (2, x, z, ay)
+        } else {
+            (3, x, -z, ay)
+        }
+    } else if z > 0.0 {
+        (4, x, -y, az)
+    } else {
+        (5, -x, -y, az)
+    };
+
+    (face, (sc / ma + 1.0) * 0.5, (tc / ma + 1.0) * 0.5)
+}
+
+/// Returns the direction through the center of a texel of a face size texels wide
+fn texel_direction(face: usize, x: u32, y: u32, size: u32) -> Vec3 {
+    let s = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+    let t = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+    face_direction(face, s, t)
+}
+
+/// Returns the solid angle of a texel of a face size texels wide
+fn texel_solid_angle(x: u32, y: u32, size: u32) -> f32 {
+    let area = |x: f32, y: f32| (x * y).atan2((x * x + y * y + 1.0).sqrt());
+    let coord = |i: u32| i as f32 / size as f32 * 2.0 - 1.0;
+    let (x0, y0, x1, y1) = (coord(x), coord(y), coord(x + 1), coord(y + 1));
+    area(x0, y0) - area(x0, y1) - area(x1, y0) + area(x1, y1)
+}
+
+/// The linear RGBA texels of the mip levels of a cube map
+pub struct CubeImage {
+    size: u32,
+    /// The texels of each face of each level
+    levels: Vec<Vec<Vec<[f32; 4]>>>,
+}
+
+impl CubeImage {
+    /// Creates a cube map from the texels of each face and box filters a full mip chain
+    pub fn new(size: u32, faces: Vec<Vec<[f32; 4]>>) -> Self {
+        let mut levels = vec![faces];
+        let mut level_size = size;
+        while level_size > 1 {
+            let previous = levels.last().unwrap();
+            let faces = previous
+                .iter()
+                .map(|face| downsample(face, level_size))
+                .collect();
+            levels.push(faces);
+            level_size /= 2;
+        }
+
+        Self { size, levels }
+    }
+
+    /// Creates a cube map of size with the color of each texel returned for its direction
+    pub fn from_fn<F: Fn(Vec3) -> [f32; 4]>(size: u32, f: F) -> Self {
+        let faces = (0..FACES)
+            .map(|face| {
+                (0..size * size)
+                    .map(|i| f(texel_direction(face, i % size, i / size, size)))
+                    .collect()
+            })
+            .collect();
+
+        Self::new(size, faces)
+    }
+
+    /// Reads the first mip level of each face of the cube map texture data of the image at path
+    pub fn from_data(path: &str, data: &TextureData) -> Result<Self> {
+        if data.kind != TextureKind::Cube {
+            return Err(Error::InvalidImage(
+                path.to_owned(),
+                format!("expected a cube map, found {:?}", data.kind),
+            ));
+        }
+
+        let faces = (0..FACES as u32)
+            .map(|face| {
+                formats::to_rgba_f32(data.format, data.mip_extent(0), data.level_pixels(face, 0)?)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self::new(data.extent.width, faces))
+    }
+
+    /// Projects the linear RGBA texels of an equirectangular image onto a cube map of size
+    /// The top row of the image is straight up and the center looks down +X
+    pub fn from_equirectangular(
+        texels: &[[f32; 4]],
+        (width, height): (u32, u32),
+        size: u32,
+    ) -> Self {
+        Self::from_fn(size, |direction| {
+            let u = 0.5 + direction.z.atan2(direction.x) / (2.0 * PI);
+            let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+
+            // Wrap horizontally and clamp vertically
+            let x = u * width as f32 - 0.5;
+            let y = (v * height as f32 - 0.5).clamp(0.0, height as f32 - 1.0);
+            let (x0, y0) = (x.floor(), y.floor());
+            let (fx, fy) = (x - x0, y - y0);
+
+            let texel = |x: i64, y: i64| {
+                let x = x.rem_euclid(width as i64) as u32;
+                let y = y.clamp(0, height as i64 - 1) as u32;
+                texels[(y * width + x) as usize]
+            };
+
+            let (x0, y0) = (x0 as i64, y0 as i64);
+            bilinear(
+                [
+                    texel(x0, y0),
+                    texel(x0 + 1, y0),
+                    texel(x0, y0 + 1),
+                    texel(x0 + 1, y0 + 1),
+                ],
+                fx,
+                fy,
+            )
+        })
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Returns the width of a mip level
+    fn level_size(&self, level: usize) -> u32 {
+        (self.size >> level).max(1)
+    }
+
+    /// Samples the cube map trilinearly in a direction at a fractional mip level
+    /// Faces are clamped at their edges
+    pub fn sample(&self, direction: Vec3, level: f32) -> [f32; 4] {
+        let level = level.clamp(0.0, (self.levels.len() - 1) as f32);
+        let (base, fraction) = (level.floor() as usize, level.fract());
+
+        let lower = self.sample_level(direction, base);
+        if fraction == 0.0 {
+            return lower;
+        }
+
+        let upper = self.sample_level(direction, base + 1);
+        lerp(lower, upper, fraction)
+    }
+
+    /// Samples a mip level of the cube map bilinearly
+    fn sample_level(&self, direction: Vec3, level: usize) -> [f32; 4] {
+        let (face, u, v) = direction_face(direction);
+        let size = self.level_size(level);
+        let texels = &self.levels[level][face];
+
+        let max = (size - 1) as f32;
+        let x = (u * size as f32 - 0.5).clamp(0.0, max);
+        let y = (v * size as f32 - 0.5).clamp(0.0, max);
+        let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+        let (x1, y1) = ((x0 + 1).min(size - 1), (y0 + 1).min(size - 1));
+        let texel = |x: u32, y: u32| texels[(y * size + x) as usize];
+
+        bilinear(
+            [texel(x0, y0), texel(x1, y0), texel(x0, y1), texel(x1, y1)],
+            x - x0 as f32,
+            y - y0 as f32,
+        )
+    }
+
+    /// Convolves the cube map into a cube map of size storing the irradiance from every direction
+    /// divided by pi, so that multiplying by albedo gives the diffuse light reflected
+    pub fn irradiance(&self, size: u32) -> Self {
+        // Sum over a small level, each texel weighted by its solid angle
+        let level = (0..self.levels.len())
+            .find(|&level| self.level_size(level) <= IRRADIANCE_SOURCE_SIZE)
+            .unwrap_or(self.levels.len() - 1);
+        let source_size = self.level_size(level);
+
+        let mut sources = Vec::new();
+        for face in 0..FACES {
+            for y in 0..source_size {
+                for x in 0..source_size {
+                    sources.push((
+                        texel_direction(face, x, y, source_size),
+                        texel_solid_angle(x, y, source_size),
+                        self.levels[level][face][(y * source_size + x) as usize],
+                    ));
+                }
+            }
+        }
+
+        Self::from_fn(size, |normal| {
+            let mut sum = [0.0; 4];
+            for (direction, solid_angle, color) in &sources {
+                let cos = Vec3::dot(&normal, direction);
+                if cos > 0.0 {
+                    for i in 0..3 {
+                        sum[i] += color[i] * cos * solid_angle;
+                    }
+                }
+            }
+
+            [sum[0] / PI, sum[1] / PI, sum[2] / PI, 1.0]
+        })
+    }
+
+    /// Prefilters the cube map for specular reflections into levels of size and its halves
+    /// Roughness increases linearly from 0 at the first level to 1 at the last
+    /// Each level is importance sampled with the GGX distribution assuming the view direction is
+    /// the normal
+    pub fn prefilter(&self, size: u32, levels: u32) -> Self {
+        let source_texel_solid_angle = 4.0 * PI / (FACES as f32 * (self.size * self.size) as f32);
+
+        let levels = (0..levels)
+            .map(|level| {
+                let level_size = (size >> level).max(1);
+                let roughness = if levels > 1 {
+                    level as f32 / (levels - 1) as f32
+                } else {
+                    0.0
+                };
+
+                (0..FACES)
+                    .map(|face| {
+                        (0..level_size * level_size)
+                            .map(|i| {
+                                let normal = texel_direction(
+                                    face,
+                                    i % level_size,
+                                    i / level_size,
+                                    level_size,
+                                );
+                                self.prefilter_texel(normal, roughness, source_texel_solid_angle)
+                            })
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self { size, levels }
+    }
+
+    /// Returns the prefiltered color of the reflection around normal at roughness
+    fn prefilter_texel(&self, normal: Vec3, roughness: f32, texel_solid_angle: f32) -> [f32; 4] {
+        if roughness == 0.0 {
+            return self.sample(normal, 0.0);
+        }
+
+        let alpha = roughness * roughness;
+        let alpha2 = alpha * alpha;
+
+        let up = if normal.z.abs() < 0.999 {
+            Vec3::forward()
+        } else {
+            Vec3::right()
+        };
+        let tangent = Vec3::cross(&up, &normal).norm();
+        let bitangent = Vec3::cross(&normal, &tangent);
+
+        let mut sum = [0.0; 4];
+        let mut weight = 0.0;
+        for i in 0..PREFILTER_SAMPLES {
+            let (xi_x, xi_y) = hammersley(i, PREFILTER_SAMPLES);
+
+            let phi = 2.0 * PI * xi_x;
+            let cos_theta = ((1.0 - xi_y) / (1.0 + (alpha2 - 1.0) * xi_y)).sqrt();
+            let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+            let half = tangent * (phi.cos() * sin_theta)
+                + bitangent * (phi.sin() * sin_theta)
+                + normal * cos_theta;
+
+            let light = half * (2.0 * Vec3::dot(&normal, &half)) - normal;
+            let n_dot_l = Vec3::dot(&normal, &light);
+            if n_dot_l <= 0.0 {
+                continue;
+            }
+
+            // Sample a blurrier level the less likely the direction is to reduce aliasing
+            let d = (cos_theta * cos_theta) * (alpha2 - 1.0) + 1.0;
+            let distribution = alpha2 / (PI * d * d);
+            let sample_solid_angle = 4.0 / (PREFILTER_SAMPLES as f32 * distribution);
+            let level = 0.5 * (sample_solid_angle / texel_solid_angle).log2() + 1.0;
+
+            let color = self.sample(light, level);
+            for c in 0..3 {
+                sum[c] += color[c] * n_dot_l;
+            }
+            weight += n_dot_l;
+        }
+
+        if weight > 0.0 {
+            [sum[0] / weight, sum[1] / weight, sum[2] / weight, 1.0]
+        } else {
+            self.sample(normal, 0.0)
+        }
+    }
+
+    /// Converts the cube map to half float texture data with the faces as layers
+    pub fn to_data(&self, path: &str) -> Result<TextureData> {
+        let mut pixels = Vec::new();
+        for face in 0..FACES {
+            for level in &self.levels {
+                pixels.extend(formats::to_rgba_f16(&level[face]));
+            }
+        }
+
+        TextureData::new_layered(
+            path,
+            Extent2D::new(self.size, self.size),
+            FORMAT,
+            self.levels.len() as u32,
+            TextureKind::Cube,
+            FACES as u32,
+            pixels,
+        )
+    }
+}
+
+/// Returns the i:th point of a Hammersley sequence of count points
+fn hammersley(i: u32, count: u32) -> (f32, f32) {
+    (
+        i as f32 / count as f32,
+        i.reverse_bits() as f32 * 2.328_306_4e-10,
+    )
+}
+
+fn lerp(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+        a[3] + (b[3] - a[3]) * t,
+    ]
+}
+
+/// Interpolates the texels top left, top right, bottom left, bottom right
+fn bilinear(texels: [[f32; 4]; 4], fx: f32, fy: f32) -> [f32; 4] {
+    lerp(
+        lerp(texels[0], texels[1], fx),
+        lerp(texels[2], texels[3], fx),
+        fy,
+    )
+}
+
+/// Halves a face of size texels by averaging 2x2 blocks
+fn downsample(texels: &[[f32; 4]], size: u32) -> Vec<[f32; 4]> {
+    let half = (size / 2).max(1);
+    let mut result = Vec::with_capacity((half * half) as usize);
+
+    for y in 0..half {
+        for x in 0..half {
+            let mut sum = [0.0; 4];
+            for (dx, dy) in &[(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let px = (x * 2 + dx).min(size - 1);
+                let py = (y * 2 + dy).min(size - 1);
+                let texel = texels[(py * size + px) as usize];
+                for c in 0..4 {
+                    sum[c] += texel[c] / 4.0;
+                }
+            }
+            result.push(sum);
+        }
+    }
+
+    result
+}
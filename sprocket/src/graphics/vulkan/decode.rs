@@ -0,0 +1,509 @@
+//! Decoding of image files into the pixels textures are uploaded from
+//! KTX2 and DDS files are loaded as is with their mip levels, EXR and Radiance HDR images are
+//! decoded to half floats and everything else is decoded with stb_image
+use super::formats;
+use super::texture::{TextureData, TextureSpec};
+use super::{Error, Result};
+use ash::vk;
+use half::f16;
+
+#[link(name = "stb_image", kind = "static")]
+extern "C" {
+    fn stbi_load_from_memory(
+        buffer: *const u8,
+        len: i32,
+        x: *mut i32,
+        y: *mut i32,
+        channels: *mut i32,
+        desired_channels: i32,
+    ) -> *mut u8;
+
+    fn stbi_load_16_from_memory(
+        buffer: *const u8,
+        len: i32,
+        x: *mut i32,
+        y: *mut i32,
+        channels: *mut i32,
+        desired_channels: i32,
+    ) -> *mut u16;
+
+    fn stbi_loadf_from_memory(
+        buffer: *const u8,
+        len: i32,
+        x: *mut i32,
+        y: *mut i32,
+        channels: *mut i32,
+        desired_channels: i32,
+    ) -> *mut f32;
+
+    fn stbi_is_16_bit_from_memory(buffer: *const u8, len: i32) -> i32;
+
+    fn stbi_image_free(retval_from_stbi_load: *mut std::ffi::c_void);
+}
+
+/// Decodes the contents of the image file at path as described by spec
+pub fn decode(path: &str, file: &[u8], spec: &TextureSpec) -> Result<TextureData> {
+    if !matches!(spec.channels, 1 | 2 | 4) {
+        return Err(invalid(path, "the number of channels must be 1, 2 or 4"));
+    }
+
+    let extension = path.rsplit('.').next().unwrap_or_default().to_lowercase();
+    match &extension[..] {
+        "ktx2" => decode_ktx2(path, file),
+        "dds" => decode_dds(path, file, spec),
+        "exr" => decode_exr(path, file, spec),
+        "hdr" => decode_stb(path, file, spec, Depth::Float),
+        _ if unsafe { stbi_is_16_bit_from_memory(file.as_ptr(), file.len() as i32) } != 0 => {
+            decode_stb(path, file, spec, Depth::Sixteen)
+        }
+        _ => decode_stb(path, file, spec, Depth::Eight),
+    }
+}
+
+fn invalid(path: &str, reason: impl ToString) -> Error {
+    Error::InvalidImage(path.to_owned(), reason.to_string())
+}
+
+/// The size of the channels an image is decoded to
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Depth {
+    Eight,
+    Sixteen,
+    /// Half floats
+    Float,
+}
+
+/// Returns the format of uncompressed pixels with channels of depth
+/// Only 4 channel 8 bit formats have an sRGB variant
+fn uncompressed_format(channels: u32, depth: Depth, spec: &TextureSpec) -> vk::Format {
+    match (depth, channels) {
+        (Depth::Eight, 1) => vk::Format::R8_UNORM,
+        (Depth::Eight, 2) => vk::Format::R8G8_UNORM,
+        (Depth::Eight, _) => {
+            formats::with_color_space(vk::Format::R8G8B8A8_UNORM, spec.color_space)
+        }
+        (Depth::Sixteen, 1) => vk::Format::R16_UNORM,
+        (Depth::Sixteen, 2) => vk::Format::R16G16_UNORM,
+        (Depth::Sixteen, _) => vk::Format::R16G16B16A16_UNORM,
+        (Depth::Float, 1) => vk::Format::R16_SFLOAT,
+        (Depth::Float, 2) => vk::Format::R16G16_SFLOAT,
+        (Depth::Float, _) => vk::Format::R16G16B16A16_SFLOAT,
+    }
+}
+
+fn decode_stb(path: &str, file: &[u8], spec: &TextureSpec, depth: Depth) -> Result<TextureData> {
+    let mut width = 0;
+    let mut height = 0;
+    let mut channels = 0;
+    let desired_channels = spec.channels as i32;
+
+    let pixels = unsafe {
+        let (buffer, len) = (file.as_ptr(), file.len() as i32);
+        let (x, y, channels) = (&mut width, &mut height, &mut channels);
+        match depth {
+            Depth::Eight => {
+                let pixels = stbi_load_from_memory(buffer, len, x, y, channels, desired_channels);
+                take_stb(pixels, *x * *y * desired_channels)
+            }
+            Depth::Sixteen => {
+                let pixels =
+                    stbi_load_16_from_memory(buffer, len, x, y, channels, desired_channels);
+                take_stb(pixels, *x * *y * desired_channels)
+                    .map(|pixels| pixels.iter().flat_map(|v| v.to_le_bytes()).collect())
+            }
+            Depth::Float => {
+                let pixels = stbi_loadf_from_memory(buffer, len, x, y, channels, desired_channels);
+                take_stb(pixels, *x * *y * desired_channels).map(|pixels| {
+                    pixels
+                        .iter()
+                        .flat_map(|v| f16::from_f32(*v).to_le_bytes())
+                        .collect()
+                })
+            }
+        }
+    }
+    .ok_or_else(|| Error::ImageReadError(path.to_owned()))?;
+
+    TextureData::new(
+        path,
+        (width, height).into(),
+        uncompressed_format(spec.channels, depth, spec),
+        1,
+        pixels,
+    )
+}
+
+/// Copies and frees the len values returned by stb_image
+/// Returns None if decoding failed
+unsafe fn take_stb<T: Copy>(pixels: *mut T, len: i32) -> Option<Vec<T>> {
+    if pixels.is_null() {
+        return None;
+    }
+
+    let result = std::slice::from_raw_parts(pixels, len as usize).to_vec();
+    stbi_image_free(pixels as _);
+    Some(result)
+}
+
+fn decode_exr(path: &str, file: &[u8], spec: &TextureSpec) -> Result<TextureData> {
+    use exr::prelude::traits::*;
+    let channels = spec.channels as usize;
+
+    let image = read()
+        .no_deep_data()
+        .largest_resolution_level()
+        .rgba_channels(
+            move |resolution, _| {
+                (
+                    resolution.width(),
+                    vec![f16::ZERO; resolution.area() * channels],
+                )
+            },
+            move |(width, pixels): &mut (usize, Vec<f16>),
+                  position: exr::math::Vec2<usize>,
+                  (r, g, b, a): (f16, f16, f16, f16)| {
+                let offset = (position.y() * *width + position.x()) * channels;
+                pixels[offset..offset + channels].copy_from_slice(&[r, g, b, a][..channels]);
+            },
+        )
+        .first_valid_layer()
+        .all_attributes()
+        .from_buffered(std::io::Cursor::new(file))
+        .map_err(|e| invalid(path, e))?;
+
+    let size = image.layer_data.size;
+    let (_, pixels) = image.layer_data.channel_data.pixels;
+
+    TextureData::new(
+        path,
+        (size.width() as u32, size.height() as u32).into(),
+        uncompressed_format(spec.channels, Depth::Float, spec),
+        1,
+        pixels.iter().flat_map(|v| v.to_le_bytes()).collect(),
+    )
+}
+
+fn decode_ktx2(path: &str, file: &[u8]) -> Result<TextureData> {
+    check_ktx2_ranges(file).map_err(|reason| invalid(path, reason))?;
+    let reader = ktx2::Reader::new(file).map_err(|e| invalid(path, e))?;
+    let header = reader.header();
+
+    if header.supercompression_scheme.is_some() {
+        return Err(invalid(
+            path,
+            "supercompressed KTX2 files are not supported",
+        ));
+    }
+
+    if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count > 1 {
+        return Err(invalid(
+            path,
+            "3D, array and cubemap textures are not supported",
+        ));
+    }
+
+    let format = header
+        .format
+        .map(|format| vk::Format::from_raw(format.0.get() as i32))
+        .ok_or_else(|| invalid(path, "KTX2 files without a format are not supported"))?;
+
+    let mut pixels = Vec::new();
+    for level in reader.levels() {
+        pixels.extend_from_slice(level);
+    }
+
+    TextureData::new(
+        path,
+        (header.pixel_width, header.pixel_height).into(),
+        format,
+        header.level_count.max(1),
+        pixels,
+    )
+}
+
+/// The size of the header of a KTX2 file up to the level index
+const KTX2_HEADER_SIZE: usize = 80;
+/// The size of an entry in the level index of a KTX2 file
+const KTX2_LEVEL_SIZE: usize = 24;
+
+/// Checks that the data format descriptor and mip levels of a KTX2 file lie inside it
+/// The ktx2 crate slices the file by these without checking all of them
+fn check_ktx2_ranges(file: &[u8]) -> std::result::Result<(), &'static str> {
+    // Reads a little endian integer of up to 8 bytes
+    let read = |offset: usize, size: usize| {
+        file[offset..offset + size]
+            .iter()
+            .rev()
+            .fold(0u64, |value, byte| value << 8 | *byte as u64)
+    };
+    let in_file = |offset: u64, length: u64| {
+        offset
+            .checked_add(length)
+            .is_some_and(|end| end <= file.len() as u64)
+    };
+
+    if file.len() < KTX2_HEADER_SIZE {
+        return Err("truncated header");
+    }
+
+    if !in_file(read(48, 4), read(52, 4)) {
+        return Err("the data format descriptor is outside the file");
+    }
+
+    let levels = read(40, 4).max(1) as usize;
+    let index_end = levels
+        .checked_mul(KTX2_LEVEL_SIZE)
+        .and_then(|size| size.checked_add(KTX2_HEADER_SIZE))
+        .filter(|end| *end <= file.len())
+        .ok_or("truncated level index")?;
+
+    for level in (KTX2_HEADER_SIZE..index_end).step_by(KTX2_LEVEL_SIZE) {
+        if !in_file(read(level, 8), read(level + 8, 8)) {
+            return Err("a mip level is outside the file");
+        }
+    }
+
+    Ok(())
+}
+
+/// Loads a DDS file
+/// Files without a DX10 header don't store if they are sRGB, so the color space of spec is used
+/// The size of the pixels is derived from the format and extent rather than the pitch or linear
+/// size in the header
+fn decode_dds(path: &str, file: &[u8], spec: &TextureSpec) -> Result<TextureData> {
+    let dds = ddsfile::Dds::read(file).map_err(|e| invalid(path, e))?;
+
+    if dds.get_depth() > 1 || dds.get_num_array_layers() > 1 {
+        return Err(invalid(
+            path,
+            "3D, array and cubemap textures are not supported",
+        ));
+    }
+
+    let format = dds
+        .get_dxgi_format()
+        .and_then(dxgi_format)
+        .or_else(|| dds.get_d3d_format().and_then(d3d_format))
+        .ok_or_else(|| invalid(path, "unsupported DDS format"))?;
+
+    let format = if dds.header10.is_none() {
+        formats::with_color_space(format, spec.color_space)
+    } else {
+        format
+    };
+
+    // The layer is validated and cut to size when creating the data
+    TextureData::new(
+        path,
+        (dds.get_width(), dds.get_height()).into(),
+        format,
+        dds.get_num_mipmap_levels().max(1),
+        dds.data,
+    )
+}
+
+fn dxgi_format(format: ddsfile::DxgiFormat) -> Option<vk::Format> {
+    use ddsfile::DxgiFormat as D;
+    use vk::Format as F;
+    let format = match format {
+        D::R8_UNorm => F::R8_UNORM,
+        D::R8G8_UNorm => F::R8G8_UNORM,
+        D::R8G8B8A8_UNorm => F::R8G8B8A8_UNORM,
+        D::R8G8B8A8_UNorm_sRGB => F::R8G8B8A8_SRGB,
+        D::B8G8R8A8_UNorm => F::B8G8R8A8_UNORM,
+        D::B8G8R8A8_UNorm_sRGB => F::B8G8R8A8_SRGB,
+        D::R16_UNorm => F::R16_UNORM,
+        D::R16_Float => F::R16_SFLOAT,
+        D::R16G16_UNorm => F::R16G16_UNORM,
+        D::R16G16_Float => F::R16G16_SFLOAT,
+        D::R16G16B16A16_UNorm => F::R16G16B16A16_UNORM,
+        D::R16G16B16A16_Float => F::R16G16B16A16_SFLOAT,
+        D::R32_Float => F::R32_SFLOAT,
+        D::R32G32_Float => F::R32G32_SFLOAT,
+        D::R32G32B32A32_Float => F::R32G32B32A32_SFLOAT,
+        D::R10G10B10A2_UNorm => F::A2B10G10R10_UNORM_PACK32,
+        D::R11G11B10_Float => F::B10G11R11_UFLOAT_PACK32,
+        D::R9G9B9E5_SharedExp => F::E5B9G9R9_UFLOAT_PACK32,
+        D::BC1_UNorm => F::BC1_RGBA_UNORM_BLOCK,
+        D::BC1_UNorm_sRGB => F::BC1_RGBA_SRGB_BLOCK,
+        D::BC2_UNorm => F::BC2_UNORM_BLOCK,
+        D::BC2_UNorm_sRGB => F::BC2_SRGB_BLOCK,
+        D::BC3_UNorm => F::BC3_UNORM_BLOCK,
+        D::BC3_UNorm_sRGB => F::BC3_SRGB_BLOCK,
+        D::BC4_UNorm => F::BC4_UNORM_BLOCK,
+        D::BC4_SNorm => F::BC4_SNORM_BLOCK,
+        D::BC5_UNorm => F::BC5_UNORM_BLOCK,
+        D::BC5_SNorm => F::BC5_SNORM_BLOCK,
+        D::BC6H_UF16 => F::BC6H_UFLOAT_BLOCK,
+        D::BC6H_SF16 => F::BC6H_SFLOAT_BLOCK,
+        D::BC7_UNorm => F::BC7_UNORM_BLOCK,
+        D::BC7_UNorm_sRGB => F::BC7_SRGB_BLOCK,
+        _ => return None,
+    };
+
+    Some(format)
+}
+
+fn d3d_format(format: ddsfile::D3DFormat) -> Option<vk::Format> {
+    use ddsfile::D3DFormat as D;
+    use vk::Format as F;
+    let format = match format {
+        D::L8 => F::R8_UNORM,
+        D::A8L8 => F::R8G8_UNORM,
+        D::L16 => F::R16_UNORM,
+        D::A8B8G8R8 => F::R8G8B8A8_UNORM,
+        D::A8R8G8B8 => F::B8G8R8A8_UNORM,
+        D::G16R16 => F::R16G16_UNORM,
+        D::A16B16G16R16 => F::R16G16B16A16_UNORM,
+        D::R16F => F::R16_SFLOAT,
+        D::G16R16F => F::R16G16_SFLOAT,
+        D::A16B16G16R16F => F::R16G16B16A16_SFLOAT,
+        D::R32F => F::R32_SFLOAT,
+        D::G32R32F => F::R32G32_SFLOAT,
+        D::A32B32G32R32F => F::R32G32B32A32_SFLOAT,
+        D::DXT1 => F::BC1_RGBA_UNORM_BLOCK,
+        D::DXT2 | D::DXT3 => F::BC2_UNORM_BLOCK,
+        D::DXT4 | D::DXT5 => F::BC3_UNORM_BLOCK,
+        _ => return None,
+    };
+
+    Some(format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::vulkan::enums::ColorSpace;
+
+    fn u32s(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    fn u64s(values: &[u64]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    fn is_invalid(result: Result<TextureData>) -> bool {
+        matches!(result, Err(Error::InvalidImage(..)))
+    }
+
+    /// A 2x2 RGBA image with two mip levels, the full size one filled with 1 and the other with 2
+    fn ktx2() -> Vec<u8> {
+        let mut file = vec![
+            0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+        ];
+        // Format, type size, width, height, depth, layers, faces, levels and supercompression
+        file.extend(u32s(&[37, 1, 2, 2, 0, 0, 1, 2, 0]));
+        // Data format descriptor and key value data
+        file.extend(u32s(&[128, 4, 0, 0]));
+        // Supercompression global data
+        file.extend(u64s(&[0, 0]));
+        // Level index
+        file.extend(u64s(&[132, 16, 16, 148, 4, 4]));
+        file.extend(u32s(&[4]));
+        file.extend([1; 16].iter());
+        file.extend([2; 4].iter());
+        file
+    }
+
+    /// A 4x4 BC1 image with two mip levels
+    fn dds(mip_levels: u32) -> Vec<u8> {
+        let mut file = b"DDS ".to_vec();
+        // Size, flags, height, width, pitch, depth and mip levels
+        file.extend(u32s(&[124, 0x2_1007, 4, 4, 0, 0, mip_levels]));
+        file.extend(u32s(&[0; 11]));
+        // Pixel format
+        file.extend(u32s(&[32, 0x4]));
+        file.extend(b"DXT1");
+        file.extend(u32s(&[0; 5]));
+        // Caps
+        file.extend(u32s(&[0x1000, 0, 0, 0, 0]));
+        file.extend([0xAA; 16].iter());
+        file
+    }
+
+    #[test]
+    fn ktx2_levels() {
+        let data = decode_ktx2("test.ktx2", &ktx2()).unwrap();
+        assert_eq!(data.format, vk::Format::R8G8B8A8_UNORM);
+        assert_eq!(data.mip_levels, 2);
+        assert_eq!(data.level_pixels(0, 0).unwrap(), [1; 16]);
+        assert_eq!(data.level_pixels(0, 1).unwrap(), [2; 4]);
+    }
+
+    #[test]
+    fn ktx2_truncated() {
+        let file = ktx2();
+        for len in 0..file.len() {
+            assert!(
+                is_invalid(decode_ktx2("test.ktx2", &file[..len])),
+                "{}",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn ktx2_out_of_range() {
+        // Level count
+        let mut file = ktx2();
+        file[40..44].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(is_invalid(decode_ktx2("test.ktx2", &file)));
+
+        // Level offset and length
+        let mut file = ktx2();
+        file[80..88].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(is_invalid(decode_ktx2("test.ktx2", &file)));
+        let mut file = ktx2();
+        file[112..120].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(is_invalid(decode_ktx2("test.ktx2", &file)));
+
+        // Data format descriptor
+        let mut file = ktx2();
+        file[48..52].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(is_invalid(decode_ktx2("test.ktx2", &file)));
+
+        // More levels than the image has room for
+        let mut file = ktx2();
+        file[20..28].copy_from_slice(&u32s(&[1, 1]));
+        assert!(is_invalid(decode_ktx2("test.ktx2", &file)));
+    }
+
+    #[test]
+    fn dds_color_space() {
+        let spec = TextureSpec {
+            color_space: ColorSpace::Srgb,
+            channels: 4,
+        };
+        let data = decode_dds("test.dds", &dds(2), &spec).unwrap();
+        assert_eq!(data.format, vk::Format::BC1_RGBA_SRGB_BLOCK);
+        assert_eq!(data.mip_levels, 2);
+        assert_eq!(data.pixels.len(), 16);
+
+        let spec = TextureSpec {
+            color_space: ColorSpace::Linear,
+            ..spec
+        };
+        let data = decode_dds("test.dds", &dds(2), &spec).unwrap();
+        assert_eq!(data.format, vk::Format::BC1_RGBA_UNORM_BLOCK);
+    }
+
+    #[test]
+    fn dds_truncated() {
+        let file = dds(2);
+        for len in 0..file.len() {
+            let result = decode_dds("test.dds", &file[..len], &TextureSpec::default());
+            assert!(is_invalid(result), "{}", len);
+        }
+    }
+
+    #[test]
+    fn dds_out_of_range() {
+        let spec = TextureSpec::default();
+        assert!(is_invalid(decode_dds("test.dds", &dds(u32::MAX), &spec)));
+
+        let mut file = dds(1);
+        file[12..20].copy_from_slice(&u32s(&[u32::MAX, u32::MAX]));
+        assert!(is_invalid(decode_dds("test.dds", &file, &spec)));
+    }
+}
@@ -33,7 +33,7 @@ impl DescriptorSetLayoutBinding {
     }
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug)]
 /// Represents a descriptor type
 /// Commented types are not yet implemented
 pub enum DescriptorType {
@@ -56,7 +56,7 @@ impl From<DescriptorType> for vk::DescriptorType {
     }
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug)]
 pub enum ShaderStage {
     Vertex = 0b1,
     TessellationControl = 0b10,
@@ -4,7 +4,7 @@
 use ash::vk;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Copy, Clone)]
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq)]
 pub enum PipelineStage {
     TopOfPipe = 0b1,
     DrawIndirect = 0b10,
@@ -31,7 +31,7 @@ impl From<PipelineStage> for vk::PipelineStageFlags {
     }
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone)]
+#[derive(Serialize, Deserialize, Copy, Clone, PartialEq)]
 pub enum AccessFlags {
     None = 0,
     IndirectCommandRead = 0b1,
@@ -65,7 +65,7 @@ pub enum AttachmentType {
     Depth,
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
 pub enum AttachmentLoadOp {
     Clear,
     Load,
@@ -82,7 +82,7 @@ impl From<AttachmentLoadOp> for vk::AttachmentLoadOp {
     }
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
 pub enum AttachmentStoreOp {
     Store,
     DontCare,
@@ -97,7 +97,7 @@ impl From<AttachmentStoreOp> for vk::AttachmentStoreOp {
     }
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
 pub enum ImageLayout {
     Undefined,
     General,
@@ -127,3 +127,294 @@ impl From<ImageLayout> for vk::ImageLayout {
         }
     }
 }
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Default)]
+pub enum PrimitiveTopology {
+    PointList,
+    LineList,
+    LineStrip,
+    #[default]
+    TriangleList,
+    TriangleStrip,
+    TriangleFan,
+}
+
+impl From<PrimitiveTopology> for vk::PrimitiveTopology {
+    fn from(topology: PrimitiveTopology) -> Self {
+        match topology {
+            PrimitiveTopology::PointList => Self::POINT_LIST,
+            PrimitiveTopology::LineList => Self::LINE_LIST,
+            PrimitiveTopology::LineStrip => Self::LINE_STRIP,
+            PrimitiveTopology::TriangleList => Self::TRIANGLE_LIST,
+            PrimitiveTopology::TriangleStrip => Self::TRIANGLE_STRIP,
+            PrimitiveTopology::TriangleFan => Self::TRIANGLE_FAN,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Default)]
+pub enum PolygonMode {
+    #[default]
+    Fill,
+    Line,
+    Point,
+}
+
+impl From<PolygonMode> for vk::PolygonMode {
+    fn from(mode: PolygonMode) -> Self {
+        match mode {
+            PolygonMode::Fill => Self::FILL,
+            PolygonMode::Line => Self::LINE,
+            PolygonMode::Point => Self::POINT,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Default)]
+pub enum CullMode {
+    #[default]
+    None,
+    Front,
+    Back,
+    FrontAndBack,
+}
+
+impl From<CullMode> for vk::CullModeFlags {
+    fn from(mode: CullMode) -> Self {
+        match mode {
+            CullMode::None => Self::NONE,
+            CullMode::Front => Self::FRONT,
+            CullMode::Back => Self::BACK,
+            CullMode::FrontAndBack => Self::FRONT_AND_BACK,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Default)]
+pub enum FrontFace {
+    #[default]
+    Clockwise,
+    CounterClockwise,
+}
+
+impl From<FrontFace> for vk::FrontFace {
+    fn from(face: FrontFace) -> Self {
+        match face {
+            FrontFace::Clockwise => Self::CLOCKWISE,
+            FrontFace::CounterClockwise => Self::COUNTER_CLOCKWISE,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CompareOp {
+    Never,
+    Less,
+    Equal,
+    LessOrEqual,
+    Greater,
+    NotEqual,
+    GreaterOrEqual,
+    Always,
+}
+
+impl From<CompareOp> for vk::CompareOp {
+    fn from(op: CompareOp) -> Self {
+        match op {
+            CompareOp::Never => Self::NEVER,
+            CompareOp::Less => Self::LESS,
+            CompareOp::Equal => Self::EQUAL,
+            CompareOp::LessOrEqual => Self::LESS_OR_EQUAL,
+            CompareOp::Greater => Self::GREATER,
+            CompareOp::NotEqual => Self::NOT_EQUAL,
+            CompareOp::GreaterOrEqual => Self::GREATER_OR_EQUAL,
+            CompareOp::Always => Self::ALWAYS,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub enum StencilOp {
+    Keep,
+    Zero,
+    Replace,
+    IncrementAndClamp,
+    DecrementAndClamp,
+    Invert,
+    IncrementAndWrap,
+    DecrementAndWrap,
+}
+
+impl From<StencilOp> for vk::StencilOp {
+    fn from(op: StencilOp) -> Self {
+        match op {
+            StencilOp::Keep => Self::KEEP,
+            StencilOp::Zero => Self::ZERO,
+            StencilOp::Replace => Self::REPLACE,
+            StencilOp::IncrementAndClamp => Self::INCREMENT_AND_CLAMP,
+            StencilOp::DecrementAndClamp => Self::DECREMENT_AND_CLAMP,
+            StencilOp::Invert => Self::INVERT,
+            StencilOp::IncrementAndWrap => Self::INCREMENT_AND_WRAP,
+            StencilOp::DecrementAndWrap => Self::DECREMENT_AND_WRAP,
+        }
+    }
+}
+
+/// How the output of the fragment shader is combined with a color attachment
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Default)]
+pub enum BlendMode {
+    /// Replaces the color
+    #[default]
+    Opaque,
+    /// Blends by the alpha of the output
+    Alpha,
+    /// Adds the output scaled by its alpha
+    Additive,
+    /// Blends output that has already been multiplied by its alpha
+    Premultiplied,
+}
+
+impl From<BlendMode> for vk::PipelineColorBlendAttachmentState {
+    fn from(mode: BlendMode) -> Self {
+        let (enable, src_color, dst_color, src_alpha, dst_alpha) = match mode {
+            BlendMode::Opaque => (
+                false,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ZERO,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ZERO,
+            ),
+            BlendMode::Alpha => (
+                true,
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            ),
+            BlendMode::Additive => (
+                true,
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ZERO,
+                vk::BlendFactor::ONE,
+            ),
+            BlendMode::Premultiplied => (
+                true,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                vk::BlendFactor::ONE,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            ),
+        };
+
+        vk::PipelineColorBlendAttachmentState {
+            blend_enable: enable as vk::Bool32,
+            src_color_blend_factor: src_color,
+            dst_color_blend_factor: dst_color,
+            color_blend_op: vk::BlendOp::ADD,
+            src_alpha_blend_factor: src_alpha,
+            dst_alpha_blend_factor: dst_alpha,
+            alpha_blend_op: vk::BlendOp::ADD,
+            color_write_mask: vk::ColorComponentFlags::R
+                | vk::ColorComponentFlags::G
+                | vk::ColorComponentFlags::B
+                | vk::ColorComponentFlags::A,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub enum Filter {
+    Nearest,
+    #[default]
+    Linear,
+}
+
+impl From<Filter> for vk::Filter {
+    fn from(filter: Filter) -> Self {
+        match filter {
+            Filter::Nearest => Self::NEAREST,
+            Filter::Linear => Self::LINEAR,
+        }
+    }
+}
+
+impl From<Filter> for vk::SamplerMipmapMode {
+    fn from(filter: Filter) -> Self {
+        match filter {
+            Filter::Nearest => Self::NEAREST,
+            Filter::Linear => Self::LINEAR,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub enum AddressMode {
+    #[default]
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+    ClampToBorder,
+}
+
+impl From<AddressMode> for vk::SamplerAddressMode {
+    fn from(mode: AddressMode) -> Self {
+        match mode {
+            AddressMode::Repeat => Self::REPEAT,
+            AddressMode::MirroredRepeat => Self::MIRRORED_REPEAT,
+            AddressMode::ClampToEdge => Self::CLAMP_TO_EDGE,
+            AddressMode::ClampToBorder => Self::CLAMP_TO_BORDER,
+        }
+    }
+}
+
+/// The color of samples outside a texture with clamp to border addressing
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub enum BorderColor {
+    TransparentBlack,
+    #[default]
+    OpaqueBlack,
+    OpaqueWhite,
+}
+
+impl From<BorderColor> for vk::BorderColor {
+    fn from(color: BorderColor) -> Self {
+        match color {
+            BorderColor::TransparentBlack => Self::FLOAT_TRANSPARENT_BLACK,
+            BorderColor::OpaqueBlack => Self::FLOAT_OPAQUE_BLACK,
+            BorderColor::OpaqueWhite => Self::FLOAT_OPAQUE_WHITE,
+        }
+    }
+}
+
+/// How the 8 bit color channels of a texture are interpreted
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub enum ColorSpace {
+    /// Colors are sRGB encoded and converted to linear when sampled, E.g; for albedo
+    #[default]
+    Srgb,
+    /// Values are sampled as stored, E.g; for normal maps and roughness
+    Linear,
+}
+
+/// How the layers of a texture are arranged and sampled
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub enum TextureKind {
+    /// A single 2D image
+    #[default]
+    Texture2D,
+    /// 2D images of the same size sampled by layer index
+    Array,
+    /// Six square faces in the order +X, -X, +Y, -Y, +Z, -Z sampled by direction
+    Cube,
+}
+
+impl From<TextureKind> for vk::ImageViewType {
+    fn from(kind: TextureKind) -> Self {
+        match kind {
+            TextureKind::Texture2D => Self::TYPE_2D,
+            TextureKind::Array => Self::TYPE_2D_ARRAY,
+            TextureKind::Cube => Self::CUBE,
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use super::cubemap::CubeImage;
+use super::resources::{AsyncResource, Resource};
+use super::{Result, Texture, TextureData, Upload, VulkanContext};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// The width of the faces of the irradiance cube map
+const IRRADIANCE_SIZE: u32 = 32;
+
+/// The width of the faces of the first level of the specular cube map
+const SPECULAR_SIZE: u32 = 128;
+
+/// The number of roughness levels prefiltered into the mip levels of the specular cube map
+const SPECULAR_LEVELS: u32 = 6;
+
+/// The cube maps generated from an environment before they are uploaded
+pub struct EnvironmentData {
+    irradiance: TextureData,
+    specular: TextureData,
+    sources: Vec<PathBuf>,
+}
+
+impl EnvironmentData {
+    /// Convolves the cube map of the image at path for diffuse and specular lighting
+    pub fn generate(path: &str, data: TextureData) -> Result<Self> {
+        let cube = CubeImage::from_data(path, &data)?;
+        Ok(Self {
+            irradiance: cube.irradiance(IRRADIANCE_SIZE).to_data(path)?,
+            specular: cube
+                .prefilter(SPECULAR_SIZE.min(cube.size()), SPECULAR_LEVELS)
+                .to_data(path)?,
+            sources: data.sources,
+        })
+    }
+}
+
+/// The lighting of a scene from its surroundings, created from a cube map
+/// The irradiance map holds the diffuse light reaching a surface facing each direction
+/// The mip levels of the specular map hold the light reflected in each direction by increasingly
+/// rough surfaces
+pub struct EnvironmentMap {
+    irradiance: Arc<Texture>,
+    specular: Arc<Texture>,
+    sources: Vec<PathBuf>,
+}
+
+impl Resource for EnvironmentMap {
+    fn load(resourcemanager: &super::ResourceManager, path: &str) -> Result<Self> {
+        let data = Self::read(path)?;
+        let context = resourcemanager.context();
+        Ok(EnvironmentMap {
+            irradiance: Arc::new(Texture::from_data(context, data.irradiance)?),
+            specular: Arc::new(Texture::from_data(context, data.specular)?),
+            sources: data.sources,
+        })
+    }
+
+    fn dependencies(&self) -> Vec<PathBuf> {
+        self.sources.clone()
+    }
+}
+
+impl AsyncResource for EnvironmentMap {
+    type Data = EnvironmentData;
+
+    fn read(path: &str) -> Result<EnvironmentData> {
+        EnvironmentData::generate(path, <Texture as AsyncResource>::read(path)?)
+    }
+
+    fn upload(
+        resourcemanager: &super::ResourceManager,
+        data: EnvironmentData,
+        upload: &mut Upload,
+    ) -> Result<Self> {
+        Ok(EnvironmentMap {
+            irradiance: Arc::new(<Texture as AsyncResource>::upload(
+                resourcemanager,
+                data.irradiance,
+                upload,
+            )?),
+            specular: Arc::new(<Texture as AsyncResource>::upload(
+                resourcemanager,
+                data.specular,
+                upload,
+            )?),
+            sources: data.sources,
+        })
+    }
+}
+
+impl EnvironmentMap {
+    /// Creates an environment without any light
+    /// Bound when no environment is used
+    pub fn empty(context: &VulkanContext) -> Result<Self> {
+        let black = || CubeImage::from_fn(1, |_| [0.0, 0.0, 0.0, 1.0]).to_data("empty environment");
+        Ok(EnvironmentMap {
+            irradiance: Arc::new(Texture::from_data(context, black()?)?),
+            specular: Arc::new(Texture::from_data(context, black()?)?),
+            sources: Vec::new(),
+        })
+    }
+
+    pub fn irradiance(&self) -> &Arc<Texture> {
+        &self.irradiance
+    }
+
+    pub fn specular(&self) -> &Arc<Texture> {
+        &self.specular
+    }
+
+    /// Returns the mip level of the specular map prefiltered for fully rough surfaces
+    pub fn max_specular_level(&self) -> f32 {
+        (self.specular.mip_levels() - 1) as f32
+    }
+}
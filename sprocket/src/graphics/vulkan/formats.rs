@@ -0,0 +1,580 @@
+//! The texel formats textures are uploaded in
+//! Block compressed formats the device can't sample are decompressed on the CPU when there is a
+//! fallback
+use super::enums::ColorSpace;
+use super::{Error, Result};
+use ash::vk;
+use half::f16;
+
+/// The size of a block of texels
+/// Uncompressed formats have blocks of a single texel
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BlockLayout {
+    pub width: u32,
+    pub height: u32,
+    /// The size in bytes of a block
+    pub size: u32,
+}
+
+impl BlockLayout {
+    const fn new(width: u32, height: u32, size: u32) -> Self {
+        BlockLayout {
+            width,
+            height,
+            size,
+        }
+    }
+
+    /// Returns the size in bytes of an image of width and height
+    /// Returns None if the size overflows
+    pub fn image_size(&self, width: u32, height: u32) -> Option<usize> {
+        let blocks_x = width.div_ceil(self.width) as usize;
+        let blocks_y = height.div_ceil(self.height) as usize;
+        blocks_x
+            .checked_mul(blocks_y)?
+            .checked_mul(self.size as usize)
+    }
+}
+
+/// Returns the block layout of a format textures can be created with
+pub fn block_layout(format: vk::Format) -> Result<BlockLayout> {
+    use vk::Format as F;
+    let layout = match format {
+        F::R8_UNORM | F::R8_SRGB => BlockLayout::new(1, 1, 1),
+        F::R8G8_UNORM | F::R8G8_SRGB | F::R16_UNORM | F::R16_SFLOAT => BlockLayout::new(1, 1, 2),
+        F::R8G8B8_UNORM | F::R8G8B8_SRGB => BlockLayout::new(1, 1, 3),
+        F::R8G8B8A8_UNORM
+        | F::R8G8B8A8_SRGB
+        | F::B8G8R8A8_UNORM
+        | F::B8G8R8A8_SRGB
+        | F::R16G16_UNORM
+        | F::R16G16_SFLOAT
+        | F::R32_SFLOAT
+        | F::A2B10G10R10_UNORM_PACK32
+        | F::B10G11R11_UFLOAT_PACK32
+        | F::E5B9G9R9_UFLOAT_PACK32 => BlockLayout::new(1, 1, 4),
+        F::R16G16B16A16_UNORM | F::R16G16B16A16_SFLOAT | F::R32G32_SFLOAT => {
+            BlockLayout::new(1, 1, 8)
+        }
+        F::R32G32B32A32_SFLOAT => BlockLayout::new(1, 1, 16),
+        F::BC1_RGB_UNORM_BLOCK
+        | F::BC1_RGB_SRGB_BLOCK
+        | F::BC1_RGBA_UNORM_BLOCK
+        | F::BC1_RGBA_SRGB_BLOCK
+        | F::BC4_UNORM_BLOCK
+        | F::BC4_SNORM_BLOCK
+        | F::ETC2_R8G8B8_UNORM_BLOCK
+        | F::ETC2_R8G8B8_SRGB_BLOCK
+        | F::ETC2_R8G8B8A1_UNORM_BLOCK
+        | F::ETC2_R8G8B8A1_SRGB_BLOCK
+        | F::EAC_R11_UNORM_BLOCK
+        | F::EAC_R11_SNORM_BLOCK => BlockLayout::new(4, 4, 8),
+        F::BC2_UNORM_BLOCK
+        | F::BC2_SRGB_BLOCK
+        | F::BC3_UNORM_BLOCK
+        | F::BC3_SRGB_BLOCK
+        | F::BC5_UNORM_BLOCK
+        | F::BC5_SNORM_BLOCK
+        | F::BC6H_UFLOAT_BLOCK
+        | F::BC6H_SFLOAT_BLOCK
+        | F::BC7_UNORM_BLOCK
+        | F::BC7_SRGB_BLOCK
+        | F::ETC2_R8G8B8A8_UNORM_BLOCK
+        | F::ETC2_R8G8B8A8_SRGB_BLOCK
+        | F::EAC_R11G11_UNORM_BLOCK
+        | F::EAC_R11G11_SNORM_BLOCK => BlockLayout::new(4, 4, 16),
+        F::ASTC_4X4_UNORM_BLOCK | F::ASTC_4X4_SRGB_BLOCK => BlockLayout::new(4, 4, 16),
+        F::ASTC_5X4_UNORM_BLOCK | F::ASTC_5X4_SRGB_BLOCK => BlockLayout::new(5, 4, 16),
+        F::ASTC_5X5_UNORM_BLOCK | F::ASTC_5X5_SRGB_BLOCK => BlockLayout::new(5, 5, 16),
+        F::ASTC_6X5_UNORM_BLOCK | F::ASTC_6X5_SRGB_BLOCK => BlockLayout::new(6, 5, 16),
+        F::ASTC_6X6_UNORM_BLOCK | F::ASTC_6X6_SRGB_BLOCK => BlockLayout::new(6, 6, 16),
+        F::ASTC_8X5_UNORM_BLOCK | F::ASTC_8X5_SRGB_BLOCK => BlockLayout::new(8, 5, 16),
+        F::ASTC_8X6_UNORM_BLOCK | F::ASTC_8X6_SRGB_BLOCK => BlockLayout::new(8, 6, 16),
+        F::ASTC_8X8_UNORM_BLOCK | F::ASTC_8X8_SRGB_BLOCK => BlockLayout::new(8, 8, 16),
+        F::ASTC_10X5_UNORM_BLOCK | F::ASTC_10X5_SRGB_BLOCK => BlockLayout::new(10, 5, 16),
+        F::ASTC_10X6_UNORM_BLOCK | F::ASTC_10X6_SRGB_BLOCK => BlockLayout::new(10, 6, 16),
+        F::ASTC_10X8_UNORM_BLOCK | F::ASTC_10X8_SRGB_BLOCK => BlockLayout::new(10, 8, 16),
+        F::ASTC_10X10_UNORM_BLOCK | F::ASTC_10X10_SRGB_BLOCK => BlockLayout::new(10, 10, 16),
+        F::ASTC_12X10_UNORM_BLOCK | F::ASTC_12X10_SRGB_BLOCK => BlockLayout::new(12, 10, 16),
+        F::ASTC_12X12_UNORM_BLOCK | F::ASTC_12X12_SRGB_BLOCK => BlockLayout::new(12, 12, 16),
+        _ => return Err(Error::UnsupportedFormat(format)),
+    };
+
+    Ok(layout)
+}
+
+/// The formats that have both an sRGB and a UNORM variant
+const COLOR_SPACE_PAIRS: &[(vk::Format, vk::Format)] = &[
+    (vk::Format::R8G8B8_UNORM, vk::Format::R8G8B8_SRGB),
+    (vk::Format::R8G8B8A8_UNORM, vk::Format::R8G8B8A8_SRGB),
+    (vk::Format::B8G8R8A8_UNORM, vk::Format::B8G8R8A8_SRGB),
+    (
+        vk::Format::BC1_RGB_UNORM_BLOCK,
+        vk::Format::BC1_RGB_SRGB_BLOCK,
+    ),
+    (
+        vk::Format::BC1_RGBA_UNORM_BLOCK,
+        vk::Format::BC1_RGBA_SRGB_BLOCK,
+    ),
+    (vk::Format::BC2_UNORM_BLOCK, vk::Format::BC2_SRGB_BLOCK),
+    (vk::Format::BC3_UNORM_BLOCK, vk::Format::BC3_SRGB_BLOCK),
+    (vk::Format::BC7_UNORM_BLOCK, vk::Format::BC7_SRGB_BLOCK),
+];
+
+/// Returns the variant of format storing colors in color_space
+/// Formats without an sRGB variant, E.g; single channel and float formats, are returned as is
+pub fn with_color_space(format: vk::Format, color_space: ColorSpace) -> vk::Format {
+    COLOR_SPACE_PAIRS
+        .iter()
+        .find(|(unorm, srgb)| *unorm == format || *srgb == format)
+        .map(|(unorm, srgb)| match color_space {
+            ColorSpace::Srgb => *srgb,
+            ColorSpace::Linear => *unorm,
+        })
+        .unwrap_or(format)
+}
+
+/// Returns the format pixels of format are converted to by convert when the device can't
+/// sample format
+/// None if there is no fallback, E.g; for BC7 and ASTC
+pub fn fallback(format: vk::Format) -> Option<vk::Format> {
+    use vk::Format as F;
+    match format {
+        F::R8G8B8_UNORM
+        | F::BC1_RGB_UNORM_BLOCK
+        | F::BC1_RGBA_UNORM_BLOCK
+        | F::BC2_UNORM_BLOCK
+        | F::BC3_UNORM_BLOCK => Some(F::R8G8B8A8_UNORM),
+        F::R8G8B8_SRGB
+        | F::BC1_RGB_SRGB_BLOCK
+        | F::BC1_RGBA_SRGB_BLOCK
+        | F::BC2_SRGB_BLOCK
+        | F::BC3_SRGB_BLOCK => Some(F::R8G8B8A8_SRGB),
+        F::BC4_UNORM_BLOCK => Some(F::R8_UNORM),
+        F::BC5_UNORM_BLOCK => Some(F::R8G8_UNORM),
+        _ => None,
+    }
+}
+
+/// Converts a single mip level of pixels of format and size to its fallback format
+pub fn convert(format: vk::Format, (width, height): (u32, u32), pixels: &[u8]) -> Result<Vec<u8>> {
+    use vk::Format as F;
+    let to = fallback(format).ok_or(Error::UnsupportedFormat(format))?;
+    let layout = block_layout(format)?;
+    let channels = block_layout(to)?.size as usize;
+    let count = width as usize * height as usize;
+
+    // Uncompressed RGB is expanded with an opaque alpha
+    if layout.width == 1 {
+        let mut result = Vec::with_capacity(count * 4);
+        for rgb in pixels.chunks_exact(3).take(count) {
+            result.extend_from_slice(&[rgb[0], rgb[1], rgb[2], 255]);
+        }
+        return Ok(result);
+    }
+
+    let mut result = vec![0; count * channels];
+    let blocks_x = width.div_ceil(4);
+
+    for (index, block) in pixels
+        .chunks_exact(layout.size as usize)
+        .take(blocks_x as usize * height.div_ceil(4) as usize)
+        .enumerate()
+    {
+        let texels = match format {
+            F::BC1_RGB_UNORM_BLOCK | F::BC1_RGB_SRGB_BLOCK => decode_bc1(block, false),
+            F::BC1_RGBA_UNORM_BLOCK | F::BC1_RGBA_SRGB_BLOCK => decode_bc1(block, true),
+            F::BC2_UNORM_BLOCK | F::BC2_SRGB_BLOCK => {
+                let mut texels = decode_bc1(&block[8..], false);
+                for (i, texel) in texels.iter_mut().enumerate() {
+                    let alpha = (block[i / 2] >> (4 * (i % 2))) & 0xF;
+                    texel[3] = alpha * 17;
+                }
+                texels
+            }
+            F::BC3_UNORM_BLOCK | F::BC3_SRGB_BLOCK => {
+                let mut texels = decode_bc1(&block[8..], false);
+                let alpha = decode_bc4(&block[..8]);
+                for (texel, alpha) in texels.iter_mut().zip(alpha.iter()) {
+                    texel[3] = *alpha;
+                }
+                texels
+            }
+            F::BC4_UNORM_BLOCK => {
+                let mut texels = [[0; 4]; 16];
+                for (texel, red) in texels.iter_mut().zip(decode_bc4(block).iter()) {
+                    texel[0] = *red;
+                }
+                texels
+            }
+            F::BC5_UNORM_BLOCK => {
+                let mut texels = [[0; 4]; 16];
+                let red = decode_bc4(&block[..8]);
+                let green = decode_bc4(&block[8..]);
+                for (i, texel) in texels.iter_mut().enumerate() {
+                    texel[0] = red[i];
+                    texel[1] = green[i];
+                }
+                texels
+            }
+            _ => return Err(Error::UnsupportedFormat(format)),
+        };
+
+        // Write the texels of the block that are inside the image
+        let (block_x, block_y) = (index as u32 % blocks_x * 4, index as u32 / blocks_x * 4);
+        for (i, texel) in texels.iter().enumerate() {
+            let (x, y) = (block_x + i as u32 % 4, block_y + i as u32 / 4);
+            if x < width && y < height {
+                let offset = (y as usize * width as usize + x as usize) * channels;
+                result[offset..offset + channels].copy_from_slice(&texel[..channels]);
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Returns the texels of a single mip level of pixels of format and size as linear RGBA floats
+/// Missing color channels are 0 and missing alpha is 1, sRGB colors are converted to linear
+/// Formats with a fallback are converted to it first
+pub fn to_rgba_f32(
+    format: vk::Format,
+    (width, height): (u32, u32),
+    pixels: &[u8],
+) -> Result<Vec<[f32; 4]>> {
+    use vk::Format as F;
+    let count = width as usize * height as usize;
+    let unorm8 = |value: u8| value as f32 / 255.0;
+    let srgb8 = |value: u8| srgb_to_linear(value);
+    let unorm16 = |bytes: &[u8]| u16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 65535.0;
+    let half = |bytes: &[u8]| f16::from_le_bytes([bytes[0], bytes[1]]).to_f32();
+    let float = |bytes: &[u8]| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+
+    // Reads texels of channels values of size bytes each
+    let read = |channels: usize, size: usize, value: &dyn Fn(&[u8]) -> f32| {
+        pixels
+            .chunks_exact(channels * size)
+            .take(count)
+            .map(|texel| {
+                let mut rgba = [0.0, 0.0, 0.0, 1.0];
+                for (channel, bytes) in rgba.iter_mut().zip(texel.chunks_exact(size)) {
+                    *channel = value(bytes);
+                }
+                rgba
+            })
+            .collect()
+    };
+
+    let texels: Vec<[f32; 4]> = match format {
+        F::R8_UNORM => read(1, 1, &|bytes| unorm8(bytes[0])),
+        F::R8G8_UNORM => read(2, 1, &|bytes| unorm8(bytes[0])),
+        F::R8G8B8A8_UNORM => read(4, 1, &|bytes| unorm8(bytes[0])),
+        F::R8G8B8A8_SRGB => pixels
+            .chunks_exact(4)
+            .take(count)
+            .map(|texel| {
+                [
+                    srgb8(texel[0]),
+                    srgb8(texel[1]),
+                    srgb8(texel[2]),
+                    unorm8(texel[3]),
+                ]
+            })
+            .collect(),
+        F::B8G8R8A8_UNORM | F::B8G8R8A8_SRGB => {
+            let swizzled: Vec<u8> = pixels
+                .chunks_exact(4)
+                .flat_map(|texel| [texel[2], texel[1], texel[0], texel[3]])
+                .collect();
+            let format = with_color_space(F::R8G8B8A8_UNORM, color_space(format));
+            return to_rgba_f32(format, (width, height), &swizzled);
+        }
+        F::R16_UNORM => read(1, 2, &unorm16),
+        F::R16G16_UNORM => read(2, 2, &unorm16),
+        F::R16G16B16A16_UNORM => read(4, 2, &unorm16),
+        F::R16_SFLOAT => read(1, 2, &half),
+        F::R16G16_SFLOAT => read(2, 2, &half),
+        F::R16G16B16A16_SFLOAT => read(4, 2, &half),
+        F::R32_SFLOAT => read(1, 4, &float),
+        F::R32G32_SFLOAT => read(2, 4, &float),
+        F::R32G32B32A32_SFLOAT => read(4, 4, &float),
+        format => match fallback(format) {
+            Some(to) => {
+                let converted = convert(format, (width, height), pixels)?;
+                return to_rgba_f32(to, (width, height), &converted);
+            }
+            None => return Err(Error::UnsupportedFormat(format)),
+        },
+    };
+
+    if texels.len() < count {
+        return Err(Error::UnsupportedFormat(format));
+    }
+
+    Ok(texels)
+}
+
+/// Packs linear RGBA floats as R16G16B16A16_SFLOAT pixels
+pub fn to_rgba_f16(texels: &[[f32; 4]]) -> Vec<u8> {
+    texels
+        .iter()
+        .flatten()
+        .flat_map(|value| f16::from_f32(*value).to_le_bytes())
+        .collect()
+}
+
+/// Returns the color space of a format, linear if it has no sRGB variant
+fn color_space(format: vk::Format) -> ColorSpace {
+    if COLOR_SPACE_PAIRS.iter().any(|(_, srgb)| *srgb == format) {
+        ColorSpace::Srgb
+    } else {
+        ColorSpace::Linear
+    }
+}
+
+/// Converts an 8 bit sRGB encoded value to linear
+pub fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts a linear value to 8 bit sRGB
+pub fn linear_to_srgb(value: f32) -> u8 {
+    let value = if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// Decodes the 4x4 RGBA texels of a BC1 color block
+/// Blocks of BC2 and BC3 always use four colors
+fn decode_bc1(block: &[u8], punchthrough: bool) -> [[u8; 4]; 16] {
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+
+    let endpoint0 = rgb565(color0);
+    let endpoint1 = rgb565(color1);
+    // Interpolates the endpoints weighted by weight0 and weight1
+    let mix = |weight0: u32, weight1: u32| {
+        let channel = |c: usize| {
+            ((endpoint0[c] as u32 * weight0 + endpoint1[c] as u32 * weight1) / (weight0 + weight1))
+                as u8
+        };
+        [channel(0), channel(1), channel(2), 255]
+    };
+
+    let palette = if color0 > color1 || !punchthrough {
+        [endpoint0, endpoint1, mix(2, 1), mix(1, 2)]
+    } else {
+        [endpoint0, endpoint1, mix(1, 1), [0, 0, 0, 0]]
+    };
+
+    let mut texels = [[0; 4]; 16];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        *texel = palette[(indices >> (2 * i)) as usize & 3];
+    }
+    texels
+}
+
+/// Decodes the 16 values of a BC4 block, also used for the alpha of BC3
+fn decode_bc4(block: &[u8]) -> [u8; 16] {
+    let (value0, value1) = (block[0] as u32, block[1] as u32);
+    let mut bits = [0; 8];
+    bits[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(bits);
+
+    let palette: Vec<u8> = if value0 > value1 {
+        (0..8)
+            .map(|i| match i {
+                0 => value0,
+                1 => value1,
+                _ => ((8 - i) * value0 + (i - 1) * value1) / 7,
+            } as u8)
+            .collect()
+    } else {
+        (0..8)
+            .map(|i| match i {
+                0 => value0,
+                1 => value1,
+                6 => 0,
+                7 => 255,
+                _ => ((6 - i) * value0 + (i - 1) * value1) / 5,
+            } as u8)
+            .collect()
+    };
+
+    let mut values = [0; 16];
+    for (i, value) in values.iter_mut().enumerate() {
+        *value = palette[(indices >> (3 * i)) as usize & 7];
+    }
+    values
+}
+
+/// Expands a 5:6:5 packed color to 8 bit RGBA
+fn rgb565(color: u16) -> [u8; 4] {
+    let red = (color >> 11) as u32 & 0x1F;
+    let green = (color >> 5) as u32 & 0x3F;
+    let blue = color as u32 & 0x1F;
+    [
+        (red * 255 / 31) as u8,
+        (green * 255 / 63) as u8,
+        (blue * 255 / 31) as u8,
+        255,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vk::Format as F;
+
+    /// Encodes a BC1 color block with a 2 bit palette index per texel
+    fn bc1(color0: u16, color1: u16, indices: [u32; 16]) -> Vec<u8> {
+        let indices = (0..16).fold(0u32, |bits, i| bits | indices[i] << (2 * i));
+        let mut block = color0.to_le_bytes().to_vec();
+        block.extend_from_slice(&color1.to_le_bytes());
+        block.extend_from_slice(&indices.to_le_bytes());
+        block
+    }
+
+    /// Encodes a BC4 block with a 3 bit palette index per texel
+    fn bc4(value0: u8, value1: u8, indices: [u64; 16]) -> Vec<u8> {
+        let indices = (0..16).fold(0u64, |bits, i| bits | indices[i] << (3 * i));
+        let mut block = vec![value0, value1];
+        block.extend_from_slice(&indices.to_le_bytes()[..6]);
+        block
+    }
+
+    const RED: u16 = 0xF800;
+    const BLUE: u16 = 0x001F;
+    const INDICES: [u32; 16] = [0, 1, 2, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    const VALUES: [u64; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 0, 0, 0, 0, 0, 0, 0, 0];
+
+    fn texels(pixels: &[u8], channels: usize) -> Vec<&[u8]> {
+        pixels.chunks_exact(channels).collect()
+    }
+
+    #[test]
+    fn bc1_four_colors() {
+        let block = bc1(RED, BLUE, INDICES);
+        let pixels = convert(F::BC1_RGBA_UNORM_BLOCK, (4, 4), &block).unwrap();
+        let texels = texels(&pixels, 4);
+        assert_eq!(texels.len(), 16);
+        assert_eq!(texels[0], [255, 0, 0, 255]);
+        assert_eq!(texels[1], [0, 0, 255, 255]);
+        assert_eq!(texels[2], [170, 0, 85, 255]);
+        assert_eq!(texels[3], [85, 0, 170, 255]);
+    }
+
+    #[test]
+    fn bc1_punchthrough_alpha() {
+        // color0 <= color1 selects three colors and transparent black
+        let block = bc1(BLUE, RED, INDICES);
+        let pixels = convert(F::BC1_RGBA_SRGB_BLOCK, (4, 4), &block).unwrap();
+        let texels = texels(&pixels, 4);
+        assert_eq!(texels[2], [127, 0, 127, 255]);
+        assert_eq!(texels[3], [0, 0, 0, 0]);
+
+        // Without alpha the block always has four colors
+        let pixels = convert(F::BC1_RGB_UNORM_BLOCK, (4, 4), &block).unwrap();
+        let texels = self::texels(&pixels, 4);
+        assert_eq!(texels[2], [85, 0, 170, 255]);
+        assert_eq!(texels[3], [170, 0, 85, 255]);
+    }
+
+    #[test]
+    fn bc4_palettes() {
+        // value0 > value1 interpolates six values
+        let block = bc4(255, 0, VALUES);
+        let pixels = convert(F::BC4_UNORM_BLOCK, (4, 4), &block).unwrap();
+        assert_eq!(pixels.len(), 16);
+        assert_eq!(&pixels[..8], [255, 0, 218, 182, 145, 109, 72, 36]);
+
+        // Otherwise four values are interpolated, followed by 0 and 255
+        let block = bc4(0, 255, VALUES);
+        let pixels = convert(F::BC4_UNORM_BLOCK, (4, 4), &block).unwrap();
+        assert_eq!(&pixels[..8], [0, 255, 51, 102, 153, 204, 0, 255]);
+    }
+
+    #[test]
+    fn bc3_and_bc5() {
+        let mut block = bc4(255, 0, VALUES);
+        block.extend(bc1(RED, BLUE, INDICES));
+        let pixels = convert(F::BC3_SRGB_BLOCK, (4, 4), &block).unwrap();
+        let texels = texels(&pixels, 4);
+        assert_eq!(texels[0], [255, 0, 0, 255]);
+        assert_eq!(texels[1], [0, 0, 255, 0]);
+        assert_eq!(texels[2], [170, 0, 85, 218]);
+
+        let mut block = bc4(255, 0, VALUES);
+        block.extend(bc4(0, 255, VALUES));
+        let pixels = convert(F::BC5_UNORM_BLOCK, (4, 4), &block).unwrap();
+        let texels = self::texels(&pixels, 2);
+        assert_eq!(texels[0], [255, 0]);
+        assert_eq!(texels[1], [0, 255]);
+        assert_eq!(texels[2], [218, 51]);
+    }
+
+    #[test]
+    fn partial_blocks() {
+        // Two blocks wide and one high, the texels outside the image are dropped
+        let mut blocks = bc1(RED, RED, [0; 16]);
+        blocks.extend(bc1(BLUE, BLUE, [0; 16]));
+        let pixels = convert(F::BC1_RGB_UNORM_BLOCK, (5, 3), &blocks).unwrap();
+        let texels = texels(&pixels, 4);
+        assert_eq!(texels.len(), 15);
+        for y in 0..3 {
+            assert_eq!(texels[y * 5 + 3], [255, 0, 0, 255]);
+            assert_eq!(texels[y * 5 + 4], [0, 0, 255, 255]);
+        }
+
+        assert_eq!(
+            block_layout(F::BC1_RGB_UNORM_BLOCK)
+                .unwrap()
+                .image_size(5, 3),
+            Some(16)
+        );
+    }
+
+    #[test]
+    fn image_size_overflow() {
+        let layout = block_layout(F::R32G32B32A32_SFLOAT).unwrap();
+        assert_eq!(layout.image_size(2, 3), Some(96));
+        assert_eq!(layout.image_size(u32::MAX, u32::MAX), None);
+    }
+
+    #[test]
+    fn color_space_variants() {
+        for (unorm, srgb) in COLOR_SPACE_PAIRS {
+            assert_eq!(with_color_space(*unorm, ColorSpace::Srgb), *srgb);
+            assert_eq!(with_color_space(*srgb, ColorSpace::Linear), *unorm);
+            assert_eq!(with_color_space(*srgb, ColorSpace::Srgb), *srgb);
+            assert_eq!(color_space(*srgb), ColorSpace::Srgb);
+            assert_eq!(color_space(*unorm), ColorSpace::Linear);
+        }
+
+        // Formats without an sRGB variant are kept
+        for format in [F::R8_UNORM, F::BC4_UNORM_BLOCK, F::R16G16B16A16_SFLOAT] {
+            assert_eq!(with_color_space(format, ColorSpace::Srgb), format);
+            assert_eq!(with_color_space(format, ColorSpace::Linear), format);
+        }
+    }
+
+    #[test]
+    fn rgba_f32() {
+        let texels = to_rgba_f32(F::B8G8R8A8_SRGB, (1, 1), &[0, 0, 255, 51]).unwrap();
+        assert_eq!(texels, [[1.0, 0.0, 0.0, 0.2]]);
+
+        let texels = to_rgba_f32(F::BC4_UNORM_BLOCK, (1, 1), &bc4(255, 0, [0; 16])).unwrap();
+        assert_eq!(texels, [[1.0, 0.0, 0.0, 1.0]]);
+
+        assert!(to_rgba_f32(F::R8G8B8A8_UNORM, (2, 2), &[0; 12]).is_err());
+    }
+}
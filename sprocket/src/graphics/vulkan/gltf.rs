@@ -0,0 +1,1779 @@
+//! Loading of glTF 2.0 files, both .gltf files with external or embedded buffers and binary .glb
+//! files
+//! The primitives of the meshes are loaded as the meshes of a model, while the materials and the
+//! images embedded in the file are addressed by paths into it, E.g;
+//! assets://models/fox.glb#materials/0, so that they load and reload like any other resource
+use super::enums::{AddressMode, Filter};
+use super::formats::linear_to_srgb;
+use super::model::MeshData;
+use super::vertexbuffer::calculate_tangents;
+use super::{cooked, decode, normalize};
+use super::{Error, MaterialSpec, Result, SamplerSpec, TextureData, TextureSpec, Vertex};
+use crate::graphics::{Camera, Extent2D, Light};
+use crate::math::*;
+use crate::physics::Transform;
+use crate::systems::animation::{AnimatedProperty, Interpolation};
+use crate::vfs;
+use ash::vk;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// The pipeline of the materials of primitives without a skin
+pub const MATERIAL_PIPELINE: &str = "assets://pipelines/default.json";
+
+/// The pipeline of the materials of skinned primitives
+/// Reads the Joints and Weights vertex attributes
+pub const SKINNED_PIPELINE: &str = "assets://pipelines/skinned.json";
+
+/// The range of point and spot lights that don't specify one
+/// glTF lights without a range reach infinitely, while the lights of the renderer fade out at
+/// their range
+const DEFAULT_LIGHT_RANGE: f32 = 20.0;
+
+/// The far plane of perspective cameras that don't specify one
+const DEFAULT_FAR: f32 = 1000.0;
+
+/// The extensions files may require
+const SUPPORTED_EXTENSIONS: &[&str] = &["KHR_lights_punctual"];
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const GLB_JSON: u32 = 0x4E4F_534A;
+const GLB_BIN: u32 = 0x004E_4942;
+
+/// The primitive modes, triangle lists, strips and fans are loaded while points and lines are
+/// skipped
+const MODE_TRIANGLES: u32 = 4;
+const MODE_TRIANGLE_STRIP: u32 = 5;
+const MODE_TRIANGLE_FAN: u32 = 6;
+
+/// Returns true if path is a glTF file or refers to a part of one
+pub fn is_gltf(path: &str) -> bool {
+    let file = split(path).map_or(path, |(file, _)| file).to_lowercase();
+    file.ends_with(".gltf") || file.ends_with(".glb")
+}
+
+/// Returns true if path refers to a part of a glTF file, E.g; a material or an embedded image
+pub fn is_part(path: &str) -> bool {
+    split(path).is_some() && is_gltf(path)
+}
+
+/// Splits a path referring to a part of a glTF file into the path of the file and the part,
+/// E.g; assets://models/fox.glb#materials/0 into assets://models/fox.glb and materials/0
+/// Only a # directly after the .gltf or .glb extension separates the part, so other paths may
+/// contain #
+/// Returns None if path refers to a whole file
+pub fn split(path: &str) -> Option<(&str, &str)> {
+    let lowercase = path.to_ascii_lowercase();
+    let index = [".gltf#", ".glb#"]
+        .iter()
+        .filter_map(|extension| lowercase.rfind(extension).map(|i| i + extension.len() - 1))
+        .max()?;
+    Some((&path[..index], &path[index + 1..]))
+}
+
+/// Returns the path of the material of primitives with material, or the default material if
+/// None, in the glTF file at path
+/// Skinned primitives use a variant rendered with SKINNED_PIPELINE
+pub fn material_path(path: &str, material: Option<usize>, skinned: bool) -> String {
+    let material = material.map_or_else(|| "default".to_owned(), |index| index.to_string());
+    let suffix = if skinned { "/skinned" } else { "" };
+    format!("{}#materials/{}{}", path, material, suffix)
+}
+
+/// Reads the spec of a material in a glTF file, E.g; assets://models/fox.glb#materials/0
+/// The materials render with the base color texture, or a texture of the base color factor if
+/// they have none, using MATERIAL_PIPELINE or SKINNED_PIPELINE
+pub fn material_spec(path: &str) -> Result<MaterialSpec> {
+    let (file, part) = split(path).ok_or_else(|| invalid(path, "not a part of the file"))?;
+    let gltf = Gltf::open_shared(file)?;
+
+    let mut parts = part.split('/');
+    let (material, skinned) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some("materials"), Some(material), None, None) => (material, false),
+        (Some("materials"), Some(material), Some("skinned"), None) => (material, true),
+        _ => return Err(invalid(path, "not a material")),
+    };
+
+    let pipeline = if skinned {
+        SKINNED_PIPELINE
+    } else {
+        MATERIAL_PIPELINE
+    };
+    let (texture, sampler) = gltf.base_color(material)?;
+    Ok(MaterialSpec::new(pipeline, vec![texture], vec![sampler]))
+}
+
+/// Decodes an image embedded in a glTF file, E.g; assets://models/fox.glb#images/0, or the
+/// single texel texture of the base color factor of a material, E.g;
+/// assets://models/fox.glb#materials/0/color
+pub fn decode_texture(path: &str) -> Result<TextureData> {
+    let (file, part) = split(path).ok_or_else(|| invalid(path, "not a part of the file"))?;
+    let gltf = Gltf::open_shared(file)?;
+
+    let parts: Vec<&str> = part.split('/').collect();
+    let mut data = match parts[..] {
+        ["images", image] => gltf.decode_image(path, gltf.index(image)?)?,
+        ["materials", material, "color"] => {
+            let color = gltf.material(material)?.map_or([1.0; 4], |material| {
+                material.pbr_metallic_roughness.base_color_factor
+            });
+            let pixel = [
+                linear_to_srgb(color[0]),
+                linear_to_srgb(color[1]),
+                linear_to_srgb(color[2]),
+                (color[3].clamp(0.0, 1.0) * 255.0).round() as u8,
+            ];
+            TextureData::new(
+                path,
+                Extent2D::new(1, 1),
+                vk::Format::R8G8B8A8_SRGB,
+                1,
+                pixel.to_vec(),
+            )?
+        }
+        _ => return Err(invalid(path, "not a texture")),
+    };
+
+    data.sources.push(PathBuf::from(file));
+    data.sources.extend_from_slice(&gltf.sources);
+    Ok(data)
+}
+
+fn invalid(path: &str, reason: impl ToString) -> Error {
+    Error::InvalidGltf(path.to_owned(), reason.to_string())
+}
+
+/// A node of the scene of a glTF file along with what it instantiates
+pub struct GltfNode {
+    pub name: Option<String>,
+    /// The transform relative to the parent node
+    pub transform: Transform,
+    pub children: Vec<usize>,
+    /// The name of the mesh in the model of the file and the path of the material of each
+    /// primitive of the mesh of the node
+    pub primitives: Vec<(String, String)>,
+    pub camera: Option<Camera>,
+    pub light: Option<Light>,
+    /// The skin deforming the primitives
+    pub skin: Option<usize>,
+}
+
+/// The joints deforming skinned primitives
+pub struct GltfSkin {
+    /// The nodes the joint indices of the vertices refer to
+    pub joints: Vec<usize>,
+    /// The matrix transforming each joint from the space of the mesh to its bind pose
+    pub inverse_bind_matrices: Vec<Mat4>,
+}
+
+/// An animation of the transforms of nodes
+pub struct GltfAnimation {
+    pub name: String,
+    pub channels: Vec<GltfChannel>,
+}
+
+/// The keyframes of a property of a node, laid out like an AnimationChannel
+pub struct GltfChannel {
+    pub node: usize,
+    pub property: AnimatedProperty,
+    pub interpolation: Interpolation,
+    pub times: Vec<f32>,
+    pub values: Vec<Vec4>,
+}
+
+/// The scene of a glTF file
+pub struct GltfScene {
+    /// The nodes at the root of the scene
+    pub roots: Vec<usize>,
+    /// All nodes in the file, including those not in the scene
+    pub nodes: Vec<GltfNode>,
+    pub skins: Vec<GltfSkin>,
+    pub animations: Vec<GltfAnimation>,
+}
+
+/// A file opened with Gltf::open_shared and the modification times of the files it was read from
+struct SharedGltf {
+    gltf: Arc<Gltf>,
+    modified: Vec<Option<SystemTime>>,
+}
+
+/// The files opened by the loads of their parts, so that each file is parsed once for all of its
+/// meshes, materials and images
+/// Cleared with clear_shared once nothing is loading
+static SHARED: Mutex<Vec<SharedGltf>> = Mutex::new(Vec::new());
+
+/// Drops the files kept by Gltf::open_shared
+pub fn clear_shared() {
+    SHARED.lock().unwrap().clear();
+}
+
+/// A parsed glTF file along with the contents of its buffers
+pub struct Gltf {
+    path: String,
+    document: Document,
+    buffers: Vec<Vec<u8>>,
+    /// The files besides the glTF file the buffers were read from
+    sources: Vec<PathBuf>,
+}
+
+impl Gltf {
+    /// Reads a .gltf or .glb file and the buffers it refers to
+    pub fn open(path: &str) -> Result<Gltf> {
+        let file = vfs::read(path)?;
+
+        let (json, mut bin) = if file.starts_with(GLB_MAGIC) {
+            parse_glb(path, &file)?
+        } else {
+            (&file[..], None)
+        };
+
+        let document: Document = serde_json::from_slice(json)?;
+        if !document.asset.version.starts_with("2.") {
+            return Err(invalid(
+                path,
+                format!("version {} is not supported", document.asset.version),
+            ));
+        }
+
+        if let Some(extension) = document
+            .extensions_required
+            .iter()
+            .find(|extension| !SUPPORTED_EXTENSIONS.contains(&&extension[..]))
+        {
+            return Err(invalid(
+                path,
+                format!("required extension {} is not supported", extension),
+            ));
+        }
+
+        let mut buffers = Vec::with_capacity(document.buffers.len());
+        let mut sources = Vec::new();
+        for (i, buffer) in document.buffers.iter().enumerate() {
+            let data = match &buffer.uri {
+                // The first buffer of binary files may be stored in the file itself
+                None if i == 0 => bin
+                    .take()
+                    .ok_or_else(|| invalid(path, "missing binary chunk"))?
+                    .to_vec(),
+                None => return Err(invalid(path, format!("buffer {} has no uri", i))),
+                Some(uri) => match decode_data_uri(uri) {
+                    Some(data) => data.ok_or_else(|| invalid(path, "invalid data uri"))?,
+                    None => {
+                        let file = resolve(path, uri);
+                        let data = vfs::read(&file)?;
+                        sources.push(PathBuf::from(file));
+                        data
+                    }
+                },
+            };
+
+            if data.len() < buffer.byte_length {
+                return Err(invalid(
+                    path,
+                    format!(
+                        "buffer {} is {} bytes but should be {}",
+                        i,
+                        data.len(),
+                        buffer.byte_length
+                    ),
+                ));
+            }
+            buffers.push(data);
+        }
+
+        Ok(Gltf {
+            path: path.to_owned(),
+            document,
+            buffers,
+            sources,
+        })
+    }
+
+    /// Opens a file like open, but reuses the file opened by an earlier call until it or any of
+    /// its buffers change, or clear_shared is called
+    pub fn open_shared(path: &str) -> Result<Arc<Gltf>> {
+        if let Some(shared) = SHARED
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|shared| shared.gltf.path == path)
+            .filter(|shared| shared.modified == shared.gltf.modified())
+        {
+            return Ok(Arc::clone(&shared.gltf));
+        }
+
+        // Parsed without holding the lock so that other files can be opened meanwhile
+        let gltf = Arc::new(Gltf::open(path)?);
+        let mut shared = SHARED.lock().unwrap();
+        shared.retain(|shared| shared.gltf.path != path);
+        shared.push(SharedGltf {
+            gltf: Arc::clone(&gltf),
+            modified: gltf.modified(),
+        });
+        Ok(gltf)
+    }
+
+    /// Returns the modification times of the file and the files its buffers were read from
+    fn modified(&self) -> Vec<Option<SystemTime>> {
+        std::iter::once(&self.path[..])
+            .chain(self.sources.iter().filter_map(|source| source.to_str()))
+            .map(cooked::modified)
+            .collect()
+    }
+
+    /// Returns the files besides the glTF file the buffers were read from
+    pub fn sources(&self) -> &[PathBuf] {
+        &self.sources
+    }
+
+    fn invalid(&self, reason: impl ToString) -> Error {
+        invalid(&self.path, reason)
+    }
+
+    /// Parses an index in a path into the file
+    fn index(&self, index: &str) -> Result<usize> {
+        index
+            .parse()
+            .map_err(|_| self.invalid(format!("invalid index '{}'", index)))
+    }
+
+    /// Returns the material with index in a path into the file, or None for the default material
+    fn material(&self, index: &str) -> Result<Option<&MaterialDef>> {
+        if index == "default" {
+            return Ok(None);
+        }
+
+        let index = self.index(index)?;
+        self.document
+            .materials
+            .get(index)
+            .map(Some)
+            .ok_or_else(|| self.invalid(format!("missing material {}", index)))
+    }
+
+    /// Returns the path and sampler of the base color texture of a material
+    /// Images stored in files of their own are loaded from their path, while embedded images are
+    /// addressed by their index in the file
+    fn base_color(&self, material: &str) -> Result<(String, SamplerSpec)> {
+        let texture = match self
+            .material(material)?
+            .and_then(|material| material.pbr_metallic_roughness.base_color_texture.as_ref())
+        {
+            Some(texture) => self
+                .document
+                .textures
+                .get(texture.index)
+                .ok_or_else(|| self.invalid(format!("missing texture {}", texture.index)))?,
+            None => {
+                return Ok((
+                    format!("{}#materials/{}/color", self.path, material),
+                    SamplerSpec::default(),
+                ))
+            }
+        };
+
+        let image = texture
+            .source
+            .ok_or_else(|| self.invalid("texture without an image"))?;
+        let path = match self
+            .document
+            .images
+            .get(image)
+            .and_then(|image| image.uri.as_ref())
+        {
+            Some(uri) if !uri.starts_with("data:") => resolve(&self.path, uri),
+            _ => format!("{}#images/{}", self.path, image),
+        };
+
+        let sampler = match texture.sampler {
+            Some(sampler) => self
+                .document
+                .samplers
+                .get(sampler)
+                .ok_or_else(|| self.invalid(format!("missing sampler {}", sampler)))?
+                .spec(),
+            None => SamplerSpec::default(),
+        };
+
+        Ok((path, sampler))
+    }
+
+    /// Decodes the image at index, which path refers to
+    fn decode_image(&self, path: &str, index: usize) -> Result<TextureData> {
+        let image = self
+            .document
+            .images
+            .get(index)
+            .ok_or_else(|| self.invalid(format!("missing image {}", index)))?;
+
+        let (bytes, extension) = match (&image.uri, image.buffer_view) {
+            (Some(uri), _) => match decode_data_uri(uri) {
+                Some(data) => (
+                    data.ok_or_else(|| self.invalid("invalid data uri"))?,
+                    mime_extension(uri.trim_start_matches("data:")),
+                ),
+                None => {
+                    let file = resolve(&self.path, uri);
+                    let extension = file.rsplit('.').next().unwrap_or_default().to_owned();
+                    (vfs::read(&file)?, extension)
+                }
+            },
+            (None, Some(view)) => (
+                self.buffer_view(view)?.0.to_vec(),
+                mime_extension(image.mime_type.as_deref().unwrap_or_default()),
+            ),
+            (None, None) => return Err(self.invalid(format!("image {} has no data", index))),
+        };
+
+        // The image is decoded by the extension of its type
+        decode::decode(
+            &format!("{}.{}", path, extension),
+            &bytes,
+            &TextureSpec::default(),
+        )
+    }
+
+    /// Returns the names of the meshes the primitives of each mesh are loaded as
+    /// The first primitive is named after its mesh and the others get their index appended, E.g;
+    /// Body, Body.1
+    /// Meshes without a name are named after their index and repeated names get the index of the
+    /// mesh appended
+    pub fn primitive_names(&self) -> Vec<Vec<String>> {
+        let mut used = HashSet::new();
+        self.document
+            .meshes
+            .iter()
+            .enumerate()
+            .map(|(i, mesh)| {
+                let mut name = mesh.name.clone().unwrap_or_else(|| i.to_string());
+                if !used.insert(name.clone()) {
+                    name = format!("{}#{}", name, i);
+                }
+
+                (0..mesh.primitives.len())
+                    .map(|primitive| match primitive {
+                        0 => name.clone(),
+                        _ => format!("{}.{}", name, primitive),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Reads the vertices and indices of the triangle primitives of all meshes
+    pub(super) fn meshes(&self) -> Result<Vec<MeshData>> {
+        let mut meshes = Vec::new();
+        for (mesh, names) in self.document.meshes.iter().zip(self.primitive_names()) {
+            for (primitive, name) in mesh.primitives.iter().zip(names) {
+                if !primitive.is_triangles() {
+                    log::warn!(
+                        "Skipping primitive '{}' in '{}' with unsupported mode {}",
+                        name,
+                        self.path,
+                        primitive.mode
+                    );
+                    continue;
+                }
+                meshes.push(self.read_primitive(primitive, name)?);
+            }
+        }
+
+        Ok(meshes)
+    }
+
+    fn read_primitive(&self, primitive: &PrimitiveDef, name: String) -> Result<MeshData> {
+        let attribute = |name: &str, components: &[usize]| -> Result<Option<Vec<f32>>> {
+            let accessor = match primitive.attributes.get(name) {
+                Some(accessor) => *accessor,
+                None => return Ok(None),
+            };
+
+            let (values, count) = self.read_accessor(accessor)?;
+            if !components.contains(&count) {
+                return Err(self.invalid(format!("attribute {} has {} components", name, count)));
+            }
+            Ok(Some(values))
+        };
+
+        let positions =
+            attribute("POSITION", &[3])?.ok_or_else(|| self.invalid("missing positions"))?;
+        let count = positions.len() / 3;
+        let mut vertices: Vec<Vertex> = positions
+            .chunks_exact(3)
+            .map(|p| Vertex {
+                position: Vec3::new(p[0], p[1], p[2]),
+                ..Default::default()
+            })
+            .collect();
+
+        // Checks that an attribute has a value for each vertex
+        let per_vertex = |name: &str, values: &[f32], components: usize| {
+            if values.len() != count * components {
+                return Err(self.invalid(format!(
+                    "attribute {} has {} values for {} vertices",
+                    name,
+                    values.len() / components,
+                    count
+                )));
+            }
+            Ok(())
+        };
+
+        let normals = attribute("NORMAL", &[3])?;
+        if let Some(normals) = &normals {
+            per_vertex("NORMAL", normals, 3)?;
+            for (vertex, n) in vertices.iter_mut().zip(normals.chunks_exact(3)) {
+                vertex.normal = Vec3::new(n[0], n[1], n[2]);
+            }
+        }
+
+        let tangents = attribute("TANGENT", &[4])?;
+        if let Some(tangents) = &tangents {
+            per_vertex("TANGENT", tangents, 4)?;
+            for (vertex, t) in vertices.iter_mut().zip(tangents.chunks_exact(4)) {
+                vertex.tangent = Vec4::new(t[0], t[1], t[2], t[3]);
+            }
+        }
+
+        let uvs = attribute("TEXCOORD_0", &[2])?;
+        if let Some(uvs) = &uvs {
+            per_vertex("TEXCOORD_0", uvs, 2)?;
+            for (vertex, uv) in vertices.iter_mut().zip(uvs.chunks_exact(2)) {
+                vertex.uv = Vec2::new(uv[0], uv[1]);
+            }
+        }
+
+        if let Some(uvs) = attribute("TEXCOORD_1", &[2])? {
+            per_vertex("TEXCOORD_1", &uvs, 2)?;
+            for (vertex, uv) in vertices.iter_mut().zip(uvs.chunks_exact(2)) {
+                vertex.uv1 = Vec2::new(uv[0], uv[1]);
+            }
+        }
+
+        // Colors may be RGB or RGBA
+        if let Some(colors) = attribute("COLOR_0", &[3, 4])? {
+            let components = colors.len() / count.max(1);
+            per_vertex("COLOR_0", &colors, components)?;
+            for (vertex, c) in vertices.iter_mut().zip(colors.chunks_exact(components)) {
+                vertex.color = Vec4::new(c[0], c[1], c[2], c.get(3).copied().unwrap_or(1.0));
+            }
+        }
+
+        if let (Some(joints), Some(weights)) =
+            (attribute("JOINTS_0", &[4])?, attribute("WEIGHTS_0", &[4])?)
+        {
+            per_vertex("JOINTS_0", &joints, 4)?;
+            per_vertex("WEIGHTS_0", &weights, 4)?;
+            for ((vertex, j), w) in vertices
+                .iter_mut()
+                .zip(joints.chunks_exact(4))
+                .zip(weights.chunks_exact(4))
+            {
+                vertex.joints = Vec4::new(j[0], j[1], j[2], j[3]);
+
+                // Quantized weights may not sum to exactly one
+                let sum = w.iter().sum::<f32>();
+                if sum > 0.0 {
+                    vertex.weights = Vec4::new(w[0], w[1], w[2], w[3]) / sum;
+                }
+            }
+        }
+
+        let indices = match primitive.indices {
+            Some(accessor) => self.read_indices(accessor)?,
+            None => (0..count as u32).collect(),
+        };
+        if let Some(index) = indices.iter().find(|index| **index as usize >= count) {
+            return Err(self.invalid(format!(
+                "index {} is out of range of {} vertices",
+                index, count
+            )));
+        }
+
+        let mut indices = match primitive.mode {
+            MODE_TRIANGLE_STRIP => (0..indices.len().saturating_sub(2))
+                .flat_map(|i| match i % 2 {
+                    0 => [indices[i], indices[i + 1], indices[i + 2]],
+                    _ => [indices[i + 1], indices[i], indices[i + 2]],
+                })
+                .collect(),
+            MODE_TRIANGLE_FAN => (1..indices.len().saturating_sub(1))
+                .flat_map(|i| [indices[0], indices[i], indices[i + 1]])
+                .collect(),
+            _ => indices,
+        };
+        indices.truncate(indices.len() / 3 * 3);
+
+        // Primitives without normals are flat shaded, which requires the vertices of each
+        // triangle to be unique
+        if normals.is_none() {
+            vertices = indices
+                .iter()
+                .map(|index| vertices[*index as usize])
+                .collect();
+            indices = (0..vertices.len() as u32).collect();
+            for triangle in vertices.chunks_exact_mut(3) {
+                let normal = Vec3::cross(
+                    &(triangle[1].position - triangle[0].position),
+                    &(triangle[2].position - triangle[0].position),
+                )
+                .norm();
+                triangle
+                    .iter_mut()
+                    .for_each(|vertex| vertex.normal = normal);
+            }
+        }
+
+        if tangents.is_none() && uvs.is_some() {
+            calculate_tangents(&mut vertices, &indices);
+        }
+
+        Ok(MeshData {
+            name,
+            vertices,
+            indices,
+        })
+    }
+
+    /// Returns the slice of the buffer a buffer view covers along with its stride
+    fn buffer_view(&self, index: usize) -> Result<(&[u8], Option<usize>)> {
+        let view = self
+            .document
+            .buffer_views
+            .get(index)
+            .ok_or_else(|| self.invalid(format!("missing buffer view {}", index)))?;
+
+        let data = view
+            .byte_offset
+            .checked_add(view.byte_length)
+            .and_then(|end| self.buffers.get(view.buffer)?.get(view.byte_offset..end))
+            .ok_or_else(|| self.invalid(format!("buffer view {} is out of range", index)))?;
+
+        Ok((data, view.byte_stride))
+    }
+
+    /// Reads count elements of components components of component_type from a buffer view
+    /// starting at offset
+    /// The components are returned as they are stored without normalization
+    fn read_raw(
+        &self,
+        view: usize,
+        offset: usize,
+        count: usize,
+        components: usize,
+        component_type: u32,
+    ) -> Result<Vec<f64>> {
+        let size = component_size(component_type)
+            .ok_or_else(|| self.invalid(format!("invalid component type {}", component_type)))?;
+        let (data, stride) = self.buffer_view(view)?;
+        let element = size * components;
+        let stride = stride.unwrap_or(element);
+        if stride < element {
+            return Err(self.invalid(format!(
+                "stride {} of view {} is smaller than its elements",
+                stride, view
+            )));
+        }
+
+        // The last element must end inside the view, which also bounds the number of values
+        let end = match count.checked_sub(1) {
+            Some(last) => last
+                .checked_mul(stride)
+                .and_then(|start| start.checked_add(offset))
+                .and_then(|start| start.checked_add(element)),
+            None => Some(offset),
+        };
+        if end.is_none_or(|end| end > data.len()) {
+            return Err(self.invalid(format!("accessor of view {} is out of range", view)));
+        }
+
+        let mut values = Vec::with_capacity(count * components);
+        for i in 0..count {
+            let start = offset + i * stride;
+            let bytes = &data[start..start + element];
+            values.extend(
+                bytes
+                    .chunks_exact(size)
+                    .map(|bytes| read_component(bytes, component_type)),
+            );
+        }
+
+        Ok(values)
+    }
+
+    /// Reads an accessor into floats, normalizing integers if the accessor is normalized
+    /// Returns the values of all elements one after another and the number of components of each
+    fn read_accessor(&self, index: usize) -> Result<(Vec<f32>, usize)> {
+        let accessor = self
+            .document
+            .accessors
+            .get(index)
+            .ok_or_else(|| self.invalid(format!("missing accessor {}", index)))?;
+        let components = type_components(&accessor.ty)
+            .ok_or_else(|| self.invalid(format!("invalid accessor type {}", accessor.ty)))?;
+
+        let mut values = match accessor.buffer_view {
+            Some(view) => self.read_raw(
+                view,
+                accessor.byte_offset,
+                accessor.count,
+                components,
+                accessor.component_type,
+            )?,
+            // Accessors without a buffer view are zeros, usually replaced by sparse values
+            // The count isn't bounded by a buffer so the allocation is allowed to fail
+            None => {
+                let too_many = || self.invalid(format!("accessor {} has too many elements", index));
+                let len = accessor
+                    .count
+                    .checked_mul(components)
+                    .ok_or_else(too_many)?;
+                let mut zeros = Vec::new();
+                zeros.try_reserve_exact(len).map_err(|_| too_many())?;
+                zeros.resize(len, 0.0);
+                zeros
+            }
+        };
+
+        if let Some(sparse) = &accessor.sparse {
+            let indices = self.read_raw(
+                sparse.indices.buffer_view,
+                sparse.indices.byte_offset,
+                sparse.count,
+                1,
+                sparse.indices.component_type,
+            )?;
+            let replacements = self.read_raw(
+                sparse.values.buffer_view,
+                sparse.values.byte_offset,
+                sparse.count,
+                components,
+                accessor.component_type,
+            )?;
+
+            for (index, replacement) in indices.iter().zip(replacements.chunks_exact(components)) {
+                let start = *index as usize * components;
+                values
+                    .get_mut(start..start + components)
+                    .ok_or_else(|| self.invalid("sparse index out of range"))?
+                    .copy_from_slice(replacement);
+            }
+        }
+
+        let scale = match accessor.normalized {
+            true => normalization(accessor.component_type),
+            false => None,
+        };
+        let values = values
+            .into_iter()
+            .map(|value| match scale {
+                // Signed values are clamped so that both the minimum and one less map to -1
+                Some(max) => (value / max).max(-1.0) as f32,
+                None => value as f32,
+            })
+            .collect();
+
+        Ok((values, components))
+    }
+
+    /// Reads an accessor of unsigned integers, E.g; indices
+    fn read_indices(&self, index: usize) -> Result<Vec<u32>> {
+        let accessor = self
+            .document
+            .accessors
+            .get(index)
+            .ok_or_else(|| self.invalid(format!("missing accessor {}", index)))?;
+
+        // Read separately from floats which can't hold all 32 bit indices
+        match (&accessor.ty[..], accessor.buffer_view, &accessor.sparse) {
+            ("SCALAR", Some(view), None) => Ok(self
+                .read_raw(
+                    view,
+                    accessor.byte_offset,
+                    accessor.count,
+                    1,
+                    accessor.component_type,
+                )?
+                .into_iter()
+                .map(|index| index as u32)
+                .collect()),
+            _ => Err(self.invalid(format!("accessor {} is not a list of indices", index))),
+        }
+    }
+
+    /// Reads the default scene along with the skins and animations
+    pub fn scene(&self) -> Result<GltfScene> {
+        let document = &self.document;
+        let names = self.primitive_names();
+
+        let roots = match document.scene.or(if document.scenes.is_empty() {
+            None
+        } else {
+            Some(0)
+        }) {
+            Some(scene) => document
+                .scenes
+                .get(scene)
+                .ok_or_else(|| self.invalid(format!("missing scene {}", scene)))?
+                .nodes
+                .clone(),
+            // Without scenes every node that isn't a child is a root
+            None => {
+                let children: HashSet<usize> = document
+                    .nodes
+                    .iter()
+                    .flat_map(|node| node.children.iter().copied())
+                    .collect();
+                (0..document.nodes.len())
+                    .filter(|node| !children.contains(node))
+                    .collect()
+            }
+        };
+
+        let lights = document
+            .extensions
+            .lights_punctual
+            .as_ref()
+            .map_or(&[][..], |lights| &lights.lights[..]);
+
+        let nodes = document
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| {
+                if let Some(child) = node
+                    .children
+                    .iter()
+                    .find(|child| **child >= document.nodes.len())
+                {
+                    return Err(self.invalid(format!("node {} has missing child {}", i, child)));
+                }
+
+                let primitives = match node.mesh {
+                    Some(mesh) => {
+                        let def = document
+                            .meshes
+                            .get(mesh)
+                            .ok_or_else(|| self.invalid(format!("missing mesh {}", mesh)))?;
+                        def.primitives
+                            .iter()
+                            .zip(&names[mesh])
+                            .filter(|(primitive, _)| primitive.is_triangles())
+                            .map(|(primitive, name)| {
+                                let skinned = node.skin.is_some()
+                                    && primitive.attributes.contains_key("JOINTS_0")
+                                    && primitive.attributes.contains_key("WEIGHTS_0");
+                                (
+                                    name.clone(),
+                                    material_path(&self.path, primitive.material, skinned),
+                                )
+                            })
+                            .collect()
+                    }
+                    None => Vec::new(),
+                };
+
+                let camera = match node.camera {
+                    Some(camera) => Some(
+                        document
+                            .cameras
+                            .get(camera)
+                            .ok_or_else(|| self.invalid(format!("missing camera {}", camera)))?
+                            .camera()
+                            .ok_or_else(|| self.invalid(format!("invalid camera {}", camera)))?,
+                    ),
+                    None => None,
+                };
+
+                let light = match &node.extensions.lights_punctual {
+                    Some(light) => Some(
+                        lights
+                            .get(light.light)
+                            .ok_or_else(|| self.invalid(format!("missing light {}", light.light)))?
+                            .light()
+                            .ok_or_else(|| {
+                                self.invalid(format!("invalid light {}", light.light))
+                            })?,
+                    ),
+                    None => None,
+                };
+
+                if let Some(skin) = node.skin.filter(|skin| *skin >= document.skins.len()) {
+                    return Err(self.invalid(format!("missing skin {}", skin)));
+                }
+
+                Ok(GltfNode {
+                    name: node.name.clone(),
+                    transform: node.transform(),
+                    children: node.children.clone(),
+                    primitives,
+                    camera,
+                    light,
+                    skin: node.skin,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let skins = document
+            .skins
+            .iter()
+            .map(|skin| {
+                if let Some(joint) = skin.joints.iter().find(|joint| **joint >= nodes.len()) {
+                    return Err(self.invalid(format!("missing joint node {}", joint)));
+                }
+
+                let inverse_bind_matrices = match skin.inverse_bind_matrices {
+                    Some(accessor) => {
+                        let (values, components) = self.read_accessor(accessor)?;
+                        if components != 16 || values.len() / 16 < skin.joints.len() {
+                            return Err(self.invalid("invalid inverse bind matrices"));
+                        }
+                        // The column major matrices of column vectors are laid out like the row
+                        // major matrices of row vectors
+                        values
+                            .chunks_exact(16)
+                            .map(|m| {
+                                let mut elements = [0.0; 16];
+                                elements.copy_from_slice(m);
+                                Mat4::from_array(elements)
+                            })
+                            .collect()
+                    }
+                    None => vec![Mat4::identity(); skin.joints.len()],
+                };
+
+                Ok(GltfSkin {
+                    joints: skin.joints.clone(),
+                    inverse_bind_matrices,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let animations = document
+            .animations
+            .iter()
+            .enumerate()
+            .map(|(i, animation)| self.read_animation(i, animation, nodes.len()))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(GltfScene {
+            roots,
+            nodes,
+            skins,
+            animations,
+        })
+    }
+
+    fn read_animation(
+        &self,
+        index: usize,
+        animation: &AnimationDef,
+        node_count: usize,
+    ) -> Result<GltfAnimation> {
+        let mut channels = Vec::new();
+        for channel in &animation.channels {
+            let node = match channel.target.node {
+                Some(node) if node < node_count => node,
+                Some(node) => return Err(self.invalid(format!("missing node {}", node))),
+                None => continue,
+            };
+
+            let property = match &channel.target.path[..] {
+                "translation" => AnimatedProperty::Translation,
+                "rotation" => AnimatedProperty::Rotation,
+                "scale" => AnimatedProperty::Scale,
+                // Morph target weights are not supported
+                path => {
+                    log::warn!(
+                        "Skipping animation of '{}' in '{}' which is not supported",
+                        path,
+                        self.path
+                    );
+                    continue;
+                }
+            };
+
+            let sampler = animation
+                .samplers
+                .get(channel.sampler)
+                .ok_or_else(|| self.invalid(format!("missing sampler {}", channel.sampler)))?;
+            let interpolation = match &sampler.interpolation[..] {
+                "STEP" => Interpolation::Step,
+                "LINEAR" => Interpolation::Linear,
+                "CUBICSPLINE" => Interpolation::CubicSpline,
+                other => return Err(self.invalid(format!("invalid interpolation {}", other))),
+            };
+
+            let (times, _) = self.read_accessor(sampler.input)?;
+            let (values, components) = self.read_accessor(sampler.output)?;
+            let values: Vec<Vec4> = match (property, components) {
+                (AnimatedProperty::Rotation, 4) => values
+                    .chunks_exact(4)
+                    .map(|v| Vec4::new(v[0], v[1], v[2], v[3]))
+                    .collect(),
+                (AnimatedProperty::Translation, 3) | (AnimatedProperty::Scale, 3) => values
+                    .chunks_exact(3)
+                    .map(|v| Vec4::new(v[0], v[1], v[2], 0.0))
+                    .collect(),
+                _ => {
+                    return Err(self.invalid(format!(
+                        "animated {:?} has {} components",
+                        property, components
+                    )))
+                }
+            };
+
+            let per_key = match interpolation {
+                Interpolation::CubicSpline => 3,
+                _ => 1,
+            };
+            if values.len() != times.len() * per_key {
+                return Err(self.invalid(format!(
+                    "animation sampler has {} values for {} keyframes",
+                    values.len(),
+                    times.len()
+                )));
+            }
+
+            channels.push(GltfChannel {
+                node,
+                property,
+                interpolation,
+                times,
+                values,
+            });
+        }
+
+        Ok(GltfAnimation {
+            name: animation.name.clone().unwrap_or_else(|| index.to_string()),
+            channels,
+        })
+    }
+}
+
+/// Splits a binary glTF file into its JSON chunk and the binary chunk if any
+fn parse_glb<'a>(path: &str, file: &'a [u8]) -> Result<(&'a [u8], Option<&'a [u8]>)> {
+    let word = |offset: usize| {
+        file.get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+
+    let version = word(4).ok_or_else(|| invalid(path, "truncated header"))?;
+    if version != 2 {
+        return Err(invalid(
+            path,
+            format!("binary version {} is not supported", version),
+        ));
+    }
+    let length =
+        (word(8).ok_or_else(|| invalid(path, "truncated header"))? as usize).min(file.len());
+
+    let mut json = None;
+    let mut bin = None;
+    let mut offset = 12;
+    while offset + 8 <= length {
+        let (size, ty) = (word(offset).unwrap() as usize, word(offset + 4).unwrap());
+        let data = file
+            .get(offset + 8..offset + 8 + size)
+            .ok_or_else(|| invalid(path, "truncated chunk"))?;
+        match ty {
+            GLB_JSON if json.is_none() => json = Some(data),
+            GLB_BIN if bin.is_none() => bin = Some(data),
+            // Unknown chunks are skipped
+            _ => {}
+        }
+        offset += 8 + size;
+    }
+
+    Ok((
+        json.ok_or_else(|| invalid(path, "missing JSON chunk"))?,
+        bin,
+    ))
+}
+
+/// Resolves a uri relative to the glTF file at path
+fn resolve(path: &str, uri: &str) -> String {
+    let uri = percent_decode(uri);
+    if vfs::split(&uri).is_some() {
+        return uri;
+    }
+
+    match path.rfind('/') {
+        Some(index) => normalize(&format!("{}/{}", &path[..index], uri)),
+        None => normalize(&uri),
+    }
+}
+
+/// Decodes the %XX escapes of a uri
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Decodes the base64 data of a data uri
+/// Returns None if uri is not a data uri and Some(None) if the data is invalid
+fn decode_data_uri(uri: &str) -> Option<Option<Vec<u8>>> {
+    let data = uri.strip_prefix("data:")?;
+    Some(
+        data.find(";base64,")
+            .and_then(|index| decode_base64(&data[index + 8..])),
+    )
+}
+
+/// Returns the file extension of images with a mime type, E.g; png for image/png;base64,...
+fn mime_extension(mime: &str) -> String {
+    let mime = mime.split([';', ',']).next().unwrap_or_default();
+    match mime {
+        "image/jpeg" => "jpg".to_owned(),
+        "image/png" => "png".to_owned(),
+        "image/ktx2" => "ktx2".to_owned(),
+        "image/vnd-ms.dds" => "dds".to_owned(),
+        other => other.rsplit('/').next().unwrap_or_default().to_owned(),
+    }
+}
+
+/// Decodes standard base64 with optional padding
+fn decode_base64(encoded: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(encoded.len() / 4 * 3);
+    let mut bits: u32 = 0;
+    let mut count = 0;
+    for c in encoded.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            c if c.is_ascii_whitespace() => continue,
+            _ => return None,
+        };
+
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            decoded.push((bits >> count) as u8);
+        }
+    }
+
+    Some(decoded)
+}
+
+/// Returns the size in bytes of a component type
+fn component_size(component_type: u32) -> Option<usize> {
+    match component_type {
+        5120 | 5121 => Some(1),
+        5122 | 5123 => Some(2),
+        5125 | 5126 => Some(4),
+        _ => None,
+    }
+}
+
+/// Returns the value a normalized integer component type divides by
+fn normalization(component_type: u32) -> Option<f64> {
+    match component_type {
+        5120 => Some(127.0),
+        5121 => Some(255.0),
+        5122 => Some(32767.0),
+        5123 => Some(65535.0),
+        _ => None,
+    }
+}
+
+/// Reads a little endian component of component_type from the start of bytes
+fn read_component(bytes: &[u8], component_type: u32) -> f64 {
+    match component_type {
+        5120 => bytes[0] as i8 as f64,
+        5121 => bytes[0] as f64,
+        5122 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+        5123 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+        5125 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+        _ => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+    }
+}
+
+/// Returns the number of components of an accessor type
+fn type_components(ty: &str) -> Option<usize> {
+    match ty {
+        "SCALAR" => Some(1),
+        "VEC2" => Some(2),
+        "VEC3" => Some(3),
+        "VEC4" | "MAT2" => Some(4),
+        "MAT3" => Some(9),
+        "MAT4" => Some(16),
+        _ => None,
+    }
+}
+
+/// Creates a rotation from the columns of a rotation matrix
+fn matrix_rotation(x: Vec3, y: Vec3, z: Vec3) -> Quaternion {
+    let trace = x.x + y.y + z.z;
+    if trace > 0.0 {
+        let s = (trace + 1.0).sqrt() * 2.0;
+        Quaternion::new((y.z - z.y) / s, (z.x - x.z) / s, (x.y - y.x) / s, 0.25 * s)
+    } else if x.x > y.y && x.x > z.z {
+        let s = (1.0 + x.x - y.y - z.z).sqrt() * 2.0;
+        Quaternion::new(0.25 * s, (y.x + x.y) / s, (z.x + x.z) / s, (y.z - z.y) / s)
+    } else if y.y > z.z {
+        let s = (1.0 + y.y - x.x - z.z).sqrt() * 2.0;
+        Quaternion::new((y.x + x.y) / s, 0.25 * s, (z.y + y.z) / s, (z.x - x.z) / s)
+    } else {
+        let s = (1.0 + z.z - x.x - y.y).sqrt() * 2.0;
+        Quaternion::new((z.x + x.z) / s, (z.y + y.z) / s, 0.25 * s, (x.y - y.x) / s)
+    }
+    .norm()
+}
+
+// The subset of the glTF document that is loaded
+// https://registry.khronos.org/glTF/specs/2.0/glTF-2.0.html
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Document {
+    asset: AssetDef,
+    scene: Option<usize>,
+    #[serde(default)]
+    scenes: Vec<SceneDef>,
+    #[serde(default)]
+    nodes: Vec<NodeDef>,
+    #[serde(default)]
+    meshes: Vec<MeshDef>,
+    #[serde(default)]
+    accessors: Vec<AccessorDef>,
+    #[serde(default)]
+    buffer_views: Vec<BufferViewDef>,
+    #[serde(default)]
+    buffers: Vec<BufferDef>,
+    #[serde(default)]
+    materials: Vec<MaterialDef>,
+    #[serde(default)]
+    textures: Vec<TextureDef>,
+    #[serde(default)]
+    images: Vec<ImageDef>,
+    #[serde(default)]
+    samplers: Vec<SamplerDef>,
+    #[serde(default)]
+    cameras: Vec<CameraDef>,
+    #[serde(default)]
+    skins: Vec<SkinDef>,
+    #[serde(default)]
+    animations: Vec<AnimationDef>,
+    #[serde(default)]
+    extensions_required: Vec<String>,
+    #[serde(default)]
+    extensions: DocumentExtensions,
+}
+
+#[derive(Deserialize)]
+struct AssetDef {
+    version: String,
+}
+
+#[derive(Deserialize)]
+struct SceneDef {
+    #[serde(default)]
+    nodes: Vec<usize>,
+}
+
+#[derive(Deserialize)]
+struct NodeDef {
+    name: Option<String>,
+    #[serde(default)]
+    children: Vec<usize>,
+    mesh: Option<usize>,
+    camera: Option<usize>,
+    skin: Option<usize>,
+    matrix: Option<[f32; 16]>,
+    translation: Option<[f32; 3]>,
+    rotation: Option<[f32; 4]>,
+    scale: Option<[f32; 3]>,
+    #[serde(default)]
+    extensions: NodeExtensions,
+}
+
+impl NodeDef {
+    /// Returns the transform of the node relative to its parent
+    /// Matrices are decomposed into translation, rotation and scale
+    fn transform(&self) -> Transform {
+        if let Some(m) = &self.matrix {
+            let x = Vec3::new(m[0], m[1], m[2]);
+            let y = Vec3::new(m[4], m[5], m[6]);
+            let z = Vec3::new(m[8], m[9], m[10]);
+            let scale = Vec3::new(x.mag(), y.mag(), z.mag());
+            let rotation = if scale.smallest() > 0.0 {
+                matrix_rotation(x / scale.x, y / scale.y, z / scale.z)
+            } else {
+                Quaternion::identity()
+            };
+            return Transform::with_rotation_scale(Vec3::new(m[12], m[13], m[14]), rotation, scale);
+        }
+
+        let translation = self.translation.unwrap_or([0.0; 3]);
+        let rotation = self.rotation.unwrap_or([0.0, 0.0, 0.0, 1.0]);
+        let scale = self.scale.unwrap_or([1.0; 3]);
+        Transform::with_rotation_scale(
+            Vec3::new(translation[0], translation[1], translation[2]),
+            Quaternion::new(rotation[0], rotation[1], rotation[2], rotation[3]),
+            Vec3::new(scale[0], scale[1], scale[2]),
+        )
+    }
+}
+
+#[derive(Deserialize, Default)]
+struct NodeExtensions {
+    #[serde(rename = "KHR_lights_punctual")]
+    lights_punctual: Option<LightRef>,
+}
+
+#[derive(Deserialize)]
+struct LightRef {
+    light: usize,
+}
+
+#[derive(Deserialize)]
+struct MeshDef {
+    name: Option<String>,
+    primitives: Vec<PrimitiveDef>,
+}
+
+#[derive(Deserialize)]
+struct PrimitiveDef {
+    attributes: HashMap<String, usize>,
+    indices: Option<usize>,
+    material: Option<usize>,
+    #[serde(default = "default_mode")]
+    mode: u32,
+}
+
+impl PrimitiveDef {
+    fn is_triangles(&self) -> bool {
+        matches!(
+            self.mode,
+            MODE_TRIANGLES | MODE_TRIANGLE_STRIP | MODE_TRIANGLE_FAN
+        )
+    }
+}
+
+fn default_mode() -> u32 {
+    MODE_TRIANGLES
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AccessorDef {
+    buffer_view: Option<usize>,
+    #[serde(default)]
+    byte_offset: usize,
+    component_type: u32,
+    #[serde(default)]
+    normalized: bool,
+    count: usize,
+    #[serde(rename = "type")]
+    ty: String,
+    sparse: Option<SparseDef>,
+}
+
+#[derive(Deserialize)]
+struct SparseDef {
+    count: usize,
+    indices: SparseIndicesDef,
+    values: SparseValuesDef,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SparseIndicesDef {
+    buffer_view: usize,
+    #[serde(default)]
+    byte_offset: usize,
+    component_type: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SparseValuesDef {
+    buffer_view: usize,
+    #[serde(default)]
+    byte_offset: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BufferViewDef {
+    buffer: usize,
+    #[serde(default)]
+    byte_offset: usize,
+    byte_length: usize,
+    byte_stride: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BufferDef {
+    uri: Option<String>,
+    byte_length: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MaterialDef {
+    #[serde(default)]
+    pbr_metallic_roughness: PbrDef,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct PbrDef {
+    base_color_factor: [f32; 4],
+    base_color_texture: Option<TextureRef>,
+}
+
+impl Default for PbrDef {
+    fn default() -> Self {
+        PbrDef {
+            base_color_factor: [1.0; 4],
+            base_color_texture: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TextureRef {
+    index: usize,
+}
+
+#[derive(Deserialize)]
+struct TextureDef {
+    sampler: Option<usize>,
+    source: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ImageDef {
+    uri: Option<String>,
+    mime_type: Option<String>,
+    buffer_view: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SamplerDef {
+    mag_filter: Option<u32>,
+    min_filter: Option<u32>,
+    #[serde(default = "default_wrap")]
+    wrap_s: u32,
+    #[serde(default = "default_wrap")]
+    wrap_t: u32,
+}
+
+fn default_wrap() -> u32 {
+    10497
+}
+
+impl SamplerDef {
+    fn spec(&self) -> SamplerSpec {
+        let address_mode = |wrap| match wrap {
+            33071 => AddressMode::ClampToEdge,
+            33648 => AddressMode::MirroredRepeat,
+            _ => AddressMode::Repeat,
+        };
+        let defaults = SamplerSpec::default();
+
+        let (min_filter, mipmap_mode) = match self.min_filter {
+            Some(9728) | Some(9984) => (Filter::Nearest, Filter::Nearest),
+            Some(9729) | Some(9985) => (Filter::Linear, Filter::Nearest),
+            Some(9986) => (Filter::Nearest, Filter::Linear),
+            _ => (defaults.min_filter, defaults.mipmap_mode),
+        };
+
+        SamplerSpec {
+            mag_filter: match self.mag_filter {
+                Some(9728) => Filter::Nearest,
+                _ => Filter::Linear,
+            },
+            min_filter,
+            mipmap_mode,
+            // Filters without mipmaps only sample the first level
+            max_lod: match self.min_filter {
+                Some(9728) | Some(9729) => 0.0,
+                _ => defaults.max_lod,
+            },
+            address_mode_u: address_mode(self.wrap_s),
+            address_mode_v: address_mode(self.wrap_t),
+            ..defaults
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CameraDef {
+    #[serde(rename = "type")]
+    ty: String,
+    perspective: Option<PerspectiveDef>,
+    orthographic: Option<OrthographicDef>,
+}
+
+impl CameraDef {
+    fn camera(&self) -> Option<Camera> {
+        match (&self.ty[..], &self.perspective, &self.orthographic) {
+            ("perspective", Some(perspective), _) => Some(Camera::perspective(
+                perspective.yfov,
+                perspective.znear,
+                perspective.zfar.unwrap_or(DEFAULT_FAR),
+            )),
+            // The magnifications are half the size of the view
+            ("orthographic", _, Some(orthographic)) => Some(Camera::orthographic(
+                orthographic.ymag * 2.0,
+                orthographic.znear,
+                orthographic.zfar,
+            )),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PerspectiveDef {
+    yfov: f32,
+    znear: f32,
+    zfar: Option<f32>,
+}
+
+#[derive(Deserialize)]
+struct OrthographicDef {
+    ymag: f32,
+    znear: f32,
+    zfar: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SkinDef {
+    inverse_bind_matrices: Option<usize>,
+    joints: Vec<usize>,
+}
+
+#[derive(Deserialize)]
+struct AnimationDef {
+    name: Option<String>,
+    channels: Vec<ChannelDef>,
+    samplers: Vec<AnimationSamplerDef>,
+}
+
+#[derive(Deserialize)]
+struct ChannelDef {
+    sampler: usize,
+    target: ChannelTargetDef,
+}
+
+#[derive(Deserialize)]
+struct ChannelTargetDef {
+    node: Option<usize>,
+    path: String,
+}
+
+#[derive(Deserialize)]
+struct AnimationSamplerDef {
+    input: usize,
+    #[serde(default = "default_interpolation")]
+    interpolation: String,
+    output: usize,
+}
+
+fn default_interpolation() -> String {
+    "LINEAR".to_owned()
+}
+
+#[derive(Deserialize, Default)]
+struct DocumentExtensions {
+    #[serde(rename = "KHR_lights_punctual")]
+    lights_punctual: Option<LightsDef>,
+}
+
+#[derive(Deserialize)]
+struct LightsDef {
+    lights: Vec<LightDef>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LightDef {
+    #[serde(rename = "type")]
+    ty: String,
+    #[serde(default = "default_color")]
+    color: [f32; 3],
+    #[serde(default = "default_intensity")]
+    intensity: f32,
+    range: Option<f32>,
+    spot: Option<SpotDef>,
+}
+
+fn default_color() -> [f32; 3] {
+    [1.0; 3]
+}
+
+fn default_intensity() -> f32 {
+    1.0
+}
+
+impl LightDef {
+    fn light(&self) -> Option<Light> {
+        let color = Vec3::new(self.color[0], self.color[1], self.color[2]);
+        let range = self.range.unwrap_or(DEFAULT_LIGHT_RANGE);
+        match &self.ty[..] {
+            "directional" => Some(Light::directional(color, self.intensity)),
+            "point" => Some(Light::point(color, self.intensity, range)),
+            "spot" => {
+                let spot = self.spot.as_ref()?;
+                Some(Light::spot(
+                    color,
+                    self.intensity,
+                    range,
+                    spot.inner_cone_angle,
+                    spot.outer_cone_angle,
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SpotDef {
+    #[serde(default)]
+    inner_cone_angle: f32,
+    #[serde(default = "default_outer_cone_angle")]
+    outer_cone_angle: f32,
+}
+
+fn default_outer_cone_angle() -> f32 {
+    std::f32::consts::FRAC_PI_4
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn split_parts() {
+        assert_eq!(
+            split("assets://models/fox.glb#materials/0"),
+            Some(("assets://models/fox.glb", "materials/0"))
+        );
+        assert_eq!(
+            split("assets://C#/scene #2.GLTF#images/1"),
+            Some(("assets://C#/scene #2.GLTF", "images/1"))
+        );
+        assert_eq!(
+            split("assets://a.glb#/b.gltf#materials/0/skinned"),
+            Some(("assets://a.glb#/b.gltf", "materials/0/skinned"))
+        );
+        assert_eq!(split("assets://models/fox#1.glb"), None);
+        assert_eq!(split("assets://textures/grid#2.png"), None);
+        assert!(!is_part("assets://models/fox#1.glb"));
+        assert!(is_gltf("assets://models/fox#1.glb"));
+    }
+
+    #[test]
+    fn open_shared_reuses_unchanged_files() {
+        let dir = std::env::temp_dir().join("sprocket-gltf-shared");
+        std::fs::create_dir_all(&dir).unwrap();
+        let buffer = dir.join("model.bin");
+        std::fs::write(&buffer, [0; 4]).unwrap();
+        let file = dir.join("model.gltf");
+        std::fs::write(
+            &file,
+            r#"{"asset":{"version":"2.0"},"buffers":[{"uri":"model.bin","byteLength":4}]}"#,
+        )
+        .unwrap();
+        let path = file.to_string_lossy();
+
+        let first = Gltf::open_shared(&path).unwrap();
+        assert!(Arc::ptr_eq(&first, &Gltf::open_shared(&path).unwrap()));
+
+        // Changing a buffer opens the file again
+        std::fs::File::options()
+            .write(true)
+            .open(&buffer)
+            .and_then(|buffer| buffer.set_modified(SystemTime::now() + Duration::from_secs(60)))
+            .unwrap();
+        let changed = Gltf::open_shared(&path).unwrap();
+        assert!(!Arc::ptr_eq(&first, &changed));
+        assert!(Arc::ptr_eq(&changed, &Gltf::open_shared(&path).unwrap()));
+
+        clear_shared();
+        assert!(!Arc::ptr_eq(&changed, &Gltf::open_shared(&path).unwrap()));
+    }
+
+    #[test]
+    fn accessors_out_of_range() {
+        let dir = std::env::temp_dir().join("sprocket-gltf-accessors");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("accessors.gltf");
+        let max = usize::MAX;
+        // Eight bytes 0 to 7
+        let json = format!(
+            r#"{{
+                "asset": {{"version": "2.0"}},
+                "buffers": [{{"uri": "data:application/octet-stream;base64,AAECAwQFBgc=", "byteLength": 8}}],
+                "bufferViews": [
+                    {{"buffer": 0, "byteLength": 8}},
+                    {{"buffer": 0, "byteOffset": {max}, "byteLength": 2}},
+                    {{"buffer": 0, "byteLength": 8, "byteStride": 1}}
+                ],
+                "accessors": [
+                    {{"bufferView": 0, "componentType": 5121, "count": 8, "type": "SCALAR"}},
+                    {{"bufferView": 0, "componentType": 5121, "count": 9, "type": "SCALAR"}},
+                    {{"bufferView": 0, "componentType": 5121, "count": 1, "type": "SCALAR", "byteOffset": {max}}},
+                    {{"bufferView": 0, "componentType": 5121, "count": {max}, "type": "VEC4"}},
+                    {{"bufferView": 1, "componentType": 5121, "count": 1, "type": "SCALAR"}},
+                    {{"bufferView": 2, "componentType": 5121, "count": 4, "type": "VEC2"}},
+                    {{"componentType": 5126, "count": {max}, "type": "VEC4"}},
+                    {{"componentType": 5126, "count": 2, "type": "VEC2"}}
+                ]
+            }}"#,
+            max = max
+        );
+        std::fs::write(&file, json).unwrap();
+        let gltf = Gltf::open(&file.to_string_lossy()).unwrap();
+
+        let (values, components) = gltf.read_accessor(0).unwrap();
+        assert_eq!(components, 1);
+        assert_eq!(values, (0..8).map(|i| i as f32).collect::<Vec<_>>());
+        assert_eq!(gltf.read_accessor(7).unwrap(), (vec![0.0; 4], 2));
+
+        for accessor in 1..7 {
+            assert!(
+                matches!(gltf.read_accessor(accessor), Err(Error::InvalidGltf(..))),
+                "accessor {}",
+                accessor
+            );
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Identifies a resource of any type in a resource manager
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct ResourceId(u64);
+
+impl ResourceId {
+    /// Returns an id that hasn't been handed out before
+    pub(super) fn next() -> Self {
+        ResourceId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl fmt::Display for ResourceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Identifies a resource of type T by the name it is stored as
+/// Stays valid across reloads and resolves to the current version of the resource
+/// Resolves to nothing once the resource has been collected
+pub struct Handle<T> {
+    id: ResourceId,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    pub(super) fn new(id: ResourceId) -> Self {
+        Handle {
+            id,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the untyped id of the resource
+    pub fn id(&self) -> ResourceId {
+        self.id
+    }
+}
+
+// Implemented manually as deriving would require T to implement the traits
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state)
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle({})", self.id)
+    }
+}
+
+/// Returns path with redundant separators, . and resolvable .. components removed so that
+/// different spellings of the same file name the same resource
+/// E.g; ./data/x.json, data//x.json and data/y/../x.json all become data/x.json
+/// The mount point of virtual paths is kept, E.g; assets://./x.json becomes assets://x.json
+/// Names that aren't paths are returned unchanged
+pub fn normalize(path: &str) -> String {
+    if let Some((point, relative)) = crate::vfs::split(path) {
+        return format!("{}://{}", point, normalize(relative));
+    }
+
+    let path = path.replace('\\', "/");
+    let absolute = path.starts_with('/');
+
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => match components.last() {
+                Some(&last) if last != ".." => {
+                    components.pop();
+                }
+                // The parent of the root is the root
+                None if absolute => {}
+                _ => components.push(".."),
+            },
+            _ => components.push(component),
+        }
+    }
+
+    match (absolute, components.is_empty()) {
+        (true, _) => format!("/{}", components.join("/")),
+        (false, true) => ".".to_owned(),
+        (false, false) => components.join("/"),
+    }
+}
+
+/// Records which resources each resource was created from
+/// E.g; a material depends on its pipeline and textures
+#[derive(Default)]
+pub(super) struct DependencyGraph {
+    /// The resources each resource depends on
+    dependencies: HashMap<ResourceId, HashSet<ResourceId>>,
+}
+
+impl DependencyGraph {
+    /// Replaces the recorded dependencies of id
+    pub fn set(&mut self, id: ResourceId, dependencies: HashSet<ResourceId>) {
+        if dependencies.is_empty() {
+            self.dependencies.remove(&id);
+        } else {
+            self.dependencies.insert(id, dependencies);
+        }
+    }
+
+    /// Returns the resources id directly depends on
+    pub fn dependencies(&self, id: ResourceId) -> Vec<ResourceId> {
+        let mut dependencies: Vec<_> = self
+            .dependencies
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .copied()
+            .collect();
+        dependencies.sort();
+        dependencies
+    }
+
+    /// Returns true if id depends on any of targets, directly or through other resources
+    pub fn depends_on(&self, id: ResourceId, targets: &HashSet<ResourceId>) -> bool {
+        let mut visited = HashSet::new();
+        let mut stack = vec![id];
+        while let Some(current) = stack.pop() {
+            for dependency in self.dependencies.get(&current).into_iter().flatten() {
+                if targets.contains(dependency) {
+                    return true;
+                }
+                if visited.insert(*dependency) {
+                    stack.push(*dependency);
+                }
+            }
+        }
+        false
+    }
+
+    /// Returns true if any resource depends directly on id
+    pub fn has_dependents(&self, id: ResourceId) -> bool {
+        self.dependencies
+            .values()
+            .any(|dependencies| dependencies.contains(&id))
+    }
+
+    /// Forgets a resource and its dependencies
+    pub fn remove(&mut self, id: ResourceId) {
+        self.dependencies.remove(&id);
+        self.dependencies.values_mut().for_each(|dependencies| {
+            dependencies.remove(&id);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_paths() {
+        assert_eq!(normalize("./data/x.json"), "data/x.json");
+        assert_eq!(normalize("data//x.json"), "data/x.json");
+        assert_eq!(normalize("data/y/../x.json"), "data/x.json");
+        assert_eq!(normalize("data\\y\\x.json"), "data/y/x.json");
+        assert_eq!(normalize("../x.json"), "../x.json");
+        assert_eq!(normalize("data/../../x.json"), "../x.json");
+        assert_eq!(normalize("/../x.json"), "/x.json");
+        assert_eq!(normalize("/"), "/");
+        assert_eq!(normalize("data/.."), ".");
+        assert_eq!(normalize("sprocket::graph::main"), "sprocket::graph::main");
+    }
+
+    #[test]
+    fn normalize_virtual_paths() {
+        assert_eq!(normalize("assets://./x.json"), "assets://x.json");
+        assert_eq!(normalize("assets://a/../b//x.json"), "assets://b/x.json");
+    }
+
+    fn set(ids: &[ResourceId]) -> HashSet<ResourceId> {
+        ids.iter().copied().collect()
+    }
+
+    fn ids(count: usize) -> Vec<ResourceId> {
+        (0..count).map(|_| ResourceId::next()).collect()
+    }
+
+    #[test]
+    fn dependents_after_reload() {
+        let ids = ids(4);
+        let (material, pipeline, old_texture, new_texture) = (ids[0], ids[1], ids[2], ids[3]);
+        let mut graph = DependencyGraph::default();
+        graph.set(material, set(&[pipeline, old_texture]));
+        assert!(graph.has_dependents(old_texture));
+        assert!(graph.depends_on(material, &set(&[old_texture])));
+
+        // Reloading the material records its dependencies again
+        graph.set(material, set(&[pipeline, new_texture]));
+        assert!(!graph.has_dependents(old_texture));
+        assert!(graph.has_dependents(new_texture));
+        let mut expected = vec![pipeline, new_texture];
+        expected.sort();
+        assert_eq!(graph.dependencies(material), expected);
+
+        graph.set(material, HashSet::new());
+        assert!(graph.dependencies(material).is_empty());
+        assert!(!graph.has_dependents(pipeline));
+    }
+
+    #[test]
+    fn transitive_dependencies() {
+        let ids = ids(4);
+        let mut graph = DependencyGraph::default();
+        graph.set(ids[0], set(&[ids[1]]));
+        graph.set(ids[1], set(&[ids[2]]));
+        // Cycles don't loop forever
+        graph.set(ids[2], set(&[ids[1]]));
+
+        let targets = |i: usize| set(&[ids[i]]);
+        assert!(graph.depends_on(ids[0], &targets(2)));
+        assert!(!graph.depends_on(ids[0], &targets(3)));
+        assert!(!graph.depends_on(ids[2], &targets(0)));
+
+        graph.remove(ids[1]);
+        assert!(!graph.depends_on(ids[0], &targets(2)));
+        assert!(!graph.has_dependents(ids[1]));
+    }
+}
@@ -1,6 +1,6 @@
 use super::buffer;
 use super::CommandPool;
-use super::{Result, VkAllocator};
+use super::{Result, Upload, VkAllocator};
 use ash::vk;
 use std::sync::Arc;
 
@@ -35,7 +35,7 @@ impl IndexBuffer {
             std::ptr::copy_nonoverlapping(indices.as_ptr() as _, data, buffer_size as usize);
         }
 
-        let (buffer, memory, _) = allocator.borrow().create_buffer(
+        let (buffer, memory, _) = allocator.read().unwrap().create_buffer(
             &vk::BufferCreateInfo::builder()
                 .size(buffer_size)
                 .usage(vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::INDEX_BUFFER)
@@ -57,7 +57,8 @@ impl IndexBuffer {
         )?;
 
         allocator
-            .borrow()
+            .read()
+            .unwrap()
             .destroy_buffer(staging_buffer, &staging_memory)?;
 
         Ok(IndexBuffer {
@@ -68,6 +69,50 @@ impl IndexBuffer {
             count: indices.len() as u32,
         })
     }
+
+    /// Creates an index buffer filled with indices by a transfer recorded into upload
+    /// The buffer may not be used until the upload has completed
+    pub fn new_staged(upload: &mut Upload, indices: &[u32]) -> Result<IndexBuffer> {
+        let buffer_size = match indices.len() {
+            0 => 1024,
+            n => (n * std::mem::size_of_val(&indices[0])) as u64,
+        };
+
+        let data = unsafe {
+            std::slice::from_raw_parts(
+                indices.as_ptr() as *const u8,
+                std::mem::size_of_val(indices),
+            )
+        };
+        let staging_buffer = upload.stage(data)?;
+
+        let allocator = Arc::clone(upload.allocator());
+        let (buffer, memory, _) = allocator.read().unwrap().create_buffer(
+            &vk::BufferCreateInfo::builder()
+                .size(buffer_size)
+                .usage(vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::INDEX_BUFFER)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                .build(),
+            &vk_mem::AllocationCreateInfo {
+                usage: vk_mem::MemoryUsage::GpuOnly,
+                ..Default::default()
+            },
+        )?;
+
+        // An empty buffer is left undefined
+        if !data.is_empty() {
+            upload.copy_buffer(staging_buffer, buffer, data.len() as u64);
+        }
+
+        Ok(IndexBuffer {
+            allocator,
+            buffer,
+            memory,
+            size: buffer_size,
+            count: indices.len() as u32,
+        })
+    }
+
     pub fn buffer(&self) -> vk::Buffer {
         self.buffer
     }
@@ -85,7 +130,8 @@ impl IndexBuffer {
 impl Drop for IndexBuffer {
     fn drop(&mut self) {
         self.allocator
-            .borrow()
+            .read()
+            .unwrap()
             .destroy_buffer(self.buffer, &self.memory)
             .expect("Failed to free vulkan memory");
     }
@@ -1,22 +1,54 @@
 use super::{
-    resources::Resource, DescriptorPool, DescriptorSet, DescriptorType, Error, Pipeline,
-    ResourceManager, Result, Sampler, Texture,
+    gltf, resources::Resource, DescriptorPool, DescriptorSet, DescriptorType, Error, Pipeline,
+    ResourceManager, Result, Sampler, SamplerSpec, Texture,
 };
 
+use crate::vfs;
 use ash::vk;
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 use std::sync::Arc;
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct MaterialSpec {
     pipeline: String,
     textures: Vec<String>,
+    /// The samplers of the textures in order, repeated if there are fewer samplers than textures
+    /// The textures are sampled with the default sampler if empty
+    #[serde(default)]
+    samplers: Vec<SamplerSpec>,
     // TODO coming features
     // color: Color,
     // reflectivity: f32,
     // smoothness: f32,
 }
 
+impl MaterialSpec {
+    /// Creates a spec rendering textures sampled with samplers using the pipeline at path
+    pub fn new(pipeline: &str, textures: Vec<String>, samplers: Vec<SamplerSpec>) -> Self {
+        MaterialSpec {
+            pipeline: pipeline.to_owned(),
+            textures,
+            samplers,
+        }
+    }
+
+    /// Returns the path of the pipeline the material renders with
+    pub fn pipeline(&self) -> &str {
+        &self.pipeline
+    }
+
+    /// Returns the paths of the textures bound in order
+    pub fn textures(&self) -> &[String] {
+        &self.textures
+    }
+
+    /// Returns the samplers of the textures
+    pub fn samplers(&self) -> &[SamplerSpec] {
+        &self.samplers
+    }
+}
+
 pub struct Material {
     pipeline: Arc<Pipeline>,
     textures: Vec<Arc<Texture>>,
@@ -25,27 +57,54 @@ pub struct Material {
     /// May be removed and replaced with descriptor pool management
     descriptor_pool: DescriptorPool,
     spec: MaterialSpec,
+    /// False if any texture was still loading and the placeholder is bound in its place
+    complete: bool,
+    /// The file the spec was read from if it isn't the path of the material, E.g; a glTF file
+    sources: Vec<PathBuf>,
 }
 
 impl Resource for Material {
+    // Materials in glTF files are addressed by their index, E.g; assets://models/fox.glb#materials/0
     fn load(resourcemanager: &ResourceManager, path: &str) -> Result<Self> {
-        let spec = serde_json::from_str(&ex::fs::read_to_string(path)?)?;
+        if let Some((file, _)) = gltf::split(path).filter(|_| gltf::is_part(path)) {
+            let mut material = Self::new(gltf::material_spec(path)?, resourcemanager)?;
+            material.sources.push(PathBuf::from(file));
+            return Ok(material);
+        }
+
+        let spec = serde_json::from_str(&vfs::read_to_string(path)?)?;
         Self::new(spec, resourcemanager)
     }
+
+    fn dependencies(&self) -> Vec<PathBuf> {
+        self.sources.clone()
+    }
 }
 
 impl Material {
     pub fn new(spec: MaterialSpec, resourcemanager: &ResourceManager) -> Result<Self> {
         let pipeline = resourcemanager.load_pipeline(&spec.pipeline)?;
 
+        // Textures that are still loading are replaced by the placeholder until they are uploaded
+        let mut complete = true;
         let textures: Vec<Arc<Texture>> = spec
             .textures
             .iter()
-            .map(|tex| resourcemanager.load_texture(tex))
+            .map(|tex| {
+                let handle = resourcemanager.load_texture_async(tex);
+                if let Some(texture) = handle.get() {
+                    return Ok(texture);
+                }
+                if let Some(reason) = handle.error() {
+                    return Err(Error::AsyncLoad(tex.to_owned(), reason));
+                }
+                complete = false;
+                resourcemanager.placeholder_texture()
+            })
             .collect::<Result<_>>()?;
 
         let context = resourcemanager.context();
-        let swapchain = resourcemanager.get_swapchain().unwrap();
+        let image_count = resourcemanager.target_info().unwrap().image_count;
 
         // Create the second per material descriptor set
         let per_material_layout = match pipeline.set_layouts().get(1) {
@@ -62,7 +121,7 @@ impl Material {
                     .iter()
                     .filter(|binding| binding.ty == DescriptorType::CombinedImageSampler)
                     .count()
-                    * swapchain.image_count()) as u32,
+                    * image_count) as u32,
             },
             // vk::DescriptorPoolSize {
             //     ty: vk::DescriptorType::UNIFORM_BUFFER,
@@ -77,20 +136,24 @@ impl Material {
 
         // Create pool for this material only
         // Will be changed when implementing descriptor pool management
-        let descriptor_pool = DescriptorPool::new(
-            &context.device,
-            &descriptor_pool_sizes,
-            swapchain.image_count() as u32,
-        )?;
+        let descriptor_pool =
+            DescriptorPool::new(&context.device, &descriptor_pool_sizes, image_count as u32)?;
 
         let descriptor_sets = DescriptorSet::new(
             &context.device,
             &descriptor_pool,
             &per_material_layout,
-            swapchain.image_count() as u32,
+            image_count as u32,
         )?;
 
-        let samplers = vec![Arc::new(Sampler::new(&context.device)?)];
+        let samplers = if spec.samplers.is_empty() {
+            vec![resourcemanager.sampler(&SamplerSpec::default())?]
+        } else {
+            spec.samplers
+                .iter()
+                .map(|sampler| resourcemanager.sampler(sampler))
+                .collect::<Result<_>>()?
+        };
 
         // Write the per material descriptor set with the textures
         DescriptorSet::write(
@@ -109,6 +172,8 @@ impl Material {
             descriptor_sets,
             descriptor_pool,
             spec,
+            complete,
+            sources: Vec::new(),
         })
     }
 
@@ -125,6 +190,11 @@ impl Material {
         &self.descriptor_sets[..]
     }
 
+    /// Returns false if the material samples placeholders for textures that are still loading
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
     pub fn spec(&self) -> &MaterialSpec {
         &self.spec
     }
@@ -132,6 +202,8 @@ impl Material {
     /// Returns self created again from spec but with updated values
     /// Called when swapchain is recreated
     pub fn recreate(&self, resourcemanager: &super::ResourceManager) -> Result<Self> {
-        Self::new(self.spec.clone(), resourcemanager)
+        let mut material = Self::new(self.spec.clone(), resourcemanager)?;
+        material.sources = self.sources.clone();
+        Ok(material)
     }
 }
@@ -1,4 +1,4 @@
-use super::{CommandPool, IndexBuffer, Result, Vertex, VertexBuffer, VkAllocator};
+use super::{CommandPool, IndexBuffer, Result, Upload, Vertex, VertexBuffer, VkAllocator};
 use ash::vk;
 use log::info;
 
@@ -29,6 +29,18 @@ impl Mesh {
         })
     }
 
+    /// Creates a new mesh whose buffers are filled by transfers recorded into upload
+    /// The mesh may not be drawn until the upload has completed
+    pub fn new_staged(upload: &mut Upload, vertices: &[Vertex], indices: &[u32]) -> Result<Mesh> {
+        let vertexbuffer = VertexBuffer::new_staged(upload, vertices)?;
+        let indexbuffer = IndexBuffer::new_staged(upload, indices)?;
+
+        Ok(Mesh {
+            vertexbuffer,
+            indexbuffer,
+        })
+    }
+
     /// Returns the index buffer
     pub fn indexbuffer(&self) -> &IndexBuffer {
         &&self.indexbuffer
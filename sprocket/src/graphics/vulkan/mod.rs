@@ -1,27 +1,45 @@
 #![allow(dead_code)]
 use crate::graphics::glfw;
 use crate::*;
-use std::cell::RefCell;
 use std::collections::HashSet;
 use std::ffi::{c_void, CStr, CString};
 use std::ptr;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
 use ash::extensions::khr::Surface;
 use ash::version::{DeviceV1_0, EntryV1_0, InstanceV1_0};
-use ash::{vk, vk::Handle, Entry};
+use ash::{vk, vk::Handle as _, Entry};
 
 mod texture;
-pub use texture::Texture;
+pub use texture::{LayeredTextureSpec, Texture, TextureData, TextureSpec};
+
+mod cubemap;
+mod decode;
+
+mod environment;
+pub use environment::EnvironmentMap;
+pub mod formats;
 
 pub mod sampler;
-pub use sampler::Sampler;
+pub use sampler::{Sampler, SamplerSpec};
 
 mod swapchain;
 pub use swapchain::Swapchain;
 
 mod pipeline;
-pub use pipeline::{Pipeline, PipelineSpec};
+pub use pipeline::{
+    DepthBias, DepthState, Pipeline, PipelineSpec, PipelineVariant, PipelineVariantKey,
+    StencilFaceState, StencilState,
+};
+
+mod reflect;
+pub use reflect::{ReflectedBinding, ShaderReflection};
+
+pub mod shader;
+mod watcher;
+
+mod upload;
+pub use upload::{Upload, UploadId, Uploader};
 
 mod renderpass;
 pub use renderpass::{
@@ -31,6 +49,15 @@ pub use renderpass::{
 mod framebuffer;
 pub use framebuffer::Framebuffer;
 
+mod rendertarget;
+pub use rendertarget::RenderTarget;
+
+pub mod shadow;
+pub use shadow::ShadowView;
+
+pub mod rendergraph;
+pub use rendergraph::{GraphPass, PassKind, RenderGraph, RenderGraphSpec};
+
 mod commandbuffer;
 pub use commandbuffer::CommandBuffer;
 pub use commandbuffer::CommandPool;
@@ -40,13 +67,16 @@ pub mod renderer;
 pub mod vertexbuffer;
 pub use vertexbuffer::Vertex;
 pub use vertexbuffer::VertexBuffer;
+pub use vertexbuffer::{VertexAttribute, VertexLayout};
 
 pub mod indexbuffer;
 pub use indexbuffer::IndexBuffer;
 
 pub mod uniformbuffer;
 pub use uniformbuffer::UniformBuffer;
-pub use uniformbuffer::UniformBufferObject;
+pub use uniformbuffer::{
+    GlobalUniforms, JointUniforms, LightData, MAX_JOINTS, MAX_LIGHTS, MAX_SHADOW_VIEWS,
+};
 
 pub mod descriptors;
 pub use descriptors::{
@@ -57,8 +87,10 @@ pub use descriptors::{
 pub mod mesh;
 pub use mesh::Mesh;
 
+pub mod gltf;
+
 pub mod model;
-pub use model::Model;
+pub use model::{Model, ModelData};
 
 pub mod material;
 pub use material::{Material, MaterialSpec};
@@ -67,24 +99,44 @@ mod buffer;
 pub use super::{Error, Result};
 
 mod resources;
-pub use resources::ResourceManager;
+pub use resources::{
+    AsyncResource, LoadHandle, LoadState, ManagedResource, ResourceManager, TargetInfo,
+};
+
+mod handle;
+pub use handle::{normalize, Handle, ResourceId};
+
+pub mod cooked;
+pub use cooked::CookedKind;
 
 pub mod enums;
 
-pub type VkAllocator = Arc<RefCell<vk_mem::Allocator>>;
+/// The allocator is internally synchronized, the lock is only written when it is destroyed
+/// Keeps the resources holding it Send and Sync
+pub type VkAllocator = Arc<RwLock<vk_mem::Allocator>>;
 
 pub struct VulkanContext {
     entry: ash::Entry,
     instance: ash::Instance,
     physical_device: vk::PhysicalDevice,
     device: ash::Device,
+    /// The optional device features that were enabled
+    features: vk::PhysicalDeviceFeatures,
+    limits: vk::PhysicalDeviceLimits,
     debug_utils_loader: ash::extensions::ext::DebugUtils,
-    debug_messenger: vk::DebugUtilsMessengerEXT,
+    /// None if validation layers are disabled
+    debug_messenger: Option<vk::DebugUtilsMessengerEXT>,
     surface_loader: Surface,
-    surface: vk::SurfaceKHR,
+    /// None if the context is headless
+    surface: Option<vk::SurfaceKHR>,
     queue_families: QueueFamilies,
     graphics_queue: vk::Queue,
-    present_queue: vk::Queue,
+    /// A second queue of the graphics family for background uploads if the device has one,
+    /// otherwise the graphics queue
+    /// Uploads generate mips with blits, so they can't use a transfer only family
+    upload_queue: vk::Queue,
+    /// None if the context is headless
+    present_queue: Option<vk::Queue>,
     allocator: VkAllocator,
     /// A pool for on-off operations like transfers
     generic_pool: Option<CommandPool>,
@@ -94,6 +146,47 @@ impl VulkanContext {
     pub fn generic_pool(&self) -> &CommandPool {
         self.generic_pool.as_ref().unwrap()
     }
+
+    /// Returns the device features that were enabled
+    pub fn features(&self) -> &vk::PhysicalDeviceFeatures {
+        &self.features
+    }
+
+    /// Returns the limits of the physical device
+    pub fn limits(&self) -> &vk::PhysicalDeviceLimits {
+        &self.limits
+    }
+
+    /// Returns true if optimally tiled images of format can be blitted with linear filtering
+    pub fn supports_linear_blit(&self, format: vk::Format) -> bool {
+        self.format_features(format).contains(
+            vk::FormatFeatureFlags::BLIT_SRC
+                | vk::FormatFeatureFlags::BLIT_DST
+                | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR,
+        )
+    }
+
+    /// Returns true if optimally tiled images of format can be sampled with linear filtering
+    pub fn supports_sampled(&self, format: vk::Format) -> bool {
+        self.format_features(format).contains(
+            vk::FormatFeatureFlags::SAMPLED_IMAGE
+                | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR,
+        )
+    }
+
+    /// Returns the features of optimally tiled images of format
+    fn format_features(&self, format: vk::Format) -> vk::FormatFeatureFlags {
+        unsafe {
+            self.instance
+                .get_physical_device_format_properties(self.physical_device, format)
+                .optimal_tiling_features
+        }
+    }
+
+    /// Returns true if the context was created without a surface and can't present
+    pub fn is_headless(&self) -> bool {
+        self.surface.is_none()
+    }
 }
 
 impl Drop for VulkanContext {
@@ -101,14 +194,18 @@ impl Drop for VulkanContext {
         info!("Dropping vulkan context");
         self.generic_pool = None;
         unsafe {
-            self.allocator.borrow_mut().destroy();
+            self.allocator.write().unwrap().destroy();
             // Drop data before device
             // This will later migrate out to materials and alike
             self.device.device_wait_idle().unwrap();
             self.device.destroy_device(None);
-            self.surface_loader.destroy_surface(self.surface, None);
-            self.debug_utils_loader
-                .destroy_debug_utils_messenger(self.debug_messenger, None);
+            if let Some(surface) = self.surface {
+                self.surface_loader.destroy_surface(surface, None);
+            }
+            if let Some(debug_messenger) = self.debug_messenger {
+                self.debug_utils_loader
+                    .destroy_debug_utils_messenger(debug_messenger, None);
+            }
             self.instance.destroy_instance(None);
         }
     }
@@ -116,35 +213,41 @@ impl Drop for VulkanContext {
 
 pub struct QueueFamilies {
     pub graphics: Option<u32>,
+    /// The number of queues in the graphics family
+    pub graphics_count: u32,
     pub present: Option<u32>,
     pub compute: Option<u32>,
     pub present_support: bool,
 }
 
 impl QueueFamilies {
+    /// Finds the queue families of a physical device
+    /// Presentation support is only queried if a surface is given
     unsafe fn find(
         instance: &ash::Instance,
         physical_device: &vk::PhysicalDevice,
         surface_loader: &Surface,
-        surface: &vk::SurfaceKHR,
+        surface: Option<&vk::SurfaceKHR>,
     ) -> QueueFamilies {
         let families = instance.get_physical_device_queue_family_properties(*physical_device);
         let mut graphics_family = None;
+        let mut graphics_count = 0;
         let mut presentation_family = None;
         let mut compute_family = None;
         let mut present_support = false;
         for (i, family) in families.iter().enumerate() {
             if family.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
                 graphics_family = Some(i as u32);
+                graphics_count = family.queue_count;
             }
-            if surface_loader
-                .get_physical_device_surface_support(*physical_device, i as u32, *surface)
-                .unwrap_or(false)
-            {
-                presentation_family = Some(i as u32);
-                present_support = surface_loader
+            if let Some(surface) = surface {
+                if surface_loader
                     .get_physical_device_surface_support(*physical_device, i as u32, *surface)
-                    .unwrap_or(false);
+                    .unwrap_or(false)
+                {
+                    presentation_family = Some(i as u32);
+                    present_support = true;
+                }
             }
             if family.queue_flags.contains(vk::QueueFlags::COMPUTE) {
                 compute_family = Some(i as u32);
@@ -153,6 +256,7 @@ impl QueueFamilies {
 
         QueueFamilies {
             graphics: graphics_family,
+            graphics_count,
             present: presentation_family,
             compute: compute_family,
             present_support,
@@ -160,77 +264,127 @@ impl QueueFamilies {
     }
 }
 
+/// Creates a vulkan context able to present to window
+/// Requires the validation layers to be installed
 pub fn init(window: &Window) -> Result<VulkanContext> {
-    unsafe {
-        let entry = match Entry::new() {
-            Ok(entry) => entry,
-            Err(_) => return Err(Error::UnsupportedAPI(super::Api::Vulkan)),
-        };
+    unsafe { create_context(Some(window), true) }
+}
 
-        let validation_layers = ["VK_LAYER_KHRONOS_validation"];
-        let device_extensions = ["VK_KHR_swapchain"];
-
-        // Ensure all requested layers exist
-        check_validation_layer_support(&entry, &validation_layers)?;
-        let instance = create_instance(&entry, &validation_layers)?;
-
-        let debug_utils_loader = ash::extensions::ext::DebugUtils::new(&entry, &instance);
-
-        let debug_messenger = create_debug_messenger(&debug_utils_loader)?;
-        let surface = create_surface(&instance, &window)?;
-        // Choose physical devices
-
-        let surface_loader = Surface::new(&entry, &instance);
-        let (physical_device, queue_families) =
-            find_physical_device(&instance, &surface_loader, &surface, &device_extensions)?;
-
-        let device = create_device(
-            &instance,
-            physical_device,
-            &queue_families,
-            &device_extensions,
-        )?;
-
-        let graphics_queue = device.get_device_queue(queue_families.graphics.unwrap(), 0);
-        let present_queue = device.get_device_queue(queue_families.present.unwrap(), 0);
-
-        let allocator_info = vk_mem::AllocatorCreateInfo {
-            device: device.clone(),
-            instance: instance.clone(),
-            physical_device,
-            preferred_large_heap_block_size: 0,
-            frame_in_use_count: 1,
-            flags: vk_mem::AllocatorCreateFlags::default(),
-            heap_size_limits: None,
-        };
+/// Creates a vulkan context without a surface
+/// The context can only render offscreen and doesn't require presentation support from the
+/// device, which allows it to run on software drivers like lavapipe on machines without a GPU or
+/// display
+/// If validation is true the validation layers are required to be installed
+pub fn init_headless(validation: bool) -> Result<VulkanContext> {
+    unsafe { create_context(None, validation) }
+}
 
-        let allocator = Arc::new(RefCell::new(vk_mem::Allocator::new(&allocator_info)?));
-
-        let generic_pool = CommandPool::new(&device, queue_families.graphics.unwrap(), true, true)?;
-
-        Ok(VulkanContext {
-            entry,
-            instance,
-            debug_utils_loader,
-            debug_messenger,
-            surface_loader,
-            surface,
-            physical_device,
-            device,
-            queue_families,
-            graphics_queue,
-            present_queue,
-            allocator,
-            generic_pool: Some(generic_pool),
-        })
-    }
+unsafe fn create_context(window: Option<&Window>, validation: bool) -> Result<VulkanContext> {
+    let entry = match Entry::new() {
+        Ok(entry) => entry,
+        Err(_) => return Err(Error::UnsupportedAPI(super::Api::Vulkan)),
+    };
+
+    let validation_layers: &[&str] = if validation {
+        &["VK_LAYER_KHRONOS_validation"]
+    } else {
+        &[]
+    };
+
+    // The swapchain is only needed when presenting
+    let device_extensions: &[&str] = match window {
+        Some(_) => &["VK_KHR_swapchain"],
+        None => &[],
+    };
+
+    // Ensure all requested layers exist
+    check_validation_layer_support(&entry, validation_layers)?;
+    let instance = create_instance(&entry, validation_layers, window.is_some())?;
+
+    let debug_utils_loader = ash::extensions::ext::DebugUtils::new(&entry, &instance);
+
+    let debug_messenger = if validation {
+        Some(create_debug_messenger(&debug_utils_loader)?)
+    } else {
+        None
+    };
 
-    // // Find physical devices
-    // let pdevices = instance..enumerate_physical_devices()?;
-    //
+    let surface = match window {
+        Some(window) => Some(create_surface(&instance, window)?),
+        None => None,
+    };
+
+    // Choose physical devices
+    let surface_loader = Surface::new(&entry, &instance);
+    let (physical_device, queue_families) = find_physical_device(
+        &instance,
+        &surface_loader,
+        surface.as_ref(),
+        device_extensions,
+    )?;
+
+    let (device, features) = create_device(
+        &instance,
+        physical_device,
+        &queue_families,
+        device_extensions,
+    )?;
+    let limits = instance
+        .get_physical_device_properties(physical_device)
+        .limits;
+
+    let graphics_queue = device.get_device_queue(queue_families.graphics.unwrap(), 0);
+    let upload_queue = if queue_families.graphics_count > 1 {
+        device.get_device_queue(queue_families.graphics.unwrap(), 1)
+    } else {
+        graphics_queue
+    };
+    let present_queue = queue_families
+        .present
+        .map(|family| device.get_device_queue(family, 0));
+
+    let allocator_info = vk_mem::AllocatorCreateInfo {
+        device: device.clone(),
+        instance: instance.clone(),
+        physical_device,
+        preferred_large_heap_block_size: 0,
+        frame_in_use_count: 1,
+        flags: vk_mem::AllocatorCreateFlags::default(),
+        heap_size_limits: None,
+    };
+
+    let allocator = Arc::new(RwLock::new(vk_mem::Allocator::new(&allocator_info)?));
+
+    let generic_pool = CommandPool::new(&device, queue_families.graphics.unwrap(), true, true)?;
+
+    Ok(VulkanContext {
+        entry,
+        instance,
+        debug_utils_loader,
+        debug_messenger,
+        surface_loader,
+        surface,
+        physical_device,
+        device,
+        features,
+        limits,
+        queue_families,
+        graphics_queue,
+        upload_queue,
+        present_queue,
+        allocator,
+        generic_pool: Some(generic_pool),
+    })
 }
 
-unsafe fn create_instance(entry: &ash::Entry, layers: &[&str]) -> Result<ash::Instance> {
+/// Creates the vulkan instance
+/// The extensions required by glfw are only enabled if windowed
+/// The debug utils extension is only enabled if any layers are requested
+unsafe fn create_instance(
+    entry: &ash::Entry,
+    layers: &[&str],
+    windowed: bool,
+) -> Result<ash::Instance> {
     let app_name = CString::new("Sprocket").unwrap();
     let app_info = vk::ApplicationInfo::builder()
         .application_name(&app_name)
@@ -240,15 +394,19 @@ unsafe fn create_instance(entry: &ash::Entry, layers: &[&str]) -> Result<ash::In
         .api_version(vk::make_version(1, 0, 0));
 
     // Extension support
-    let mut glfw_extension_count = 0;
-    let glfw_extensions = glfw::glfwGetRequiredInstanceExtensions(&mut glfw_extension_count);
-
-    let mut extensions = Vec::with_capacity(glfw_extension_count as usize);
-    for i in 0..glfw_extension_count {
-        let extension = *glfw_extensions.offset(i as isize);
-        extensions.push(extension);
+    let mut extensions = Vec::new();
+    if windowed {
+        let mut glfw_extension_count = 0;
+        let glfw_extensions = glfw::glfwGetRequiredInstanceExtensions(&mut glfw_extension_count);
+
+        for i in 0..glfw_extension_count {
+            let extension = *glfw_extensions.offset(i as isize);
+            extensions.push(extension);
+        }
+    }
+    if !layers.is_empty() {
+        extensions.push(b"VK_EXT_debug_utils\0".as_ptr() as *const i8);
     }
-    extensions.push(b"VK_EXT_debug_utils\0".as_ptr() as *const i8);
 
     // Convert the slice to *const *const null terminated
     let layers = utils::vec_to_null_terminated(layers);
@@ -330,11 +488,14 @@ unsafe fn create_surface(instance: &ash::Instance, window: &Window) -> Result<vk
     Ok(vk::SurfaceKHR::from_raw(surface_handle))
 }
 
+/// Rates how suitable a physical device is
+/// Returns 0 if the device is unsuitable
+/// Presentation and swapchain support are only required if a surface is given
 unsafe fn rate_device(
     instance: &ash::Instance,
     device: &vk::PhysicalDevice,
     surface_loader: &Surface,
-    surface: &vk::SurfaceKHR,
+    surface: Option<&vk::SurfaceKHR>,
     extensions: &[&str],
 ) -> u32 {
     let mut score = 1;
@@ -371,26 +532,29 @@ unsafe fn rate_device(
     if queue_families.graphics.is_none() {
         return 0;
     }
-    if queue_families.present.is_none() {
-        return 0;
-    }
 
-    if !queue_families.present_support {
-        return 0;
-    }
+    if let Some(surface) = surface {
+        if queue_families.present.is_none() {
+            return 0;
+        }
 
-    // Check adequate swapchain support
-    let (_capabilities, formats, present_modes) =
-        match Swapchain::query_support(device, surface_loader, surface) {
-            Ok(v) => v,
-            Err(_) => return 0,
-        };
+        if !queue_families.present_support {
+            return 0;
+        }
 
-    if formats.is_empty() {
-        return 0;
-    }
-    if present_modes.is_empty() {
-        return 0;
+        // Check adequate swapchain support
+        let (_capabilities, formats, present_modes) =
+            match Swapchain::query_support(device, surface_loader, surface) {
+                Ok(v) => v,
+                Err(_) => return 0,
+            };
+
+        if formats.is_empty() {
+            return 0;
+        }
+        if present_modes.is_empty() {
+            return 0;
+        }
     }
 
     if properties.device_type == vk::PhysicalDeviceType::DISCRETE_GPU {
@@ -407,7 +571,7 @@ unsafe fn rate_device(
 unsafe fn find_physical_device(
     instance: &ash::Instance,
     surface_loader: &Surface,
-    surface: &vk::SurfaceKHR,
+    surface: Option<&vk::SurfaceKHR>,
     device_extensions: &[&str],
 ) -> Result<(vk::PhysicalDevice, QueueFamilies)> {
     let devices = instance.enumerate_physical_devices().unwrap_or_default();
@@ -441,27 +605,43 @@ unsafe fn create_device(
     pdevice: vk::PhysicalDevice,
     queue_families: &QueueFamilies,
     device_extensions: &[&str],
-) -> Result<ash::Device> {
-    let priorities = [1.0];
+) -> Result<(ash::Device, vk::PhysicalDeviceFeatures)> {
+    // Uploads get a second, lower priority graphics queue if there is one
+    let priorities = [1.0, 0.5];
 
     let mut queue_infos = Vec::new();
 
     let mut unique_families = HashSet::new();
     unique_families.insert(queue_families.graphics.unwrap());
-    unique_families.insert(queue_families.present.unwrap());
+    if let Some(present) = queue_families.present {
+        unique_families.insert(present);
+    }
     debug!("Unique queue families {}", unique_families.len());
 
     for queue_family in unique_families {
+        let count = if Some(queue_family) == queue_families.graphics {
+            queue_families.graphics_count.min(2) as usize
+        } else {
+            1
+        };
         let queue_info = vk::DeviceQueueCreateInfo::builder()
             .queue_family_index(queue_family)
-            .queue_priorities(&priorities)
+            .queue_priorities(&priorities[..count])
             .build();
         queue_infos.push(queue_info);
     }
 
+    // Wireframe pipelines, clamped depth bias, anisotropic filtering and block compressed textures
+    // are enabled when supported
+    let supported = instance.get_physical_device_features(pdevice);
     let features = vk::PhysicalDeviceFeatures {
         shader_clip_distance: 1,
-        sampler_anisotropy: vk::TRUE,
+        sampler_anisotropy: supported.sampler_anisotropy,
+        fill_mode_non_solid: supported.fill_mode_non_solid,
+        depth_bias_clamp: supported.depth_bias_clamp,
+        texture_compression_bc: supported.texture_compression_bc,
+        texture_compression_etc2: supported.texture_compression_etc2,
+        texture_compression_astc_ldr: supported.texture_compression_astc_ldr,
         ..Default::default()
     };
 
@@ -474,9 +654,8 @@ unsafe fn create_device(
         .enabled_features(&features)
         .enabled_extension_names(&device_extensions);
 
-    instance
-        .create_device(pdevice, &device_create_info, None)
-        .map_err(|e| e.into())
+    let device = instance.create_device(pdevice, &device_create_info, None)?;
+    Ok((device, features))
 }
 
 fn create_semaphore(device: &ash::Device) -> Result<vk::Semaphore> {
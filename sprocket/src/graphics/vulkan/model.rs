@@ -1,14 +1,39 @@
-use super::{resources::Resource, CommandPool, Error, Mesh, Result, Vertex, VkAllocator};
+use super::resources::{AsyncResource, Resource};
+use super::vertexbuffer::calculate_tangents;
+use super::{cooked, gltf, CookedKind, Error, Mesh, Result, Upload, Vertex, VertexAttribute};
 use crate::math::*;
-use ash::{self, vk};
-use ex::fs;
+use crate::vfs;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::PathBuf;
 pub struct Model {
-    meshes: HashMap<String, Mesh>,
+    /// The meshes in the order they appear in the file
+    meshes: Vec<Mesh>,
+    /// Maps a mesh name to its index in meshes
+    names: HashMap<String, usize>,
+    /// The files besides the model file the meshes were read from, E.g; glTF buffers
+    sources: Vec<PathBuf>,
+}
+
+/// The meshes parsed from a model file before they are uploaded
+/// Also the format models are cooked to
+#[derive(Serialize, Deserialize)]
+pub struct ModelData {
+    meshes: Vec<MeshData>,
+    #[serde(skip)]
+    sources: Vec<PathBuf>,
+}
+
+/// The vertices and indices of a single mesh in a model file
+#[derive(Serialize, Deserialize)]
+pub(super) struct MeshData {
+    pub(super) name: String,
+    pub(super) vertices: Vec<Vertex>,
+    pub(super) indices: Vec<u32>,
 }
 
 impl Resource for Model {
-    // Loads a model from a collada file into meshes
+    // Loads a model from a collada or glTF file into meshes
     fn load(resourcemanager: &super::ResourceManager, path: &str) -> Result<Self> {
         let context = resourcemanager.context();
         let allocator = &context.allocator;
@@ -16,9 +41,88 @@ impl Resource for Model {
         let queue = context.graphics_queue;
         let commandpool = context.generic_pool();
 
-        let root = simple_xml::from_string(&fs::read_to_string(path)?)?;
+        let data = Self::read(path)?;
+        let mut meshes = Vec::new();
+        let mut names = HashMap::new();
+        for mesh in data.meshes {
+            names.insert(mesh.name, meshes.len());
+            meshes.push(Mesh::new(
+                allocator,
+                device,
+                queue,
+                commandpool,
+                &mesh.vertices,
+                &mesh.indices,
+            )?);
+        }
+
+        Ok(Model {
+            meshes,
+            names,
+            sources: data.sources,
+        })
+    }
+
+    fn dependencies(&self) -> Vec<PathBuf> {
+        self.sources.clone()
+    }
+}
+
+impl AsyncResource for Model {
+    type Data = ModelData;
+
+    fn read(path: &str) -> Result<ModelData> {
+        match cooked::find(CookedKind::Model, path) {
+            Some(cooked) => cooked::read(&cooked),
+            None => ModelData::from_file(path),
+        }
+    }
+
+    fn upload(
+        _resourcemanager: &super::ResourceManager,
+        data: ModelData,
+        upload: &mut Upload,
+    ) -> Result<Self> {
+        let mut meshes = Vec::new();
+        let mut names = HashMap::new();
+        for mesh in data.meshes {
+            names.insert(mesh.name, meshes.len());
+            meshes.push(Mesh::new_staged(upload, &mesh.vertices, &mesh.indices)?);
+        }
+
+        Ok(Model {
+            meshes,
+            names,
+            sources: data.sources,
+        })
+    }
+}
+
+impl ModelData {
+    /// Parses the meshes of a model file with the parser for its extension
+    /// .gltf and .glb files are read as glTF and all other files as collada
+    pub fn from_file(path: &str) -> Result<ModelData> {
+        if gltf::is_gltf(path) {
+            Self::from_gltf(path)
+        } else {
+            Self::from_collada(path)
+        }
+    }
+
+    /// Parses the triangle primitives of the meshes of a glTF file
+    /// Each primitive is a mesh of its own, see Gltf::primitive_names
+    pub fn from_gltf(path: &str) -> Result<ModelData> {
+        let gltf = gltf::Gltf::open_shared(path)?;
+        Ok(ModelData {
+            meshes: gltf.meshes()?,
+            sources: gltf.sources().to_vec(),
+        })
+    }
+
+    /// Parses the meshes of a collada file
+    pub fn from_collada(path: &str) -> Result<ModelData> {
+        let root = simple_xml::from_string(&vfs::read_to_string(path)?)?;
         let lib_geometries = &root.try_get_nodes("library_geometries")?[0];
-        let mut meshes = HashMap::new();
 
         let asset = &root.try_get_nodes("asset")?[0];
         let up_axis = &asset.try_get_nodes("up_axis")?[0];
@@ -32,68 +136,106 @@ impl Resource for Model {
             }
         };
 
-        for geometry in lib_geometries.try_get_nodes("geometry")?.iter() {
-            let (name, mesh) = parse_collada_geometry(
-                geometry,
-                allocator,
-                device,
-                queue,
-                commandpool,
-                axis_transform,
-            )?;
-            meshes.insert(name, mesh);
-        }
+        let meshes = lib_geometries
+            .try_get_nodes("geometry")?
+            .iter()
+            .map(|geometry| parse_collada_geometry(geometry, axis_transform))
+            .collect::<Result<_>>()?;
+
+        Ok(ModelData {
+            meshes,
+            sources: Vec::new(),
+        })
+    }
 
-        Ok(Model { meshes })
+    /// Returns the number of meshes in the model
+    pub fn mesh_count(&self) -> usize {
+        self.meshes.len()
     }
 }
 
 impl Model {
+    /// Returns the mesh at index in the order they were defined in the file
     pub fn get_mesh_index(&self, index: usize) -> Option<&Mesh> {
-        self.meshes.iter().skip(index).next().map(|(_, v)| v)
+        self.meshes.get(index)
+    }
+
+    /// Returns the mesh with name
+    pub fn get_mesh(&self, name: &str) -> Option<&Mesh> {
+        self.get_mesh_index(*self.names.get(name)?)
+    }
+
+    /// Returns the index of the mesh with name
+    pub fn mesh_index(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+
+    /// Returns the number of meshes in the model
+    pub fn mesh_count(&self) -> usize {
+        self.meshes.len()
     }
 }
 
 // Parses a single mesh/geometry from a collada xml structure
+// Reads positions, normals, vertex colors, and the first two uv sets if present
+// Tangents are calculated if the mesh has both normals and uvs
 fn parse_collada_geometry(
     node: &simple_xml::Node,
-
-    allocator: &VkAllocator,
-    device: &ash::Device,
-    queue: vk::Queue,
-    commandpool: &CommandPool,
     axis_transform: fn(Vec3) -> Vec3,
-) -> Result<(String, Mesh)> {
+) -> Result<MeshData> {
     let name = node.try_get_attribute("name")?;
-    let id = node.try_get_attribute("id")?;
     let mesh = &node.try_get_nodes("mesh")?[0];
-    let sources = mesh.try_get_nodes("source")?;
-
-    let source_positions = id.to_owned() + "-positions";
-    let source_normals = id.to_owned() + "-normals";
-    let source_map_0 = id.to_owned() + "-map-0";
 
-    // Create new empty array
-    let mut positions: Vec<f32> = Vec::new();
-    let mut _normals: Vec<f32> = Vec::new();
-    let mut uvs: Vec<f32> = Vec::new();
-
-    // Parse all positions, normals and uvs
-    for source in sources {
+    // Parse all sources into flat arrays along with their strides
+    let mut sources: HashMap<&str, (usize, Vec<f32>)> = HashMap::new();
+    for source in mesh.try_get_nodes("source")? {
         let source_id = source.try_get_attribute("id")?;
         let array = &source.try_get_nodes("float_array")?[0];
+        let accessor = &source.try_get_nodes("technique_common")?[0].try_get_nodes("accessor")?[0];
+        let stride = parse_attribute(accessor, "stride")?;
 
-        if source_id == &source_positions {
-            positions = parse_xml_array(&array, None)?;
-        } else if source_id == &source_normals {
-            _normals = parse_xml_array(&array, None)?;
-        } else if source_id == &source_map_0 {
-            uvs = parse_xml_array(&array, None)?;
-        }
+        sources.insert(source_id, (stride, parse_xml_array(array, None)?));
     }
 
+    // The positions are referenced through the vertices element
+    let vertices_node = &mesh.try_get_nodes("vertices")?[0];
+    let position_input = vertices_node
+        .try_get_nodes("input")?
+        .iter()
+        .find(|input| attribute(input, "semantic").ok() == Some("POSITION"))
+        .ok_or(Error::ParseError)?;
+    let source_positions = attribute(position_input, "source")?;
+
     let triangles = &mesh.try_get_nodes("triangles")?[0];
 
+    // The attribute, offset in each index group, and source of each input
+    let mut inputs = Vec::new();
+    // The number of indices for each vertex
+    let mut group_size = 0;
+    for input in triangles.try_get_nodes("input")? {
+        let offset: usize = parse_attribute(input, "offset")?;
+        group_size = group_size.max(offset + 1);
+
+        let set = attribute(input, "set")
+            .ok()
+            .and_then(|set| set.parse::<usize>().ok())
+            .unwrap_or(0);
+
+        let (attribute, source) = match (attribute(input, "semantic")?, set) {
+            ("VERTEX", _) => (VertexAttribute::Position, source_positions),
+            ("NORMAL", _) => (VertexAttribute::Normal, attribute(input, "source")?),
+            ("TEXCOORD", 0) => (VertexAttribute::Uv, attribute(input, "source")?),
+            ("TEXCOORD", 1) => (VertexAttribute::Uv1, attribute(input, "source")?),
+            ("COLOR", 0) => (VertexAttribute::Color, attribute(input, "source")?),
+            _ => continue,
+        };
+
+        let source = sources
+            .get(source.trim_start_matches('#'))
+            .ok_or(Error::ParseError)?;
+        inputs.push((attribute, offset, source));
+    }
+
     // Parse the triangles/indices
     let triangles_list: Vec<usize> = parse_xml_array(
         &triangles.try_get_nodes("p")?[0],
@@ -101,41 +243,82 @@ fn parse_collada_geometry(
             triangles
                 .try_get_attribute("count")?
                 .parse::<usize>()
-                .map_err(|_| Error::ParseError)?,
+                .map_err(|_| Error::ParseError)?
+                * 3
+                * group_size,
         ),
     )?;
 
     let mut vertices: Vec<Vertex> = Vec::new();
     let mut indices: Vec<u32> = Vec::new();
 
-    let mut vertex_map: HashMap<(usize, usize, usize), usize> = HashMap::new();
-
-    for i in (0..triangles_list.len()).step_by(3) {
-        let pos = triangles_list[i];
-        let normal = triangles_list[i + 1];
-        let uv = triangles_list[i + 2];
+    // Reuse vertices with the same combination of indices
+    let mut vertex_map: HashMap<&[usize], usize> = HashMap::new();
 
-        indices.push(match vertex_map.get(&(pos, normal, uv)) {
-            Some(i) => {
-                // log::info!("Reusing vertex {},{}", pos, uv);
-                *i as u32
-            }
+    for group in triangles_list.chunks_exact(group_size) {
+        indices.push(match vertex_map.get(group) {
+            Some(i) => *i as u32,
             // Create new vertex and add to map
             // Push the new node index to the indeices
             None => {
-                vertices.push(Vertex {
-                    // Get the correct vertex from the positions array
-                    // Correctly transform
-                    position: axis_transform(array_to_vec3(&positions, pos)),
-                    uv: array_to_vec2(&uvs, uv),
-                });
-                vertex_map.insert((pos, normal, uv), vertices.len() - 1);
+                let mut vertex = Vertex::default();
+                for (attribute, offset, (stride, values)) in &inputs {
+                    let value = &values[group[*offset] * stride..];
+                    match attribute {
+                        VertexAttribute::Position => {
+                            vertex.position = axis_transform(array_to_vec3(value, 0))
+                        }
+                        VertexAttribute::Normal => {
+                            vertex.normal = axis_transform(array_to_vec3(value, 0))
+                        }
+                        VertexAttribute::Uv => vertex.uv = array_to_vec2(value, 0),
+                        VertexAttribute::Uv1 => vertex.uv1 = array_to_vec2(value, 0),
+                        // Colors may be RGB or RGBA
+                        VertexAttribute::Color => {
+                            vertex.color = Vec4::new(
+                                value[0],
+                                value[1],
+                                value[2],
+                                if *stride > 3 { value[3] } else { 1.0 },
+                            )
+                        }
+                        VertexAttribute::Tangent
+                        | VertexAttribute::Joints
+                        | VertexAttribute::Weights => {}
+                    }
+                }
+
+                vertices.push(vertex);
+                vertex_map.insert(group, vertices.len() - 1);
                 (vertices.len() - 1) as u32
             }
         });
     }
-    Mesh::new(allocator, device, queue, commandpool, &vertices, &indices)
-        .map(|mesh| (name.to_owned(), mesh))
+
+    let has_attribute = |attribute| inputs.iter().any(|input| input.0 == attribute);
+    if has_attribute(VertexAttribute::Normal) && has_attribute(VertexAttribute::Uv) {
+        calculate_tangents(&mut vertices, &indices);
+    }
+
+    Ok(MeshData {
+        name: name.to_owned(),
+        vertices,
+        indices,
+    })
+}
+
+/// Returns an attribute of a node
+/// simple_xml keeps the closing quote of the last attribute of self-closing elements, E.g;
+/// <input offset="0"/>, which is removed
+fn attribute<'a>(node: &'a simple_xml::Node, name: &str) -> Result<&'a str> {
+    Ok(node.try_get_attribute(name)?.trim_end_matches('"'))
+}
+
+/// Parses an attribute of a node into T
+fn parse_attribute<T: std::str::FromStr>(node: &simple_xml::Node, name: &str) -> Result<T> {
+    attribute(node, name)?
+        .parse::<T>()
+        .map_err(|_| Error::ParseError)
 }
 
 /// Creates a vector from 3 elements in an array of floats
@@ -1,26 +1,332 @@
 use super::descriptors::ShaderStage;
+use super::enums::{
+    BlendMode, CompareOp, CullMode, FrontFace, PolygonMode, PrimitiveTopology, StencilOp,
+};
+use super::reflect::{self, ShaderReflection};
 use super::{resources::Resource, DescriptorSetLayout, DescriptorSetLayoutSpec, Error, Result};
-use super::{vertexbuffer::Vertex, RenderPass};
+use super::{shader, uniformbuffer, DescriptorSetLayoutBinding, RenderPass, VertexLayout};
 
+use crate::vfs;
 use ash::version::DeviceV1_0;
 use ash::vk;
-use ex::fs;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::{ffi::CStr, sync::Arc};
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct PipelineSpec {
+    /// GLSL source or SPIR-V
     pub vertex_shader: String,
+    /// GLSL source or SPIR-V
     pub fragment_shader: String,
+    /// Not supported yet, kept for existing specs
+    #[serde(default)]
     pub geometry_shader: String,
-    pub renderpass: String,
+    /// Preprocessor defines set when compiling GLSL sources
+    #[serde(default)]
+    pub defines: HashMap<String, String>,
     /// The descriptor set layouts
     /// If used in a normal rendering setup the setup is like this
     /// 0: Global data
     /// 1: Per material data
     /// 2: Per entity/draw data
+    /// Derived from the shaders if empty, otherwise checked against them
+    /// Derived layouts always use the global layout for set 0
+    #[serde(default)]
     pub layouts: Vec<DescriptorSetLayoutSpec>,
+    /// Derived from the shaders if empty, otherwise checked against them
+    #[serde(default)]
     pub push_constants: Vec<PushConstantRange>,
+    /// The vertex attributes read by the vertex shader in location order
+    #[serde(default)]
+    pub vertex_layout: VertexLayout,
+    /// The primitives the vertices are assembled into
+    #[serde(default)]
+    pub topology: PrimitiveTopology,
+    #[serde(default)]
+    pub polygon_mode: PolygonMode,
+    #[serde(default)]
+    pub cull_mode: CullMode,
+    #[serde(default)]
+    pub front_face: FrontFace,
+    #[serde(default)]
+    pub depth: DepthState,
+    /// The stencil test is disabled if None
+    /// Requires a depth attachment with a stencil component
+    #[serde(default)]
+    pub stencil: Option<StencilState>,
+    /// The blend mode of each color attachment in order
+    /// A single mode applies to all attachments and no modes leaves them opaque
+    #[serde(default)]
+    pub blend: Vec<BlendMode>,
+    /// Offsets the depth of the rasterized primitives, e.g; to avoid z-fighting of decals
+    #[serde(default)]
+    pub depth_bias: Option<DepthBias>,
+}
+
+impl PipelineSpec {
+    /// Checks for combinations of state that can't be used regardless of the renderpass
+    pub fn validate(&self) -> Result<()> {
+        if self.depth.write && !self.depth.test {
+            return Err(Error::InvalidPipelineSpec(
+                "depth writes require the depth test".to_owned(),
+            ));
+        }
+
+        let triangles = matches!(
+            self.topology,
+            PrimitiveTopology::TriangleList
+                | PrimitiveTopology::TriangleStrip
+                | PrimitiveTopology::TriangleFan
+        );
+
+        if !triangles && self.polygon_mode != PolygonMode::Fill {
+            return Err(Error::InvalidPipelineSpec(format!(
+                "polygon mode {:?} requires a triangle topology",
+                self.polygon_mode
+            )));
+        }
+
+        if !triangles && self.cull_mode != CullMode::None {
+            return Err(Error::InvalidPipelineSpec(format!(
+                "{:?} primitives can't be culled",
+                self.topology
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Derives the descriptor set layouts and push constant ranges from the reflected shader
+    /// stages if they are omitted, otherwise checks that they cover everything the shaders use
+    fn resolve(&mut self, reflections: &[(&str, ShaderReflection)]) -> Result<()> {
+        if self.layouts.is_empty() {
+            self.layouts = derive_layouts(reflections)?;
+        }
+
+        if self.push_constants.is_empty() {
+            self.push_constants = derive_push_constants(reflections);
+        }
+
+        for (path, reflection) in reflections {
+            let mismatch = |reason: String| Error::ShaderMismatch((*path).to_owned(), reason);
+            let stage = reflection.stage as u32;
+
+            for used in &reflection.bindings {
+                let binding = self
+                    .layouts
+                    .get(used.set as usize)
+                    .and_then(|layout| layout.bindings.iter().find(|b| b.slot == used.binding))
+                    .ok_or_else(|| {
+                        mismatch(format!(
+                            "set {} binding {} is not in the layouts",
+                            used.set, used.binding
+                        ))
+                    })?;
+
+                if binding.ty != used.ty {
+                    return Err(mismatch(format!(
+                        "set {} binding {} is a {:?} but the layout declares a {:?}",
+                        used.set, used.binding, used.ty, binding.ty
+                    )));
+                }
+
+                if binding.count < used.count {
+                    return Err(mismatch(format!(
+                        "set {} binding {} has {} descriptors but the layout declares {}",
+                        used.set, used.binding, used.count, binding.count
+                    )));
+                }
+
+                if stage_flags(&binding.stages) & stage == 0 {
+                    return Err(mismatch(format!(
+                        "set {} binding {} is not visible to the {:?} stage",
+                        used.set, used.binding, reflection.stage
+                    )));
+                }
+            }
+
+            if let Some((offset, size)) = reflection.push_constants {
+                let covered = self.push_constants.iter().any(|range| {
+                    stage_flags(&range.stages) & stage != 0
+                        && range.offset <= offset
+                        && offset + size <= range.offset + range.size
+                });
+
+                if !covered {
+                    return Err(mismatch(format!(
+                        "push constants {}..{} of the {:?} stage are not in a push constant range",
+                        offset,
+                        offset + size,
+                        reflection.stage
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Creates a layout for each set up to the highest one used by the shaders
+/// Set 0 is reserved for the renderer's global data
+fn derive_layouts(
+    reflections: &[(&str, ShaderReflection)],
+) -> Result<Vec<DescriptorSetLayoutSpec>> {
+    let set_count = reflections
+        .iter()
+        .flat_map(|(_, reflection)| reflection.bindings.iter())
+        .map(|binding| binding.set + 1)
+        .max()
+        .unwrap_or(0);
+
+    let mut layouts = vec![DescriptorSetLayoutSpec { bindings: vec![] }; set_count as usize];
+    if let Some(global) = layouts.first_mut() {
+        *global = uniformbuffer::global_layout_spec();
+    }
+
+    for (path, reflection) in reflections {
+        for used in reflection
+            .bindings
+            .iter()
+            .filter(|binding| binding.set != 0)
+        {
+            let bindings = &mut layouts[used.set as usize].bindings;
+            match bindings.iter_mut().find(|b| b.slot == used.binding) {
+                Some(binding) if binding.ty != used.ty || binding.count != used.count => {
+                    return Err(Error::ShaderMismatch(
+                        (*path).to_owned(),
+                        format!(
+                            "set {} binding {} is declared differently by another stage",
+                            used.set, used.binding
+                        ),
+                    ))
+                }
+                Some(binding) => binding.stages.push(reflection.stage),
+                None => bindings.push(DescriptorSetLayoutBinding {
+                    slot: used.binding,
+                    ty: used.ty,
+                    count: used.count,
+                    stages: vec![reflection.stage],
+                }),
+            }
+        }
+    }
+
+    Ok(layouts)
+}
+
+/// Creates a single range spanning the push constants of all stages that use them
+fn derive_push_constants(reflections: &[(&str, ShaderReflection)]) -> Vec<PushConstantRange> {
+    let used: Vec<_> = reflections
+        .iter()
+        .filter_map(|(_, reflection)| {
+            reflection
+                .push_constants
+                .map(|range| (reflection.stage, range))
+        })
+        .collect();
+
+    if used.is_empty() {
+        return vec![];
+    }
+
+    let start = used
+        .iter()
+        .map(|(_, (offset, _))| *offset)
+        .min()
+        .unwrap_or(0);
+    let end = used
+        .iter()
+        .map(|(_, (offset, size))| offset + size)
+        .max()
+        .unwrap_or(0);
+
+    vec![PushConstantRange {
+        size: end - start,
+        offset: start,
+        stages: used.iter().map(|(stage, _)| *stage).collect(),
+    }]
+}
+
+/// How fragments are tested against and written to the depth attachment
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct DepthState {
+    pub test: bool,
+    pub write: bool,
+    pub compare_op: CompareOp,
+}
+
+impl Default for DepthState {
+    fn default() -> Self {
+        DepthState {
+            test: true,
+            write: true,
+            compare_op: CompareOp::Less,
+        }
+    }
+}
+
+/// The stencil operations of front and back facing primitives
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct StencilState {
+    pub front: StencilFaceState,
+    pub back: StencilFaceState,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct StencilFaceState {
+    pub fail_op: StencilOp,
+    pub pass_op: StencilOp,
+    pub depth_fail_op: StencilOp,
+    pub compare_op: CompareOp,
+    pub compare_mask: u32,
+    pub write_mask: u32,
+    pub reference: u32,
+}
+
+impl Default for StencilFaceState {
+    fn default() -> Self {
+        StencilFaceState {
+            fail_op: StencilOp::Keep,
+            pass_op: StencilOp::Keep,
+            depth_fail_op: StencilOp::Keep,
+            compare_op: CompareOp::Always,
+            compare_mask: 0xff,
+            write_mask: 0xff,
+            reference: 0,
+        }
+    }
+}
+
+impl From<StencilFaceState> for vk::StencilOpState {
+    fn from(state: StencilFaceState) -> Self {
+        vk::StencilOpState {
+            fail_op: state.fail_op.into(),
+            pass_op: state.pass_op.into(),
+            depth_fail_op: state.depth_fail_op.into(),
+            compare_op: state.compare_op.into(),
+            compare_mask: state.compare_mask,
+            write_mask: state.write_mask,
+            reference: state.reference,
+        }
+    }
+}
+
+/// The depth bias applied to rasterized primitives
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct DepthBias {
+    /// A constant depth added to each fragment
+    pub constant: f32,
+    /// Scales the slope of the primitive before it is added
+    pub slope: f32,
+    /// The maximum bias if not zero
+    /// Requires the depth bias clamp device feature
+    #[serde(default)]
+    pub clamp: f32,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -35,107 +341,293 @@ impl PushConstantRange {
         vk::PushConstantRange {
             size: self.size,
             offset: self.offset,
-            stage_flags: vk::ShaderStageFlags::from_raw(
-                self.stages.iter().fold(0, |acc, val| acc | ((*val) as u32)),
-            ),
+            stage_flags: vk::ShaderStageFlags::from_raw(stage_flags(&self.stages)),
         }
     }
 }
 
+/// The renderpass independent part of a pipeline loaded from a PipelineSpec
+/// Holds the descriptor set layouts and the pipeline layout used to create descriptor sets and
+/// push constants
+/// The actual vulkan pipelines are created on demand for each renderpass as PipelineVariants
 pub struct Pipeline {
     device: ash::Device,
     layout: vk::PipelineLayout,
     set_layouts: Vec<DescriptorSetLayout>,
-    pipeline: vk::Pipeline,
-    renderpass: Arc<RenderPass>,
     spec: PipelineSpec,
+    /// The vertex attribute locations read by the vertex shader
+    vertex_inputs: Vec<u32>,
+    vertex_code: Vec<u32>,
+    fragment_code: Vec<u32>,
+    /// The shader files and their includes
+    sources: Vec<PathBuf>,
 }
 
 impl Resource for Pipeline {
     fn load(resourcemanager: &super::ResourceManager, path: &str) -> Result<Self> {
-        let spec: PipelineSpec = serde_json::from_str(&ex::fs::read_to_string(path)?)?;
+        let spec: PipelineSpec = serde_json::from_str(&vfs::read_to_string(path)?)?;
         let context = resourcemanager.context();
 
-        Self::new(&context.device, spec, resourcemanager)
+        Self::new(&context.device, spec)
+    }
+
+    fn dependencies(&self) -> Vec<PathBuf> {
+        self.sources.clone()
     }
 }
 
 impl Pipeline {
+    /// Creates the pipeline layout from spec
+    /// The shaders are reflected to fill in omitted layouts and push constants and to check the
+    /// declared ones
+    pub fn new(device: &ash::Device, mut spec: PipelineSpec) -> Result<Self> {
+        spec.validate()?;
+
+        let vertex_shader = shader::load(&spec.vertex_shader, &spec.defines)?;
+        let fragment_shader = shader::load(&spec.fragment_shader, &spec.defines)?;
+
+        let vertex = reflect_shader(&spec.vertex_shader, &vertex_shader.code)?;
+        let fragment = reflect_shader(&spec.fragment_shader, &fragment_shader.code)?;
+        let vertex_inputs = vertex.inputs.clone();
+        let (vertex_path, fragment_path) =
+            (spec.vertex_shader.clone(), spec.fragment_shader.clone());
+        spec.resolve(&[(&vertex_path, vertex), (&fragment_path, fragment)])?;
+
+        let mut sources = vertex_shader.files;
+        sources.extend(fragment_shader.files);
+        sources.sort();
+        sources.dedup();
+
+        let mut set_layouts = Vec::with_capacity(spec.layouts.len());
+
+        for layout_spec in &spec.layouts {
+            // TODO avoid clone
+            set_layouts.push(DescriptorSetLayout::new(device, layout_spec.clone())?)
+        }
+
+        let vk_set_layouts: Vec<vk::DescriptorSetLayout> =
+            set_layouts.iter().map(|layout| layout.vk()).collect();
+
+        let push_constants = spec
+            .push_constants
+            .iter()
+            .map(|v| v.to_vk())
+            .collect::<Vec<_>>();
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&vk_set_layouts)
+            .push_constant_ranges(&push_constants);
+
+        let pipeline_layout =
+            unsafe { device.create_pipeline_layout(&pipeline_layout_info, None)? };
+
+        Ok(Pipeline {
+            device: device.clone(),
+            layout: pipeline_layout,
+            set_layouts,
+            spec,
+            vertex_inputs,
+            vertex_code: vertex_shader.code,
+            fragment_code: fragment_shader.code,
+            sources,
+        })
+    }
+
+    pub fn layout(&self) -> vk::PipelineLayout {
+        self.layout
+    }
+
+    pub fn set_layouts(&self) -> &[DescriptorSetLayout] {
+        &self.set_layouts[..]
+    }
+
+    /// Returns the spec the pipeline was created from with the layouts and push constants
+    /// derived from the shaders filled in
+    pub fn spec(&self) -> &PipelineSpec {
+        &self.spec
+    }
+
+    /// Returns the shader files the pipeline was compiled from, including the files they include
+    pub fn sources(&self) -> &[PathBuf] {
+        &self.sources
+    }
+
+    /// Returns the stages of all push constant ranges
+    pub fn push_constant_stages(&self) -> vk::ShaderStageFlags {
+        vk::ShaderStageFlags::from_raw(
+            self.spec
+                .push_constants
+                .iter()
+                .fold(0, |acc, range| acc | stage_flags(&range.stages)),
+        )
+    }
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        unsafe { self.device.destroy_pipeline_layout(self.layout, None) };
+    }
+}
+
+/// Identifies a pipeline created for a specific renderpass and vertex layout
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct PipelineVariantKey {
+    /// The path of the pipeline spec
+    pub pipeline: String,
+    /// The name of the renderpass, either a path or a name registered by the renderer
+    pub renderpass: String,
+    /// The vertex attributes read from the vertex buffer
+    pub vertex_layout: VertexLayout,
+}
+
+/// A vulkan pipeline created from a Pipeline for one renderpass and vertex layout
+/// Shares the pipeline layout of its base pipeline so that the same descriptor sets and push
+/// constants can be used with all variants
+pub struct PipelineVariant {
+    device: ash::Device,
+    pipeline: vk::Pipeline,
+    base: Arc<Pipeline>,
+    /// Kept alive for as long as the variant
+    renderpass: Arc<RenderPass>,
+}
+
+impl PipelineVariant {
+    /// Creates a variant of base rendering in the first subpass of renderpass
+    /// Renderpasses without color attachments are treated as depth only and skip the fragment
+    /// shader
+    /// Fails if the state of base isn't supported by renderpass or the enabled device features
     pub fn new(
         device: &ash::Device,
-        spec: PipelineSpec,
-        resourcemanager: &super::ResourceManager,
+        features: &vk::PhysicalDeviceFeatures,
+        base: &Arc<Pipeline>,
+        renderpass: &Arc<RenderPass>,
+        vertex_layout: &VertexLayout,
     ) -> Result<Self> {
+        let spec = base.spec();
         let shader_entry_point = unsafe { CStr::from_ptr("main\0".as_ptr() as _) };
 
-        // Shader stages
-        let vertex_shader_module = create_shader_module(device, &spec.vertex_shader)?;
+        let subpass = renderpass.spec().subpasses.first();
+        let color_attachment_count = subpass.map_or(0, |subpass| subpass.color_attachments.len());
+        let has_depth = subpass.is_some_and(|subpass| subpass.depth_attachment.is_some());
+
+        // One blend state is required for each color attachment of the subpass
+        let blend_modes = match spec.blend.len() {
+            0 => vec![BlendMode::Opaque; color_attachment_count],
+            1 => vec![spec.blend[0]; color_attachment_count],
+            n if n == color_attachment_count => spec.blend.clone(),
+            n => {
+                return Err(Error::InvalidPipelineSpec(format!(
+                    "{} blend modes for {} color attachments",
+                    n, color_attachment_count
+                )))
+            }
+        };
+
+        if spec.polygon_mode != PolygonMode::Fill && features.fill_mode_non_solid == vk::FALSE {
+            return Err(Error::InvalidPipelineSpec(format!(
+                "polygon mode {:?} is not supported by the device",
+                spec.polygon_mode
+            )));
+        }
 
-        let fragment_shader_module = create_shader_module(device, &spec.fragment_shader)?;
+        if spec.depth_bias.is_some_and(|bias| bias.clamp != 0.0)
+            && features.depth_bias_clamp == vk::FALSE
+        {
+            return Err(Error::InvalidPipelineSpec(
+                "depth bias clamp is not supported by the device".to_owned(),
+            ));
+        }
+
+        if spec.stencil.is_some() && !(has_depth && has_stencil(renderpass.depth_format())) {
+            return Err(Error::InvalidPipelineSpec(
+                "the stencil test requires a depth attachment with a stencil component".to_owned(),
+            ));
+        }
 
-        let vertex_shader_info = vk::PipelineShaderStageCreateInfo::builder()
+        // Every location read by the vertex shader needs an attribute
+        if let Some(location) = base
+            .vertex_inputs
+            .iter()
+            .find(|location| **location as usize >= vertex_layout.attributes.len())
+        {
+            return Err(Error::ShaderMismatch(
+                spec.vertex_shader.clone(),
+                format!(
+                    "location {} is not in a vertex layout of {} attributes",
+                    location,
+                    vertex_layout.attributes.len()
+                ),
+            ));
+        }
+
+        // Shader stages
+        let vertex_shader_module = create_shader_module(device, &base.vertex_code)?;
+
+        let fragment_shader_module = if color_attachment_count > 0 {
+            match create_shader_module(device, &base.fragment_code) {
+                Ok(module) => Some(module),
+                Err(e) => {
+                    unsafe { device.destroy_shader_module(vertex_shader_module, None) };
+                    return Err(e);
+                }
+            }
+        } else {
+            None
+        };
+
+        let mut shader_stages = vec![vk::PipelineShaderStageCreateInfo::builder()
             .stage(vk::ShaderStageFlags::VERTEX)
             .module(vertex_shader_module)
             .name(&shader_entry_point)
-            .build();
-
-        let fragment_shader_info = vk::PipelineShaderStageCreateInfo::builder()
-            .stage(vk::ShaderStageFlags::FRAGMENT)
-            .module(fragment_shader_module)
-            .name(&shader_entry_point)
-            .build();
-
-        let shader_stages = [vertex_shader_info, fragment_shader_info];
+            .build()];
+
+        if let Some(module) = fragment_shader_module {
+            shader_stages.push(
+                vk::PipelineShaderStageCreateInfo::builder()
+                    .stage(vk::ShaderStageFlags::FRAGMENT)
+                    .module(module)
+                    .name(shader_entry_point)
+                    .build(),
+            );
+        }
 
         // Vertex input
-        let binding_descriptions = [Vertex::binding_description()];
-        let attribute_descriptions = Vertex::attribute_descriptions();
+        let binding_descriptions = [vertex_layout.binding_description()];
+        let attribute_descriptions = vertex_layout.attribute_descriptions();
         let vertex_input_info = vk::PipelineVertexInputStateCreateInfo::builder()
             .vertex_binding_descriptions(&binding_descriptions)
             .vertex_attribute_descriptions(&attribute_descriptions);
 
         // Input assembly
         let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::builder()
-            .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .topology(spec.topology.into())
             .primitive_restart_enable(false);
 
-        let extent = resourcemanager.get_swapchain().unwrap().extent();
-
         // Viewports and scissors
-        let viewports = [vk::Viewport {
-            x: 0.0,
-            y: 0.0,
-            width: extent.width as f32,
-            height: extent.height as f32,
-            min_depth: 0.0,
-            max_depth: 1.0,
-        }];
-
-        let scissors = [vk::Rect2D {
-            offset: vk::Offset2D { x: 0, y: 0 },
-            extent: vk::Extent2D {
-                width: extent.width,
-                height: extent.height,
-            },
-        }];
-
-        let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
-            .viewports(&viewports)
-            .scissors(&scissors);
+        // Both are set dynamically when recording so that the pipeline doesn't depend on the
+        // size of the render target
+        let viewport_state = vk::PipelineViewportStateCreateInfo {
+            viewport_count: 1,
+            scissor_count: 1,
+            ..Default::default()
+        };
 
         // Rasterizer
+        let depth_bias = spec.depth_bias.unwrap_or(DepthBias {
+            constant: 0.0,
+            slope: 0.0,
+            clamp: 0.0,
+        });
         let rasterizer = vk::PipelineRasterizationStateCreateInfo::builder()
             .depth_clamp_enable(false)
             .rasterizer_discard_enable(false)
-            .polygon_mode(vk::PolygonMode::FILL)
-            .cull_mode(vk::CullModeFlags::NONE)
-            .depth_bias_enable(false)
-            .front_face(vk::FrontFace::CLOCKWISE)
+            .polygon_mode(spec.polygon_mode.into())
+            .cull_mode(spec.cull_mode.into())
+            .front_face(spec.front_face.into())
             .line_width(1.0)
-            .depth_bias_enable(false)
-            .depth_bias_constant_factor(0.0)
-            .depth_bias_clamp(0.0)
-            .depth_bias_slope_factor(0.0);
+            .depth_bias_enable(spec.depth_bias.is_some())
+            .depth_bias_constant_factor(depth_bias.constant)
+            .depth_bias_clamp(depth_bias.clamp)
+            .depth_bias_slope_factor(depth_bias.slope);
 
         // Multisampling
         let multisampling = vk::PipelineMultisampleStateCreateInfo::builder()
@@ -146,26 +638,9 @@ impl Pipeline {
             .alpha_to_coverage_enable(false)
             .alpha_to_one_enable(false);
 
-        // Depth and stencil testing
-        // TODO
-
         // Color blending
-        let color_blend_attachment = vk::PipelineColorBlendAttachmentState::builder()
-            .color_write_mask(
-                vk::ColorComponentFlags::R
-                    | vk::ColorComponentFlags::G
-                    | vk::ColorComponentFlags::B
-                    | vk::ColorComponentFlags::A,
-            )
-            .blend_enable(false)
-            .src_color_blend_factor(vk::BlendFactor::ONE)
-            .dst_color_blend_factor(vk::BlendFactor::ZERO)
-            .src_alpha_blend_factor(vk::BlendFactor::ONE)
-            .dst_alpha_blend_factor(vk::BlendFactor::ZERO)
-            .alpha_blend_op(vk::BlendOp::ADD)
-            .build();
-
-        let color_blend_attachments = [color_blend_attachment];
+        let color_blend_attachments: Vec<vk::PipelineColorBlendAttachmentState> =
+            blend_modes.into_iter().map(|mode| mode.into()).collect();
         let color_blending = vk::PipelineColorBlendStateCreateInfo::builder()
             .logic_op_enable(false)
             .logic_op(vk::LogicOp::COPY)
@@ -174,50 +649,28 @@ impl Pipeline {
 
         let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo {
             s_type: vk::StructureType::PIPELINE_DEPTH_STENCIL_STATE_CREATE_INFO,
-            depth_test_enable: vk::TRUE,
-            depth_write_enable: vk::TRUE,
-            depth_compare_op: vk::CompareOp::LESS,
+            depth_test_enable: spec.depth.test as vk::Bool32,
+            depth_write_enable: spec.depth.write as vk::Bool32,
+            depth_compare_op: spec.depth.compare_op.into(),
             depth_bounds_test_enable: vk::FALSE,
             min_depth_bounds: 0.0,
             max_depth_bounds: 1.0,
-            stencil_test_enable: vk::FALSE,
-            front: Default::default(),
-            back: Default::default(),
+            stencil_test_enable: spec.stencil.is_some() as vk::Bool32,
+            front: spec
+                .stencil
+                .map_or(Default::default(), |stencil| stencil.front.into()),
+            back: spec
+                .stencil
+                .map_or(Default::default(), |stencil| stencil.back.into()),
             flags: Default::default(),
             p_next: std::ptr::null(),
         };
 
         // Dynamic state
-        // TODO
-        // let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::LINE_WIDTH];
-
-        // let dynamic_states =
-        //     vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
-
-        // Pipeline layout
-        let mut set_layouts = Vec::with_capacity(spec.layouts.len());
-
-        for layout_spec in &spec.layouts {
-            // TODO avoid clone
-            set_layouts.push(DescriptorSetLayout::new(device, layout_spec.clone())?)
-        }
-
-        let vk_set_layouts: Vec<vk::DescriptorSetLayout> =
-            set_layouts.iter().map(|layout| layout.vk()).collect();
-
-        let push_constants = spec
-            .push_constants
-            .iter()
-            .map(|v| v.to_vk())
-            .collect::<Vec<_>>();
-        let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
-            .set_layouts(&vk_set_layouts)
-            .push_constant_ranges(&push_constants);
-
-        let pipeline_layout =
-            unsafe { device.create_pipeline_layout(&pipeline_layout_info, None)? };
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
 
-        let renderpass = resourcemanager.load_renderpass(&spec.renderpass)?;
+        let dynamic_state =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
 
         let pipeline_info = vk::GraphicsPipelineCreateInfo::builder()
             .stages(&shader_stages)
@@ -228,7 +681,8 @@ impl Pipeline {
             .multisample_state(&multisampling)
             .color_blend_state(&color_blending)
             .depth_stencil_state(&depth_stencil_state)
-            .layout(pipeline_layout)
+            .dynamic_state(&dynamic_state)
+            .layout(base.layout())
             .render_pass(renderpass.vk())
             .subpass(0)
             .base_pipeline_handle(vk::Pipeline::null())
@@ -236,24 +690,24 @@ impl Pipeline {
             .build();
 
         let pipeline = unsafe {
-            device
-                .create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info], None)
-                .map_err(|e| Error::VulkanError(e.1))?[0]
+            device.create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info], None)
         };
 
         // Destroy shader modules
         unsafe {
             device.destroy_shader_module(vertex_shader_module, None);
-            device.destroy_shader_module(fragment_shader_module, None);
+            if let Some(module) = fragment_shader_module {
+                device.destroy_shader_module(module, None);
+            }
         }
 
-        Ok(Pipeline {
+        let pipeline = pipeline.map_err(|e| Error::VulkanError(e.1))?[0];
+
+        Ok(PipelineVariant {
             device: device.clone(),
-            layout: pipeline_layout,
-            set_layouts,
             pipeline,
-            renderpass,
-            spec,
+            base: Arc::clone(base),
+            renderpass: Arc::clone(renderpass),
         })
     }
 
@@ -261,39 +715,54 @@ impl Pipeline {
         self.pipeline
     }
 
+    /// Returns the layout shared with the base pipeline
     pub fn layout(&self) -> vk::PipelineLayout {
-        self.layout
+        self.base.layout()
     }
 
-    pub fn set_layouts(&self) -> &[DescriptorSetLayout] {
-        &self.set_layouts[..]
+    /// Returns the pipeline the variant was created from
+    pub fn base(&self) -> &Arc<Pipeline> {
+        &self.base
     }
 
-    /// Returns self created again from spec but with updated values
-    /// Called when swapchain is recreated
-    pub fn recreate(&self, resourcemanager: &super::ResourceManager) -> Result<Pipeline> {
-        Self::new(&self.device, self.spec.clone(), resourcemanager)
+    pub fn renderpass(&self) -> &Arc<RenderPass> {
+        &self.renderpass
     }
 }
 
-impl Drop for Pipeline {
+impl Drop for PipelineVariant {
     fn drop(&mut self) {
-        unsafe {
-            self.device.destroy_pipeline_layout(self.layout, None);
-            self.device.destroy_pipeline(self.pipeline, None);
-        };
+        unsafe { self.device.destroy_pipeline(self.pipeline, None) };
     }
 }
 
-fn create_shader_module(device: &ash::Device, filename: &str) -> Result<vk::ShaderModule> {
-    let mut file = fs::File::open(filename)?;
+/// Returns true if format has a stencil component
+fn has_stencil(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::S8_UINT
+            | vk::Format::D16_UNORM_S8_UINT
+            | vk::Format::D24_UNORM_S8_UINT
+            | vk::Format::D32_SFLOAT_S8_UINT
+    )
+}
 
-    let code = match ash::util::read_spv(&mut file) {
-        Ok(code) => code,
-        Err(e) => return Err(Error::SPVReadError(e, filename.to_owned())),
-    };
+/// Combines shader stages into vk::ShaderStageFlags bits
+fn stage_flags(stages: &[ShaderStage]) -> u32 {
+    stages.iter().fold(0, |acc, val| acc | ((*val) as u32))
+}
 
-    let create_info = vk::ShaderModuleCreateInfo::builder().code(&code);
+fn reflect_shader(filename: &str, code: &[u32]) -> Result<ShaderReflection> {
+    reflect::reflect(code).map_err(|e| match e {
+        Error::ShaderReflection(reason) => {
+            Error::ShaderReflection(format!("{}: {}", filename, reason))
+        }
+        e => e,
+    })
+}
+
+fn create_shader_module(device: &ash::Device, code: &[u32]) -> Result<vk::ShaderModule> {
+    let create_info = vk::ShaderModuleCreateInfo::builder().code(code);
 
     unsafe {
         device
@@ -301,3 +770,210 @@ fn create_shader_module(device: &ash::Device, filename: &str) -> Result<vk::Shad
             .map_err(|e| e.into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::vulkan::DescriptorType;
+    use reflect::ReflectedBinding;
+
+    fn spec() -> PipelineSpec {
+        serde_json::from_str(r#"{ "vertex_shader": "a.vert", "fragment_shader": "a.frag" }"#)
+            .unwrap()
+    }
+
+    fn binding(set: u32, binding: u32, ty: DescriptorType) -> ReflectedBinding {
+        ReflectedBinding {
+            set,
+            binding,
+            ty,
+            count: 1,
+        }
+    }
+
+    fn reflection(
+        stage: ShaderStage,
+        bindings: Vec<ReflectedBinding>,
+        push_constants: Option<(u32, u32)>,
+    ) -> ShaderReflection {
+        ShaderReflection {
+            stage,
+            bindings,
+            push_constants,
+            inputs: vec![],
+        }
+    }
+
+    /// A vertex stage using the globals, per draw data and 64 bytes of push constants and a
+    /// fragment stage using a material texture and 16 bytes of push constants after them
+    fn reflections() -> Vec<(&'static str, ShaderReflection)> {
+        vec![
+            (
+                "a.vert",
+                reflection(
+                    ShaderStage::Vertex,
+                    vec![
+                        binding(0, 0, DescriptorType::UniformBuffer),
+                        binding(2, 0, DescriptorType::UniformBuffer),
+                    ],
+                    Some((0, 64)),
+                ),
+            ),
+            (
+                "a.frag",
+                reflection(
+                    ShaderStage::Fragment,
+                    vec![
+                        binding(1, 0, DescriptorType::CombinedImageSampler),
+                        binding(2, 0, DescriptorType::UniformBuffer),
+                    ],
+                    Some((64, 16)),
+                ),
+            ),
+        ]
+    }
+
+    fn is_invalid(spec: &PipelineSpec) -> bool {
+        matches!(spec.validate(), Err(Error::InvalidPipelineSpec(_)))
+    }
+
+    fn is_mismatch(spec: &mut PipelineSpec, reflections: &[(&str, ShaderReflection)]) -> bool {
+        matches!(spec.resolve(reflections), Err(Error::ShaderMismatch(..)))
+    }
+
+    #[test]
+    fn defaults() {
+        let spec = spec();
+        assert!(spec.geometry_shader.is_empty());
+        assert!(spec.validate().is_ok());
+    }
+
+    #[test]
+    fn depth_write_requires_test() {
+        let mut spec = spec();
+        spec.depth.test = false;
+        assert!(is_invalid(&spec));
+
+        spec.depth.write = false;
+        assert!(spec.validate().is_ok());
+    }
+
+    #[test]
+    fn triangle_only_state() {
+        for topology in [PrimitiveTopology::PointList, PrimitiveTopology::LineList] {
+            let mut spec = spec();
+            spec.topology = topology;
+            assert!(spec.validate().is_ok());
+
+            spec.polygon_mode = PolygonMode::Line;
+            assert!(is_invalid(&spec));
+
+            spec.polygon_mode = PolygonMode::Fill;
+            spec.cull_mode = CullMode::Back;
+            assert!(is_invalid(&spec));
+        }
+
+        let mut spec = spec();
+        spec.polygon_mode = PolygonMode::Line;
+        spec.cull_mode = CullMode::Back;
+        assert!(spec.validate().is_ok());
+    }
+
+    #[test]
+    fn derive_merges_stages() {
+        let mut spec = spec();
+        spec.resolve(&reflections()).unwrap();
+
+        assert_eq!(spec.layouts.len(), 3);
+        assert!(spec.layouts[0] == uniformbuffer::global_layout_spec());
+        assert!(
+            spec.layouts[1].bindings
+                == [DescriptorSetLayoutBinding {
+                    slot: 0,
+                    ty: DescriptorType::CombinedImageSampler,
+                    count: 1,
+                    stages: vec![ShaderStage::Fragment],
+                }]
+        );
+        assert!(
+            spec.layouts[2].bindings
+                == [DescriptorSetLayoutBinding {
+                    slot: 0,
+                    ty: DescriptorType::UniformBuffer,
+                    count: 1,
+                    stages: vec![ShaderStage::Vertex, ShaderStage::Fragment],
+                }]
+        );
+
+        assert_eq!(spec.push_constants.len(), 1);
+        let range = &spec.push_constants[0];
+        assert_eq!((range.offset, range.size), (0, 80));
+        assert_eq!(range.stages, [ShaderStage::Vertex, ShaderStage::Fragment]);
+    }
+
+    #[test]
+    fn derive_rejects_conflicting_stages() {
+        let mut reflections = reflections();
+        reflections[1].1.bindings[1].ty = DescriptorType::CombinedImageSampler;
+        assert!(is_mismatch(&mut spec(), &reflections));
+    }
+
+    #[test]
+    fn declared_layouts_match_shaders() {
+        let mut derived = spec();
+        derived.resolve(&reflections()).unwrap();
+        assert!(derived.clone().resolve(&reflections()).is_ok());
+
+        // Type mismatch
+        let mut spec = derived.clone();
+        spec.layouts[1].bindings[0].ty = DescriptorType::UniformBuffer;
+        assert!(is_mismatch(&mut spec, &reflections()));
+
+        // Fewer descriptors than the shader uses
+        let mut more = reflections();
+        more[1].1.bindings[0].count = 4;
+        assert!(is_mismatch(&mut derived.clone(), &more));
+
+        // Missing binding
+        let mut spec = derived.clone();
+        spec.layouts[2].bindings.clear();
+        assert!(is_mismatch(&mut spec, &reflections()));
+
+        // Not visible to a stage using it
+        let mut spec = derived.clone();
+        spec.layouts[2].bindings[0].stages = vec![ShaderStage::Vertex];
+        assert!(is_mismatch(&mut spec, &reflections()));
+    }
+
+    #[test]
+    fn declared_push_constants_cover_shaders() {
+        let range = |offset, size, stages: &[ShaderStage]| PushConstantRange {
+            offset,
+            size,
+            stages: stages.to_vec(),
+        };
+        let both = [ShaderStage::Vertex, ShaderStage::Fragment];
+
+        let mut spec = spec();
+        spec.push_constants = vec![range(0, 80, &both)];
+        assert!(spec.resolve(&reflections()).is_ok());
+
+        // Too small for the fragment stage
+        let mut spec = self::spec();
+        spec.push_constants = vec![range(0, 72, &both)];
+        assert!(is_mismatch(&mut spec, &reflections()));
+
+        // Not visible to the fragment stage
+        let mut spec = self::spec();
+        spec.push_constants = vec![range(0, 80, &[ShaderStage::Vertex])];
+        assert!(is_mismatch(&mut spec, &reflections()));
+
+        // Separate ranges per stage
+        let mut spec = self::spec();
+        spec.push_constants = vec![
+            range(0, 64, &[ShaderStage::Vertex]),
+            range(64, 16, &[ShaderStage::Fragment]),
+        ];
+        assert!(spec.resolve(&reflections()).is_ok());
+    }
+}
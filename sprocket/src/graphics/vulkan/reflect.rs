@@ -0,0 +1,618 @@
+use super::descriptors::{DescriptorType, ShaderStage};
+use super::{Error, Result};
+use std::collections::HashMap;
+
+/// The first word of every SPIR-V module
+const MAGIC: u32 = 0x0723_0203;
+
+// Opcodes
+const OP_ENTRY_POINT: u32 = 15;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+// Decorations
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILTIN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+// Storage classes
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_INPUT: u32 = 1;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_PUSH_CONSTANT: u32 = 9;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+
+/// A descriptor used by a shader
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReflectedBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub ty: DescriptorType,
+    /// The number of descriptors in an arrayed binding, otherwise 1
+    pub count: u32,
+}
+
+/// The interface of a shader stage as declared in its SPIR-V
+#[derive(Clone, Debug)]
+pub struct ShaderReflection {
+    pub stage: ShaderStage,
+    pub bindings: Vec<ReflectedBinding>,
+    /// The offset and size in bytes of the push constant block if any
+    pub push_constants: Option<(u32, u32)>,
+    /// The locations of the attributes read by a vertex shader
+    pub inputs: Vec<u32>,
+}
+
+/// A type declared in the module
+#[derive(Clone)]
+enum Type {
+    Scalar {
+        width: u32,
+    },
+    Vector {
+        component: u32,
+        count: u32,
+    },
+    Matrix {
+        column: u32,
+        columns: u32,
+    },
+    Image,
+    Sampler,
+    SampledImage,
+    /// The length is None for runtime arrays
+    Array {
+        element: u32,
+        length: Option<u32>,
+    },
+    Struct {
+        members: Vec<u32>,
+    },
+    Pointer {
+        storage: u32,
+        pointee: u32,
+    },
+}
+
+/// The decorations of an id or struct member relevant to reflection
+#[derive(Default, Clone)]
+struct Decorations {
+    block: bool,
+    buffer_block: bool,
+    builtin: bool,
+    array_stride: Option<u32>,
+    matrix_stride: Option<u32>,
+    location: Option<u32>,
+    binding: Option<u32>,
+    set: Option<u32>,
+    offset: Option<u32>,
+}
+
+impl Decorations {
+    fn apply(&mut self, decoration: u32, literal: Option<u32>) {
+        match decoration {
+            DECORATION_BLOCK => self.block = true,
+            DECORATION_BUFFER_BLOCK => self.buffer_block = true,
+            DECORATION_BUILTIN => self.builtin = true,
+            DECORATION_ARRAY_STRIDE => self.array_stride = literal,
+            DECORATION_MATRIX_STRIDE => self.matrix_stride = literal,
+            DECORATION_LOCATION => self.location = literal,
+            DECORATION_BINDING => self.binding = literal,
+            DECORATION_DESCRIPTOR_SET => self.set = literal,
+            DECORATION_OFFSET => self.offset = literal,
+            _ => {}
+        }
+    }
+}
+
+/// The parsed declarations of a module
+#[derive(Default)]
+struct Module {
+    stage: Option<ShaderStage>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    decorations: HashMap<u32, Decorations>,
+    member_decorations: HashMap<(u32, u32), Decorations>,
+    /// The result type and storage class of each variable
+    variables: Vec<(u32, u32, u32)>,
+}
+
+/// Reflects the descriptors, push constants and vertex inputs of a SPIR-V module
+/// Only the first entry point is considered
+pub fn reflect(code: &[u32]) -> Result<ShaderReflection> {
+    let module = parse(code)?;
+    let stage = module
+        .stage
+        .ok_or_else(|| Error::ShaderReflection("missing entry point".to_owned()))?;
+
+    let mut reflection = ShaderReflection {
+        stage,
+        bindings: Vec::new(),
+        push_constants: None,
+        inputs: Vec::new(),
+    };
+
+    for (ty, id, storage) in &module.variables {
+        let decorations = module.decorations.get(id).cloned().unwrap_or_default();
+        let pointee = match module.types.get(ty) {
+            Some(Type::Pointer { pointee, .. }) => *pointee,
+            _ => {
+                return Err(Error::ShaderReflection(format!(
+                    "variable %{} is not a pointer",
+                    id
+                )))
+            }
+        };
+
+        match *storage {
+            STORAGE_UNIFORM_CONSTANT | STORAGE_UNIFORM | STORAGE_STORAGE_BUFFER => {
+                let (set, binding) = match (decorations.set, decorations.binding) {
+                    (Some(set), Some(binding)) => (set, binding),
+                    _ => continue,
+                };
+
+                // Arrays of descriptors
+                let (element, count) = match module.types.get(&pointee) {
+                    Some(Type::Array {
+                        element,
+                        length: Some(length),
+                    }) => (*element, *length),
+                    Some(Type::Array { length: None, .. }) => {
+                        return Err(Error::UnimplementedFeature("Runtime descriptor arrays"))
+                    }
+                    _ => (pointee, 1),
+                };
+
+                let block = module
+                    .decorations
+                    .get(&element)
+                    .cloned()
+                    .unwrap_or_default();
+                let ty = match (*storage, module.types.get(&element)) {
+                    (STORAGE_UNIFORM_CONSTANT, Some(Type::SampledImage)) => {
+                        DescriptorType::CombinedImageSampler
+                    }
+                    (STORAGE_UNIFORM, Some(Type::Struct { .. })) if block.block => {
+                        DescriptorType::UniformBuffer
+                    }
+                    (STORAGE_UNIFORM_CONSTANT, Some(Type::Image))
+                    | (STORAGE_UNIFORM_CONSTANT, Some(Type::Sampler)) => {
+                        return Err(Error::UnimplementedFeature("Separate images and samplers"))
+                    }
+                    _ => return Err(Error::UnimplementedFeature("Storage buffers")),
+                };
+
+                reflection.bindings.push(ReflectedBinding {
+                    set,
+                    binding,
+                    ty,
+                    count,
+                });
+            }
+            STORAGE_PUSH_CONSTANT => {
+                let members = match module.types.get(&pointee) {
+                    Some(Type::Struct { members }) => members.len() as u32,
+                    _ => continue,
+                };
+
+                let mut start = u32::MAX;
+                let mut end = 0;
+                for member in 0..members {
+                    let (offset, size) = module.member_range(pointee, member)?;
+                    start = start.min(offset);
+                    end = end.max(offset + size);
+                }
+
+                if members > 0 {
+                    reflection.push_constants = Some((start, end - start));
+                }
+            }
+            STORAGE_INPUT if stage == ShaderStage::Vertex && !decorations.builtin => {
+                if let Some(location) = decorations.location {
+                    reflection.inputs.push(location);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    reflection
+        .bindings
+        .sort_by_key(|binding| (binding.set, binding.binding));
+    reflection.inputs.sort_unstable();
+    Ok(reflection)
+}
+
+/// Returns the number of operands read from an instruction with opcode
+fn operand_count(opcode: u32) -> usize {
+    match opcode {
+        OP_ENTRY_POINT | OP_TYPE_IMAGE | OP_TYPE_SAMPLER | OP_TYPE_STRUCT => 1,
+        OP_TYPE_INT
+        | OP_TYPE_FLOAT
+        | OP_TYPE_SAMPLED_IMAGE
+        | OP_TYPE_RUNTIME_ARRAY
+        | OP_DECORATE => 2,
+        OP_TYPE_VECTOR | OP_TYPE_MATRIX | OP_TYPE_ARRAY | OP_TYPE_POINTER | OP_CONSTANT
+        | OP_VARIABLE | OP_MEMBER_DECORATE => 3,
+        _ => 0,
+    }
+}
+
+fn parse(code: &[u32]) -> Result<Module> {
+    if code.len() < 5 || code[0] != MAGIC {
+        return Err(Error::ShaderReflection("not a SPIR-V module".to_owned()));
+    }
+
+    let mut module = Module::default();
+    let mut index = 5;
+    while index < code.len() {
+        let word_count = (code[index] >> 16) as usize;
+        let opcode = code[index] & 0xffff;
+        if word_count == 0 || index + word_count > code.len() {
+            return Err(Error::ShaderReflection(format!(
+                "truncated instruction at word {}",
+                index
+            )));
+        }
+
+        let operands = &code[index + 1..index + word_count];
+        if operands.len() < operand_count(opcode) {
+            return Err(Error::ShaderReflection(format!(
+                "instruction {} at word {} has {} operands",
+                opcode,
+                index,
+                operands.len()
+            )));
+        }
+        index += word_count;
+
+        match opcode {
+            OP_ENTRY_POINT if module.stage.is_none() => {
+                module.stage = Some(match operands[0] {
+                    0 => ShaderStage::Vertex,
+                    1 => ShaderStage::TessellationControl,
+                    2 => ShaderStage::TessellationEvaluation,
+                    3 => ShaderStage::Geometry,
+                    4 => ShaderStage::Fragment,
+                    5 => ShaderStage::Compute,
+                    _ => return Err(Error::UnimplementedFeature("Execution model")),
+                });
+            }
+            OP_TYPE_INT | OP_TYPE_FLOAT => {
+                module
+                    .types
+                    .insert(operands[0], Type::Scalar { width: operands[1] });
+            }
+            OP_TYPE_VECTOR => {
+                module.types.insert(
+                    operands[0],
+                    Type::Vector {
+                        component: operands[1],
+                        count: operands[2],
+                    },
+                );
+            }
+            OP_TYPE_MATRIX => {
+                module.types.insert(
+                    operands[0],
+                    Type::Matrix {
+                        column: operands[1],
+                        columns: operands[2],
+                    },
+                );
+            }
+            OP_TYPE_IMAGE => {
+                module.types.insert(operands[0], Type::Image);
+            }
+            OP_TYPE_SAMPLER => {
+                module.types.insert(operands[0], Type::Sampler);
+            }
+            OP_TYPE_SAMPLED_IMAGE => {
+                module.types.insert(operands[0], Type::SampledImage);
+            }
+            OP_TYPE_ARRAY => {
+                let length = module.constants.get(&operands[2]).copied().ok_or_else(|| {
+                    Error::ShaderReflection(format!(
+                        "array length %{} is not a constant",
+                        operands[2]
+                    ))
+                })?;
+                module.types.insert(
+                    operands[0],
+                    Type::Array {
+                        element: operands[1],
+                        length: Some(length),
+                    },
+                );
+            }
+            OP_TYPE_RUNTIME_ARRAY => {
+                module.types.insert(
+                    operands[0],
+                    Type::Array {
+                        element: operands[1],
+                        length: None,
+                    },
+                );
+            }
+            OP_TYPE_STRUCT => {
+                module.types.insert(
+                    operands[0],
+                    Type::Struct {
+                        members: operands[1..].to_vec(),
+                    },
+                );
+            }
+            OP_TYPE_POINTER => {
+                module.types.insert(
+                    operands[0],
+                    Type::Pointer {
+                        storage: operands[1],
+                        pointee: operands[2],
+                    },
+                );
+            }
+            OP_CONSTANT => {
+                // Only the low word matters for array lengths
+                module.constants.insert(operands[1], operands[2]);
+            }
+            OP_VARIABLE => {
+                module
+                    .variables
+                    .push((operands[0], operands[1], operands[2]));
+            }
+            OP_DECORATE => {
+                module
+                    .decorations
+                    .entry(operands[0])
+                    .or_default()
+                    .apply(operands[1], operands.get(2).copied());
+            }
+            OP_MEMBER_DECORATE => {
+                module
+                    .member_decorations
+                    .entry((operands[0], operands[1]))
+                    .or_default()
+                    .apply(operands[2], operands.get(3).copied());
+            }
+            _ => {}
+        }
+    }
+
+    Ok(module)
+}
+
+impl Module {
+    /// Returns the offset and size in bytes of a member of an explicitly laid out struct
+    fn member_range(&self, structure: u32, member: u32) -> Result<(u32, u32)> {
+        let decorations = self
+            .member_decorations
+            .get(&(structure, member))
+            .cloned()
+            .unwrap_or_default();
+        let ty = match self.types.get(&structure) {
+            Some(Type::Struct { members }) => members[member as usize],
+            _ => {
+                return Err(Error::ShaderReflection(format!(
+                    "%{} is not a struct",
+                    structure
+                )))
+            }
+        };
+
+        let offset = decorations.offset.ok_or_else(|| {
+            Error::ShaderReflection(format!("member {} of %{} has no offset", member, structure))
+        })?;
+
+        let size = match self.types.get(&ty) {
+            Some(Type::Matrix { column, columns }) => match decorations.matrix_stride {
+                Some(stride) => stride * columns,
+                None => self.size_of(*column)? * columns,
+            },
+            _ => self.size_of(ty)?,
+        };
+
+        Ok((offset, size))
+    }
+
+    /// Returns the size in bytes of a type in an explicitly laid out block
+    fn size_of(&self, ty: u32) -> Result<u32> {
+        match self.types.get(&ty) {
+            Some(Type::Scalar { width }) => Ok(width / 8),
+            Some(Type::Vector { component, count }) => Ok(self.size_of(*component)? * count),
+            Some(Type::Matrix { column, columns }) => Ok(self.size_of(*column)? * columns),
+            Some(Type::Array {
+                element,
+                length: Some(length),
+            }) => {
+                let stride = self
+                    .decorations
+                    .get(&ty)
+                    .and_then(|decorations| decorations.array_stride);
+                match stride {
+                    Some(stride) => Ok(stride * length),
+                    None => Ok(self.size_of(*element)? * length),
+                }
+            }
+            Some(Type::Struct { members }) => {
+                let mut end = 0;
+                for member in 0..members.len() as u32 {
+                    let (offset, size) = self.member_range(ty, member)?;
+                    end = end.max(offset + size);
+                }
+                Ok(end)
+            }
+            _ => Err(Error::ShaderReflection(format!(
+                "the size of %{} is unknown",
+                ty
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodes an instruction with its word count
+    fn op(opcode: u32, operands: &[u32]) -> Vec<u32> {
+        let mut words = vec![((operands.len() as u32 + 1) << 16) | opcode];
+        words.extend_from_slice(operands);
+        words
+    }
+
+    /// Prefixes instructions with a module header
+    fn module(instructions: &[Vec<u32>]) -> Vec<u32> {
+        let mut words = vec![MAGIC, 0x0001_0000, 0, 64, 0];
+        instructions
+            .iter()
+            .for_each(|instruction| words.extend_from_slice(instruction));
+        words
+    }
+
+    /// The instructions of a vertex shader with a uniform buffer at set 0 binding 1, an array of
+    /// three samplers at set 1 binding 0, a push constant block of a mat4 and a uint, and inputs
+    /// at location 2 and 0
+    fn vertex_shader() -> Vec<Vec<u32>> {
+        // "main" followed by its terminator
+        let entry_point = op(OP_ENTRY_POINT, &[0, 30, 0x6e69_616d, 0]);
+        vec![
+            entry_point,
+            // Push constants
+            op(OP_DECORATE, &[5, DECORATION_BLOCK]),
+            op(OP_MEMBER_DECORATE, &[5, 0, DECORATION_OFFSET, 0]),
+            op(OP_MEMBER_DECORATE, &[5, 0, DECORATION_MATRIX_STRIDE, 16]),
+            op(OP_MEMBER_DECORATE, &[5, 1, DECORATION_OFFSET, 64]),
+            // Uniform buffer
+            op(OP_DECORATE, &[8, DECORATION_BLOCK]),
+            op(OP_MEMBER_DECORATE, &[8, 0, DECORATION_OFFSET, 0]),
+            op(OP_DECORATE, &[10, DECORATION_DESCRIPTOR_SET, 0]),
+            op(OP_DECORATE, &[10, DECORATION_BINDING, 1]),
+            // Samplers
+            op(OP_DECORATE, &[16, DECORATION_DESCRIPTOR_SET, 1]),
+            op(OP_DECORATE, &[16, DECORATION_BINDING, 0]),
+            // Inputs
+            op(OP_DECORATE, &[18, DECORATION_LOCATION, 2]),
+            op(OP_DECORATE, &[19, DECORATION_LOCATION, 0]),
+            op(OP_DECORATE, &[20, DECORATION_BUILTIN, 42]),
+            // Types
+            op(OP_TYPE_FLOAT, &[1, 32]),
+            op(OP_TYPE_VECTOR, &[2, 1, 4]),
+            op(OP_TYPE_MATRIX, &[3, 2, 4]),
+            op(OP_TYPE_INT, &[4, 32, 0]),
+            op(OP_TYPE_STRUCT, &[5, 3, 4]),
+            op(OP_TYPE_POINTER, &[6, STORAGE_PUSH_CONSTANT, 5]),
+            op(OP_VARIABLE, &[6, 7, STORAGE_PUSH_CONSTANT]),
+            op(OP_TYPE_STRUCT, &[8, 2]),
+            op(OP_TYPE_POINTER, &[9, STORAGE_UNIFORM, 8]),
+            op(OP_VARIABLE, &[9, 10, STORAGE_UNIFORM]),
+            op(OP_TYPE_IMAGE, &[11, 1, 1, 0, 0, 0, 1, 0]),
+            op(OP_TYPE_SAMPLED_IMAGE, &[12, 11]),
+            op(OP_CONSTANT, &[4, 13, 3]),
+            op(OP_TYPE_ARRAY, &[14, 12, 13]),
+            op(OP_TYPE_POINTER, &[15, STORAGE_UNIFORM_CONSTANT, 14]),
+            op(OP_VARIABLE, &[15, 16, STORAGE_UNIFORM_CONSTANT]),
+            op(OP_TYPE_POINTER, &[17, STORAGE_INPUT, 2]),
+            op(OP_VARIABLE, &[17, 18, STORAGE_INPUT]),
+            op(OP_VARIABLE, &[17, 19, STORAGE_INPUT]),
+            op(OP_VARIABLE, &[17, 20, STORAGE_INPUT]),
+        ]
+    }
+
+    fn assert_reflection_error(code: &[u32]) {
+        match reflect(code) {
+            Err(Error::ShaderReflection(_)) => {}
+            Err(e) => panic!("Unexpected error {}", e),
+            Ok(_) => panic!("Malformed module was reflected"),
+        }
+    }
+
+    #[test]
+    fn reflects_vertex_shader() {
+        let reflection = reflect(&module(&vertex_shader())).unwrap();
+        assert_eq!(reflection.stage, ShaderStage::Vertex);
+        assert_eq!(
+            reflection.bindings,
+            vec![
+                ReflectedBinding {
+                    set: 0,
+                    binding: 1,
+                    ty: DescriptorType::UniformBuffer,
+                    count: 1,
+                },
+                ReflectedBinding {
+                    set: 1,
+                    binding: 0,
+                    ty: DescriptorType::CombinedImageSampler,
+                    count: 3,
+                },
+            ]
+        );
+        assert_eq!(reflection.push_constants, Some((0, 68)));
+        assert_eq!(reflection.inputs, vec![0, 2]);
+    }
+
+    #[test]
+    fn rejects_invalid_header() {
+        assert_reflection_error(&[]);
+        assert_reflection_error(&[0, 0x0001_0000, 0, 64, 0]);
+    }
+
+    #[test]
+    fn rejects_truncated_module() {
+        let code = module(&vertex_shader());
+        // No prefix of the module panics, whether or not it ends on an instruction boundary
+        for len in 5..code.len() {
+            let _ = reflect(&code[..len]);
+        }
+
+        let mut instructions = vertex_shader();
+        instructions.push(vec![(4 << 16) | OP_TYPE_VECTOR, 40]);
+        assert_reflection_error(&module(&instructions));
+    }
+
+    #[test]
+    fn rejects_missing_operands() {
+        for opcode in &[
+            OP_ENTRY_POINT,
+            OP_TYPE_INT,
+            OP_TYPE_VECTOR,
+            OP_TYPE_MATRIX,
+            OP_TYPE_ARRAY,
+            OP_TYPE_POINTER,
+            OP_CONSTANT,
+            OP_VARIABLE,
+            OP_DECORATE,
+            OP_MEMBER_DECORATE,
+        ] {
+            // Each instruction is one operand short
+            let operands = vec![1; operand_count(*opcode) - 1];
+            assert_reflection_error(&module(&[op(*opcode, &operands)]));
+        }
+    }
+
+    #[test]
+    fn rejects_zero_word_count() {
+        let mut instructions = vertex_shader();
+        instructions.insert(1, vec![OP_TYPE_FLOAT]);
+        assert_reflection_error(&module(&instructions));
+    }
+}
@@ -1,19 +1,158 @@
 use super::VulkanContext;
 use super::*;
-use crate::graphics::vulkan;
+use crate::graphics::{
+    vulkan, Camera, CameraTarget, EnvironmentComponent, Extent2D, Image, Light, LightKind,
+    MaterialComponent, MeshComponent, SkinComponent,
+};
 use ecs::{ComponentArray, Entity};
+use enums::{AccessFlags, AttachmentLoadOp, AttachmentStoreOp, ImageLayout, PipelineStage};
 use math::Mat4;
-use physics::Transform;
+use physics::{world_transform, Parent, Transform};
+use rendergraph::{GraphPass, PassKind, RenderGraph, RenderGraphSpec};
+use shadow::{ShadowView, SHADOW_ATLAS, SHADOW_ATLAS_SIZE};
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 const MAX_FRAMES_IN_FLIGHT: usize = 2;
 
+/// The maximum number of cameras rendered each frame
+/// Each camera uses its own global uniform buffer per swapchain image
+/// Cameras past this in priority order are not rendered
+const MAX_CAMERAS: usize = 8;
+
+/// The maximum number of fullscreen passes in the render graph
+const MAX_FULLSCREEN_PASSES: usize = 8;
+
+/// The number of global uniform buffers per output image
+/// The cameras are followed by one slot for each view in the shadow atlas and one for each
+/// fullscreen pass
+const GLOBAL_SLOTS: usize = MAX_CAMERAS + MAX_SHADOW_VIEWS + MAX_FULLSCREEN_PASSES;
+
+/// The depth subtracted when comparing against the shadow atlas to avoid shadow acne
+const SHADOW_DEPTH_BIAS: f32 = 0.002;
+
+/// How far along the normal surfaces are moved in world units before sampling the shadow atlas
+const SHADOW_NORMAL_OFFSET: f32 = 0.02;
+
+/// The passes rendered each frame
+const RENDER_GRAPH: &str = "assets://rendergraphs/default.json";
+
+/// The name the renderpass of cameras rendering to textures is registered as
+const OFFSCREEN_RENDERPASS: &str = "sprocket::offscreen";
+
+/// The color format of the images rendered to when headless
+const HEADLESS_COLOR_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+/// The ambient light color written to the global uniforms
+const AMBIENT_COLOR: math::Vec4 = math::Vec4 {
+    x: 0.1,
+    y: 0.1,
+    z: 0.1,
+    w: 1.0,
+};
+
+/// The color the render targets are cleared to before any camera has rendered
+const CLEAR_COLOR: math::Vec4 = math::Vec4 {
+    x: 0.0,
+    y: 0.0,
+    z: 0.01,
+    w: 1.0,
+};
+
+/// The per draw push constant data
+/// The view and projection are read from the global uniforms
 struct EntityData {
-    mvp: Mat4,
+    model: Mat4,
+}
+
+/// The per draw push constant data of pipelines reading the Joints vertex attribute
+/// The vertices are placed in world space by the joints, so the model is left as identity
+#[repr(C)]
+struct SkinnedEntityData {
+    model: Mat4,
+    /// The index of the first joint of the entity in the joint uniforms
+    joint_offset: u32,
+}
+
+/// A mesh resolved from a MeshComponent
+enum ResolvedMesh {
+    /// The mesh at index in a shared model
+    Model(Arc<Model>, usize),
+    /// A mesh created from inline data and owned by a single entity
+    Owned(Mesh),
+}
+
+impl ResolvedMesh {
+    fn mesh(&self) -> &Mesh {
+        match self {
+            // The index is validated when resolved
+            ResolvedMesh::Model(model, index) => model.get_mesh_index(*index).unwrap(),
+            ResolvedMesh::Owned(mesh) => mesh,
+        }
+    }
+}
+
+/// The views rendered into the shadow atlas during a frame
+#[derive(Default)]
+struct Shadows {
+    views: Vec<ShadowView>,
+    /// The index of the first view and the number of views of each shadow casting light
+    lights: HashMap<Entity, (usize, usize)>,
+}
+
+/// The images the render graph outputs to
+enum Output {
+    /// Presented to a window
+    Swapchain(Box<Swapchain>),
+    /// Rendered offscreen with one image for each frame in flight
+    Headless(Vec<Texture>),
+}
+
+impl Output {
+    fn image_count(&self) -> usize {
+        match self {
+            Output::Swapchain(swapchain) => swapchain.image_count(),
+            Output::Headless(images) => images.len(),
+        }
+    }
+
+    fn extent(&self) -> Extent2D {
+        match self {
+            Output::Swapchain(swapchain) => swapchain.extent(),
+            Output::Headless(images) => images[0].extent(),
+        }
+    }
+
+    fn images(&self) -> Vec<&Texture> {
+        match self {
+            Output::Swapchain(swapchain) => (0..swapchain.image_count())
+                .map(|i| swapchain.image(i))
+                .collect(),
+            Output::Headless(images) => images.iter().collect(),
+        }
+    }
+
+    /// Returns the color image of image_index along with the layout it is left in after a frame
+    fn color(&self, image_index: u32) -> (&Texture, vk::ImageLayout) {
+        match self {
+            Output::Swapchain(swapchain) => (
+                swapchain.image(image_index as usize),
+                vk::ImageLayout::PRESENT_SRC_KHR,
+            ),
+            Output::Headless(images) => (
+                &images[image_index as usize],
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            ),
+        }
+    }
 }
 
 pub struct Renderer {
     context: Arc<VulkanContext>,
+    /// Renders into offscreen targets instead of a swapchain
+    headless: bool,
     resourcemanager: Arc<ResourceManager>,
     image_available_semaphores: Vec<vk::Semaphore>,
     render_finished_semaphores: Vec<vk::Semaphore>,
@@ -22,20 +161,69 @@ pub struct Renderer {
     current_frame: usize,
     data: Data,
     frame_count: usize,
+    /// The output image the last frame was rendered to
+    /// None if no frame has been rendered since the output was created
+    last_image: Option<u32>,
+    /// The time written to the global uniforms of the current frame
+    /// x: elapsed seconds, y: delta seconds, z: frame count
+    time: math::Vec4,
+    /// The transforms of entities relative to their parents
     entities: ComponentArray<Transform>,
+    parents: ComponentArray<Parent>,
+    /// The transforms of entities in world space, resolved from entities and parents each frame
+    world_transforms: HashMap<Entity, Transform>,
+    cameras: ComponentArray<Camera>,
+    lights: ComponentArray<Light>,
+    mesh_components: ComponentArray<MeshComponent>,
+    material_components: ComponentArray<MaterialComponent>,
+    environment_components: ComponentArray<EnvironmentComponent>,
+    skins: ComponentArray<SkinComponent>,
+    /// The index of the first joint of each skinned entity in the joint uniforms of the frame
+    /// Skinned entities without an offset are not drawn
+    joint_offsets: HashMap<Entity, u32>,
+    /// The meshes loaded from mesh_components
+    meshes: HashMap<Entity, ResolvedMesh>,
+    /// The materials loaded from material_components
+    materials: HashMap<Entity, Arc<Material>>,
+    /// The environment loaded from the first of environment_components
+    environment: Option<Arc<EnvironmentMap>>,
+    /// The pipeline variants that failed to be created so that they aren't retried every frame
+    failed_variants: RefCell<HashSet<PipelineVariantKey>>,
+    /// The materials of fullscreen passes that failed to load
+    failed_materials: RefCell<HashSet<String>>,
+    /// The shadow casting lights that didn't fit in the shadow atlas the last time shadows were
+    /// planned, so that the warning is logged once each time a light loses its shadows
+    unshadowed_lights: RefCell<HashSet<Entity>>,
+    /// The offscreen targets of cameras rendering to textures
+    render_targets: HashMap<Entity, RenderTarget>,
+    /// Graphics resources no longer used but that may still be in use by frames in flight
+    /// Stores the frame the resource was retired and drops it when no frame can use it
+    retired: Vec<(Box<dyn Any>, usize)>,
 }
 
 struct Data {
-    swapchain: Arc<Swapchain>,
+    output: Output,
     commandpool: CommandPool,
     commandbuffers: Vec<CommandBuffer>,
-    framebuffers: Vec<Framebuffer>,
-    material: Arc<Material>,
-    model: Arc<Model>,
+    /// One global uniform buffer for each camera slot for each output image
+    /// Indexed by global_index
     uniformbuffers: Vec<UniformBuffer>,
     descriptor_pool: DescriptorPool,
+    /// The descriptor sets at set 0 pointing to the uniformbuffers at the same index
     global_descriptors: Vec<DescriptorSet>,
-    renderpass: Arc<RenderPass>,
+    /// The joint uniforms of each output image, shared by all of its global descriptor sets
+    joint_buffers: Vec<UniformBuffer>,
+    /// The passes rendered to the output
+    graph: RenderGraph,
+    /// The renderpass of cameras rendering to textures
+    /// Leaves the color attachment ready to be sampled
+    offscreen_renderpass: Arc<RenderPass>,
+    shadow_sampler: Arc<Sampler>,
+    /// Bound to the global descriptors when there is no environment
+    empty_environment: Arc<EnvironmentMap>,
+    environment_sampler: Arc<Sampler>,
+    /// The environment the global descriptors of each output image sample
+    bound_environments: Vec<Arc<EnvironmentMap>>,
 }
 
 impl Renderer {
@@ -43,10 +231,140 @@ impl Renderer {
         self.entities.insert_component(entity, transform);
     }
 
+    /// Stops rendering an entity
+    pub fn remove_entity(&mut self, entity: Entity) {
+        self.entities.remove_component(entity);
+    }
+
+    /// Places the transform of entity relative to the transform of its parent
+    pub fn insert_parent(&mut self, entity: Entity, parent: Parent) {
+        self.parents.insert_component(entity, parent);
+    }
+
+    /// Places the transform of entity in world space
+    pub fn remove_parent(&mut self, entity: Entity) {
+        self.parents.remove_component(entity);
+    }
+
+    /// Deforms the mesh of entity by the world transforms of the joints of skin
+    /// The mesh is only drawn with pipelines reading the Joints and Weights vertex attributes
+    pub fn insert_skin(&mut self, entity: Entity, skin: SkinComponent) {
+        self.skins.insert_component(entity, skin);
+    }
+
+    /// Stops deforming the mesh of entity
+    pub fn remove_skin(&mut self, entity: Entity) {
+        self.skins.remove_component(entity);
+    }
+
+    /// Renders the scene from entity using camera
+    /// The view is taken from the transform of the same entity
+    pub fn insert_camera(&mut self, entity: Entity, camera: Camera) {
+        self.cameras.insert_component(entity, camera);
+    }
+
+    /// Stops rendering from a camera
+    pub fn remove_camera(&mut self, entity: Entity) {
+        self.cameras.remove_component(entity);
+        if let Some(target) = self.render_targets.remove(&entity) {
+            self.retire(target);
+        }
+    }
+
+    /// Lights the scene from entity
+    /// The position and direction are taken from the transform of the same entity
+    pub fn insert_light(&mut self, entity: Entity, light: Light) {
+        self.lights.insert_component(entity, light);
+    }
+
+    /// Stops lighting the scene from entity
+    pub fn remove_light(&mut self, entity: Entity) {
+        self.lights.remove_component(entity);
+        self.unshadowed_lights.borrow_mut().remove(&entity);
+    }
+
+    /// Sets the mesh an entity renders
+    /// The mesh is loaded the next time a frame is drawn
+    pub fn insert_mesh(&mut self, entity: Entity, mesh: MeshComponent) {
+        self.mesh_components.insert_component(entity, mesh);
+        if let Some(mesh) = self.meshes.remove(&entity) {
+            self.retire(mesh);
+        }
+    }
+
+    /// Removes the mesh of an entity and releases it
+    pub fn remove_mesh(&mut self, entity: Entity) {
+        self.mesh_components.remove_component(entity);
+        if let Some(mesh) = self.meshes.remove(&entity) {
+            self.retire(mesh);
+        }
+    }
+
+    /// Sets the material an entity renders with
+    /// The material is loaded the next time a frame is drawn
+    pub fn insert_material(&mut self, entity: Entity, material: MaterialComponent) {
+        self.material_components.insert_component(entity, material);
+        if let Some(material) = self.materials.remove(&entity) {
+            self.retire(material);
+        }
+    }
+
+    /// Removes the material of an entity and releases it
+    pub fn remove_material(&mut self, entity: Entity) {
+        self.material_components.remove_component(entity);
+        if let Some(material) = self.materials.remove(&entity) {
+            self.retire(material);
+        }
+    }
+
+    /// Lights the scene with an environment and draws its skybox
+    /// The environment is generated in the background and the first one inserted is used if
+    /// several entities have one
+    pub fn insert_environment(&mut self, entity: Entity, environment: EnvironmentComponent) {
+        self.environment_components
+            .insert_component(entity, environment);
+        if let Some(environment) = self.environment.take() {
+            self.retire(environment);
+        }
+    }
+
+    /// Removes the environment of an entity
+    pub fn remove_environment(&mut self, entity: Entity) {
+        self.environment_components.remove_component(entity);
+        if let Some(environment) = self.environment.take() {
+            self.retire(environment);
+        }
+    }
+
+    /// Creates a renderer presenting to window
+    /// Fails if the context is headless
     pub fn new(
         context: Arc<VulkanContext>,
         window: &Window,
         resourcemanager: Arc<ResourceManager>,
+    ) -> Result<Renderer> {
+        if context.is_headless() {
+            return Err(Error::MissingSurface);
+        }
+
+        Self::create(context, false, window.extent(), resourcemanager)
+    }
+
+    /// Creates a renderer drawing offscreen into targets of extent without presenting
+    /// Works with both headless and windowed contexts
+    pub fn new_headless(
+        context: Arc<VulkanContext>,
+        extent: Extent2D,
+        resourcemanager: Arc<ResourceManager>,
+    ) -> Result<Renderer> {
+        Self::create(context, true, extent, resourcemanager)
+    }
+
+    fn create(
+        context: Arc<VulkanContext>,
+        headless: bool,
+        extent: Extent2D,
+        resourcemanager: Arc<ResourceManager>,
     ) -> Result<Renderer> {
         let mut image_available_semaphores = Vec::new();
         let mut render_finished_semaphores = Vec::new();
@@ -60,14 +378,16 @@ impl Renderer {
             in_flight_fences.push(vulkan::create_fence(&context.device)?);
         }
 
-        let data = Self::create_data(&context, window, &resourcemanager)?;
+        let output = Self::create_output(&context, headless, extent, &resourcemanager, None)?;
+        let data = Self::create_data(&context, output, &resourcemanager)?;
 
-        for _ in 0..data.swapchain.image_count() {
+        for _ in 0..data.output.image_count() {
             images_in_flight.push(vk::Fence::null());
         }
 
         Ok(Renderer {
             context,
+            headless,
             image_available_semaphores,
             render_finished_semaphores,
             in_flight_fences,
@@ -75,117 +395,146 @@ impl Renderer {
             current_frame: 0,
             data,
             frame_count: 0,
+            last_image: None,
+            time: math::Vec4::zero(),
             resourcemanager,
-            entities: ComponentArray::new(),
+            entities: ComponentArray::untracked(),
+            parents: ComponentArray::untracked(),
+            world_transforms: HashMap::new(),
+            cameras: ComponentArray::untracked(),
+            lights: ComponentArray::untracked(),
+            mesh_components: ComponentArray::untracked(),
+            material_components: ComponentArray::untracked(),
+            environment_components: ComponentArray::untracked(),
+            skins: ComponentArray::untracked(),
+            joint_offsets: HashMap::new(),
+            meshes: HashMap::new(),
+            materials: HashMap::new(),
+            environment: None,
+            failed_variants: RefCell::new(HashSet::new()),
+            failed_materials: RefCell::new(HashSet::new()),
+            unshadowed_lights: RefCell::new(HashSet::new()),
+            render_targets: HashMap::new(),
+            retired: Vec::new(),
         })
     }
 
-    pub fn draw_frame(&mut self, window: &Window, _time: &Time) {
+    /// Renders a frame to the output
+    /// The output is recreated if it no longer matches extent
+    pub fn draw_frame(&mut self, extent: Extent2D, time: &Time) {
+        // Drop resources that are no longer used by any frame in flight
+        let frame_count = self.frame_count;
+        self.retired
+            .retain(|(_, retired)| frame_count - retired < MAX_FRAMES_IN_FLIGHT);
+
+        // Resolve the materials and models again so that entities render with the reloaded ones
+        // and the materials created while their textures were loading
+        let reloaded = self.resourcemanager.reload_changed();
+        let loaded = self.resourcemanager.update_async();
+        if reloaded || loaded {
+            let materials = std::mem::take(&mut self.materials);
+            self.retire(materials);
+            self.failed_materials.borrow_mut().clear();
+
+            let (models, owned) = std::mem::take(&mut self.meshes)
+                .into_iter()
+                .partition(|(_, mesh)| matches!(mesh, ResolvedMesh::Model(..)));
+            self.meshes = owned;
+            self.retire::<HashMap<Entity, ResolvedMesh>>(models);
+
+            if let Some(environment) = self.environment.take() {
+                self.retire(environment);
+            }
+        }
+
+        self.resolve_components();
+        self.resolve_world_transforms();
+        self.update_render_targets();
+        self.time = math::Vec4::new(
+            time.elapsed_f32(),
+            time.delta_f32(),
+            time.framecount() as f32,
+            0.0,
+        );
+
         let device = &self.context.device;
 
         vulkan::wait_for_fences(device, &[self.in_flight_fences[self.current_frame]], true);
 
-        // Update uniform buffer for this frame
-        let (image_index, suboptimal) = match self
-            .data
-            .swapchain
-            .acquire_next_image(&self.image_available_semaphores[self.current_frame])
-        {
-            Ok(v) => v,
-            Err(Error::VulkanError(vk::Result::ERROR_OUT_OF_DATE_KHR)) => {
-                self.recreate(window);
-                return;
+        let image_index = match &self.data.output {
+            Output::Swapchain(swapchain) => {
+                let (image_index, suboptimal) = match swapchain
+                    .acquire_next_image(&self.image_available_semaphores[self.current_frame])
+                {
+                    Ok(v) => v,
+                    Err(Error::VulkanError(vk::Result::ERROR_OUT_OF_DATE_KHR)) => {
+                        self.recreate(extent);
+                        return;
+                    }
+                    Err(e) => {
+                        error!("Failed to present to swapchain '{}'", e);
+                        return;
+                    }
+                };
+
+                if suboptimal {
+                    self.recreate(extent);
+                    return;
+                }
+                image_index
             }
-            Err(e) => {
-                error!("Failed to present to swapchain '{}'", e);
-                return;
+            Output::Headless(images) => {
+                if images[0].extent().width != extent.width
+                    || images[0].extent().height != extent.height
+                {
+                    self.recreate(extent);
+                    return;
+                }
+                // There is one image per frame in flight
+                self.current_frame as u32
             }
         };
 
-        if suboptimal {
-            self.recreate(window);
-            return;
+        // Check if a previous frame is using this image (i.e. there is its fence to wait on)
+        // Must be done before the commandbuffer and uniform buffers of the image are reused
+        if self.images_in_flight[image_index as usize] != vk::Fence::null() {
+            vulkan::wait_for_fences(device, &[self.images_in_flight[image_index as usize]], true)
         }
 
-        // Reset and record command buffers
-        let commandbuffer = &mut self.data.commandbuffers[image_index as usize];
-
-        iferr!("Failed to reset commandbuffers", commandbuffer.reset());
-        iferr!(
-            "Failed to begin recording command buffer",
-            commandbuffer.begin(Default::default())
-        );
-
-        commandbuffer.begin_renderpass(
-            &self.data.renderpass,
-            &self.data.framebuffers[image_index as usize],
-            math::Vec4::new(0.0, 0.0, 0.01, 1.0),
-        );
-        // TODO MaterialComponent and MeshComponent
-        let material = &self.data.material;
-        let mesh = self.data.model.get_mesh_index(0).unwrap();
-
-        // Iterate all entities
-        for transform in &mut self.entities.into_iter() {
-            commandbuffer.bind_material(
-                &material,
-                &self.data.global_descriptors[image_index as usize],
-                image_index,
-            );
-            let model = Mat4::translate(transform.position);
-            let view = Mat4::translate(Vec3::new(0.0, 0.0, -5.0)); // Camera
-            let proj = Mat4::perspective(window.aspect(), 1.0, 0.1, 10.0); // Camera
-
-            // proj: Mat4::ortho(window.aspect() as f32 * 2.0, 2 as f32, 0.0, 10.0),
-            let entity_data = EntityData {
-                mvp: model * view * proj,
-            };
+        self.bind_environment(image_index);
+        self.write_joints(image_index);
+        let device = &self.context.device;
 
-            commandbuffer.push_contants(
-                material.pipeline().layout(),
-                vk::ShaderStageFlags::VERTEX,
-                0,
-                &entity_data,
+        // Reset and record command buffers
+        {
+            let commandbuffer = &mut self.data.commandbuffers[image_index as usize];
+            iferr!("Failed to reset commandbuffers", commandbuffer.reset());
+            iferr!(
+                "Failed to begin recording command buffer",
+                commandbuffer.begin(Default::default())
             );
-
-            commandbuffer.bind_mesh(mesh);
-            commandbuffer.draw_indexed(mesh.index_count());
         }
 
-        commandbuffer.end_renderpass();
+        self.record_graph(image_index);
 
         iferr!(
-            "Failed to begin recording command buffer",
-            commandbuffer.end()
+            "Failed to end recording command buffer",
+            self.data.commandbuffers[image_index as usize].end()
         );
 
-        // let ub_data = UniformBufferObject {
-        //     model:
-        //     // Mat4::rotate_z(self.frame_count as f32 / 30.0),
-        //     Mat4::rotate_y(time.elapsed_f32())
-        //         * Mat4::translate(Vec3::new(0.0, 0.5 * time.elapsed_f32().sin(), 0.0))
-        //     * Mat4::translate(Vec3::new(0.0, 0.0, -5.0)),
-        //     view: Mat4::identity(),
-        //     proj: Mat4::perspective(window.aspect(), 1.0, 0.1, 10.0),
-        //     // proj: Mat4::ortho(window.aspect() as f32 * 2.0, 2 as f32, 0.0, 10.0),
-        // };
-
-        // iferr!(
-        //     "Failed to write to uniformbuffer",
-        // self.data.uniformbuffers[image_index as usize].write(&ub_data, None)
-        // );
-
-        // Check if a previous frame is using this image (i.e. there is its fence to wait on)
-        if self.images_in_flight[image_index as usize] != vk::Fence::null() {
-            vulkan::wait_for_fences(device, &[self.images_in_flight[image_index as usize]], true)
-        }
-
         self.images_in_flight[image_index as usize] = self.in_flight_fences[self.current_frame];
 
         // Submit the primary command buffer
-        let wait_semaphores = [self.image_available_semaphores[self.current_frame]];
-        let signal_semaphores = [self.render_finished_semaphores[self.current_frame]];
-        let wait_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
+        // Nothing is acquired or presented when headless
+        let (wait_semaphores, signal_semaphores, wait_stages) = if self.headless {
+            (vec![], vec![], vec![])
+        } else {
+            (
+                vec![self.image_available_semaphores[self.current_frame]],
+                vec![self.render_finished_semaphores[self.current_frame]],
+                vec![vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT],
+            )
+        };
 
         vulkan::reset_fences(device, &[self.in_flight_fences[self.current_frame]]);
 
@@ -202,33 +551,947 @@ impl Renderer {
             )
         );
 
+        self.last_image = Some(image_index);
+
         // Present it to the swapchain
-        let suboptimal = match self.data.swapchain.present(
-            image_index,
-            self.context.present_queue,
-            &signal_semaphores,
-        ) {
-            Ok(v) => v,
-            Err(Error::VulkanError(vk::Result::ERROR_OUT_OF_DATE_KHR)) => {
-                self.recreate(window);
+        if let Output::Swapchain(swapchain) = &self.data.output {
+            let suboptimal = match swapchain.present(
+                image_index,
+                self.context.present_queue.unwrap(),
+                &signal_semaphores,
+            ) {
+                Ok(v) => v,
+                Err(Error::VulkanError(vk::Result::ERROR_OUT_OF_DATE_KHR)) => {
+                    self.recreate(extent);
+                    return;
+                }
+                Err(e) => {
+                    error!("Failed to present to swapchain '{}'", e);
+                    return;
+                }
+            };
+
+            if suboptimal {
+                self.recreate(extent);
                 return;
             }
+        }
+
+        self.current_frame = (self.current_frame + 1) % MAX_FRAMES_IN_FLIGHT;
+        self.frame_count += 1;
+    }
+
+    /// Copies the color output of the last rendered frame back to host memory
+    /// Waits for the device to be idle
+    /// Works for both swapchain and headless output
+    pub fn capture_frame(&self) -> Result<Image> {
+        let image_index = self.last_image.ok_or(Error::MissingFrame)?;
+        let (texture, layout) = self.data.output.color(image_index);
+
+        // Swizzle BGRA formats to RGBA
+        let bgra = match texture.format() {
+            vk::Format::R8G8B8A8_SRGB | vk::Format::R8G8B8A8_UNORM => false,
+            vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM => true,
+            format => return Err(Error::UnsupportedFormat(format)),
+        };
+
+        unsafe { self.context.device.device_wait_idle()? };
+
+        let extent = texture.extent();
+        let size = extent.width as usize * extent.height as usize * 4;
+        let (buffer, memory, info) = buffer::create_readback(&self.context.allocator, size as u64)?;
+
+        let region = buffer::ImageRegion {
+            image: texture.image(),
+            layout,
+            extent,
+            aspect: vk::ImageAspectFlags::COLOR,
+        };
+        let result = buffer::copy_from_image(
+            &self.context.device,
+            self.context.graphics_queue,
+            self.context.generic_pool(),
+            &region,
+            buffer,
+        )
+        .and_then(|_| {
+            // The memory may not be host coherent
+            self.context
+                .allocator
+                .read()
+                .unwrap()
+                .invalidate_allocation(&memory, 0, size)?;
+
+            let mut pixels = vec![0; size];
+            unsafe {
+                std::ptr::copy_nonoverlapping(info.get_mapped_data(), pixels.as_mut_ptr(), size)
+            };
+            Ok(pixels)
+        });
+
+        // Free the readback buffer before propagating any errors
+        self.context
+            .allocator
+            .read()
+            .unwrap()
+            .destroy_buffer(buffer, &memory)?;
+        let mut pixels = result?;
+
+        if bgra {
+            pixels
+                .chunks_exact_mut(4)
+                .for_each(|pixel| pixel.swap(0, 2));
+        }
+
+        Ok(Image::new(extent.width, extent.height, pixels))
+    }
+
+    /// Keeps a resource alive until all frames in flight that may use it have finished
+    fn retire<T: 'static>(&mut self, resource: T) {
+        self.retired.push((Box::new(resource), self.frame_count));
+    }
+
+    /// Retires the resolved materials of all entities so that they are resolved again
+    fn unresolve_materials(&mut self) {
+        let materials: Vec<Arc<Material>> = self
+            .materials
+            .drain()
+            .map(|(_, material)| material)
+            .collect();
+        self.retire(materials);
+    }
+
+    /// Loads the meshes and materials of entities that haven't been resolved yet
+    /// Components that fail to load are logged and removed so that they aren't retried every frame
+    fn resolve_components(&mut self) {
+        let unresolved: Vec<Entity> = self
+            .mesh_components
+            .iter()
+            .map(|(entity, _)| entity)
+            .filter(|entity| !self.meshes.contains_key(entity))
+            .collect();
+
+        for entity in unresolved {
+            let component = self.mesh_components.get_component(entity).unwrap();
+            match self.resolve_mesh(component) {
+                Ok(Some(mesh)) => {
+                    self.meshes.insert(entity, mesh);
+                }
+                // The entity isn't drawn until the model has loaded
+                Ok(None) => {}
+                Err(e) => {
+                    error!("Failed to load mesh {:?} '{}'", component, e);
+                    self.mesh_components.remove_component(entity);
+                }
+            }
+        }
+
+        let unresolved: Vec<Entity> = self
+            .material_components
+            .iter()
+            .map(|(entity, _)| entity)
+            .filter(|entity| !self.materials.contains_key(entity))
+            .collect();
+
+        for entity in unresolved {
+            let component = self.material_components.get_component(entity).unwrap();
+            match self.resourcemanager.load_material(&component.path) {
+                Ok(material) => {
+                    self.materials.insert(entity, material);
+                }
+                Err(e) => {
+                    error!("Failed to load material '{}' '{}'", component.path, e);
+                    self.material_components.remove_component(entity);
+                }
+            }
+        }
+
+        let unresolved = self
+            .environment_components
+            .iter()
+            .next()
+            .filter(|_| self.environment.is_none())
+            .map(|(entity, component)| (entity, component.cubemap.clone()));
+
+        if let Some((entity, cubemap)) = unresolved {
+            let handle = self.resourcemanager.load_environment_async(&cubemap);
+            if let Some(reason) = handle.error() {
+                error!("Failed to load environment '{}' '{}'", cubemap, reason);
+                self.environment_components.remove_component(entity);
+            } else {
+                self.environment = handle.get();
+            }
+        }
+    }
+
+    /// Combines the transforms of entities with the transforms of their parents
+    fn resolve_world_transforms(&mut self) {
+        let entities = &self.entities;
+        let parents = &self.parents;
+        self.world_transforms = entities
+            .iter()
+            .filter_map(|(entity, _)| {
+                let transform = world_transform(
+                    entity,
+                    |entity| entities.get_component(entity),
+                    |entity| parents.get_component(entity).map(|parent| parent.entity),
+                )?;
+                Some((entity, transform))
+            })
+            .collect();
+    }
+
+    /// Writes the joints of the skinned entities into the joint uniforms of an output image
+    /// Skins that don't fit in MAX_JOINTS are left without an offset
+    /// No frame in flight may use the joint uniforms of the image
+    fn write_joints(&mut self, image_index: u32) {
+        self.joint_offsets.clear();
+        if self.skins.iter().next().is_none() {
+            return;
+        }
+
+        let mut uniforms = JointUniforms::default();
+        let mut count = 0;
+        for (entity, skin) in self.skins.iter() {
+            if count + skin.joints.len() > MAX_JOINTS {
+                continue;
+            }
+
+            for (i, joint) in skin.joints.iter().enumerate() {
+                let world = self
+                    .world_transforms
+                    .get(joint)
+                    .map_or(Mat4::identity(), |transform| transform.create_worldmatrix());
+                let inverse_bind = skin
+                    .inverse_bind_matrices
+                    .get(i)
+                    .copied()
+                    .unwrap_or_else(Mat4::identity);
+                uniforms.joints[count + i] = inverse_bind * world;
+            }
+
+            self.joint_offsets.insert(entity, count as u32);
+            count += skin.joints.len();
+        }
+
+        iferr!(
+            "Failed to write joint uniforms",
+            self.data.joint_buffers[image_index as usize].write(&uniforms, None)
+        );
+    }
+
+    /// Returns the environment component in use if any
+    fn environment_component(&self) -> Option<&EnvironmentComponent> {
+        self.environment_components
+            .iter()
+            .next()
+            .map(|(_, component)| component)
+    }
+
+    /// Points the global descriptors of an output image at the current environment unless they
+    /// already are
+    /// No frame in flight may use the descriptors of the image
+    fn bind_environment(&mut self, image_index: u32) {
+        let environment = self
+            .environment
+            .as_ref()
+            .unwrap_or(&self.data.empty_environment);
+        if Arc::ptr_eq(
+            &self.data.bound_environments[image_index as usize],
+            environment,
+        ) {
+            return;
+        }
+
+        let slots = global_index(image_index, 0)..global_index(image_index + 1, 0);
+        let image = image_index as usize;
+        iferr!(
+            "Failed to write global descriptors",
+            write_global_descriptors(
+                &self.context.device,
+                &self.data.global_descriptors[slots.clone()],
+                global_buffers(
+                    &self.data.uniformbuffers[slots],
+                    &self.data.joint_buffers[image..=image],
+                ),
+                self.data.graph.texture(SHADOW_ATLAS).unwrap(),
+                &self.data.shadow_sampler,
+                environment,
+                &self.data.environment_sampler,
+            )
+        );
+        self.data.bound_environments[image_index as usize] = Arc::clone(environment);
+    }
+
+    /// Returns the environment parameters of the global uniforms
+    /// x: intensity or 0 without an environment, y: the specular mip level of fully rough surfaces
+    fn environment_params(&self) -> math::Vec4 {
+        match (&self.environment, self.environment_component()) {
+            (Some(environment), Some(component)) => math::Vec4::new(
+                component.intensity,
+                environment.max_specular_level(),
+                0.0,
+                0.0,
+            ),
+            _ => math::Vec4::zero(),
+        }
+    }
+
+    /// Returns None if the model of the mesh is still loading in the background
+    fn resolve_mesh(&self, component: &MeshComponent) -> Result<Option<ResolvedMesh>> {
+        match component {
+            MeshComponent::Model { path, mesh } => {
+                let handle = self.resourcemanager.load_model_async(path);
+                if let Some(reason) = handle.error() {
+                    return Err(Error::AsyncLoad(path.to_owned(), reason));
+                }
+                let model = match handle.get() {
+                    Some(model) => model,
+                    None => return Ok(None),
+                };
+                let index = match mesh {
+                    Some(name) => model.mesh_index(name),
+                    None if model.mesh_count() > 0 => Some(0),
+                    None => None,
+                };
+
+                match index {
+                    Some(index) => Ok(Some(ResolvedMesh::Model(model, index))),
+                    None => Err(Error::MissingMesh(
+                        path.to_owned(),
+                        mesh.as_ref().map_or("0", |name| name).to_owned(),
+                    )),
+                }
+            }
+            MeshComponent::Data { vertices, indices } => Ok(Some(ResolvedMesh::Owned(Mesh::new(
+                &self.context.allocator,
+                &self.context.device,
+                self.context.graphics_queue,
+                self.context.generic_pool(),
+                vertices,
+                indices,
+            )?))),
+        }
+    }
+
+    /// Creates, resizes, and retires the offscreen targets of the cameras rendering to textures
+    /// New targets are registered in the resource manager under the name of the camera target
+    fn update_render_targets(&mut self) {
+        let frame_count = self.frame_count;
+        for (entity, camera) in self.cameras.iter() {
+            let (name, extent): (&str, Extent2D) = match &camera.target {
+                CameraTarget::Texture {
+                    name,
+                    width,
+                    height,
+                } => (name, (*width, *height).into()),
+                CameraTarget::Window => {
+                    // The camera may previously have rendered to a texture
+                    if let Some(target) = self.render_targets.remove(&entity) {
+                        self.retired.push((Box::new(target), frame_count));
+                    }
+                    continue;
+                }
+            };
+
+            if let Some(target) = self.render_targets.get(&entity) {
+                // The texture may have been replaced or collected from the resource manager
+                let registered = self
+                    .resourcemanager
+                    .get_texture(name)
+                    .is_some_and(|texture| Arc::ptr_eq(&texture, target.color()));
+                if target.extent().width == extent.width
+                    && target.extent().height == extent.height
+                    && registered
+                {
+                    continue;
+                }
+            }
+
+            let target = match RenderTarget::new(
+                &self.context.allocator,
+                &self.context.device,
+                &self.data.offscreen_renderpass,
+                self.resourcemanager.target_info().unwrap().color_format,
+                extent,
+            ) {
+                Ok(target) => target,
+                Err(e) => {
+                    error!("Failed to create render target for camera '{}'", e);
+                    continue;
+                }
+            };
+
+            self.resourcemanager
+                .insert_texture(name, Arc::clone(target.color()));
+
+            if let Some(old) = self.render_targets.insert(entity, target) {
+                self.retired.push((Box::new(old), frame_count));
+            }
+        }
+    }
+
+    /// Records the passes of the render graph into the commandbuffer of image_index
+    /// The cameras rendering to textures are recorded in their own renderpasses before the first
+    /// scene pass so that they can be sampled by it
+    /// The commandbuffer is expected to be recording
+    fn record_graph(&self, image_index: u32) {
+        let commandbuffer = &self.data.commandbuffers[image_index as usize];
+
+        let mut cameras: Vec<(Entity, &Camera)> = self.cameras.iter().collect();
+        cameras.sort_by_key(|(_, camera)| camera.priority);
+        // The slot of each camera is its index in the sorted list
+        cameras.truncate(MAX_CAMERAS);
+
+        // Directional shadows are fit to the window camera drawn on top
+        let main_camera = cameras
+            .iter()
+            .rev()
+            .find(|(_, camera)| camera.target == CameraTarget::Window);
+        let shadows = self.plan_shadows(main_camera);
+
+        let mut offscreen_recorded = false;
+        let mut fullscreen_slot = MAX_CAMERAS + MAX_SHADOW_VIEWS;
+
+        for pass in self.data.graph.passes() {
+            match pass.kind() {
+                PassKind::Shadows => {
+                    self.record_shadows(commandbuffer, pass, &shadows, image_index)
+                }
+                PassKind::Scene => {
+                    if !offscreen_recorded {
+                        self.record_offscreen_cameras(&cameras, image_index, &shadows);
+                        offscreen_recorded = true;
+                    }
+
+                    commandbuffer.begin_renderpass(
+                        pass.renderpass(),
+                        pass.framebuffer(image_index),
+                        CLEAR_COLOR,
+                    );
+
+                    for (slot, (entity, camera)) in cameras.iter().enumerate() {
+                        if camera.target == CameraTarget::Window {
+                            self.record_camera(
+                                *entity,
+                                pass.renderpass_name(),
+                                pass.extent(),
+                                image_index,
+                                slot,
+                                &shadows,
+                            );
+                        }
+                    }
+
+                    commandbuffer.end_renderpass();
+                }
+                PassKind::Fullscreen { material } => {
+                    self.record_fullscreen(
+                        commandbuffer,
+                        pass,
+                        material,
+                        image_index,
+                        fullscreen_slot,
+                    );
+                    fullscreen_slot += 1;
+                }
+            }
+        }
+    }
+
+    /// Records the cameras rendering to textures into their render targets
+    /// cameras is sorted by priority with the slot of each camera being its index
+    fn record_offscreen_cameras(
+        &self,
+        cameras: &[(Entity, &Camera)],
+        image_index: u32,
+        shadows: &Shadows,
+    ) {
+        let commandbuffer = &self.data.commandbuffers[image_index as usize];
+        for (slot, (entity, _)) in cameras.iter().enumerate() {
+            let target = match self.render_targets.get(entity) {
+                Some(target) => target,
+                None => continue,
+            };
+
+            commandbuffer.begin_renderpass(
+                &self.data.offscreen_renderpass,
+                target.framebuffer(),
+                CLEAR_COLOR,
+            );
+            self.record_camera(
+                *entity,
+                OFFSCREEN_RENDERPASS,
+                target.extent(),
+                image_index,
+                slot,
+                shadows,
+            );
+            commandbuffer.end_renderpass();
+        }
+    }
+
+    /// Draws a triangle covering the attachments of pass with the material at path
+    /// Writes the time and resolution of the pass into the global uniforms of slot
+    /// The attachments are still cleared if the material can't be used
+    fn record_fullscreen(
+        &self,
+        commandbuffer: &CommandBuffer,
+        pass: &GraphPass,
+        path: &str,
+        image_index: u32,
+        slot: usize,
+    ) {
+        commandbuffer.begin_renderpass(
+            pass.renderpass(),
+            pass.framebuffer(image_index),
+            CLEAR_COLOR,
+        );
+
+        if let Some(material) = self.fullscreen_material(path) {
+            let extent = pass.extent();
+            let globals = GlobalUniforms {
+                time: self.time,
+                resolution: math::Vec4::new(
+                    extent.width as f32,
+                    extent.height as f32,
+                    1.0 / extent.width as f32,
+                    1.0 / extent.height as f32,
+                ),
+                ambient: AMBIENT_COLOR,
+                ..Default::default()
+            };
+
+            let global_index = global_index(image_index, slot);
+            iferr!(
+                "Failed to write fullscreen global uniforms",
+                self.data.uniformbuffers[global_index].write(&globals, None)
+            );
+            let global_set = &self.data.global_descriptors[global_index];
+
+            if let Some(variant) = self.pipeline_variant(&material, pass.renderpass_name()) {
+                let rect = vk::Rect2D {
+                    offset: vk::Offset2D { x: 0, y: 0 },
+                    extent: extent.into(),
+                };
+                commandbuffer.set_viewport(rect);
+                commandbuffer.set_scissor(rect);
+                commandbuffer.bind_material(&material, &variant, global_set, image_index);
+                commandbuffer.draw();
+            }
+        }
+
+        commandbuffer.end_renderpass();
+    }
+
+    /// Returns the material of a fullscreen pass or skybox
+    /// Returns None and logs the error only the first time if the material can't be loaded
+    fn fullscreen_material(&self, path: &str) -> Option<Arc<Material>> {
+        if self.failed_materials.borrow().contains(path) {
+            return None;
+        }
+
+        match self.resourcemanager.load_material(path) {
+            Ok(material) => Some(material),
             Err(e) => {
-                error!("Failed to present to swapchain '{}'", e);
-                return;
+                error!("Failed to load material '{}' '{}'", path, e);
+                self.failed_materials.borrow_mut().insert(path.to_owned());
+                None
             }
+        }
+    }
+
+    /// Records the draw commands for all entities as seen from a camera
+    /// Clears and draws only to the camera's viewport on a target of extent
+    /// Cameras with a viewport entirely outside the target are skipped
+    /// Writes the global uniforms of the camera into the uniform buffer of slot
+    /// Expects renderpass to be active
+    fn record_camera(
+        &self,
+        camera_entity: Entity,
+        renderpass: &str,
+        extent: Extent2D,
+        image_index: u32,
+        slot: usize,
+        shadows: &Shadows,
+    ) {
+        let commandbuffer = &self.data.commandbuffers[image_index as usize];
+        let (camera, camera_transform) = match (
+            self.cameras.get_component(camera_entity),
+            self.world_transforms.get(&camera_entity),
+        ) {
+            (Some(camera), Some(transform)) => (camera, transform),
+            _ => return,
         };
 
-        if suboptimal {
-            self.recreate(window);
-            return;
+        // The viewport may extend past the target, the scissor and cleared area are clamped to it
+        let scissor = match camera.pixel_scissor(extent) {
+            Some((x, y, width, height)) => vk::Rect2D {
+                offset: vk::Offset2D { x, y },
+                extent: vk::Extent2D { width, height },
+            },
+            None => return,
+        };
+
+        let (x, y, width, height) = camera.pixel_viewport(extent);
+        let rect = vk::Rect2D {
+            offset: vk::Offset2D { x, y },
+            extent: vk::Extent2D { width, height },
+        };
+
+        commandbuffer.clear_attachments(scissor, camera.clear_color);
+
+        let view = camera_transform.create_viewmatrix();
+        let proj = camera.projection_matrix(camera.aspect(extent));
+        let position = camera_transform.position;
+
+        let mut globals = GlobalUniforms {
+            view,
+            proj,
+            viewproj: view * proj,
+            camera_pos: math::Vec4::new(position.x, position.y, position.z, 1.0),
+            time: self.time,
+            resolution: math::Vec4::new(
+                width as f32,
+                height as f32,
+                1.0 / width as f32,
+                1.0 / height as f32,
+            ),
+            ambient: AMBIENT_COLOR,
+            shadow_params: math::Vec4::new(
+                1.0 / SHADOW_ATLAS_SIZE as f32,
+                SHADOW_DEPTH_BIAS,
+                SHADOW_NORMAL_OFFSET,
+                0.0,
+            ),
+            environment: self.environment_params(),
+            ..Default::default()
+        };
+        self.gather_lights(position, shadows, &mut globals);
+
+        for (i, view) in shadows.views.iter().enumerate() {
+            globals.shadow_matrices[i] = view.view * view.proj;
+            globals.shadow_rects[i] = shadow::tile_rect(i);
         }
 
-        self.current_frame = (self.current_frame + 1) % MAX_FRAMES_IN_FLIGHT;
-        self.frame_count += 1;
+        let global_index = global_index(image_index, slot);
+        iferr!(
+            "Failed to write global uniforms",
+            self.data.uniformbuffers[global_index].write(&globals, None)
+        );
+        let global_set = &self.data.global_descriptors[global_index];
+
+        commandbuffer.set_viewport(rect);
+        commandbuffer.set_scissor(scissor);
+
+        self.record_entities(commandbuffer, renderpass, global_set, image_index);
+
+        // Cameras without a clear color draw over the previous cameras which already have a sky
+        if camera.clear_color.is_some() {
+            self.record_skybox(commandbuffer, renderpass, global_set, image_index);
+        }
     }
 
-    fn recreate(&mut self, window: &Window) {
+    /// Draws a triangle covering the viewport with the skybox material of the environment
+    /// The material is expected to draw at the far plane with a depth test so that only the
+    /// background is covered
+    /// Nothing is drawn until the textures of the material have loaded
+    fn record_skybox(
+        &self,
+        commandbuffer: &CommandBuffer,
+        renderpass: &str,
+        global_set: &DescriptorSet,
+        image_index: u32,
+    ) {
+        let path = match self
+            .environment_component()
+            .and_then(|component| component.skybox.as_ref())
+        {
+            Some(path) => path,
+            None => return,
+        };
+
+        let material = match self.fullscreen_material(path) {
+            Some(material) if material.is_complete() => material,
+            _ => return,
+        };
+
+        if let Some(variant) = self.pipeline_variant(&material, renderpass) {
+            commandbuffer.bind_material(&material, &variant, global_set, image_index);
+            commandbuffer.draw();
+        }
+    }
+
+    /// Draws all entities with both a mesh and a material using the variants of their pipelines
+    /// for renderpass
+    /// Entities whose pipeline can't be created for renderpass are skipped
+    /// Expects renderpass to be active with the viewport set
+    fn record_entities(
+        &self,
+        commandbuffer: &CommandBuffer,
+        renderpass: &str,
+        global_set: &DescriptorSet,
+        image_index: u32,
+    ) {
+        // Avoid rebinding when consecutive entities share material
+        let mut bound: Option<(&Arc<Material>, Option<Arc<PipelineVariant>>)> = None;
+
+        for (entity, _) in self.entities.iter() {
+            let (mesh, material, transform) = match (
+                self.meshes.get(&entity),
+                self.materials.get(&entity),
+                self.world_transforms.get(&entity),
+            ) {
+                (Some(mesh), Some(material), Some(transform)) => (mesh.mesh(), material, transform),
+                _ => continue,
+            };
+
+            if !bound
+                .as_ref()
+                .is_some_and(|(bound, _)| Arc::ptr_eq(bound, material))
+            {
+                let variant = self.pipeline_variant(material, renderpass);
+                if let Some(variant) = &variant {
+                    commandbuffer.bind_material(material, variant, global_set, image_index);
+                }
+                bound = Some((material, variant));
+            }
+
+            if !bound.as_ref().is_some_and(|(_, variant)| variant.is_some()) {
+                continue;
+            }
+
+            let pipeline = material.pipeline();
+            let skinned = pipeline
+                .spec()
+                .vertex_layout
+                .attributes
+                .contains(&VertexAttribute::Joints);
+
+            if skinned {
+                // Skinned meshes are only drawn once their joints are written
+                let joint_offset = match self.joint_offsets.get(&entity) {
+                    Some(joint_offset) => *joint_offset,
+                    None => continue,
+                };
+                let entity_data = SkinnedEntityData {
+                    model: Mat4::identity(),
+                    joint_offset,
+                };
+                commandbuffer.push_contants(
+                    pipeline.layout(),
+                    pipeline.push_constant_stages(),
+                    0,
+                    &entity_data,
+                );
+            } else {
+                let entity_data = EntityData {
+                    model: transform.create_worldmatrix(),
+                };
+                commandbuffer.push_contants(
+                    pipeline.layout(),
+                    pipeline.push_constant_stages(),
+                    0,
+                    &entity_data,
+                );
+            }
+
+            commandbuffer.bind_mesh(mesh);
+            commandbuffer.draw_indexed(mesh.index_count());
+        }
+    }
+
+    /// Returns the variant of the pipeline of material for renderpass
+    /// Returns None and logs the error only the first time if the variant can't be created
+    fn pipeline_variant(
+        &self,
+        material: &Material,
+        renderpass: &str,
+    ) -> Option<Arc<PipelineVariant>> {
+        let key = PipelineVariantKey {
+            pipeline: material.spec().pipeline().to_owned(),
+            renderpass: renderpass.to_owned(),
+            vertex_layout: material.pipeline().spec().vertex_layout.clone(),
+        };
+
+        if self.failed_variants.borrow().contains(&key) {
+            return None;
+        }
+
+        match self.resourcemanager.load_pipeline_variant(&key) {
+            Ok(variant) => Some(variant),
+            Err(e) => {
+                error!(
+                    "Failed to create pipeline '{}' for renderpass '{}' '{}'",
+                    key.pipeline, key.renderpass, e
+                );
+                self.failed_variants.borrow_mut().insert(key);
+                None
+            }
+        }
+    }
+
+    /// Returns the views of the shadow casting lights to render into the shadow atlas
+    /// Directional lights are fit to the view of main_camera and are left without shadows if there
+    /// is none
+    /// Lights are added in the same order as gather_lights until the atlas is full
+    fn plan_shadows(&self, main_camera: Option<&(Entity, &Camera)>) -> Shadows {
+        let camera = main_camera
+            .and_then(|(entity, camera)| Some((self.world_transforms.get(entity)?, *camera)));
+        let camera_pos = camera.map_or(math::Vec3::zero(), |(transform, _)| transform.position);
+
+        let mut casters: Vec<(f32, Entity, &Light, &Transform)> = self
+            .lights
+            .iter()
+            .filter(|(_, light)| light.casts_shadows)
+            .filter_map(|(entity, light)| {
+                let transform = self.world_transforms.get(&entity)?;
+                let distance = match light.range() {
+                    Some(range) => (transform.position - camera_pos).mag() - range,
+                    None => f32::NEG_INFINITY,
+                };
+                Some((distance, entity, light, transform))
+            })
+            .collect();
+        casters.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        let mut shadows = Shadows::default();
+        for (_, entity, light, transform) in casters {
+            let views = match light.kind {
+                LightKind::Directional => match camera {
+                    Some((camera_transform, camera)) => shadow::directional_cascades(
+                        transform,
+                        camera_transform,
+                        camera,
+                        camera.aspect(self.data.output.extent()),
+                    )
+                    .to_vec(),
+                    None => continue,
+                },
+                LightKind::Spot {
+                    range, outer_angle, ..
+                } => vec![shadow::spot_view(transform, range, outer_angle)],
+                LightKind::Point { range } => shadow::point_views(transform, range).to_vec(),
+            };
+
+            // Lights that don't fit in the atlas are left without shadows
+            if shadows.views.len() + views.len() > MAX_SHADOW_VIEWS {
+                if self.unshadowed_lights.borrow_mut().insert(entity) {
+                    warn!(
+                        "The light of {} casts no shadows as it needs {} tiles and {} of {} tiles in the shadow atlas are used",
+                        entity,
+                        views.len(),
+                        shadows.views.len(),
+                        MAX_SHADOW_VIEWS
+                    );
+                }
+                continue;
+            }
+            self.unshadowed_lights.borrow_mut().remove(&entity);
+
+            shadows
+                .lights
+                .insert(entity, (shadows.views.len(), views.len()));
+            shadows.views.extend(views);
+        }
+
+        shadows
+    }
+
+    /// Records the depth of all entities from each shadow view into its tile of the shadow atlas
+    /// rendered to by pass
+    /// Writes the global uniforms of each view into the slots following the cameras
+    /// The atlas is cleared even without any views so that it is always ready to be sampled
+    fn record_shadows(
+        &self,
+        commandbuffer: &CommandBuffer,
+        pass: &GraphPass,
+        shadows: &Shadows,
+        image_index: u32,
+    ) {
+        commandbuffer.begin_renderpass(
+            pass.renderpass(),
+            pass.framebuffer(image_index),
+            CLEAR_COLOR,
+        );
+
+        for (i, view) in shadows.views.iter().enumerate() {
+            let globals = GlobalUniforms {
+                view: view.view,
+                proj: view.proj,
+                viewproj: view.view * view.proj,
+                time: self.time,
+                ..Default::default()
+            };
+
+            let global_index = global_index(image_index, MAX_CAMERAS + i);
+            iferr!(
+                "Failed to write shadow global uniforms",
+                self.data.uniformbuffers[global_index].write(&globals, None)
+            );
+            let global_set = &self.data.global_descriptors[global_index];
+
+            let (x, y, size) = shadow::tile_viewport(i);
+            let rect = vk::Rect2D {
+                offset: vk::Offset2D { x, y },
+                extent: vk::Extent2D {
+                    width: size,
+                    height: size,
+                },
+            };
+            commandbuffer.set_viewport(rect);
+            commandbuffer.set_scissor(rect);
+
+            self.record_entities(
+                commandbuffer,
+                pass.renderpass_name(),
+                global_set,
+                image_index,
+            );
+        }
+
+        commandbuffer.end_renderpass();
+    }
+
+    /// Writes the lights affecting a camera at camera_pos into globals
+    /// If there are more than MAX_LIGHTS lights the directional lights are kept first, followed by
+    /// the lights closest to reaching the camera
+    fn gather_lights(
+        &self,
+        camera_pos: math::Vec3,
+        shadows: &Shadows,
+        globals: &mut GlobalUniforms,
+    ) {
+        let mut lights: Vec<(f32, LightData)> = self
+            .lights
+            .iter()
+            .filter_map(|(entity, light)| {
+                let transform = self.world_transforms.get(&entity)?;
+                let distance = match light.range() {
+                    Some(range) => (transform.position - camera_pos).mag() - range,
+                    None => f32::NEG_INFINITY,
+                };
+                let mut data = light.data(transform);
+                if let Some((first, count)) = shadows.lights.get(&entity) {
+                    data.params.z = *first as f32;
+                    data.params.w = *count as f32;
+                }
+                Some((distance, data))
+            })
+            .collect();
+
+        if lights.len() > MAX_LIGHTS {
+            lights.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+            lights.truncate(MAX_LIGHTS);
+        }
+
+        globals.light_count = lights.len() as u32;
+        for (dst, (_, light)) in globals.lights.iter_mut().zip(lights) {
+            *dst = light;
+        }
+    }
+
+    fn recreate(&mut self, extent: Extent2D) {
         info!("Recreating renderer");
         unsafe {
             iferr!(
@@ -237,79 +1500,168 @@ impl Renderer {
             );
         }
 
+        let previous = self.resourcemanager.target_info().unwrap();
+        let output = iferr!(
+            "Failed to recreate output",
+            Self::create_output(
+                &self.context,
+                self.headless,
+                extent,
+                &self.resourcemanager,
+                Some(&self.data.output),
+            )
+        );
+        let target_info = self.resourcemanager.target_info().unwrap();
+        self.last_image = None;
+
+        // When only the size changes the pipelines, renderpasses and descriptors are kept and only
+        // the output and the graph resources sized by it are created again
+        // The shadow atlas is bound to the global descriptors and must keep its size
+        if target_info.color_format == previous.color_format
+            && target_info.depth_format == previous.depth_format
+            && target_info.image_count == previous.image_count
+            && target_info.presentable == previous.presentable
+            && !self.data.graph.is_output_sized(SHADOW_ATLAS)
+        {
+            self.data.output = output;
+            let replaced = iferr!(
+                "Failed to resize render graph",
+                self.data
+                    .graph
+                    .resize(&self.resourcemanager, &self.data.output.images())
+            );
+
+            // Materials sampling the replaced graph resources are resolved again
+            if replaced {
+                iferr!(
+                    "Failed to recreate materials",
+                    self.resourcemanager.recreate_materials()
+                );
+                self.unresolve_materials();
+            }
+            return;
+        }
+
+        // The render graph is rebuilt first so that the materials are recreated for the new
+        // output and with the new graph resources
+        self.data = iferr!(
+            "Failed to recreate renderer",
+            Self::create_data(&self.context, output, &self.resourcemanager)
+        );
+
         log::info!("Recreating resource manager");
         match self.resourcemanager.recreate() {
             Ok(_) => {}
             Err(e) => log::error!("Failed to recreate resource manager: {}", e),
         };
 
-        self.data = iferr!(
-            "Failed to recreate renderer",
-            Self::create_data(&self.context, window, &self.resourcemanager)
-        );
+        // The materials have been replaced in the resource manager and are resolved again
+        self.unresolve_materials();
+        self.failed_variants.borrow_mut().clear();
+        self.failed_materials.borrow_mut().clear();
+
+        // The image count may have changed
+        self.images_in_flight = vec![vk::Fence::null(); self.data.output.image_count()];
     }
 
+    /// Creates the renderer resources rendering to output
+    /// The target info of resourcemanager is expected to describe output
     fn create_data(
         context: &Arc<VulkanContext>,
-        window: &Window,
+        output: Output,
         resourcemanager: &Arc<ResourceManager>,
     ) -> Result<Data> {
-        let swapchain = Arc::new(Swapchain::new(
-            &context.instance,
-            &context.physical_device,
-            &context.device,
-            &context.allocator,
-            &context.surface_loader,
-            &context.surface,
-            &context.queue_families,
-            window.extent(),
-        )?);
+        let image_count = output.image_count();
+        let target_info = resourcemanager.target_info().unwrap();
 
-        resourcemanager.set_swapchain(Arc::clone(&swapchain));
+        // The graph registers its renderpasses so that pipeline variants can be created for them
+        // by name
+        let graph_spec = RenderGraphSpec::load(RENDER_GRAPH)?;
+        let graph = RenderGraph::new(resourcemanager, &graph_spec, &output.images())?;
+
+        let fullscreen_passes = graph
+            .passes()
+            .iter()
+            .filter(|pass| matches!(pass.kind(), PassKind::Fullscreen { .. }))
+            .count();
+        if fullscreen_passes > MAX_FULLSCREEN_PASSES {
+            return Err(Error::InvalidRenderGraph(format!(
+                "more than {} fullscreen passes",
+                MAX_FULLSCREEN_PASSES
+            )));
+        }
+
+        let shadow_atlas = graph
+            .texture(SHADOW_ATLAS)
+            .ok_or_else(|| Error::InvalidRenderGraph(format!("missing '{}'", SHADOW_ATLAS)))?;
+        let atlas_extent = shadow_atlas.extent();
+        if atlas_extent.width != SHADOW_ATLAS_SIZE || atlas_extent.height != SHADOW_ATLAS_SIZE {
+            return Err(Error::InvalidRenderGraph(format!(
+                "'{}' must be {} pixels wide and high",
+                SHADOW_ATLAS, SHADOW_ATLAS_SIZE
+            )));
+        }
+
+        let shadow_sampler = resourcemanager.sampler(&SamplerSpec::shadow())?;
+        let environment_sampler = resourcemanager.sampler(&SamplerSpec::default())?;
+        let empty_environment = Arc::new(EnvironmentMap::empty(context)?);
 
-        let global_descriptor_layout_spec = DescriptorSetLayoutSpec {
-            bindings: vec![DescriptorSetLayoutBinding {
-                slot: 0,
-                ty: DescriptorType::UniformBuffer,
-                count: 1,
-                stages: vec![ShaderStage::Vertex],
-            }],
-        };
         let global_descriptor_layout =
-            DescriptorSetLayout::new(&context.device, global_descriptor_layout_spec)?;
-        let mut uniformbuffers = Vec::new();
-        for _ in 0..swapchain.image_count() {
+            DescriptorSetLayout::new(&context.device, uniformbuffer::global_layout_spec())?;
+        let global_count = image_count * GLOBAL_SLOTS;
+        let mut uniformbuffers = Vec::with_capacity(global_count);
+        for _ in 0..global_count {
             uniformbuffers.push(UniformBuffer::new(
                 &context.allocator,
-                std::mem::size_of::<UniformBufferObject>() as u64,
+                std::mem::size_of::<GlobalUniforms>() as u64,
             )?);
         }
 
+        let joint_buffers = (0..image_count)
+            .map(|_| {
+                UniformBuffer::new(
+                    &context.allocator,
+                    std::mem::size_of::<JointUniforms>() as u64,
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+
         let descriptor_pool = DescriptorPool::new(
             &context.device,
-            &[vk::DescriptorPoolSize {
-                descriptor_count: swapchain.image_count() as u32,
-                ty: vk::DescriptorType::UNIFORM_BUFFER,
-            }],
-            swapchain.image_count() as u32,
+            &[
+                // The global uniforms and the joint uniforms
+                vk::DescriptorPoolSize {
+                    descriptor_count: global_count as u32 * 2,
+                    ty: vk::DescriptorType::UNIFORM_BUFFER,
+                },
+                // The shadow atlas and the two environment maps
+                vk::DescriptorPoolSize {
+                    descriptor_count: global_count as u32 * 3,
+                    ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                },
+            ],
+            global_count as u32,
         )?;
 
-        // Create descriptor set for mvp data
+        // Create descriptor set for global data
         let global_descriptors = DescriptorSet::new(
             &context.device,
             &descriptor_pool,
             &global_descriptor_layout,
-            swapchain.image_count() as u32,
+            global_count as u32,
         )?;
 
         // Write global descriptors
-        DescriptorSet::write(
+        // Every slot samples the same shadow atlas as the graph orders its writes after the reads of
+        // previous frames
+        write_global_descriptors(
             &context.device,
             &global_descriptors,
-            &global_descriptor_layout.spec(),
-            uniformbuffers.iter(),
-            [].iter(),
-            [].iter(),
+            global_buffers(&uniformbuffers, &joint_buffers),
+            shadow_atlas,
+            &shadow_sampler,
+            &empty_environment,
+            &environment_sampler,
         )?;
 
         let commandpool = CommandPool::new(
@@ -319,53 +1671,203 @@ impl Renderer {
             true,
         )?;
 
-        let material = resourcemanager.load_material("./data/materials/default.json")?;
-        let renderpass = resourcemanager.load_renderpass("./data/renderpasses/default.json")?;
-
-        let mut framebuffers = Vec::with_capacity(swapchain.image_count());
-        for i in 0..swapchain.image_count() {
-            framebuffers.push(Framebuffer::new(
-                &context.device,
-                &[swapchain.image(i), swapchain.depth_image()],
-                &renderpass,
-                swapchain.extent(),
-            )?)
-        }
-
-        let mut commandbuffers =
-            CommandBuffer::new_primary(&context.device, &commandpool, swapchain.image_count())?;
+        let commandbuffers =
+            CommandBuffer::new_primary(&context.device, &commandpool, image_count)?;
 
-        let model = resourcemanager.load_model("./data/models/suzanne.dae")?;
-
-        let mesh = model.get_mesh_index(0).unwrap();
-
-        for (i, commandbuffer) in commandbuffers.iter_mut().enumerate() {
-            commandbuffer.begin(Default::default())?;
-            commandbuffer.begin_renderpass(
-                &renderpass,
-                &framebuffers[i],
-                math::Vec4::new(0.0, 0.0, 0.01, 1.0),
-            );
-            commandbuffer.bind_material(&material, &global_descriptors[i], i as u32);
-            commandbuffer.bind_mesh(mesh);
-            commandbuffer.draw_indexed(mesh.index_count());
-            commandbuffer.end_renderpass();
-            commandbuffer.end()?;
-        }
+        let offscreen_renderpass = Arc::new(RenderPass::new(
+            &context.device,
+            offscreen_renderpass_spec(),
+            target_info.color_format,
+            target_info.depth_format,
+        )?);
+        resourcemanager.insert_renderpass(OFFSCREEN_RENDERPASS, Arc::clone(&offscreen_renderpass));
 
         Ok(Data {
-            swapchain,
+            output,
             commandpool,
             commandbuffers,
-            framebuffers,
-            material,
-            model,
             uniformbuffers,
             descriptor_pool,
             global_descriptors,
-            renderpass,
+            joint_buffers,
+            graph,
+            offscreen_renderpass,
+            shadow_sampler,
+            bound_environments: vec![Arc::clone(&empty_environment); image_count],
+            empty_environment,
+            environment_sampler,
         })
     }
+
+    /// Creates the images of extent rendered to and sets the target info of resourcemanager to
+    /// describe them
+    /// The swapchain of old is handed over to the new swapchain
+    fn create_output(
+        context: &Arc<VulkanContext>,
+        headless: bool,
+        extent: Extent2D,
+        resourcemanager: &Arc<ResourceManager>,
+        old: Option<&Output>,
+    ) -> Result<Output> {
+        if headless {
+            return Self::create_headless_output(context, extent, resourcemanager);
+        }
+
+        let old_swapchain = match old {
+            Some(Output::Swapchain(swapchain)) => Some(swapchain.as_ref()),
+            _ => None,
+        };
+        Self::create_swapchain_output(context, extent, resourcemanager, old_swapchain)
+    }
+
+    /// Creates a swapchain presenting to the surface of the context
+    fn create_swapchain_output(
+        context: &Arc<VulkanContext>,
+        extent: Extent2D,
+        resourcemanager: &Arc<ResourceManager>,
+        old_swapchain: Option<&Swapchain>,
+    ) -> Result<Output> {
+        let surface = context.surface.as_ref().ok_or(Error::MissingSurface)?;
+        let swapchain = Box::new(Swapchain::new(
+            &context.instance,
+            &context.physical_device,
+            &context.device,
+            &context.allocator,
+            &context.surface_loader,
+            surface,
+            &context.queue_families,
+            extent,
+            old_swapchain,
+        )?);
+
+        resourcemanager.set_target_info(swapchain.target_info());
+
+        Ok(Output::Swapchain(swapchain))
+    }
+
+    /// Creates one offscreen image of extent for each frame in flight
+    fn create_headless_output(
+        context: &Arc<VulkanContext>,
+        extent: Extent2D,
+        resourcemanager: &Arc<ResourceManager>,
+    ) -> Result<Output> {
+        resourcemanager.set_target_info(TargetInfo {
+            color_format: HEADLESS_COLOR_FORMAT,
+            depth_format: Texture::DEPTH_FORMAT,
+            image_count: MAX_FRAMES_IN_FLIGHT,
+            extent,
+            presentable: false,
+        });
+
+        let images = (0..MAX_FRAMES_IN_FLIGHT)
+            .map(|_| {
+                Texture::new_color_attachment(
+                    &context.allocator,
+                    &context.device,
+                    HEADLESS_COLOR_FORMAT,
+                    extent,
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Output::Headless(images))
+    }
+}
+
+/// Returns the index of the global uniform buffer and descriptor set for a slot
+/// Slots below MAX_CAMERAS belong to cameras, followed by the shadow views and fullscreen passes
+fn global_index(image_index: u32, slot: usize) -> usize {
+    image_index as usize * GLOBAL_SLOTS + slot
+}
+
+/// Returns the uniform buffers bound by each global descriptor set in order of their bindings
+/// Each global uniform buffer is followed by the joint uniforms of its output image
+fn global_buffers<'a>(
+    uniformbuffers: &'a [UniformBuffer],
+    joint_buffers: &'a [UniformBuffer],
+) -> impl Iterator<Item = &'a UniformBuffer> {
+    uniformbuffers
+        .chunks(GLOBAL_SLOTS)
+        .zip(joint_buffers)
+        .flat_map(|(globals, joints)| globals.iter().flat_map(move |globals| [globals, joints]))
+}
+
+/// Writes global descriptor sets to read uniformbuffers as returned by global_buffers, the
+/// shadow atlas and the cube maps of environment
+fn write_global_descriptors<'a>(
+    device: &ash::Device,
+    sets: &[DescriptorSet],
+    uniformbuffers: impl Iterator<Item = &'a UniformBuffer>,
+    shadow_atlas: &'a Arc<Texture>,
+    shadow_sampler: &'a Arc<Sampler>,
+    environment: &'a EnvironmentMap,
+    environment_sampler: &'a Arc<Sampler>,
+) -> Result<()> {
+    let textures = [
+        shadow_atlas,
+        environment.irradiance(),
+        environment.specular(),
+    ];
+    let samplers = [shadow_sampler, environment_sampler, environment_sampler];
+    DescriptorSet::write(
+        device,
+        sets,
+        &uniformbuffer::global_layout_spec(),
+        uniformbuffers,
+        textures.iter().copied().cycle(),
+        samplers.iter().copied().cycle(),
+    )
+}
+
+/// Creates the spec of the renderpass of cameras rendering to textures
+/// Leaves the color attachment in a layout that can be sampled by later renderpasses
+fn offscreen_renderpass_spec() -> RenderPassSpec {
+    RenderPassSpec {
+        subpasses: vec![Subpass {
+            color_attachments: vec![0],
+            depth_attachment: Some(1),
+        }],
+        dependencies: vec![
+            // Wait for previous frames to finish sampling before writing
+            SubpassDependency {
+                src_subpass: SubpassIndex::External,
+                dst_subpass: SubpassIndex::Internal(0),
+                src_stage: PipelineStage::FragmentShader,
+                dst_stage: PipelineStage::ColorAttachmentOutput,
+                src_access: AccessFlags::ShaderRead,
+                dst_access: AccessFlags::ColorAttachmentWrite,
+            },
+            // Make the writes visible to later sampling
+            SubpassDependency {
+                src_subpass: SubpassIndex::Internal(0),
+                dst_subpass: SubpassIndex::External,
+                src_stage: PipelineStage::ColorAttachmentOutput,
+                dst_stage: PipelineStage::FragmentShader,
+                src_access: AccessFlags::ColorAttachmentWrite,
+                dst_access: AccessFlags::ShaderRead,
+            },
+        ],
+        attachments: vec![
+            Attachment {
+                store_op: AttachmentStoreOp::Store,
+                load_op: AttachmentLoadOp::Clear,
+                initial_layout: ImageLayout::Undefined,
+                final_layout: ImageLayout::ShaderReadOnly,
+                layout: ImageLayout::ColorAttachment,
+                sample_count: 1,
+                format: ImageFormat::Color,
+            },
+            Attachment {
+                store_op: AttachmentStoreOp::DontCare,
+                load_op: AttachmentLoadOp::Clear,
+                initial_layout: ImageLayout::Undefined,
+                final_layout: ImageLayout::DepthStencilAttachment,
+                layout: ImageLayout::DepthStencilAttachment,
+                sample_count: 1,
+                format: ImageFormat::Depth,
+            },
+        ],
+    }
 }
 
 impl Drop for Renderer {
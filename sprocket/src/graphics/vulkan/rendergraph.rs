@@ -0,0 +1,921 @@
+use super::enums::{AccessFlags, AttachmentLoadOp, AttachmentStoreOp, ImageLayout, PipelineStage};
+use super::{
+    Attachment, Error, Framebuffer, ImageFormat, RenderPass, RenderPassSpec, ResourceManager,
+    Result, Subpass, SubpassDependency, SubpassIndex, Texture,
+};
+use crate::graphics::Extent2D;
+use crate::vfs;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// The name of the resource imported from the renderer that is presented or captured
+/// Every graph must have a pass writing to it
+pub const OUTPUT: &str = "output";
+
+/// The prefix of the names the renderpasses of the passes are registered as in the resource manager
+const RENDERPASS_PREFIX: &str = "sprocket::graph::";
+
+/// The size of a resource in the render graph
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+pub enum GraphSize {
+    /// The same size as the output
+    #[default]
+    Output,
+    /// The size of the output multiplied by a factor
+    Scaled(f32),
+    /// A size in pixels independent of the output
+    Fixed { width: u32, height: u32 },
+}
+
+impl GraphSize {
+    /// Returns the size in pixels when rendering to an output of extent
+    pub fn extent(&self, output: Extent2D) -> Extent2D {
+        match *self {
+            GraphSize::Output => output,
+            GraphSize::Scaled(factor) => Extent2D::new(
+                ((output.width as f32 * factor) as u32).max(1),
+                ((output.height as f32 * factor) as u32).max(1),
+            ),
+            GraphSize::Fixed { width, height } => Extent2D::new(width, height),
+        }
+    }
+}
+
+/// Describes a texture that is allocated by the graph and lives for the duration of a frame
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GraphResourceSpec {
+    pub name: String,
+    /// Color resources have the color format of the output
+    pub format: ImageFormat,
+    #[serde(default)]
+    pub size: GraphSize,
+}
+
+/// Describes what a pass records
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum PassKind {
+    /// Draws the depth of the shadow casting lights into the depth attachment
+    Shadows,
+    /// Draws the cameras rendering to the window
+    Scene,
+    /// Draws a triangle covering the attachments with material
+    /// The material samples the resources read by the pass through their names
+    Fullscreen { material: String },
+}
+
+/// Describes a pass by the resources it reads and writes
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PassSpec {
+    pub name: String,
+    pub kind: PassKind,
+    /// The resources sampled by the pass
+    #[serde(default)]
+    pub reads: Vec<String>,
+    /// The resources rendered to as color attachments in order
+    #[serde(default)]
+    pub colors: Vec<String>,
+    /// The resource rendered to as the depth attachment
+    #[serde(default)]
+    pub depth: Option<String>,
+}
+
+impl PassSpec {
+    /// Returns the names of all resources rendered to by the pass, colors first
+    fn attachments(&self) -> impl Iterator<Item = &str> {
+        self.colors
+            .iter()
+            .chain(self.depth.iter())
+            .map(String::as_str)
+    }
+}
+
+/// Specifies the passes of a frame
+/// Passes are recorded in the order they are declared and must come after the passes writing the
+/// resources they read
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RenderGraphSpec {
+    pub resources: Vec<GraphResourceSpec>,
+    pub passes: Vec<PassSpec>,
+}
+
+impl RenderGraphSpec {
+    /// Loads a render graph spec from json
+    pub fn load(path: &str) -> Result<Self> {
+        Ok(serde_json::from_str(&vfs::read_to_string(path)?)?)
+    }
+}
+
+/// How a pass uses a resource
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Usage {
+    Read,
+    Write,
+}
+
+/// A pass of the graph with its renderpass and framebuffers
+pub struct GraphPass {
+    spec: PassSpec,
+    /// The name renderpass is registered as in the resource manager
+    renderpass_name: String,
+    renderpass: Arc<RenderPass>,
+    /// One framebuffer for each output image if the pass renders to the output, otherwise a
+    /// single framebuffer
+    framebuffers: Vec<Framebuffer>,
+    extent: Extent2D,
+}
+
+impl GraphPass {
+    pub fn name(&self) -> &str {
+        &self.spec.name
+    }
+
+    pub fn kind(&self) -> &PassKind {
+        &self.spec.kind
+    }
+
+    pub fn spec(&self) -> &PassSpec {
+        &self.spec
+    }
+
+    /// Returns the name the renderpass is registered as
+    /// Used to create pipeline variants for the pass
+    pub fn renderpass_name(&self) -> &str {
+        &self.renderpass_name
+    }
+
+    pub fn renderpass(&self) -> &Arc<RenderPass> {
+        &self.renderpass
+    }
+
+    /// Returns the framebuffer to render to when drawing to the output image at image_index
+    pub fn framebuffer(&self, image_index: u32) -> &Framebuffer {
+        if self.framebuffers.len() == 1 {
+            &self.framebuffers[0]
+        } else {
+            &self.framebuffers[image_index as usize]
+        }
+    }
+
+    /// Returns the size of the attachments of the pass
+    pub fn extent(&self) -> Extent2D {
+        self.extent
+    }
+}
+
+/// The passes of a frame compiled from a RenderGraphSpec for an output
+/// Passes that don't contribute to the output are culled
+/// The graph allocates the resources of the passes and derives the load and store operations,
+/// layouts and dependencies of their renderpasses from how the resources are used
+/// Resources are left ready to be sampled after their last use in a frame
+/// Must be created again when the formats of the output change, but only resized when its size
+/// changes
+pub struct RenderGraph {
+    passes: Vec<GraphPass>,
+    /// The resources used by the passes, excluding the output
+    resources: HashMap<String, GraphResourceSpec>,
+    /// The resources read by any pass
+    /// Only these are registered in the resource manager as nothing else can be sampled
+    sampled: HashSet<String>,
+    textures: HashMap<String, Arc<Texture>>,
+}
+
+impl RenderGraph {
+    /// Compiles spec for the output images described by the target info of resourcemanager
+    /// The renderpasses and the resources read by passes are registered in resourcemanager so
+    /// that materials can sample the resources by name
+    pub fn new(
+        resourcemanager: &ResourceManager,
+        spec: &RenderGraphSpec,
+        output: &[&Texture],
+    ) -> Result<Self> {
+        let context = resourcemanager.context();
+        let target_info = resourcemanager.target_info().unwrap();
+        let Schedule {
+            formats,
+            passes,
+            uses,
+        } = schedule(spec)?;
+
+        let mut compiled = Vec::with_capacity(passes.len());
+        for (i, pass) in passes.iter().enumerate() {
+            let renderpass_spec =
+                renderpass_spec(i, pass, &formats, &uses, target_info.presentable);
+            let renderpass = Arc::new(RenderPass::new(
+                &context.device,
+                renderpass_spec,
+                target_info.color_format,
+                target_info.depth_format,
+            )?);
+
+            let renderpass_name = format!("{}{}", RENDERPASS_PREFIX, pass.name);
+            resourcemanager.insert_renderpass(&renderpass_name, Arc::clone(&renderpass));
+
+            compiled.push(GraphPass {
+                spec: (*pass).clone(),
+                renderpass_name,
+                renderpass,
+                framebuffers: Vec::new(),
+                extent: target_info.extent,
+            });
+        }
+
+        // Only the resources used by the remaining passes are allocated
+        let resources = spec
+            .resources
+            .iter()
+            .filter(|resource| uses.contains_key(resource.name.as_str()))
+            .map(|resource| (resource.name.clone(), resource.clone()))
+            .collect();
+
+        let sampled = passes
+            .iter()
+            .flat_map(|pass| pass.reads.iter().cloned())
+            .collect();
+
+        let mut graph = RenderGraph {
+            passes: compiled,
+            resources,
+            sampled,
+            textures: HashMap::new(),
+        };
+
+        graph.allocate(resourcemanager, output)?;
+        Ok(graph)
+    }
+
+    /// Resizes the resources and framebuffers to the output images of the current target info of
+    /// resourcemanager
+    /// The output is expected to have the same formats and presentability as the graph was
+    /// compiled for
+    /// Resources sized independently of the output are kept
+    /// Returns true if any resource registered in resourcemanager was replaced
+    pub fn resize(
+        &mut self,
+        resourcemanager: &ResourceManager,
+        output: &[&Texture],
+    ) -> Result<bool> {
+        self.allocate(resourcemanager, output)
+    }
+
+    /// Creates the textures that don't match the size of the output and the framebuffers of all
+    /// passes
+    /// Returns true if any registered texture was replaced
+    fn allocate(&mut self, resourcemanager: &ResourceManager, output: &[&Texture]) -> Result<bool> {
+        let context = resourcemanager.context();
+        let target_info = resourcemanager.target_info().unwrap();
+
+        let mut replaced = false;
+        for (name, resource) in &self.resources {
+            let extent = resource.size.extent(target_info.extent);
+            if let Some(texture) = self.textures.get(name) {
+                let current = texture.extent();
+                if (current.width, current.height) == (extent.width, extent.height) {
+                    continue;
+                }
+            }
+
+            let texture = Arc::new(match resource.format {
+                ImageFormat::Depth => {
+                    Texture::new_depth_attachment(&context.allocator, &context.device, extent)?
+                }
+                _ => Texture::new_color_attachment(
+                    &context.allocator,
+                    &context.device,
+                    target_info.color_format,
+                    extent,
+                )?,
+            });
+
+            if self.sampled.contains(name) {
+                replaced |= resourcemanager
+                    .insert_texture(name, Arc::clone(&texture))
+                    .is_some();
+            }
+            self.textures.insert(name.clone(), texture);
+        }
+
+        for pass in &mut self.passes {
+            let textures = &self.textures;
+            let attachments = |image: usize| -> Vec<&Texture> {
+                pass.spec
+                    .attachments()
+                    .map(|name| match name {
+                        OUTPUT => output[image],
+                        name => &textures[name],
+                    })
+                    .collect()
+            };
+
+            let extent = attachments(0)[0].extent();
+            if attachments(0).iter().any(|attachment| {
+                (attachment.extent().width, attachment.extent().height)
+                    != (extent.width, extent.height)
+            }) {
+                return Err(invalid(format!(
+                    "the attachments of pass '{}' differ in size",
+                    pass.spec.name
+                )));
+            }
+
+            let framebuffer_count = if pass.spec.attachments().any(|name| name == OUTPUT) {
+                output.len()
+            } else {
+                1
+            };
+
+            pass.framebuffers = (0..framebuffer_count)
+                .map(|image| {
+                    Framebuffer::new(
+                        &context.device,
+                        &attachments(image),
+                        &pass.renderpass,
+                        extent,
+                    )
+                })
+                .collect::<Result<Vec<_>>>()?;
+            pass.extent = extent;
+        }
+
+        Ok(replaced)
+    }
+
+    /// Returns the passes that contribute to the output in the order they are recorded
+    pub fn passes(&self) -> &[GraphPass] {
+        &self.passes
+    }
+
+    /// Returns the texture allocated for a resource
+    /// Returns None for the output and resources not used by any remaining pass
+    pub fn texture(&self, name: &str) -> Option<&Arc<Texture>> {
+        self.textures.get(name)
+    }
+
+    /// Returns true if the size of a resource depends on the size of the output
+    pub fn is_output_sized(&self, name: &str) -> bool {
+        name == OUTPUT
+            || self
+                .resources
+                .get(name)
+                .is_some_and(|resource| !matches!(resource.size, GraphSize::Fixed { .. }))
+    }
+}
+
+fn invalid(reason: String) -> Error {
+    Error::InvalidRenderGraph(reason)
+}
+
+/// The passes of a graph that contribute to the output with the uses of their resources
+struct Schedule<'a> {
+    formats: HashMap<&'a str, ImageFormat>,
+    passes: Vec<&'a PassSpec>,
+    /// The uses of each resource in the order of the passes
+    uses: HashMap<&'a str, Vec<(usize, Usage)>>,
+}
+
+/// Validates spec and orders the uses of the resources by the passes that are not culled
+fn schedule(spec: &RenderGraphSpec) -> Result<Schedule<'_>> {
+    let mut formats: HashMap<&str, ImageFormat> = HashMap::new();
+    formats.insert(OUTPUT, ImageFormat::Color);
+    for resource in &spec.resources {
+        if resource.format == ImageFormat::Undefined {
+            return Err(invalid(format!(
+                "resource '{}' has an undefined format",
+                resource.name
+            )));
+        }
+
+        if formats.insert(&resource.name, resource.format).is_some() {
+            return Err(invalid(format!(
+                "resource '{}' is declared more than once",
+                resource.name
+            )));
+        }
+    }
+
+    let mut names = HashSet::new();
+    for pass in &spec.passes {
+        validate_pass(pass, &formats)?;
+        if !names.insert(pass.name.as_str()) {
+            return Err(invalid(format!(
+                "pass '{}' is declared more than once",
+                pass.name
+            )));
+        }
+    }
+
+    let passes = cull(&spec.passes);
+    if !passes
+        .iter()
+        .any(|pass| pass.attachments().any(|a| a == OUTPUT))
+    {
+        return Err(invalid(format!("no pass writes to '{}'", OUTPUT)));
+    }
+
+    let mut uses: HashMap<&str, Vec<(usize, Usage)>> = HashMap::new();
+    for (i, pass) in passes.iter().enumerate() {
+        for name in &pass.reads {
+            let previous = uses.entry(name.as_str()).or_default();
+            if !previous.iter().any(|(_, usage)| *usage == Usage::Write) {
+                return Err(invalid(format!(
+                    "pass '{}' reads '{}' before any pass writes it",
+                    pass.name, name
+                )));
+            }
+            previous.push((i, Usage::Read));
+        }
+        for name in pass.attachments() {
+            uses.entry(name).or_default().push((i, Usage::Write));
+        }
+    }
+
+    Ok(Schedule {
+        formats,
+        passes,
+        uses,
+    })
+}
+
+/// Checks that the resources of pass exist and match the attachments they are used as
+fn validate_pass(pass: &PassSpec, formats: &HashMap<&str, ImageFormat>) -> Result<()> {
+    let lookup = |name: &str| {
+        formats.get(name).copied().ok_or_else(|| {
+            invalid(format!(
+                "pass '{}' uses undeclared resource '{}'",
+                pass.name, name
+            ))
+        })
+    };
+
+    for name in &pass.reads {
+        lookup(name)?;
+        if pass.attachments().any(|attachment| attachment == name) {
+            return Err(invalid(format!(
+                "pass '{}' both reads and writes '{}'",
+                pass.name, name
+            )));
+        }
+    }
+
+    let mut written = HashSet::new();
+    for name in pass.attachments() {
+        if !written.insert(name) {
+            return Err(invalid(format!(
+                "pass '{}' writes '{}' more than once",
+                pass.name, name
+            )));
+        }
+
+        let expected = if pass.depth.as_deref() == Some(name) {
+            ImageFormat::Depth
+        } else {
+            ImageFormat::Color
+        };
+
+        if lookup(name)? != expected {
+            return Err(invalid(format!(
+                "pass '{}' uses '{}' as an attachment of the wrong format",
+                pass.name, name
+            )));
+        }
+    }
+
+    if pass.attachments().next().is_none() {
+        return Err(invalid(format!("pass '{}' has no attachments", pass.name)));
+    }
+
+    Ok(())
+}
+
+/// Returns the passes that contribute to the output, in order
+/// Passes rendering to an attachment also depend on the earlier passes rendering to it as the
+/// contents are kept
+fn cull(passes: &[PassSpec]) -> Vec<&PassSpec> {
+    let mut needed: HashSet<&str> = HashSet::new();
+    needed.insert(OUTPUT);
+
+    let mut live = vec![false; passes.len()];
+    for (i, pass) in passes.iter().enumerate().rev() {
+        if !pass.attachments().any(|name| needed.contains(name)) {
+            log::info!("Culling render graph pass '{}'", pass.name);
+            continue;
+        }
+
+        live[i] = true;
+        needed.extend(pass.reads.iter().map(String::as_str));
+        needed.extend(pass.attachments());
+    }
+
+    passes
+        .iter()
+        .zip(live)
+        .filter(|(_, live)| *live)
+        .map(|(pass, _)| pass)
+        .collect()
+}
+
+/// Returns the stage and access a use of a resource waits on
+fn src_scope(usage: Usage, depth: bool) -> (PipelineStage, AccessFlags) {
+    match (usage, depth) {
+        (Usage::Read, _) => (PipelineStage::FragmentShader, AccessFlags::ShaderRead),
+        (Usage::Write, true) => (
+            PipelineStage::LateFragmentTests,
+            AccessFlags::DepthStencilAttachmentWrite,
+        ),
+        (Usage::Write, false) => (
+            PipelineStage::ColorAttachmentOutput,
+            AccessFlags::ColorAttachmentWrite,
+        ),
+    }
+}
+
+/// Returns the stage and access where a use of a resource starts
+fn dst_scope(usage: Usage, depth: bool) -> (PipelineStage, AccessFlags) {
+    match (usage, depth) {
+        (Usage::Write, true) => (
+            PipelineStage::EarlyFragmentTests,
+            AccessFlags::DepthStencilAttachmentWrite,
+        ),
+        (usage, depth) => src_scope(usage, depth),
+    }
+}
+
+/// Returns the layout a resource is in during a use
+fn usage_layout(usage: Usage, depth: bool) -> ImageLayout {
+    match (usage, depth) {
+        (Usage::Read, true) => ImageLayout::DepthStencilReadOnly,
+        (Usage::Read, false) => ImageLayout::ShaderReadOnly,
+        (Usage::Write, true) => ImageLayout::DepthStencilAttachment,
+        (Usage::Write, false) => ImageLayout::ColorAttachment,
+    }
+}
+
+/// Creates the renderpass of the pass at index from the uses of its resources before and after it
+fn renderpass_spec(
+    index: usize,
+    pass: &PassSpec,
+    formats: &HashMap<&str, ImageFormat>,
+    uses: &HashMap<&str, Vec<(usize, Usage)>>,
+    presentable: bool,
+) -> RenderPassSpec {
+    let mut attachments = Vec::new();
+    let mut dependencies = Vec::new();
+    let mut depend = |src: SubpassIndex,
+                      dst: SubpassIndex,
+                      (src_stage, src_access): (PipelineStage, AccessFlags),
+                      (dst_stage, dst_access): (PipelineStage, AccessFlags)| {
+        let dependency = SubpassDependency {
+            src_subpass: src,
+            dst_subpass: dst,
+            src_stage,
+            dst_stage,
+            src_access,
+            dst_access,
+        };
+        if !dependencies.contains(&dependency) {
+            dependencies.push(dependency);
+        }
+    };
+
+    for name in pass.attachments() {
+        let depth = formats[name] == ImageFormat::Depth;
+        let uses = &uses[name];
+        let position = uses
+            .iter()
+            .position(|(i, usage)| *i == index && *usage == Usage::Write)
+            .unwrap();
+        let previous = position.checked_sub(1).map(|i| uses[i].1);
+        let next = uses.get(position + 1).map(|(_, usage)| *usage);
+
+        // The first use in a frame waits on the last use of the previous frame
+        let waits_on = previous.unwrap_or(uses[uses.len() - 1].1);
+        depend(
+            SubpassIndex::External,
+            SubpassIndex::Internal(0),
+            src_scope(waits_on, depth),
+            dst_scope(Usage::Write, depth),
+        );
+
+        // Make the writes visible to sampling after the pass, including by materials outside the
+        // graph
+        if next != Some(Usage::Write) && name != OUTPUT {
+            depend(
+                SubpassIndex::Internal(0),
+                SubpassIndex::External,
+                src_scope(Usage::Write, depth),
+                dst_scope(Usage::Read, depth),
+            );
+        }
+
+        let read_later = uses.iter().any(|(_, usage)| *usage == Usage::Read);
+        let final_layout = match next {
+            Some(usage) => usage_layout(usage, depth),
+            None if name == OUTPUT && presentable => ImageLayout::PresentSrc,
+            None if name == OUTPUT => ImageLayout::TransferSrc,
+            None => usage_layout(Usage::Read, depth),
+        };
+
+        attachments.push(Attachment {
+            load_op: match previous {
+                Some(_) => AttachmentLoadOp::Load,
+                None => AttachmentLoadOp::Clear,
+            },
+            store_op: if next.is_some() || read_later || name == OUTPUT {
+                AttachmentStoreOp::Store
+            } else {
+                AttachmentStoreOp::DontCare
+            },
+            initial_layout: match previous {
+                Some(usage) => usage_layout(usage, depth),
+                None => ImageLayout::Undefined,
+            },
+            final_layout,
+            layout: usage_layout(Usage::Write, depth),
+            sample_count: 1,
+            format: formats[name],
+        });
+    }
+
+    for name in &pass.reads {
+        let depth = formats[name.as_str()] == ImageFormat::Depth;
+        let uses = &uses[name.as_str()];
+        let position = uses
+            .iter()
+            .position(|(i, usage)| *i == index && *usage == Usage::Read)
+            .unwrap();
+
+        // Earlier reads have already made the writes visible
+        if uses[position - 1].1 == Usage::Write {
+            depend(
+                SubpassIndex::External,
+                SubpassIndex::Internal(0),
+                src_scope(Usage::Write, depth),
+                dst_scope(Usage::Read, depth),
+            );
+        }
+    }
+
+    RenderPassSpec {
+        subpasses: vec![Subpass {
+            color_attachments: (0..pass.colors.len()).collect(),
+            depth_attachment: pass.depth.as_ref().map(|_| pass.colors.len()),
+        }],
+        dependencies,
+        attachments,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resource(name: &str, format: ImageFormat) -> GraphResourceSpec {
+        GraphResourceSpec {
+            name: name.to_string(),
+            format,
+            size: GraphSize::Output,
+        }
+    }
+
+    fn pass(name: &str, reads: &[&str], colors: &[&str], depth: Option<&str>) -> PassSpec {
+        PassSpec {
+            name: name.to_string(),
+            kind: PassKind::Fullscreen {
+                material: name.to_string(),
+            },
+            reads: reads.iter().map(|s| s.to_string()).collect(),
+            colors: colors.iter().map(|s| s.to_string()).collect(),
+            depth: depth.map(str::to_string),
+        }
+    }
+
+    /// Renders shadows sampled by the scene, which is tonemapped to the output with an overlay
+    /// drawn on top
+    fn graph() -> RenderGraphSpec {
+        RenderGraphSpec {
+            resources: vec![
+                resource("shadow", ImageFormat::Depth),
+                resource("hdr", ImageFormat::Color),
+                resource("depth", ImageFormat::Depth),
+            ],
+            passes: vec![
+                pass("shadows", &[], &[], Some("shadow")),
+                pass("scene", &["shadow"], &["hdr"], Some("depth")),
+                pass("tonemap", &["hdr"], &[OUTPUT], None),
+                pass("overlay", &[], &[OUTPUT], None),
+            ],
+        }
+    }
+
+    fn is_invalid(spec: &RenderGraphSpec) -> bool {
+        matches!(schedule(spec), Err(Error::InvalidRenderGraph(_)))
+    }
+
+    fn compile(spec: &RenderGraphSpec, presentable: bool) -> Vec<RenderPassSpec> {
+        let schedule = schedule(spec).unwrap();
+        schedule
+            .passes
+            .iter()
+            .enumerate()
+            .map(|(i, pass)| {
+                renderpass_spec(i, pass, &schedule.formats, &schedule.uses, presentable)
+            })
+            .collect()
+    }
+
+    fn dependency(
+        src_subpass: SubpassIndex,
+        dst_subpass: SubpassIndex,
+        (src_stage, src_access): (PipelineStage, AccessFlags),
+        (dst_stage, dst_access): (PipelineStage, AccessFlags),
+    ) -> SubpassDependency {
+        SubpassDependency {
+            src_subpass,
+            dst_subpass,
+            src_stage,
+            dst_stage,
+            src_access,
+            dst_access,
+        }
+    }
+
+    #[test]
+    fn cull_unused_outputs() {
+        let mut spec = graph();
+        spec.resources.push(resource("unused", ImageFormat::Color));
+        spec.passes
+            .insert(0, pass("before", &[], &["unused"], None));
+        spec.passes.push(pass("after", &["hdr"], &["unused"], None));
+
+        let schedule = schedule(&spec).unwrap();
+        let names: Vec<_> = schedule
+            .passes
+            .iter()
+            .map(|pass| pass.name.as_str())
+            .collect();
+        assert_eq!(names, ["shadows", "scene", "tonemap", "overlay"]);
+        assert!(!schedule.uses.contains_key("unused"));
+        assert_eq!(schedule.uses["hdr"], [(1, Usage::Write), (2, Usage::Read)]);
+    }
+
+    #[test]
+    fn cull_everything_without_output() {
+        let mut spec = graph();
+        spec.passes.truncate(2);
+        assert!(is_invalid(&spec));
+    }
+
+    #[test]
+    fn reject_read_before_write() {
+        let mut spec = graph();
+        spec.passes.swap(0, 1);
+        assert!(is_invalid(&spec));
+    }
+
+    #[test]
+    fn reject_duplicates() {
+        // Writing an attachment twice
+        let mut spec = graph();
+        spec.passes[2].colors.push(OUTPUT.to_string());
+        assert!(is_invalid(&spec));
+
+        // Reading and writing the same resource
+        let mut spec = graph();
+        spec.passes[1].reads.push("depth".to_string());
+        assert!(is_invalid(&spec));
+
+        // Declaring a pass or resource twice
+        let mut spec = graph();
+        spec.passes.push(pass("overlay", &[], &[OUTPUT], None));
+        assert!(is_invalid(&spec));
+
+        let mut spec = graph();
+        spec.resources.push(resource("hdr", ImageFormat::Color));
+        assert!(is_invalid(&spec));
+    }
+
+    #[test]
+    fn reject_unknown_resources() {
+        let mut spec = graph();
+        spec.passes[2].reads.push("missing".to_string());
+        assert!(is_invalid(&spec));
+
+        let mut spec = graph();
+        spec.passes[3].colors.push("missing".to_string());
+        assert!(is_invalid(&spec));
+
+        // Wrong formats
+        let mut spec = graph();
+        spec.passes[3].colors.push("depth".to_string());
+        assert!(is_invalid(&spec));
+
+        let mut spec = graph();
+        spec.resources
+            .push(resource("undefined", ImageFormat::Undefined));
+        assert!(is_invalid(&spec));
+    }
+
+    #[test]
+    fn attachment_operations_and_layouts() {
+        let renderpasses = compile(&graph(), true);
+
+        // Sampled by the scene
+        let shadow = &renderpasses[0].attachments[0];
+        assert_eq!(shadow.load_op, AttachmentLoadOp::Clear);
+        assert_eq!(shadow.store_op, AttachmentStoreOp::Store);
+        assert_eq!(shadow.initial_layout, ImageLayout::Undefined);
+        assert_eq!(shadow.layout, ImageLayout::DepthStencilAttachment);
+        assert_eq!(shadow.final_layout, ImageLayout::DepthStencilReadOnly);
+
+        let hdr = &renderpasses[1].attachments[0];
+        assert_eq!(hdr.load_op, AttachmentLoadOp::Clear);
+        assert_eq!(hdr.store_op, AttachmentStoreOp::Store);
+        assert_eq!(hdr.layout, ImageLayout::ColorAttachment);
+        assert_eq!(hdr.final_layout, ImageLayout::ShaderReadOnly);
+
+        // Never read, but left ready to be sampled
+        let depth = &renderpasses[1].attachments[1];
+        assert_eq!(depth.load_op, AttachmentLoadOp::Clear);
+        assert_eq!(depth.store_op, AttachmentStoreOp::DontCare);
+        assert_eq!(depth.final_layout, ImageLayout::DepthStencilReadOnly);
+        assert_eq!(renderpasses[1].subpasses[0].depth_attachment, Some(1));
+
+        // Kept for the overlay
+        let output = &renderpasses[2].attachments[0];
+        assert_eq!(output.load_op, AttachmentLoadOp::Clear);
+        assert_eq!(output.store_op, AttachmentStoreOp::Store);
+        assert_eq!(output.initial_layout, ImageLayout::Undefined);
+        assert_eq!(output.final_layout, ImageLayout::ColorAttachment);
+
+        let output = &renderpasses[3].attachments[0];
+        assert_eq!(output.load_op, AttachmentLoadOp::Load);
+        assert_eq!(output.store_op, AttachmentStoreOp::Store);
+        assert_eq!(output.initial_layout, ImageLayout::ColorAttachment);
+        assert_eq!(output.final_layout, ImageLayout::PresentSrc);
+
+        let renderpasses = compile(&graph(), false);
+        assert_eq!(
+            renderpasses[3].attachments[0].final_layout,
+            ImageLayout::TransferSrc
+        );
+    }
+
+    #[test]
+    fn dependencies_across_frames() {
+        let renderpasses = compile(&graph(), true);
+        let read = (PipelineStage::FragmentShader, AccessFlags::ShaderRead);
+        let depth_write = (
+            PipelineStage::LateFragmentTests,
+            AccessFlags::DepthStencilAttachmentWrite,
+        );
+        let depth_write_start = (
+            PipelineStage::EarlyFragmentTests,
+            AccessFlags::DepthStencilAttachmentWrite,
+        );
+        let color_write = (
+            PipelineStage::ColorAttachmentOutput,
+            AccessFlags::ColorAttachmentWrite,
+        );
+
+        // The shadow map is written after the scene of the previous frame sampled it
+        let shadows = &renderpasses[0].dependencies;
+        assert!(shadows.contains(&dependency(
+            SubpassIndex::External,
+            SubpassIndex::Internal(0),
+            read,
+            depth_write_start
+        )));
+        assert!(shadows.contains(&dependency(
+            SubpassIndex::Internal(0),
+            SubpassIndex::External,
+            depth_write,
+            read
+        )));
+        assert_eq!(shadows.len(), 2);
+
+        // The scene samples the shadow map after it is written
+        let scene = &renderpasses[1].dependencies;
+        assert!(scene.contains(&dependency(
+            SubpassIndex::External,
+            SubpassIndex::Internal(0),
+            depth_write,
+            read
+        )));
+
+        // The output is written after the overlay of the previous frame wrote it and the
+        // overlay waits on the tonemapping
+        for renderpass in &renderpasses[2..] {
+            assert!(renderpass.dependencies.contains(&dependency(
+                SubpassIndex::External,
+                SubpassIndex::Internal(0),
+                color_write,
+                color_write
+            )));
+        }
+        // Nothing samples the output
+        assert!(!renderpasses[3]
+            .dependencies
+            .iter()
+            .any(|dependency| dependency.dst_subpass == SubpassIndex::External));
+    }
+}
@@ -1,6 +1,7 @@
 use super::enums::*;
 use super::resources::Resource;
 use super::Result;
+use crate::vfs;
 use ash::version::DeviceV1_0;
 use ash::vk;
 use serde::{Deserialize, Serialize};
@@ -21,7 +22,7 @@ pub struct Subpass {
     pub depth_attachment: Option<usize>,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub enum SubpassIndex {
     /// The external pseudo subpass that happens at before or after evrything else depending if src or dst pass
     /// In other words, specified the beginnings or ends
@@ -38,7 +39,7 @@ impl From<SubpassIndex> for u32 {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
 pub struct SubpassDependency {
     pub src_subpass: SubpassIndex,
     pub dst_subpass: SubpassIndex,
@@ -87,7 +88,7 @@ impl Attachment {
     }
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
 pub enum ImageFormat {
     Undefined,
     Color,
@@ -98,24 +99,37 @@ pub struct RenderPass {
     device: ash::Device,
     renderpass: vk::RenderPass,
     spec: RenderPassSpec,
+    /// The format of depth attachments
+    depth_format: vk::Format,
 }
 
 impl Resource for RenderPass {
     fn load(resourcemanager: &super::ResourceManager, path: &str) -> Result<Self> {
-        let spec: RenderPassSpec = serde_json::from_str(&ex::fs::read_to_string(path)?)?;
+        let mut spec: RenderPassSpec = serde_json::from_str(&vfs::read_to_string(path)?)?;
         let context = resourcemanager.context();
-        let swapchain = match resourcemanager.get_swapchain() {
-            Some(swapchain) => swapchain,
+        let target_info = match resourcemanager.target_info() {
+            Some(target_info) => target_info,
             None => {
-                log::error!("Swapchain in resource manager is None");
+                log::error!("Target info in resource manager is None");
                 panic!();
             }
         };
+
+        // There is nothing to present to when headless
+        // Leave the attachments ready to be copied from instead
+        if !target_info.presentable {
+            for attachment in &mut spec.attachments {
+                if let ImageLayout::PresentSrc = attachment.final_layout {
+                    attachment.final_layout = ImageLayout::TransferSrc;
+                }
+            }
+        }
+
         Self::new(
             &context.device,
             spec,
-            swapchain.format(),
-            swapchain.depth_format(),
+            target_info.color_format,
+            target_info.depth_format,
         )
     }
 }
@@ -209,6 +223,7 @@ impl RenderPass {
             device: device.clone(),
             renderpass,
             spec,
+            depth_format,
         })
     }
 
@@ -217,6 +232,16 @@ impl RenderPass {
         self.renderpass
     }
 
+    /// Returns the spec the renderpass was created from
+    pub fn spec(&self) -> &RenderPassSpec {
+        &self.spec
+    }
+
+    /// Returns the format depth attachments were created with
+    pub fn depth_format(&self) -> vk::Format {
+        self.depth_format
+    }
+
     /// Returns self created again from spec but with updated values
     /// Called when swapchain is recreated
     pub fn recreate(
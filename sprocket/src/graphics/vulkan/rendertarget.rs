@@ -0,0 +1,54 @@
+use super::{Framebuffer, RenderPass, Result, Texture, VkAllocator};
+use crate::graphics::Extent2D;
+use ash::vk;
+use std::sync::Arc;
+
+/// An offscreen color and depth attachment pair that can be rendered to and then sampled
+/// The color texture is reference counted so that it can be shared with materials through the
+/// resource manager
+pub struct RenderTarget {
+    color: Arc<Texture>,
+    depth: Texture,
+    framebuffer: Framebuffer,
+    extent: Extent2D,
+}
+
+impl RenderTarget {
+    /// Creates a new render target with the color format of the renderpass color attachment
+    /// The renderpass is expected to leave the color attachment in shader read only layout
+    pub fn new(
+        allocator: &VkAllocator,
+        device: &ash::Device,
+        renderpass: &RenderPass,
+        color_format: vk::Format,
+        extent: Extent2D,
+    ) -> Result<Self> {
+        let color = Texture::new_color_attachment(allocator, device, color_format, extent)?;
+        let depth = Texture::new_depth(allocator, device, extent)?;
+        let framebuffer = Framebuffer::new(device, &[&color, &depth], renderpass, extent)?;
+
+        Ok(RenderTarget {
+            color: Arc::new(color),
+            depth,
+            framebuffer,
+            extent,
+        })
+    }
+
+    /// Returns the color texture that is rendered to
+    pub fn color(&self) -> &Arc<Texture> {
+        &self.color
+    }
+
+    pub fn depth(&self) -> &Texture {
+        &self.depth
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    pub fn extent(&self) -> Extent2D {
+        self.extent
+    }
+}
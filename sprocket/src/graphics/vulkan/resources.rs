@@ -1,11 +1,34 @@
-use super::{Material, Model, Pipeline, RenderPass, Result, Swapchain, Texture, VulkanContext};
+use super::gltf;
+use super::handle::{normalize, DependencyGraph, Handle, ResourceId};
+use super::watcher::FileWatcher;
+use super::{
+    EnvironmentMap, Material, Model, Pipeline, PipelineVariant, PipelineVariantKey, RenderPass,
+    Result, Sampler, SamplerSpec, Texture, TextureData, Upload, UploadId, Uploader, VulkanContext,
+};
+use crate::graphics::Extent2D;
 use ash::version::DeviceV1_0;
+use ash::vk;
 use log::*;
 use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex, RwLock},
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex, RwLock},
+    thread::{self, JoinHandle},
+    time::Duration,
 };
 
+/// How often the files of loaded resources are checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The number of garbage cycles resources replaced by a reload are kept alive for frames in flight
+const RELOAD_GARBAGE_CYCLES: u32 = 2;
+
+/// The number of threads reading resources loaded asynchronously
+const LOAD_WORKERS: usize = 2;
+
+/// The size in pixels of the checkerboard sampled in place of textures still loading
+const PLACEHOLDER_SIZE: u32 = 8;
+
 /// Represents a resource soon to be deleted
 struct Garbage<T> {
     resource: Arc<T>,
@@ -27,6 +50,10 @@ impl<T> Garbage<T> {
 pub struct ResourceInfo {
     name: String,
     ty: &'static str,
+    /// None for resources not tracked by id, E.g; pipeline variants
+    id: Option<ResourceId>,
+    /// The resources this resource was created from
+    dependencies: Vec<ResourceId>,
     strong_refs: usize,
     weak_refs: usize,
 }
@@ -41,14 +68,186 @@ pub trait Resource {
     fn load(resourcemanager: &ResourceManager, path: &str) -> Result<Self>
     where
         Self: Sized;
+
+    /// Returns the files besides its own path the resource was created from, E.g; shaders
+    /// The resource is loaded again when any of them or its own file changes on disk
+    fn dependencies(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+}
+
+/// A resource stored by the ResourceManager which can be referred to by handles
+pub trait ManagedResource: Resource + Sized + 'static {
+    /// Returns the system storing the loaded resources of this type
+    fn system(resourcemanager: &ResourceManager) -> &ResourceSystem<Self>;
+}
+
+/// A resource that can be read from disk on a worker thread and uploaded to the GPU afterwards
+pub trait AsyncResource: ManagedResource + Send {
+    /// The decoded contents of the file, E.g; the pixels of an image
+    type Data: Send + 'static;
+
+    /// Reads and decodes the file at path
+    /// Called on a worker thread and may not use the graphics context
+    fn read(path: &str) -> Result<Self::Data>;
+
+    /// Creates the resource from data with the transfers of its contents recorded into upload
+    /// Called on the thread owning the resource manager
+    /// The resource is not handed out until the upload has completed
+    fn upload(
+        resourcemanager: &ResourceManager,
+        data: Self::Data,
+        upload: &mut Upload,
+    ) -> Result<Self>;
+}
+
+/// The state of a resource loaded asynchronously
+pub enum LoadState<T> {
+    Loading,
+    Ready(Arc<T>),
+    /// The reason the resource failed to load
+    Failed(String),
+}
+
+/// Refers to a resource being loaded in the background
+/// Resolves to the resource once it has been read and uploaded
+/// Handles are polled, the resource manager advances the loads in update_async
+pub struct LoadHandle<T> {
+    state: Arc<Mutex<LoadState<T>>>,
+}
+
+impl<T> LoadHandle<T> {
+    fn new(state: LoadState<T>) -> Self {
+        LoadHandle {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    fn set(&self, state: LoadState<T>) {
+        *self.state.lock().unwrap() = state;
+    }
+
+    /// Returns the resource if it has finished loading
+    pub fn get(&self) -> Option<Arc<T>> {
+        match &*self.state.lock().unwrap() {
+            LoadState::Ready(resource) => Some(Arc::clone(resource)),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the resource is neither loaded nor has failed to load
+    pub fn is_loading(&self) -> bool {
+        matches!(*self.state.lock().unwrap(), LoadState::Loading)
+    }
+
+    /// Returns the reason the resource failed to load if it did
+    pub fn error(&self) -> Option<String> {
+        match &*self.state.lock().unwrap() {
+            LoadState::Failed(reason) => Some(reason.to_owned()),
+            _ => None,
+        }
+    }
+}
+
+impl<T> Clone for LoadHandle<T> {
+    fn clone(&self) -> Self {
+        LoadHandle {
+            state: Arc::clone(&self.state),
+        }
+    }
+}
+
+/// Runs on a worker and returns the work left for the thread owning the resource manager
+type LoadJob = Box<dyn FnOnce() -> ReadResult + Send>;
+/// Uploads the data read by a worker
+type ReadResult = Box<dyn FnOnce(&ResourceManager) + Send>;
+/// Stores a resource once its upload has completed
+type UploadCompletion = Box<dyn FnOnce(&ResourceManager) + Send>;
+
+/// Reads resources on a pool of worker threads
+struct Loader {
+    jobs: Mutex<Option<mpsc::Sender<LoadJob>>>,
+    results: Mutex<mpsc::Receiver<ReadResult>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Loader {
+    fn new(worker_count: usize) -> Self {
+        let (job_sender, job_receiver) = mpsc::channel::<LoadJob>();
+        let (result_sender, result_receiver) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        let workers = (0..worker_count)
+            .map(|i| {
+                let jobs = Arc::clone(&job_receiver);
+                let results = result_sender.clone();
+                thread::Builder::new()
+                    .name(format!("resource loader {}", i))
+                    .spawn(move || loop {
+                        // Release the receiver before running the job so other workers can wait
+                        let job = jobs.lock().unwrap().recv();
+                        match job {
+                            Ok(job) => {
+                                if results.send(job()).is_err() {
+                                    break;
+                                }
+                            }
+                            Err(_) => break,
+                        }
+                    })
+                    .expect("Failed to spawn resource loader thread")
+            })
+            .collect();
+
+        Loader {
+            jobs: Mutex::new(Some(job_sender)),
+            results: Mutex::new(result_receiver),
+            workers,
+        }
+    }
+
+    fn spawn(&self, job: LoadJob) {
+        if let Some(jobs) = &*self.jobs.lock().unwrap() {
+            jobs.send(job).expect("Resource loader threads have exited");
+        }
+    }
+
+    /// Returns the results the workers have finished since the previous call
+    fn finished(&self) -> Vec<ReadResult> {
+        self.results.lock().unwrap().try_iter().collect()
+    }
+}
+
+impl Drop for Loader {
+    fn drop(&mut self) {
+        // Closing the channel stops the workers once they have finished their current job
+        self.jobs.lock().unwrap().take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
 }
 
 /// Manages a single type of resource
 /// Used internally in ResourceManager
 /// Should not be used standalone but can be used to assemble your own type of resource manager
+/// Names are normalized so that different spellings of the same path refer to the same resource
+/// Locks that are held together are taken in the order garbage, resources, loading, ids and lastly
+/// the dependency graph of the resource manager
 pub struct ResourceSystem<T: Resource> {
     resources: RwLock<HashMap<String, Arc<T>>>,
     garbage: Mutex<Vec<Garbage<T>>>,
+    /// The resources being loaded asynchronously
+    loading: Mutex<HashMap<String, LoadHandle<T>>>,
+    /// The ids of the names that are loaded, loading or have handles
+    ids: RwLock<Ids>,
+}
+
+/// Maps names to ids and back
+#[derive(Default)]
+struct Ids {
+    by_name: HashMap<String, ResourceId>,
+    by_id: HashMap<ResourceId, String>,
 }
 
 impl<T: Resource> ResourceSystem<T> {
@@ -56,21 +255,95 @@ impl<T: Resource> ResourceSystem<T> {
         ResourceSystem {
             resources: RwLock::new(HashMap::new()),
             garbage: Mutex::new(Vec::new()),
+            loading: Mutex::new(HashMap::new()),
+            ids: RwLock::new(Ids::default()),
         }
     }
 
+    /// Returns a handle to the resource stored as name
+    /// The handle resolves once the resource is loaded or inserted
+    pub fn handle(&self, name: &str) -> Handle<T> {
+        Handle::new(self.assign_id(&normalize(name)))
+    }
+
+    /// Returns the resource a handle refers to if it is loaded
+    pub fn resolve(&self, handle: Handle<T>) -> Option<Arc<T>> {
+        let name = self.name(handle.id())?;
+        self.resources.read().unwrap().get(&name).map(Arc::clone)
+    }
+
+    /// Returns true if any resource is being loaded asynchronously
+    fn is_loading(&self) -> bool {
+        self.loading
+            .lock()
+            .unwrap()
+            .values()
+            .any(|handle| handle.is_loading())
+    }
+
+    /// Returns the id of the resource stored as name if it has one
+    pub fn id(&self, name: &str) -> Option<ResourceId> {
+        self.ids
+            .read()
+            .unwrap()
+            .by_name
+            .get(&normalize(name))
+            .copied()
+    }
+
+    /// Returns the name of the resource with id
+    pub fn name(&self, id: ResourceId) -> Option<String> {
+        self.ids.read().unwrap().by_id.get(&id).cloned()
+    }
+
+    /// Returns the id of an already normalized name, assigning a new one if it has none
+    fn assign_id(&self, name: &str) -> ResourceId {
+        if let Some(id) = self.ids.read().unwrap().by_name.get(name) {
+            return *id;
+        }
+
+        let mut ids = self.ids.write().unwrap();
+        if let Some(id) = ids.by_name.get(name) {
+            return *id;
+        }
+
+        let id = ResourceId::next();
+        ids.by_name.insert(name.to_owned(), id);
+        ids.by_id.insert(id, name.to_owned());
+        id
+    }
+
+    /// Loads the resource stored as name while recording the resources it requests as its
+    /// dependencies
+    fn load_tracked(
+        &self,
+        resourcemanager: &ResourceManager,
+        id: ResourceId,
+        name: &str,
+    ) -> Result<T> {
+        resourcemanager.begin_load(id);
+        let resource = T::load(resourcemanager, name);
+        resourcemanager.end_load(id, resource.is_ok());
+        resource
+    }
+
     /// Loads and stores a resource if it doesn't already exist
     /// The resource will be stored as the path name
     /// If a resource with the name already exists, the existing one will be returned
     /// Will wait for write lock of textures
     pub fn load(&self, resourcemanager: &ResourceManager, path: &str) -> Result<Arc<T>> {
+        let path = &normalize(path);
+        let id = self.assign_id(path);
+        resourcemanager.record_dependency(id);
+
         match self.resources.read().unwrap().get(path) {
             Some(resource) => return Ok(Arc::clone(resource)),
             None => {}
         }
 
         // Load outside match to drop RwLock read guard
-        let resource = Arc::new(T::load(resourcemanager, path)?);
+        let resource = Arc::new(self.load_tracked(resourcemanager, id, path)?);
+        resourcemanager.watch(path, &resource.dependencies());
 
         self.resources
             .write()
@@ -79,100 +352,314 @@ impl<T: Resource> ResourceSystem<T> {
         Ok(resource)
     }
 
+    /// Starts loading a resource in the background if it isn't already loaded or loading
+    /// The file is read on a worker and uploaded when the resource manager is next updated
+    /// Returns a handle that resolves to the resource once the upload has completed
+    pub fn load_async(&self, resourcemanager: &ResourceManager, path: &str) -> LoadHandle<T>
+    where
+        T: AsyncResource,
+    {
+        let path = normalize(path);
+        resourcemanager.record_dependency(self.assign_id(&path));
+
+        if let Some(resource) = self.get(&path) {
+            return LoadHandle::new(LoadState::Ready(resource));
+        }
+
+        let mut loading = self.loading.lock().unwrap();
+        if let Some(handle) = loading.get(&path) {
+            return handle.clone();
+        }
+
+        let handle = LoadHandle::new(LoadState::Loading);
+        loading.insert(path.clone(), handle.clone());
+
+        resourcemanager.loader.spawn(Box::new(move || {
+            let data = T::read(&path);
+            Box::new(move |resourcemanager: &ResourceManager| match data {
+                Ok(data) => resourcemanager.upload::<T>(&path, data),
+                Err(e) => T::system(resourcemanager).finish(resourcemanager, &path, Err(e)),
+            })
+        }));
+
+        handle
+    }
+
+    /// Stores a resource loaded asynchronously and resolves its handle
+    /// A resource loaded synchronously in the meantime takes precedence
+    /// Failed handles are kept so that the failure is reported to later requests until the next
+    /// garbage collection
+    fn finish(&self, resourcemanager: &ResourceManager, path: &str, resource: Result<T>) {
+        let mut loading = self.loading.lock().unwrap();
+        let handle = match loading.get(path) {
+            Some(handle) => handle.clone(),
+            None => return,
+        };
+
+        match resource {
+            Ok(resource) => {
+                loading.remove(path);
+                drop(loading);
+                let resource = match self.get(path) {
+                    Some(existing) => existing,
+                    None => {
+                        let resource = Arc::new(resource);
+                        resourcemanager.watch(path, &resource.dependencies());
+                        self.insert(path, Arc::clone(&resource));
+                        resource
+                    }
+                };
+                handle.set(LoadState::Ready(resource));
+            }
+            Err(e) => {
+                error!("Failed to load '{}' '{}'", path, e);
+                handle.set(LoadState::Failed(e.to_string()));
+            }
+        }
+    }
+
+    /// Stores an already created resource under name
+    /// Replaces and returns any existing resource with the same name
+    pub fn insert(&self, name: &str, resource: Arc<T>) -> Option<Arc<T>> {
+        let name = normalize(name);
+        self.assign_id(&name);
+        self.resources.write().unwrap().insert(name, resource)
+    }
+
+    /// Stores resource under name and places the resource it replaces in the garbage
+    /// The replaced resource will get deleted after garbage_cycles cleanup cycles
+    pub fn replace(&self, name: &str, resource: Arc<T>, garbage_cycles: u32) {
+        if let Some(old) = self.insert(name, resource) {
+            self.garbage
+                .lock()
+                .unwrap()
+                .push(Garbage::new(old, garbage_cycles));
+        }
+    }
+
+    /// Loads the resources for which predicate returns true again from their paths and swaps them
+    /// in place, placing the old ones in the garbage
+    /// The dependencies of the reloaded resources are recorded again
+    /// Resources that fail to load are logged and kept as they were
+    /// Returns the ids of the replaced resources
+    pub fn reload<F: Fn(ResourceId, &str, &T) -> bool>(
+        &self,
+        resourcemanager: &ResourceManager,
+        predicate: F,
+        garbage_cycles: u32,
+    ) -> HashSet<ResourceId> {
+        let names: Vec<(ResourceId, String)> = self
+            .resources
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, resource)| (self.assign_id(name), name, resource))
+            .filter(|(id, name, resource)| predicate(*id, name, resource))
+            .map(|(id, name, _)| (id, name.to_owned()))
+            .collect();
+
+        let ty = type_name::<T>();
+
+        names
+            .into_iter()
+            .filter(
+                |(id, name)| match self.load_tracked(resourcemanager, *id, name) {
+                    Ok(resource) => {
+                        info!("Reloaded {} '{}'", ty, name);
+                        resourcemanager.watch(name, &resource.dependencies());
+                        self.replace(name, Arc::new(resource), garbage_cycles);
+                        true
+                    }
+                    Err(e) => {
+                        error!("Failed to reload {} '{}' '{}'", ty, name, e);
+                        false
+                    }
+                },
+            )
+            .map(|(id, _)| id)
+            .collect()
+    }
+
     /// path to return a reference to an already loaded texture
     /// Returns None if the texture isn't loaded
     pub fn get(&self, path: &str) -> Option<Arc<T>> {
         self.resources
             .read()
             .unwrap()
-            .get(path)
+            .get(&normalize(path))
             .map(|v| Arc::clone(v))
     }
 
     /// Goes through the loaded resources and places all resources with no other references in a garbage
+    /// Resources other resources depend on are kept until their dependents have been collected
     /// The actual resource will get deleted after garbage_cycles cleanup cycles so that it is no longer in use by a pipeline
-    pub fn collect_garbage(&self, garbage_cycles: u32) {
+    pub fn collect_garbage(&self, resourcemanager: &ResourceManager, garbage_cycles: u32) {
+        self.collect(&resourcemanager.dependencies, garbage_cycles)
+    }
+
+    /// Collects the garbage with the dependencies recorded in graph
+    fn collect(&self, graph: &Mutex<DependencyGraph>, garbage_cycles: u32) {
         // Acquire a lock for the whole function to avoid having a resource getting a user midway through TOCTOU
         let mut garbage = self.garbage.lock().unwrap();
         let mut resources = self.resources.write().unwrap();
+        let mut loading = self.loading.lock().unwrap();
+        let mut ids = self.ids.write().unwrap();
+        let mut graph = graph.lock().unwrap();
 
         // Remove garbage with 0 cycles remaining
         garbage.retain(|v| v.cycles_remaining > 0);
         // Remove one cycle from existing garbage
         garbage.iter_mut().for_each(|v| v.cycles_remaining -= 1);
 
+        // Forget failed loads nothing refers to so that they can be retried
+        loading.retain(|_, handle| handle.is_loading() || Arc::strong_count(&handle.state) > 1);
+
         // Remove all elements with one 1 (self) strong reference and place into garbage
-        resources.retain(|_, r| {
-            if Arc::strong_count(&r) == 1 {
-                garbage.push(Garbage::new(Arc::clone(&r), garbage_cycles));
+        resources.retain(|name, r| {
+            let dependent = ids
+                .by_name
+                .get(name)
+                .is_some_and(|id| graph.has_dependents(*id));
+            if Arc::strong_count(r) == 1 && !dependent {
+                garbage.push(Garbage::new(Arc::clone(r), garbage_cycles));
                 false
             } else {
                 true
             }
         });
+
+        // Forget the ids and dependencies of the names that are no longer loaded
+        let Ids { by_name, by_id } = &mut *ids;
+        by_name.retain(|name, id| {
+            if resources.contains_key(name) || loading.contains_key(name) {
+                true
+            } else {
+                by_id.remove(id);
+                graph.remove(*id);
+                false
+            }
+        });
     }
 
-    pub fn info(&self) -> Vec<ResourceInfo> {
-        let ty = std::any::type_name::<T>();
-        let ty = &ty[ty.rfind("::").map(|v| v + 2).unwrap_or(0)..];
-        self.resources
-            .read()
-            .unwrap()
+    pub fn info(&self, resourcemanager: &ResourceManager) -> Vec<ResourceInfo> {
+        let ty = type_name::<T>();
+        let resources = self.resources.read().unwrap();
+        let ids = self.ids.read().unwrap();
+        let graph = resourcemanager.dependencies.lock().unwrap();
+        resources
             .iter()
-            .map(|(k, v)| ResourceInfo {
-                name: k.to_owned(),
-                ty,
-                strong_refs: Arc::strong_count(v),
-                weak_refs: Arc::weak_count(v),
+            .map(|(k, v)| {
+                let id = ids.by_name.get(k).copied();
+                ResourceInfo {
+                    name: k.to_owned(),
+                    ty,
+                    id,
+                    dependencies: id.map(|id| graph.dependencies(id)).unwrap_or_default(),
+                    strong_refs: Arc::strong_count(v),
+                    weak_refs: Arc::weak_count(v),
+                }
             })
             .collect()
     }
 }
 
+/// Returns the name of T without its module path
+fn type_name<T>() -> &'static str {
+    let ty = std::any::type_name::<T>();
+    &ty[ty.rfind("::").map(|v| v + 2).unwrap_or(0)..]
+}
+
+/// Describes the images the renderer draws into
+/// Used by resources that depend on the output, like renderpasses and materials
+#[derive(Clone, Copy)]
+pub struct TargetInfo {
+    pub color_format: vk::Format,
+    pub depth_format: vk::Format,
+    /// The number of images rendered to in rotation
+    pub image_count: usize,
+    pub extent: Extent2D,
+    /// False if rendering headless without a swapchain to present to
+    pub presentable: bool,
+}
+
 /// Keeps track of loaded resources across threads
 /// Automatically reference counts resources and removes no longer used ones with .cleanup()
 pub struct ResourceManager {
     context: Arc<VulkanContext>,
-    // The current swapchain
-    swapchain: RwLock<Option<Arc<Swapchain>>>,
+    // The images currently rendered to
+    target_info: RwLock<Option<TargetInfo>>,
     textures: ResourceSystem<Texture>,
     models: ResourceSystem<Model>,
     renderpasses: ResourceSystem<RenderPass>,
     pipelines: ResourceSystem<Pipeline>,
+    /// The vulkan pipelines created from the pipelines for each renderpass they are used in
+    pipeline_variants: RwLock<HashMap<PipelineVariantKey, Arc<PipelineVariant>>>,
+    variant_garbage: Mutex<Vec<Garbage<PipelineVariant>>>,
     materials: ResourceSystem<Material>,
+    environments: ResourceSystem<EnvironmentMap>,
+    /// The samplers created for each spec, shared by everything sampling with an identical spec
+    samplers: Mutex<HashMap<SamplerSpec, Arc<Sampler>>>,
+    /// Watches the files loaded resources were created from
+    watcher: FileWatcher,
+    /// Reads the resources loaded asynchronously
+    loader: Loader,
+    /// Uploads the resources read by the loader
+    uploader: Mutex<Uploader>,
+    /// The resources to store once their uploads have completed
+    uploads: Mutex<HashMap<UploadId, UploadCompletion>>,
+    /// Sampled in place of textures that are still loading
+    placeholder: Mutex<Option<Arc<Texture>>>,
+    /// The resources each resource was created from
+    dependencies: Mutex<DependencyGraph>,
+    /// The resources currently being loaded and the resources they have requested so far
+    load_stack: Mutex<Vec<(ResourceId, HashSet<ResourceId>)>>,
 }
 
 impl ResourceManager {
     /// Creates a new resource manager
     /// Should only exist one per application or graphics context
-    pub fn new(context: Arc<VulkanContext>) -> Self {
-        ResourceManager {
+    pub fn new(context: Arc<VulkanContext>) -> Result<Self> {
+        let uploader = Uploader::new(
+            &context.device,
+            &context.allocator,
+            context.upload_queue,
+            context.queue_families.graphics.unwrap(),
+        )?;
+
+        Ok(ResourceManager {
             context,
             textures: ResourceSystem::new(),
             models: ResourceSystem::new(),
-            swapchain: RwLock::new(None),
+            target_info: RwLock::new(None),
             renderpasses: ResourceSystem::new(),
             pipelines: ResourceSystem::new(),
+            pipeline_variants: RwLock::new(HashMap::new()),
+            variant_garbage: Mutex::new(Vec::new()),
             materials: ResourceSystem::new(),
-        }
+            environments: ResourceSystem::new(),
+            samplers: Mutex::new(HashMap::new()),
+            watcher: FileWatcher::new(WATCH_INTERVAL),
+            loader: Loader::new(LOAD_WORKERS),
+            uploader: Mutex::new(uploader),
+            uploads: Mutex::new(HashMap::new()),
+            placeholder: Mutex::new(None),
+            dependencies: Mutex::new(DependencyGraph::default()),
+            load_stack: Mutex::new(Vec::new()),
+        })
     }
 
     pub fn context(&self) -> &Arc<VulkanContext> {
         &self.context
     }
 
-    /// Sets the current swapchain
-    pub fn set_swapchain(&self, swapchain: Arc<Swapchain>) {
-        self.swapchain.write().unwrap().replace(swapchain);
+    /// Sets the description of the images currently rendered to
+    pub fn set_target_info(&self, target_info: TargetInfo) {
+        self.target_info.write().unwrap().replace(target_info);
     }
 
-    /// Returns the currently set swapchain if any
-    pub fn get_swapchain(&self) -> Option<Arc<Swapchain>> {
-        self.swapchain
-            .read()
-            .unwrap()
-            .as_ref()
-            .map(|val| Arc::clone(val))
+    /// Returns the currently set target info if any
+    pub fn target_info(&self) -> Option<TargetInfo> {
+        *self.target_info.read().unwrap()
     }
 
     /// Loads and stores a texture if it doesn't already exist
@@ -188,7 +675,63 @@ impl ResourceManager {
         self.textures.get(path)
     }
 
+    /// Starts loading a texture in the background if it isn't already loaded
+    /// Returns a handle that resolves to the texture once it has been uploaded
+    pub fn load_texture_async(&self, path: &str) -> LoadHandle<Texture> {
+        self.textures.load_async(self, path)
+    }
+
+    /// Returns the checkerboard texture sampled in place of textures that are still loading
+    pub fn placeholder_texture(&self) -> Result<Arc<Texture>> {
+        let mut placeholder = self.placeholder.lock().unwrap();
+        if let Some(texture) = &*placeholder {
+            return Ok(Arc::clone(texture));
+        }
+
+        let pixels = (0..PLACEHOLDER_SIZE * PLACEHOLDER_SIZE)
+            .flat_map(
+                |i| match (i % PLACEHOLDER_SIZE + i / PLACEHOLDER_SIZE) % 2 {
+                    0 => [255, 0, 255, 255],
+                    _ => [0, 0, 0, 255],
+                },
+            )
+            .collect();
+
+        let texture = Arc::new(Texture::from_data(
+            &self.context,
+            TextureData::new(
+                "placeholder",
+                (PLACEHOLDER_SIZE, PLACEHOLDER_SIZE).into(),
+                vk::Format::R8G8B8A8_SRGB,
+                1,
+                pixels,
+            )?,
+        )?);
+        placeholder.replace(Arc::clone(&texture));
+        Ok(texture)
+    }
+
+    /// Returns the sampler for spec
+    /// The sampler is created unless one already exists for an identical spec
+    pub fn sampler(&self, spec: &SamplerSpec) -> Result<Arc<Sampler>> {
+        let mut samplers = self.samplers.lock().unwrap();
+        if let Some(sampler) = samplers.get(spec) {
+            return Ok(Arc::clone(sampler));
+        }
+
+        let sampler = Arc::new(Sampler::new(&self.context, spec)?);
+        samplers.insert(*spec, Arc::clone(&sampler));
+        Ok(sampler)
+    }
+
+    /// Stores a texture that wasn't loaded from disk, E.g; a render target
+    /// Subsequent loads of name will return the inserted texture
+    pub fn insert_texture(&self, name: &str, texture: Arc<Texture>) -> Option<Arc<Texture>> {
+        self.textures.insert(name, texture)
+    }
+
     /// Loads and stores a model if it doesn't already exist
+    /// .gltf and .glb files are loaded as glTF and all other files as collada
     /// The model will be stored as the path name
     /// If a model with the name already exists, the existing one will be returned
     /// Will not block for write access if model is already loaded
@@ -196,6 +739,12 @@ impl ResourceManager {
         self.models.load(self, path)
     }
 
+    /// Starts loading a model in the background if it isn't already loaded
+    /// Returns a handle that resolves to the model once its meshes have been uploaded
+    pub fn load_model_async(&self, path: &str) -> LoadHandle<Model> {
+        self.models.load_async(self, path)
+    }
+
     /// path to return a reference to an already loaded model
     /// Returns None if the model isn't loaded
     pub fn get_model(&self, path: &str) -> Option<Arc<Model>> {
@@ -212,7 +761,33 @@ impl ResourceManager {
     /// The renderpass will be stored as the path name
     /// If a renderpass with the name already exists, the existing one will be returned
     pub fn load_pipeline(&self, path: &str) -> Result<Arc<Pipeline>> {
-        self.pipelines.load(&self, path)
+        self.pipelines.load(self, path)
+    }
+
+    /// Returns the vulkan pipeline for a pipeline in a renderpass with a vertex layout
+    /// The variant is created the first time it is requested and kept for as long as its pipeline
+    /// and renderpass are loaded
+    /// The pipeline and renderpass are loaded by name if they aren't already
+    pub fn load_pipeline_variant(&self, key: &PipelineVariantKey) -> Result<Arc<PipelineVariant>> {
+        if let Some(variant) = self.pipeline_variants.read().unwrap().get(key) {
+            return Ok(Arc::clone(variant));
+        }
+
+        let pipeline = self.load_pipeline(&key.pipeline)?;
+        let renderpass = self.load_renderpass(&key.renderpass)?;
+        let variant = Arc::new(PipelineVariant::new(
+            &self.context.device,
+            self.context.features(),
+            &pipeline,
+            &renderpass,
+            &key.vertex_layout,
+        )?);
+
+        self.pipeline_variants
+            .write()
+            .unwrap()
+            .insert(key.clone(), Arc::clone(&variant));
+        Ok(variant)
     }
 
     /// Loads and stores a renderpass from json if it doesn't already exist
@@ -222,6 +797,16 @@ impl ResourceManager {
         self.renderpasses.load(self, path)
     }
 
+    /// Stores a renderpass that wasn't loaded from disk, E.g; one created by the renderer
+    /// Subsequent loads of name will return the inserted renderpass
+    pub fn insert_renderpass(
+        &self,
+        name: &str,
+        renderpass: Arc<RenderPass>,
+    ) -> Option<Arc<RenderPass>> {
+        self.renderpasses.insert(name, renderpass)
+    }
+
     /// path to return a reference to an already loaded model
     /// Returns None if the renderpass isn't loaded
     pub fn get_renderpass(&self, path: &str) -> Option<Arc<RenderPass>> {
@@ -240,23 +825,69 @@ impl ResourceManager {
     pub fn get_material(&self, path: &str) -> Option<Arc<Material>> {
         self.materials.get(path)
     }
+
+    /// Starts generating the environment lighting of a cube map texture in the background if it
+    /// isn't already loaded
+    /// Returns a handle that resolves to the environment once its cube maps have been uploaded
+    pub fn load_environment_async(&self, path: &str) -> LoadHandle<EnvironmentMap> {
+        self.environments.load_async(self, path)
+    }
+
     /// Will place each resource no longer used in a garbage list
     /// The actual resource will get deleted after garbage_cycles cleanup cycles so that it is no longer in use by a pipeline
     /// Should only be called from one thread to avoid thread blocking
     pub fn collect_garbage(&self, garbage_cycles: u32) {
-        self.textures.collect_garbage(garbage_cycles);
-        self.models.collect_garbage(garbage_cycles);
-        self.renderpasses.collect_garbage(garbage_cycles);
-        self.pipelines.collect_garbage(garbage_cycles);
-        self.materials.collect_garbage(garbage_cycles);
+        // Variants keep their pipeline alive and are collected first, once nothing but the variants
+        // and the pipeline system refer to the pipeline or it has been replaced
+        let mut garbage = self.variant_garbage.lock().unwrap();
+        let mut variants = self.pipeline_variants.write().unwrap();
+        garbage.retain(|v| v.cycles_remaining > 0);
+        garbage.iter_mut().for_each(|v| v.cycles_remaining -= 1);
+
+        let mut variant_refs: HashMap<*const Pipeline, usize> = HashMap::new();
+        for variant in variants.values() {
+            *variant_refs.entry(Arc::as_ptr(variant.base())).or_default() += 1;
+        }
+
+        variants.retain(|key, variant| {
+            let base = variant.base();
+            let used = Arc::strong_count(base) > 1 + variant_refs[&Arc::as_ptr(base)];
+            let current = self
+                .pipelines
+                .get(&key.pipeline)
+                .is_some_and(|pipeline| Arc::ptr_eq(&pipeline, base));
+
+            if Arc::strong_count(variant) > 1 || (used && current) {
+                true
+            } else {
+                garbage.push(Garbage::new(Arc::clone(variant), garbage_cycles));
+                false
+            }
+        });
+        drop(variants);
+        drop(garbage);
+
+        // Dependents are collected first so that the resources they depend on can be collected once
+        // their garbage has been freed
+        self.materials.collect_garbage(self, garbage_cycles);
+        // Samplers are kept alive by the materials using them, including collected ones that are
+        // still in flight
+        self.samplers
+            .lock()
+            .unwrap()
+            .retain(|_, sampler| Arc::strong_count(sampler) > 1);
+        self.pipelines.collect_garbage(self, garbage_cycles);
+        self.renderpasses.collect_garbage(self, garbage_cycles);
+        self.models.collect_garbage(self, garbage_cycles);
+        self.environments.collect_garbage(self, garbage_cycles);
+        self.textures.collect_garbage(self, garbage_cycles);
     }
 
     pub fn recreate(&self) -> Result<()> {
-        let swapchain = self.swapchain.read().unwrap();
-        let swapchain = swapchain.as_ref().unwrap();
+        let target_info = self.target_info().unwrap();
 
-        let color_format = swapchain.format();
-        let depth_format = swapchain.depth_format();
+        let color_format = target_info.color_format;
+        let depth_format = target_info.depth_format;
 
         // Separate into separate scopes to drop Write lock
         {
@@ -273,20 +904,17 @@ impl ResourceManager {
             // Replace the old with the new
             let _ = std::mem::replace(&mut *renderpasses, new_renderpasses);
         }
-        {
-            let mut pipelines = self.pipelines.resources.write().unwrap();
-            // Now recreate the pipelines
-            // They will query self for the renderpasses which are now replaced
-            let new_pipelines: HashMap<_, _> = pipelines
-                .iter()
-                .map(|(k, v)| match v.recreate(&self) {
-                    Ok(v) => Ok((k.to_owned(), Arc::new(v))),
-                    Err(e) => Err(e),
-                })
-                .collect::<Result<HashMap<_, _>>>()?;
 
-            let _ = std::mem::replace(&mut *pipelines, new_pipelines);
-        }
+        // The variants refer to the old renderpasses and are created again when next requested
+        self.pipeline_variants.write().unwrap().clear();
+
+        self.recreate_materials()
+    }
+
+    /// Creates all materials again so that they sample the textures currently registered under
+    /// their names
+    /// Called when textures materials may use, like render graph resources, are replaced
+    pub fn recreate_materials(&self) -> Result<()> {
         {
             let mut materials = self.materials.resources.write().unwrap();
             // Now recreate the pipelines
@@ -304,23 +932,470 @@ impl ResourceManager {
         Ok(())
     }
 
+    /// Returns a handle to the resource of type T stored as name
+    /// The handle resolves once the resource is loaded and keeps resolving to the current version
+    /// across reloads
+    pub fn handle<T: ManagedResource>(&self, name: &str) -> Handle<T> {
+        T::system(self).handle(name)
+    }
+
+    /// Returns the resource a handle refers to if it is loaded
+    pub fn resolve<T: ManagedResource>(&self, handle: Handle<T>) -> Option<Arc<T>> {
+        T::system(self).resolve(handle)
+    }
+
+    /// Starts recording the resources requested while loading the resource with id
+    fn begin_load(&self, id: ResourceId) {
+        self.load_stack.lock().unwrap().push((id, HashSet::new()));
+    }
+
+    /// Stops recording for the resource with id and stores what it requested as its dependencies
+    /// if it loaded successfully
+    fn end_load(&self, id: ResourceId, loaded: bool) {
+        let (top, dependencies) = self
+            .load_stack
+            .lock()
+            .unwrap()
+            .pop()
+            .expect("Mismatched resource load tracking");
+        debug_assert_eq!(top, id);
+
+        if loaded {
+            self.dependencies.lock().unwrap().set(id, dependencies);
+        }
+    }
+
+    /// Records that the resource currently being loaded, if any, depends on the resource with id
+    fn record_dependency(&self, id: ResourceId) {
+        if let Some((_, dependencies)) = self.load_stack.lock().unwrap().last_mut() {
+            dependencies.insert(id);
+        }
+    }
+
+    /// Records and submits the upload of a resource read by a worker
+    /// The resource is stored once the upload has completed
+    fn upload<T: AsyncResource>(&self, path: &str, data: T::Data) {
+        let uploaded = (|| {
+            let mut upload = self.uploader.lock().unwrap().begin()?;
+            let resource = match T::upload(self, data, &mut upload) {
+                Ok(resource) => resource,
+                Err(e) => {
+                    self.uploader.lock().unwrap().discard(upload);
+                    return Err(e);
+                }
+            };
+
+            let id = self.uploader.lock().unwrap().submit(upload)?;
+            Ok((id, resource))
+        })();
+
+        match uploaded {
+            Ok((id, resource)) => {
+                let path = path.to_owned();
+                self.uploads.lock().unwrap().insert(
+                    id,
+                    Box::new(move |resourcemanager: &ResourceManager| {
+                        T::system(resourcemanager).finish(resourcemanager, &path, Ok(resource))
+                    }),
+                );
+            }
+            Err(e) => T::system(self).finish(self, path, Err(e)),
+        }
+    }
+
+    /// Returns true if any resource is still loading in the background
+    pub fn is_loading(&self) -> bool {
+        self.textures.is_loading() || self.models.is_loading() || self.environments.is_loading()
+    }
+
+    /// Advances the resources loading in the background
+    /// Uploads the resources workers have finished reading and stores the ones whose uploads have
+    /// completed, resolving their handles
+    /// Returns true if any material was replaced, in which case materials held elsewhere should be
+    /// requested again
+    /// Should be called regularly from the thread owning the resource manager, E.g; each frame
+    pub fn update_async(&self) -> bool {
+        for read in self.loader.finished() {
+            read(self);
+        }
+
+        // glTF files are shared by the loads of their parts until nothing is loading
+        if !self.is_loading() {
+            gltf::clear_shared();
+        }
+
+        let completed = self.uploader.lock().unwrap().poll();
+        if completed.is_empty() {
+            return false;
+        }
+
+        let completions: Vec<UploadCompletion> = {
+            let mut uploads = self.uploads.lock().unwrap();
+            completed
+                .iter()
+                .filter_map(|id| uploads.remove(id))
+                .collect()
+        };
+        for completion in completions {
+            completion(self);
+        }
+
+        // Materials created while their textures were loading are created again once all their
+        // textures are
+        let materials = self.materials.reload(
+            self,
+            |_, _, material| {
+                !material.is_complete()
+                    && material
+                        .spec()
+                        .textures()
+                        .iter()
+                        .all(|texture| self.textures.get(texture).is_some())
+            },
+            RELOAD_GARBAGE_CYCLES,
+        );
+
+        !materials.is_empty()
+    }
+
+    /// Starts watching the file of a loaded resource and the files it depends on
+    fn watch(&self, path: &str, dependencies: &[PathBuf]) {
+        self.watcher.watch(path);
+        dependencies
+            .iter()
+            .for_each(|dependency| self.watcher.watch(dependency));
+    }
+
+    /// Reloads the resources whose files changed on disk and swaps them in place
+    /// Changes propagate to dependent resources, E.g; materials are recreated when their pipeline
+    /// or one of their textures is reloaded
+    /// Resources that fail to reload are logged and kept as they were
+    /// Returns true if any resource was replaced, in which case resources held elsewhere should be
+    /// requested again
+    pub fn reload_changed(&self) -> bool {
+        let changed = self.watcher.poll();
+        if changed.is_empty() {
+            return false;
+        }
+
+        // A resource is reloaded if its files changed or if it depends on a reloaded resource
+        // The systems are reloaded in dependency order so that dependents see the new versions
+        let stale = |id: ResourceId,
+                     name: &str,
+                     dependencies: Vec<PathBuf>,
+                     reloaded: &HashSet<ResourceId>| {
+            changed.iter().any(|path| path == Path::new(name))
+                || dependencies
+                    .iter()
+                    .any(|dependency| changed.contains(dependency))
+                || self.dependencies.lock().unwrap().depends_on(id, reloaded)
+        };
+
+        let mut reloaded = HashSet::new();
+        let textures = self.textures.reload(
+            self,
+            |id, name, texture| stale(id, name, texture.dependencies(), &reloaded),
+            RELOAD_GARBAGE_CYCLES,
+        );
+        reloaded.extend(textures);
+        let environments = self.environments.reload(
+            self,
+            |id, name, environment| stale(id, name, environment.dependencies(), &reloaded),
+            RELOAD_GARBAGE_CYCLES,
+        );
+        reloaded.extend(environments);
+        let models = self.models.reload(
+            self,
+            |id, name, model| stale(id, name, model.dependencies(), &reloaded),
+            RELOAD_GARBAGE_CYCLES,
+        );
+        reloaded.extend(models);
+        let renderpasses = self.renderpasses.reload(
+            self,
+            |id, name, renderpass| stale(id, name, renderpass.dependencies(), &reloaded),
+            RELOAD_GARBAGE_CYCLES,
+        );
+        reloaded.extend(renderpasses);
+        let pipelines = self.pipelines.reload(
+            self,
+            |id, name, pipeline| stale(id, name, pipeline.dependencies(), &reloaded),
+            RELOAD_GARBAGE_CYCLES,
+        );
+        reloaded.extend(pipelines);
+
+        // The variants of replaced pipelines and renderpasses are created again when next
+        // requested
+        {
+            let is_reloaded = |id: Option<ResourceId>| id.is_some_and(|id| reloaded.contains(&id));
+            let mut garbage = self.variant_garbage.lock().unwrap();
+            self.pipeline_variants
+                .write()
+                .unwrap()
+                .retain(|key, variant| {
+                    if is_reloaded(self.pipelines.id(&key.pipeline))
+                        || is_reloaded(self.renderpasses.id(&key.renderpass))
+                    {
+                        garbage.push(Garbage::new(Arc::clone(variant), RELOAD_GARBAGE_CYCLES));
+                        false
+                    } else {
+                        true
+                    }
+                });
+        }
+
+        let materials = self.materials.reload(
+            self,
+            |id, name, material| stale(id, name, material.dependencies(), &reloaded),
+            RELOAD_GARBAGE_CYCLES,
+        );
+        reloaded.extend(materials);
+
+        !reloaded.is_empty()
+    }
+
     /// Returns a descripctive status about the resources currently managed
     pub fn info(&self) -> Vec<ResourceInfo> {
         let mut result = Vec::new();
-        result.extend(self.textures.info());
-        result.extend(self.models.info());
-        result.extend(self.renderpasses.info());
-        result.extend(self.pipelines.info());
-        result.extend(self.materials.info());
+        result.extend(self.textures.info(self));
+        result.extend(self.environments.info(self));
+        result.extend(self.models.info(self));
+        result.extend(self.renderpasses.info(self));
+        result.extend(self.pipelines.info(self));
+        result.extend(
+            self.pipeline_variants
+                .read()
+                .unwrap()
+                .iter()
+                .map(|(key, variant)| ResourceInfo {
+                    name: format!("{} in {}", key.pipeline, key.renderpass),
+                    ty: "PipelineVariant",
+                    id: None,
+                    dependencies: self
+                        .pipelines
+                        .id(&key.pipeline)
+                        .into_iter()
+                        .chain(self.renderpasses.id(&key.renderpass))
+                        .collect(),
+                    strong_refs: Arc::strong_count(variant),
+                    weak_refs: Arc::weak_count(variant),
+                }),
+        );
+        result.extend(self.materials.info(self));
 
         result
     }
+
+    /// Returns the graph of the managed resources and their dependencies in the DOT format
+    /// Edges point from a resource to the resources it depends on
+    pub fn dependency_graph(&self) -> String {
+        let mut dot = String::from("digraph resources {\n");
+        for (index, info) in self.info().iter().enumerate() {
+            let node = match info.id {
+                Some(id) => format!("r{}", id),
+                None => format!("n{}", index),
+            };
+
+            dot.push_str(&format!(
+                "    {} [label=\"{} {}\\n{} refs\"];\n",
+                node,
+                info.ty,
+                info.name.replace('"', "\\\""),
+                info.strong_refs
+            ));
+            for dependency in &info.dependencies {
+                dot.push_str(&format!("    {} -> r{};\n", node, dependency));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+impl ManagedResource for Texture {
+    fn system(resourcemanager: &ResourceManager) -> &ResourceSystem<Self> {
+        &resourcemanager.textures
+    }
+}
+
+impl ManagedResource for Model {
+    fn system(resourcemanager: &ResourceManager) -> &ResourceSystem<Self> {
+        &resourcemanager.models
+    }
+}
+
+impl ManagedResource for RenderPass {
+    fn system(resourcemanager: &ResourceManager) -> &ResourceSystem<Self> {
+        &resourcemanager.renderpasses
+    }
+}
+
+impl ManagedResource for Pipeline {
+    fn system(resourcemanager: &ResourceManager) -> &ResourceSystem<Self> {
+        &resourcemanager.pipelines
+    }
+}
+
+impl ManagedResource for Material {
+    fn system(resourcemanager: &ResourceManager) -> &ResourceSystem<Self> {
+        &resourcemanager.materials
+    }
+}
+
+impl ManagedResource for EnvironmentMap {
+    fn system(resourcemanager: &ResourceManager) -> &ResourceSystem<Self> {
+        &resourcemanager.environments
+    }
 }
 
 impl Drop for ResourceManager {
     fn drop(&mut self) {
         info!("Dropping resource manager");
         unsafe { self.context.device.device_wait_idle().unwrap() }
+        // Uploads that haven't been polled still own their resources
+        self.uploads.lock().unwrap().clear();
         // Drop all other values
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn load_handle_transitions() {
+        let handle = LoadHandle::new(LoadState::Loading);
+        let clone = handle.clone();
+        assert!(clone.is_loading());
+        assert!(clone.get().is_none());
+        assert!(clone.error().is_none());
+
+        // Every clone sees the state set through any handle
+        handle.set(LoadState::Ready(Arc::new(5)));
+        assert!(!clone.is_loading());
+        assert_eq!(clone.get().as_deref(), Some(&5));
+        assert!(clone.error().is_none());
+
+        handle.set(LoadState::Failed("missing file".to_owned()));
+        assert!(!clone.is_loading());
+        assert!(clone.get().is_none());
+        assert_eq!(clone.error().as_deref(), Some("missing file"));
+    }
+
+    /// Spawns count jobs incrementing counter and returns the counter
+    fn spawn_jobs(loader: &Loader, count: usize, delay: Duration) -> Arc<AtomicUsize> {
+        let counter = Arc::new(AtomicUsize::new(0));
+        for _ in 0..count {
+            let counter = Arc::clone(&counter);
+            loader.spawn(Box::new(move || {
+                thread::sleep(delay);
+                counter.fetch_add(1, Ordering::SeqCst);
+                Box::new(|_: &ResourceManager| {})
+            }));
+        }
+        counter
+    }
+
+    #[test]
+    fn loader_returns_results() {
+        let loader = Loader::new(2);
+        spawn_jobs(&loader, 8, Duration::from_millis(0));
+
+        let mut finished = 0;
+        for _ in 0..1000 {
+            finished += loader.finished().len();
+            if finished == 8 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(finished, 8);
+        assert!(loader.finished().is_empty());
+    }
+
+    #[test]
+    fn loader_shutdown_finishes_queued_jobs() {
+        let loader = Loader::new(2);
+        let counter = spawn_jobs(&loader, 6, Duration::from_millis(5));
+
+        // Dropping joins the workers once the queue is drained
+        drop(loader);
+        assert_eq!(counter.load(Ordering::SeqCst), 6);
+    }
+
+    struct Dummy;
+
+    impl Resource for Dummy {
+        fn load(_: &ResourceManager, _: &str) -> Result<Self> {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn handles_resolve_by_normalized_name() {
+        let system = ResourceSystem::<Dummy>::new();
+        let handle = system.handle("./data//a.json");
+        assert!(system.resolve(handle).is_none());
+        assert_eq!(system.id("data/a.json"), Some(handle.id()));
+        assert_eq!(system.name(handle.id()).as_deref(), Some("data/a.json"));
+
+        let resource = Arc::new(Dummy);
+        system.insert("data/b/../a.json", Arc::clone(&resource));
+        assert!(Arc::ptr_eq(&system.resolve(handle).unwrap(), &resource));
+    }
+
+    #[test]
+    fn garbage_after_last_reference_drops() {
+        let system = ResourceSystem::<Dummy>::new();
+        let graph = Mutex::new(DependencyGraph::default());
+        let handle = system.handle("a");
+        system.insert("a", Arc::new(Dummy));
+
+        let resource = system.resolve(handle).unwrap();
+        system.collect(&graph, 1);
+        assert!(system.resolve(handle).is_some());
+        assert!(system.garbage.lock().unwrap().is_empty());
+
+        drop(resource);
+        system.collect(&graph, 1);
+        assert!(system.resolve(handle).is_none());
+        assert_eq!(system.id("a"), None);
+        assert_eq!(system.name(handle.id()), None);
+        assert_eq!(system.garbage.lock().unwrap().len(), 1);
+
+        // Freed once the cycles have passed
+        system.collect(&graph, 1);
+        assert_eq!(system.garbage.lock().unwrap().len(), 1);
+        system.collect(&graph, 1);
+        assert!(system.garbage.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn garbage_keeps_dependencies_of_live_resources() {
+        let system = ResourceSystem::<Dummy>::new();
+        let graph = Mutex::new(DependencyGraph::default());
+        let dependent = system.handle("material");
+        let dependency = system.handle("texture");
+        system.insert("material", Arc::new(Dummy));
+        system.insert("texture", Arc::new(Dummy));
+        graph
+            .lock()
+            .unwrap()
+            .set(dependent.id(), [dependency.id()].iter().copied().collect());
+
+        let material = system.resolve(dependent).unwrap();
+        system.collect(&graph, 0);
+        assert!(system.resolve(dependency).is_some());
+
+        // The dependency is collected in the cycle after its dependent
+        drop(material);
+        system.collect(&graph, 0);
+        assert!(system.resolve(dependent).is_none());
+        assert!(system.resolve(dependency).is_some());
+        assert!(!graph.lock().unwrap().has_dependents(dependency.id()));
+
+        system.collect(&graph, 0);
+        assert!(system.resolve(dependency).is_none());
+    }
+}
@@ -1,47 +1,174 @@
 use ash::version::DeviceV1_0;
 use ash::vk;
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
 
-use super::Result;
+use super::enums::{AddressMode, BorderColor, CompareOp, Filter};
+use super::{Result, VulkanContext};
+
+/// Describes how a texture is sampled
+/// Samplers of identical specs are shared through ResourceManager::sampler
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct SamplerSpec {
+    pub mag_filter: Filter,
+    pub min_filter: Filter,
+    /// How samples of adjacent mip levels are combined
+    pub mipmap_mode: Filter,
+    pub address_mode_u: AddressMode,
+    pub address_mode_v: AddressMode,
+    pub address_mode_w: AddressMode,
+    pub border_color: BorderColor,
+    /// The maximum anisotropy of anisotropic filtering, which is disabled if None
+    /// Clamped to the limit of the device and ignored if the device doesn't support it
+    pub anisotropy: Option<f32>,
+    pub mip_lod_bias: f32,
+    /// The range of mip levels that are sampled
+    /// Mip levels are numbered from 0 which is the full size image
+    pub min_lod: f32,
+    pub max_lod: f32,
+    /// Samples are compared against a reference value instead of returned if Some, E.g; for
+    /// shadow mapping
+    pub compare_op: Option<CompareOp>,
+}
+
+impl Default for SamplerSpec {
+    /// Trilinear and anisotropic filtering of all mip levels with repeat addressing
+    fn default() -> Self {
+        SamplerSpec {
+            mag_filter: Filter::Linear,
+            min_filter: Filter::Linear,
+            mipmap_mode: Filter::Linear,
+            address_mode_u: AddressMode::Repeat,
+            address_mode_v: AddressMode::Repeat,
+            address_mode_w: AddressMode::Repeat,
+            border_color: BorderColor::OpaqueBlack,
+            anisotropy: Some(16.0),
+            mip_lod_bias: 0.0,
+            min_lod: 0.0,
+            max_lod: vk::LOD_CLAMP_NONE,
+            compare_op: None,
+        }
+    }
+}
+
+impl SamplerSpec {
+    /// Compares against depth textures for shadow mapping
+    /// Samples outside the texture are treated as lit
+    pub fn shadow() -> Self {
+        SamplerSpec {
+            mag_filter: Filter::Nearest,
+            min_filter: Filter::Nearest,
+            mipmap_mode: Filter::Nearest,
+            address_mode_u: AddressMode::ClampToBorder,
+            address_mode_v: AddressMode::ClampToBorder,
+            address_mode_w: AddressMode::ClampToBorder,
+            border_color: BorderColor::OpaqueWhite,
+            anisotropy: None,
+            mip_lod_bias: 0.0,
+            min_lod: 0.0,
+            max_lod: 0.0,
+            compare_op: Some(CompareOp::LessOrEqual),
+        }
+    }
+
+    fn key(&self) -> SpecKey {
+        SpecKey {
+            filters: [self.mag_filter, self.min_filter, self.mipmap_mode],
+            address_modes: [
+                self.address_mode_u,
+                self.address_mode_v,
+                self.address_mode_w,
+            ],
+            border_color: self.border_color,
+            anisotropy: self.anisotropy.map(f32::to_bits),
+            lod: [
+                self.mip_lod_bias.to_bits(),
+                self.min_lod.to_bits(),
+                self.max_lod.to_bits(),
+            ],
+            compare_op: self.compare_op,
+        }
+    }
+}
+
+/// The fields of a spec with floats as their bits so that specs can be compared and hashed
+#[derive(PartialEq, Eq, Hash)]
+struct SpecKey {
+    filters: [Filter; 3],
+    address_modes: [AddressMode; 3],
+    border_color: BorderColor,
+    anisotropy: Option<u32>,
+    lod: [u32; 3],
+    compare_op: Option<CompareOp>,
+}
+
+impl PartialEq for SamplerSpec {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for SamplerSpec {}
+
+impl Hash for SamplerSpec {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
 
 pub struct Sampler {
     device: ash::Device,
     sampler: vk::Sampler,
+    spec: SamplerSpec,
 }
 
 impl Sampler {
-    pub fn new(device: &ash::Device) -> Result<Sampler> {
+    /// Creates a sampler from spec
+    /// Prefer ResourceManager::sampler which shares samplers between identical specs
+    pub fn new(context: &VulkanContext, spec: &SamplerSpec) -> Result<Sampler> {
+        let anisotropy = spec
+            .anisotropy
+            .filter(|_| context.features().sampler_anisotropy == vk::TRUE)
+            .map(|anisotropy| anisotropy.clamp(1.0, context.limits().max_sampler_anisotropy));
+
         let sampler_info = vk::SamplerCreateInfo {
             s_type: vk::StructureType::SAMPLER_CREATE_INFO,
-            mag_filter: vk::Filter::LINEAR,
-            min_filter: vk::Filter::LINEAR,
-            address_mode_u: vk::SamplerAddressMode::REPEAT,
-            address_mode_v: vk::SamplerAddressMode::REPEAT,
-            address_mode_w: vk::SamplerAddressMode::REPEAT,
-            anisotropy_enable: vk::TRUE,
-            max_anisotropy: 16.0,
-            border_color: vk::BorderColor::INT_OPAQUE_BLACK,
+            mag_filter: spec.mag_filter.into(),
+            min_filter: spec.min_filter.into(),
+            address_mode_u: spec.address_mode_u.into(),
+            address_mode_v: spec.address_mode_v.into(),
+            address_mode_w: spec.address_mode_w.into(),
+            anisotropy_enable: anisotropy.is_some() as vk::Bool32,
+            max_anisotropy: anisotropy.unwrap_or(1.0),
+            border_color: spec.border_color.into(),
             unnormalized_coordinates: vk::FALSE,
-            compare_enable: vk::FALSE,
-            compare_op: vk::CompareOp::ALWAYS,
-            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
-            mip_lod_bias: 0.0,
-            min_lod: 0.0,
-            max_lod: 0.0,
+            compare_enable: spec.compare_op.is_some() as vk::Bool32,
+            compare_op: spec.compare_op.unwrap_or(CompareOp::Always).into(),
+            mipmap_mode: spec.mipmap_mode.into(),
+            mip_lod_bias: spec.mip_lod_bias,
+            min_lod: spec.min_lod,
+            max_lod: spec.max_lod,
             flags: Default::default(),
             p_next: std::ptr::null(),
         };
 
-        let sampler = unsafe { device.create_sampler(&sampler_info, None)? };
+        let sampler = unsafe { context.device.create_sampler(&sampler_info, None)? };
 
         Ok(Sampler {
-            device: device.clone(),
+            device: context.device.clone(),
             sampler,
+            spec: *spec,
         })
     }
 
     pub fn vk(&self) -> vk::Sampler {
         self.sampler
     }
+
+    pub fn spec(&self) -> &SamplerSpec {
+        &self.spec
+    }
 }
 
 impl Drop for Sampler {
@@ -0,0 +1,350 @@
+use super::{cooked, normalize, CookedKind, Error, Result};
+use crate::vfs;
+use bevy_glsl_to_spirv::ShaderType;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// glslang's process wide state can't be set up by several threads at once
+static COMPILER: Mutex<()> = Mutex::new(());
+
+/// The SPIR-V of a shader stage and the files it was created from
+pub struct CompiledShader {
+    pub code: Vec<u32>,
+    /// The shader file followed by every file it includes
+    pub files: Vec<PathBuf>,
+}
+
+/// A GLSL shader compiled ahead of time with a set of defines
+/// Shaders are cooked to a list of these, one for each set of defines they are used with
+#[derive(Serialize, Deserialize)]
+pub struct CookedShader {
+    pub defines: HashMap<String, String>,
+    pub code: Vec<u32>,
+    /// The shader file followed by every file it includes
+    pub files: Vec<String>,
+}
+
+impl CookedShader {
+    pub fn new(compiled: CompiledShader, defines: &HashMap<String, String>) -> Self {
+        CookedShader {
+            defines: defines.clone(),
+            code: compiled.code,
+            files: compiled
+                .files
+                .iter()
+                .map(|file| file.to_string_lossy().into_owned())
+                .collect(),
+        }
+    }
+}
+
+/// Loads the SPIR-V of a shader stage
+/// GLSL sources are recognized by their stage extension, E.g; .vert or .frag, and compiled with
+/// defines set after the #version directive unless a cooked variant with the same defines is up
+/// to date
+/// Any other file is read as precompiled SPIR-V and defines are ignored
+pub fn load(path: &str, defines: &HashMap<String, String>) -> Result<CompiledShader> {
+    if is_glsl(path) {
+        if let Some(compiled) = load_cooked(path, defines) {
+            return Ok(compiled);
+        }
+    }
+
+    compile(path, defines)
+}
+
+/// Returns true if path is a GLSL source recognized by its stage extension
+pub fn is_glsl(path: &str) -> bool {
+    stage(path).is_some()
+}
+
+fn stage(path: &str) -> Option<ShaderType> {
+    match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+        Some("vert") => Some(ShaderType::Vertex),
+        Some("frag") => Some(ShaderType::Fragment),
+        Some("geom") => Some(ShaderType::Geometry),
+        Some("tesc") => Some(ShaderType::TessellationControl),
+        Some("tese") => Some(ShaderType::TessellationEvaluation),
+        Some("comp") => Some(ShaderType::Compute),
+        _ => None,
+    }
+}
+
+/// Returns the cooked variant of path compiled with defines if neither the shader nor its
+/// includes have changed since it was cooked
+fn load_cooked(path: &str, defines: &HashMap<String, String>) -> Option<CompiledShader> {
+    let cooked_path = cooked::find(CookedKind::Shader, path)?;
+    let variants: Vec<CookedShader> = match cooked::read(&cooked_path) {
+        Ok(variants) => variants,
+        Err(e) => {
+            log::warn!("{}", e);
+            return None;
+        }
+    };
+
+    let variant = variants
+        .into_iter()
+        .find(|variant| &variant.defines == defines)?;
+
+    if cooked::is_stale(&cooked_path, &variant.files) {
+        return None;
+    }
+
+    Some(CompiledShader {
+        code: variant.code,
+        files: variant.files.into_iter().map(PathBuf::from).collect(),
+    })
+}
+
+/// Compiles a GLSL source or reads a SPIR-V file without considering cooked variants
+pub fn compile(path: &str, defines: &HashMap<String, String>) -> Result<CompiledShader> {
+    let ty = match stage(path) {
+        Some(ty) => ty,
+        None => {
+            let code = ash::util::read_spv(&mut Cursor::new(vfs::read(path)?))
+                .map_err(|e| Error::SPVReadError(e, path.to_owned()))?;
+
+            return Ok(CompiledShader {
+                code,
+                files: vec![PathBuf::from(path)],
+            });
+        }
+    };
+
+    let mut files = Vec::new();
+    let mut source = String::new();
+    preprocess(
+        Path::new(path),
+        defines,
+        &mut files,
+        &mut Vec::new(),
+        &mut source,
+    )?;
+
+    let code = {
+        let _guard = COMPILER.lock().unwrap();
+        bevy_glsl_to_spirv::compile(&source, ty, None)
+    };
+
+    // Errors are reported as <source string>:<line>, name the files they refer to
+    let code = code.map_err(|log| {
+        let legend: Vec<String> = files
+            .iter()
+            .enumerate()
+            .map(|(index, file)| format!("{}: {}", index, file.display()))
+            .collect();
+        Error::ShaderCompile(
+            path.to_owned(),
+            format!("{}Source strings:\n{}", log, legend.join("\n")),
+        )
+    })?;
+
+    Ok(CompiledShader { code, files })
+}
+
+/// Appends the source of path to out with #include directives replaced by the included files
+/// Includes are resolved relative to the including file
+/// #line directives keep the line numbers of errors and give each file its own source string
+/// number, its index in files
+/// Includes inside block comments are skipped, but conditionals are not evaluated so files included
+/// in inactive #if and #ifdef blocks are still read and must exist
+fn preprocess(
+    path: &Path,
+    defines: &HashMap<String, String>,
+    files: &mut Vec<PathBuf>,
+    stack: &mut Vec<PathBuf>,
+    out: &mut String,
+) -> Result<()> {
+    if stack.iter().any(|included| included == path) {
+        return Err(Error::ShaderCompile(
+            stack[0].display().to_string(),
+            format!("{} includes itself", path.display()),
+        ));
+    }
+
+    let source = vfs::read_to_string(&path.to_string_lossy())?;
+    let index = files.len();
+    files.push(path.to_owned());
+    stack.push(path.to_owned());
+
+    if stack.len() > 1 {
+        out.push_str(&format!("#line 1 {}\n", index));
+    }
+
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut in_comment = false;
+    for (line, text) in source.lines().enumerate() {
+        let directive = text.trim_start();
+        let commented = in_comment;
+        in_comment = ends_in_comment(text, in_comment);
+
+        if commented {
+            out.push_str(text);
+            out.push('\n');
+            continue;
+        }
+
+        if let Some(include) = directive.strip_prefix("#include") {
+            let name = include
+                .trim()
+                .trim_matches(|c| c == '"' || c == '<' || c == '>');
+            let include = normalize(&dir.join(name).to_string_lossy());
+            preprocess(Path::new(&include), defines, files, stack, out)?;
+            out.push_str(&format!("#line {} {}\n", line + 2, index));
+            continue;
+        }
+
+        out.push_str(text);
+        out.push('\n');
+
+        if stack.len() == 1 && directive.starts_with("#version") {
+            for (name, value) in defines {
+                out.push_str(&format!("#define {} {}\n", name, value));
+            }
+            out.push_str(&format!("#line {} {}\n", line + 2, index));
+        }
+    }
+
+    stack.pop();
+    Ok(())
+}
+
+/// Returns true if a block comment is open at the end of a line
+/// in_comment is true if one is open at its start
+fn ends_in_comment(text: &str, mut in_comment: bool) -> bool {
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (in_comment, c, chars.peek()) {
+            (true, '*', Some('/')) => {
+                chars.next();
+                in_comment = false;
+            }
+            (false, '/', Some('/')) => break,
+            (false, '/', Some('*')) => {
+                chars.next();
+                in_comment = true;
+            }
+            _ => {}
+        }
+    }
+    in_comment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes files to a new directory in the temp dir and returns it
+    fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sprocket-shader-{}", name));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (file, source) in files {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, source).unwrap();
+        }
+        dir
+    }
+
+    fn run(dir: &Path, defines: &HashMap<String, String>) -> Result<(String, Vec<PathBuf>)> {
+        let mut files = Vec::new();
+        let mut out = String::new();
+        let path = normalize(&dir.join("main.vert").to_string_lossy());
+        preprocess(
+            Path::new(&path),
+            defines,
+            &mut files,
+            &mut Vec::new(),
+            &mut out,
+        )?;
+        Ok((out, files))
+    }
+
+    #[test]
+    fn expands_nested_includes() {
+        let dir = write_files(
+            "nested",
+            &[
+                (
+                    "main.vert",
+                    "#version 450\n#include \"lib/a.glsl\"\nvoid main() {}\n",
+                ),
+                ("lib/a.glsl", "// a\n#include \"../b.glsl\"\nfloat a;\n"),
+                ("b.glsl", "float b;\n"),
+            ],
+        );
+
+        let (out, files) = run(&dir, &HashMap::new()).unwrap();
+        let names: Vec<_> = files
+            .iter()
+            .map(|file| file.strip_prefix(&dir).unwrap().to_owned())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                PathBuf::from("main.vert"),
+                PathBuf::from("lib/a.glsl"),
+                PathBuf::from("b.glsl")
+            ]
+        );
+        assert_eq!(
+            out,
+            "#version 450\n#line 2 0\n\
+             #line 1 1\n// a\n\
+             #line 1 2\nfloat b;\n\
+             #line 3 1\nfloat a;\n\
+             #line 3 0\nvoid main() {}\n"
+        );
+    }
+
+    #[test]
+    fn defines_follow_version() {
+        let dir = write_files(
+            "defines",
+            &[("main.vert", "#version 450\nvoid main() {}\n")],
+        );
+        let mut defines = HashMap::new();
+        defines.insert("SHADOWS".to_owned(), "1".to_owned());
+
+        let (out, _) = run(&dir, &defines).unwrap();
+        assert_eq!(
+            out,
+            "#version 450\n#define SHADOWS 1\n#line 2 0\nvoid main() {}\n"
+        );
+    }
+
+    #[test]
+    fn rejects_include_cycles() {
+        let dir = write_files(
+            "cycle",
+            &[
+                ("main.vert", "#version 450\n#include \"a.glsl\"\n"),
+                ("a.glsl", "#include \"b.glsl\"\n"),
+                ("b.glsl", "#include \"a.glsl\"\n"),
+            ],
+        );
+
+        match run(&dir, &HashMap::new()) {
+            Err(Error::ShaderCompile(_, reason)) => assert!(reason.contains("includes itself")),
+            _ => panic!("Include cycle was not rejected"),
+        }
+    }
+
+    #[test]
+    fn skips_includes_in_block_comments() {
+        let source = "#version 450\n/* #include \"missing.glsl\"\n#include \"missing.glsl\"\n*/ \
+                      // /*\n#include \"a.glsl\"\n";
+        let dir = write_files(
+            "comments",
+            &[("main.vert", source), ("a.glsl", "float a;\n")],
+        );
+
+        let (out, files) = run(&dir, &HashMap::new()).unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(out.contains("#include \"missing.glsl\""));
+        assert!(out.ends_with("#line 1 1\nfloat a;\n#line 6 0\n"));
+    }
+}
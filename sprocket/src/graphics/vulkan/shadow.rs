@@ -0,0 +1,189 @@
+use crate::graphics::{Camera, Projection};
+use crate::math::{Mat4, Quaternion, Vec3, Vec4};
+use crate::physics::Transform;
+use std::f32::consts::{FRAC_PI_2, PI};
+
+/// The width and height in texels of the shadow atlas
+pub const SHADOW_ATLAS_SIZE: u32 = 2048;
+
+/// The number of tiles along each side of the shadow atlas
+/// Each shadow view is rendered into its own tile, a directional light takes SHADOW_CASCADES
+/// tiles, a spot light one and a point light six
+/// The atlas fits at most 16 views, E.g; a directional light and two point lights, and lights
+/// closest to the camera are given tiles first while the rest are left without shadows
+pub const SHADOW_ATLAS_TILES: u32 = 4;
+
+/// The number of cascades a directional light is split into
+pub const SHADOW_CASCADES: usize = 4;
+
+/// How far from the camera directional lights cast shadows
+pub const SHADOW_DISTANCE: f32 = 40.0;
+
+/// How far behind each cascade shadow casters are included
+pub const SHADOW_CASTER_DISTANCE: f32 = 50.0;
+
+/// The near plane of spot and point light shadows
+pub const SHADOW_NEAR: f32 = 0.05;
+
+/// The name of the render graph resource the shadow casting lights are rendered into
+/// Sampled by every camera and expected to be SHADOW_ATLAS_SIZE in each dimension
+pub const SHADOW_ATLAS: &str = "shadow_atlas";
+
+/// A view into the shadow atlas
+#[derive(Clone, Copy)]
+pub struct ShadowView {
+    pub view: Mat4,
+    pub proj: Mat4,
+}
+
+/// Returns the viewport in pixels as x, y, size of the atlas tile at index
+pub fn tile_viewport(index: usize) -> (i32, i32, u32) {
+    let size = SHADOW_ATLAS_SIZE / SHADOW_ATLAS_TILES;
+    let x = index as u32 % SHADOW_ATLAS_TILES;
+    let y = index as u32 / SHADOW_ATLAS_TILES;
+    ((x * size) as i32, (y * size) as i32, size)
+}
+
+/// Returns the atlas tile at index in texture coordinates
+/// xy: offset, zw: scale
+pub fn tile_rect(index: usize) -> Vec4 {
+    let (x, y, size) = tile_viewport(index);
+    let atlas = SHADOW_ATLAS_SIZE as f32;
+    Vec4::new(
+        x as f32 / atlas,
+        y as f32 / atlas,
+        size as f32 / atlas,
+        size as f32 / atlas,
+    )
+}
+
+/// Returns the distances from the camera where each cascade ends
+/// Blends logarithmic and uniform splits to keep resolution close to the camera
+pub fn cascade_splits(near: f32, far: f32) -> [f32; SHADOW_CASCADES] {
+    const LAMBDA: f32 = 0.75;
+    let mut splits = [0.0; SHADOW_CASCADES];
+    for (i, split) in splits.iter_mut().enumerate() {
+        let p = (i + 1) as f32 / SHADOW_CASCADES as f32;
+        let log = near * (far / near).powf(p);
+        let uniform = near + (far - near) * p;
+        *split = LAMBDA * log + (1.0 - LAMBDA) * uniform;
+    }
+    splits
+}
+
+/// Returns the cascades of a directional light covering the view of camera up to
+/// SHADOW_DISTANCE
+/// Each cascade encloses its slice of the view frustum in a sphere so that the projection doesn't
+/// change size as the camera rotates, and is snapped to texels to avoid shimmering as it moves
+pub fn directional_cascades(
+    light: &Transform,
+    camera_transform: &Transform,
+    camera: &Camera,
+    aspect: f32,
+) -> [ShadowView; SHADOW_CASCADES] {
+    let near = camera.near;
+    let far = camera.far.min(SHADOW_DISTANCE);
+    let splits = cascade_splits(near, far);
+
+    let (forward, right, up) = (
+        camera_transform.forward(),
+        camera_transform.right(),
+        camera_transform.up(),
+    );
+
+    let (light_forward, light_right, light_up) = (light.forward(), light.right(), light.up());
+    let tile_size = (SHADOW_ATLAS_SIZE / SHADOW_ATLAS_TILES) as f32;
+
+    let mut cascades = [ShadowView {
+        view: Mat4::identity(),
+        proj: Mat4::identity(),
+    }; SHADOW_CASCADES];
+
+    let mut start = near;
+    for (cascade, end) in cascades.iter_mut().zip(splits.iter()) {
+        // The corners of the slice of the view frustum between start and end
+        let mut corners = Vec::with_capacity(8);
+        for distance in &[start, *end] {
+            let half_height = match camera.projection {
+                Projection::Perspective { fov } => distance * (fov * 0.5).tan(),
+                Projection::Orthographic { size } => size * 0.5,
+            };
+            let half_width = half_height * aspect;
+            let center = camera_transform.position + forward * *distance;
+            for (x, y) in &[(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)] {
+                corners.push(center + right * (half_width * x) + up * (half_height * y));
+            }
+        }
+
+        let center = corners
+            .iter()
+            .fold(Vec3::zero(), |acc, corner| acc + *corner)
+            * 0.125;
+        let radius = corners
+            .iter()
+            .map(|corner| (*corner - center).mag())
+            .fold(0.0, f32::max);
+        // Round up to avoid the projection changing size from floating point errors
+        let radius = (radius * 16.0).ceil() / 16.0;
+
+        // Move the center in whole texels in the plane of the light
+        let texel = radius * 2.0 / tile_size;
+        let snap = |v: f32| (v / texel).floor() * texel;
+        let center = light_right * snap(Vec3::dot(&center, &light_right))
+            + light_up * snap(Vec3::dot(&center, &light_up))
+            + light_forward * Vec3::dot(&center, &light_forward);
+
+        let eye = Transform::with_rotation_scale(
+            center - light_forward * (radius + SHADOW_CASTER_DISTANCE),
+            light.rotation,
+            Vec3::one(),
+        );
+
+        *cascade = ShadowView {
+            view: eye.create_viewmatrix(),
+            proj: Mat4::ortho(
+                radius * 2.0,
+                radius * 2.0,
+                0.0,
+                radius * 2.0 + SHADOW_CASTER_DISTANCE,
+            ),
+        };
+        start = *end;
+    }
+
+    cascades
+}
+
+/// Returns the view of a spot light covering its outer cone
+pub fn spot_view(light: &Transform, range: f32, outer_angle: f32) -> ShadowView {
+    let fov = (outer_angle * 2.0).clamp(0.01, PI - 0.01);
+    ShadowView {
+        view: light.create_viewmatrix(),
+        proj: Mat4::perspective(1.0, fov, SHADOW_NEAR, range.max(SHADOW_NEAR * 2.0)),
+    }
+}
+
+/// Returns the six views of a point light looking along +x, -x, +y, -y, +z, -z
+/// The order must match the face selection in data/shaders/include/lighting.glsl
+pub fn point_views(light: &Transform, range: f32) -> [ShadowView; 6] {
+    let faces = [
+        Quaternion::from_axis_angle(Vec3::up(), -FRAC_PI_2),
+        Quaternion::from_axis_angle(Vec3::up(), FRAC_PI_2),
+        Quaternion::from_axis_angle(Vec3::right(), FRAC_PI_2),
+        Quaternion::from_axis_angle(Vec3::right(), -FRAC_PI_2),
+        Quaternion::from_axis_angle(Vec3::up(), PI),
+        Quaternion::identity(),
+    ];
+
+    let proj = Mat4::perspective(1.0, FRAC_PI_2, SHADOW_NEAR, range.max(SHADOW_NEAR * 2.0));
+    let mut views = [ShadowView {
+        view: Mat4::identity(),
+        proj,
+    }; 6];
+
+    for (view, face) in views.iter_mut().zip(faces.iter()) {
+        view.view =
+            Transform::with_rotation_scale(light.position, *face, Vec3::one()).create_viewmatrix();
+    }
+    views
+}
@@ -1,4 +1,4 @@
-use super::{Texture, VkAllocator};
+use super::{TargetInfo, Texture, VkAllocator};
 use crate::graphics::Extent2D;
 use crate::*;
 use ash::vk;
@@ -25,6 +25,7 @@ impl Swapchain {
         surface: &vk::SurfaceKHR,
         queue_families: &graphics::vulkan::QueueFamilies,
         extent: Extent2D,
+        old_swapchain: Option<&Swapchain>,
     ) -> Result<Swapchain> {
         unsafe {
             let (capabilities, formats, present_modes) =
@@ -44,13 +45,19 @@ impl Swapchain {
                 .image_color_space(format.color_space)
                 .image_format(format.format)
                 .image_extent(extent)
-                .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+                // Allow copying from the images to capture frames if supported
+                .image_usage(
+                    vk::ImageUsageFlags::COLOR_ATTACHMENT
+                        | (capabilities.supported_usage_flags & vk::ImageUsageFlags::TRANSFER_SRC),
+                )
                 .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
                 .pre_transform(capabilities.current_transform)
                 .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
                 .present_mode(present_mode)
                 .clipped(true)
-                .image_array_layers(1);
+                .image_array_layers(1)
+                // Lets the presentation engine hand over images of the swapchain being replaced
+                .old_swapchain(old_swapchain.map_or(vk::SwapchainKHR::null(), |old| old.swapchain));
 
             let queue_family_indices = [
                 queue_families.graphics.unwrap(),
@@ -161,6 +168,17 @@ impl Swapchain {
         &self.depth_image
     }
 
+    /// Describes the swapchain images for resources rendering to them
+    pub fn target_info(&self) -> TargetInfo {
+        TargetInfo {
+            color_format: self.format,
+            depth_format: self.depth_format(),
+            image_count: self.image_count(),
+            extent: self.extent,
+            presentable: true,
+        }
+    }
+
     /// Returns the index to the next available image in the swapchain
     pub fn acquire_next_image(&self, semaphore: &vk::Semaphore) -> Result<(u32, bool)> {
         unsafe {
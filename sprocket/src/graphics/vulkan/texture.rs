@@ -1,8 +1,17 @@
-use super::{buffer, resources::Resource, CommandBuffer, CommandPool, Error, Result, VkAllocator};
+use super::cubemap::CubeImage;
+use super::enums::{ColorSpace, TextureKind};
+use super::resources::{AsyncResource, Resource};
+use super::{
+    cooked, decode, formats, gltf, CookedKind, Error, Result, Upload, Uploader, VkAllocator,
+    VulkanContext,
+};
 use crate::graphics::Extent2D;
+use crate::vfs;
 use ash::version::DeviceV1_0;
 use ash::vk;
-use std::ffi::CString;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 pub struct Texture {
@@ -15,111 +24,624 @@ pub struct Texture {
     layout: vk::ImageLayout,
     size: vk::DeviceSize,
     extent: Extent2D,
+    mip_levels: u32,
+    kind: TextureKind,
+    layers: u32,
     owns_image: bool,
+    /// The files besides its own the texture was loaded from, E.g; its spec
+    sources: Vec<PathBuf>,
 }
 
-#[link(name = "stb_image", kind = "static")]
-extern "C" {
-    pub fn stbi_load(
-        filename: *const i8,
-        x: *mut i32,
-        y: *mut i32,
-        channels: *mut i32,
-        desired_channels: i32,
-    ) -> *mut u8;
+/// Describes how an image file is decoded
+/// Read from <image>.json next to the image if it exists, E.g; textures/normal.png.json
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct TextureSpec {
+    /// The color space of 8 bit RGBA images and DDS files without a DX10 header
+    /// Ignored by other formats which are always linear or store their color space
+    pub color_space: ColorSpace,
+    /// The number of channels decoded, 1, 2 or 4
+    /// Ignored by KTX2 and DDS files
+    pub channels: u32,
+}
+
+impl Default for TextureSpec {
+    fn default() -> Self {
+        TextureSpec {
+            color_space: ColorSpace::Srgb,
+            channels: 4,
+        }
+    }
+}
+
+impl TextureSpec {
+    /// Returns the path of the spec of the image file at path
+    pub fn path(image: &str) -> String {
+        format!("{}.json", image)
+    }
+
+    /// Reads the spec of the image file at path
+    /// Returns the default spec if the image has none
+    pub fn load(image: &str) -> Result<TextureSpec> {
+        let path = Self::path(image);
+        if vfs::exists(&path) {
+            Ok(serde_json::from_str(&vfs::read_to_string(&path)?)?)
+        } else {
+            Ok(TextureSpec::default())
+        }
+    }
+}
+
+/// Describes a texture assembled from other image files
+/// Read from texture paths ending in .json, E.g; textures/sky.json
+/// The images are loaded like any other texture, including their specs and cooked versions
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum LayeredTextureSpec {
+    /// A cube map from six square images of the same size and format in the order +X, -X, +Y,
+    /// -Y, +Z, -Z
+    Cube { faces: Vec<String> },
+    /// A cube map with faces of size pixels projected from an equirectangular image, E.g; an HDR
+    /// panorama
+    /// The faces are stored as half floats
+    Equirectangular { image: String, size: u32 },
+    /// A 2D array with a layer for each image, all of the same size and format
+    Array { layers: Vec<String> },
+}
+
+impl LayeredTextureSpec {
+    /// Returns true if path refers to a layered texture description rather than an image
+    pub fn is_layered(path: &str) -> bool {
+        path.to_lowercase().ends_with(".json")
+    }
+
+    /// Returns the paths of the images the texture is assembled from
+    pub fn images(&self) -> Vec<String> {
+        match self {
+            LayeredTextureSpec::Cube { faces } => faces.clone(),
+            LayeredTextureSpec::Equirectangular { image, .. } => vec![image.clone()],
+            LayeredTextureSpec::Array { layers } => layers.clone(),
+        }
+    }
+
+    /// Loads the images and assembles them into the texture described by the spec at path
+    fn assemble(&self, path: &str) -> Result<TextureData> {
+        let images = self
+            .images()
+            .iter()
+            .map(|image| <Texture as AsyncResource>::read(image))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut sources: Vec<PathBuf> = self.images().iter().map(PathBuf::from).collect();
+        sources.extend(
+            images
+                .iter()
+                .flat_map(|image| image.sources.iter().cloned()),
+        );
+
+        let mut data = match self {
+            LayeredTextureSpec::Cube { .. } => {
+                TextureData::from_layers(path, TextureKind::Cube, images)
+            }
+            LayeredTextureSpec::Equirectangular { size, .. } => {
+                TextureData::equirectangular(path, &images[0], *size)
+            }
+            LayeredTextureSpec::Array { .. } => {
+                TextureData::from_layers(path, TextureKind::Array, images)
+            }
+        }?;
+
+        data.sources = sources;
+        Ok(data)
+    }
+}
+
+/// The decoded pixels of an image file
+/// Also the format textures are cooked to
+#[derive(Serialize, Deserialize)]
+pub struct TextureData {
+    pub extent: Extent2D,
+    #[serde(with = "raw_format")]
+    pub format: vk::Format,
+    pub mip_levels: u32,
+    pub kind: TextureKind,
+    /// The number of layers, six for cube maps
+    pub layers: u32,
+    /// The pixels of each layer one after another, each with its mip levels starting with the full
+    /// size image
+    pub pixels: Vec<u8>,
+    /// The files besides the image the data was decoded from
+    #[serde(skip)]
+    pub sources: Vec<PathBuf>,
+}
+
+impl TextureData {
+    /// Creates 2D texture data from the pixels of mip_levels levels of the image at path
+    pub fn new(
+        path: &str,
+        extent: Extent2D,
+        format: vk::Format,
+        mip_levels: u32,
+        pixels: Vec<u8>,
+    ) -> Result<TextureData> {
+        Self::new_layered(
+            path,
+            extent,
+            format,
+            mip_levels,
+            TextureKind::Texture2D,
+            1,
+            pixels,
+        )
+    }
+
+    /// Creates texture data of kind from the pixels of mip_levels levels of each layer of the
+    /// image at path
+    /// Pixels past the last level of the last layer are dropped
+    pub fn new_layered(
+        path: &str,
+        extent: Extent2D,
+        format: vk::Format,
+        mip_levels: u32,
+        kind: TextureKind,
+        layers: u32,
+        pixels: Vec<u8>,
+    ) -> Result<TextureData> {
+        let mut data = TextureData {
+            extent,
+            format,
+            mip_levels,
+            kind,
+            layers,
+            pixels,
+            sources: Vec::new(),
+        };
+
+        data.validate(path)?;
+        data.pixels.truncate(data.layer_size()? * layers as usize);
+        Ok(data)
+    }
+
+    /// Decodes the image file at path as described by its spec, or assembles the layered texture
+    /// described at path
+    /// KTX2 and DDS files keep their mip levels and layers and other images have a single level
+    pub fn decode(path: &str) -> Result<TextureData> {
+        if gltf::is_part(path) {
+            return gltf::decode_texture(path);
+        }
+
+        if LayeredTextureSpec::is_layered(path) {
+            let spec: LayeredTextureSpec = serde_json::from_str(&vfs::read_to_string(path)?)?;
+            return spec.assemble(path);
+        }
+
+        let spec = TextureSpec::load(path)?;
+        decode::decode(path, &vfs::read(path)?, &spec)
+    }
+
+    /// Combines textures of the same size and format into the layers of a texture of kind
+    /// The layers keep the mip levels all of them have
+    pub fn from_layers(
+        path: &str,
+        kind: TextureKind,
+        layers: Vec<TextureData>,
+    ) -> Result<TextureData> {
+        let first = layers
+            .first()
+            .ok_or_else(|| Error::InvalidImage(path.to_owned(), "no layers".to_owned()))?;
+        let (extent, format) = (first.extent, first.format);
+
+        if let Some(layer) = layers.iter().find(|layer| {
+            (layer.extent.width, layer.extent.height) != (extent.width, extent.height)
+                || layer.format != format
+        }) {
+            return Err(Error::InvalidImage(
+                path.to_owned(),
+                format!(
+                    "layers of {}x{} {:?} and {}x{} {:?} differ",
+                    extent.width,
+                    extent.height,
+                    format,
+                    layer.extent.width,
+                    layer.extent.height,
+                    layer.format
+                ),
+            ));
+        }
+
+        let mip_levels = layers.iter().map(|layer| layer.mip_levels).min().unwrap();
+        let mut pixels = Vec::new();
+        for layer in &layers {
+            for level in 0..mip_levels {
+                pixels.extend_from_slice(layer.level_pixels(0, level)?);
+            }
+        }
+
+        Self::new_layered(
+            path,
+            extent,
+            format,
+            mip_levels,
+            kind,
+            layers.len() as u32,
+            pixels,
+        )
+    }
+
+    /// Projects the first mip level of an equirectangular image onto the faces of a cube map of
+    /// size pixels with a full mip chain
+    pub fn equirectangular(path: &str, image: &TextureData, size: u32) -> Result<TextureData> {
+        if size == 0 {
+            return Err(Error::InvalidImage(
+                path.to_owned(),
+                "cube maps must be at least 1 pixel wide".to_owned(),
+            ));
+        }
+
+        let texels =
+            formats::to_rgba_f32(image.format, image.mip_extent(0), image.level_pixels(0, 0)?)?;
+        CubeImage::from_equirectangular(&texels, image.mip_extent(0), size).to_data(path)
+    }
+
+    /// Returns the pixels of a mip level of a layer
+    pub fn level_pixels(&self, layer: u32, level: u32) -> Result<&[u8]> {
+        let offset = layer as usize * self.layer_size()?
+            + (0..level)
+                .map(|level| self.mip_size(level))
+                .sum::<Result<usize>>()?;
+        Ok(&self.pixels[offset..offset + self.mip_size(level)?])
+    }
+
+    /// Checks that the format is known, that the layers match the kind and that there are pixels
+    /// for every mip level of every layer
+    fn validate(&self, path: &str) -> Result<()> {
+        let invalid = |reason: String| Err(Error::InvalidImage(path.to_owned(), reason));
+
+        // Checked before the size as the levels are computed from them
+        if self.extent.width == 0 || self.extent.height == 0 {
+            return invalid("empty image".to_owned());
+        } else if self.mip_levels == 0 || self.mip_levels > self.full_mip_levels() {
+            return invalid(format!("invalid number of mip levels {}", self.mip_levels));
+        } else if self.layers == 0 || (self.kind == TextureKind::Texture2D && self.layers > 1) {
+            return invalid(format!(
+                "invalid number of layers {} for {:?}",
+                self.layers, self.kind
+            ));
+        } else if self.kind == TextureKind::Cube
+            && (self.layers != 6 || self.extent.width != self.extent.height)
+        {
+            return invalid("cube maps must have six square faces".to_owned());
+        }
+
+        let layout = formats::block_layout(self.format)?;
+        let expected = (0..self.mip_levels)
+            .try_fold(0usize, |size, level| {
+                let (width, height) = self.mip_extent(level);
+                size.checked_add(layout.image_size(width, height)?)
+            })
+            .and_then(|size| size.checked_mul(self.layers as usize));
+
+        let expected = match expected {
+            Some(expected) => expected,
+            None => {
+                return invalid(format!(
+                    "the size of {}x{} with {} layers overflows",
+                    self.extent.width, self.extent.height, self.layers
+                ))
+            }
+        };
+
+        if self.pixels.len() < expected {
+            invalid(format!(
+                "expected {} bytes of pixels for {} mip levels of {} layers of {:?}, found {}",
+                expected,
+                self.mip_levels,
+                self.layers,
+                self.format,
+                self.pixels.len()
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Replaces the mip levels with the full chain down to 1x1
+    /// Each level is downsampled from the previous with a box filter, averaging sRGB colors in
+    /// linear space
+    /// Only 8 bit uncompressed formats are supported, the levels of other formats are kept
+    pub fn generate_mips(&mut self) {
+        let (channels, srgb) = match self.format {
+            vk::Format::R8_UNORM => (1, false),
+            vk::Format::R8G8_UNORM => (2, false),
+            vk::Format::R8G8B8A8_UNORM | vk::Format::B8G8R8A8_UNORM => (4, false),
+            vk::Format::R8G8B8A8_SRGB | vk::Format::B8G8R8A8_SRGB => (4, true),
+            _ => return,
+        };
+
+        let size = |(width, height): (u32, u32)| (width * height * channels) as usize;
+        let stride: usize = (0..self.mip_levels)
+            .map(|level| size(self.mip_extent(level)))
+            .sum();
+
+        let levels = self.full_mip_levels();
+        let mut pixels = Vec::new();
+        for layer in 0..self.layers as usize {
+            let mut previous = self.pixels[layer * stride..][..size(self.mip_extent(0))].to_vec();
+            pixels.extend_from_slice(&previous);
+
+            for level in 1..levels {
+                previous = downsample(&previous, self.mip_extent(level - 1), channels, srgb);
+                pixels.extend_from_slice(&previous);
+            }
+        }
+
+        self.pixels = pixels;
+        self.mip_levels = levels;
+    }
+
+    /// Returns the number of mip levels of a full chain down to 1x1
+    pub fn full_mip_levels(&self) -> u32 {
+        32 - self
+            .extent
+            .width
+            .max(self.extent.height)
+            .max(1)
+            .leading_zeros()
+    }
+
+    /// Returns the width and height of a mip level
+    pub fn mip_extent(&self, level: u32) -> (u32, u32) {
+        (
+            (self.extent.width >> level).max(1),
+            (self.extent.height >> level).max(1),
+        )
+    }
+
+    /// Returns the size in bytes of a mip level of a single layer
+    /// # Panics
+    /// If the size overflows, which validation rules out
+    fn mip_size(&self, level: u32) -> Result<usize> {
+        let (width, height) = self.mip_extent(level);
+        Ok(formats::block_layout(self.format)?
+            .image_size(width, height)
+            .expect("Texture size overflows"))
+    }
+
+    /// Returns the size in bytes of all mip levels of a single layer
+    fn layer_size(&self) -> Result<usize> {
+        (0..self.mip_levels).map(|level| self.mip_size(level)).sum()
+    }
+
+    /// Converts the pixels to the fallback of their format, E.g; decompresses BC3 to 8 bit RGBA
+    fn into_fallback(self) -> Result<TextureData> {
+        let format = formats::fallback(self.format).ok_or(Error::UnsupportedFormat(self.format))?;
+
+        let mut pixels = Vec::new();
+        for layer in 0..self.layers {
+            for level in 0..self.mip_levels {
+                pixels.extend(formats::convert(
+                    self.format,
+                    self.mip_extent(level),
+                    self.level_pixels(layer, level)?,
+                )?);
+            }
+        }
+
+        Ok(TextureData {
+            format,
+            pixels,
+            ..self
+        })
+    }
+
+    /// Returns the pixels laid out in a buffer and the copies of each mip level of each layer from
+    /// it to an image
+    /// Levels are padded to start at multiples of 4 bytes as required by copies
+    fn staging(&self) -> Result<(Cow<'_, [u8]>, Vec<vk::BufferImageCopy>)> {
+        let aligned = formats::block_layout(self.format)?.size % 4 == 0;
+        let mut padded = Vec::new();
+        let mut regions = Vec::new();
+        let mut src = 0;
+        let mut dst = 0;
+
+        for layer in 0..self.layers {
+            for level in 0..self.mip_levels {
+                let (width, height) = self.mip_extent(level);
+                let size = self.mip_size(level)?;
+
+                if !aligned {
+                    dst = (dst + 3) & !3;
+                    padded.resize(dst, 0);
+                    padded.extend_from_slice(&self.pixels[src..src + size]);
+                }
+
+                regions.push(vk::BufferImageCopy {
+                    buffer_offset: dst as vk::DeviceSize,
+                    buffer_row_length: 0,
+                    buffer_image_height: 0,
+                    image_subresource: vk::ImageSubresourceLayers {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        mip_level: level,
+                        base_array_layer: layer,
+                        layer_count: 1,
+                    },
+                    image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+                    image_extent: vk::Extent3D {
+                        width,
+                        height,
+                        depth: 1,
+                    },
+                });
+
+                src += size;
+                dst += size;
+            }
+        }
+
+        let pixels = if aligned {
+            Cow::Borrowed(&self.pixels[..src])
+        } else {
+            Cow::Owned(padded)
+        };
+
+        Ok((pixels, regions))
+    }
+}
+
+/// Serializes formats as their raw value
+mod raw_format {
+    use ash::vk;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(format: &vk::Format, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i32(format.as_raw())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<vk::Format, D::Error> {
+        Ok(vk::Format::from_raw(i32::deserialize(deserializer)?))
+    }
 }
 
 impl Resource for Texture {
     // Load a texture from an image file on disk
     fn load(resourcemanager: &super::ResourceManager, path: &str) -> Result<Self> {
-        let context = resourcemanager.context();
-        let allocator = &context.allocator;
-        let device = &context.device;
-        let queue = context.graphics_queue;
-        let commandpool = context.generic_pool();
-        let filename = CString::new(path).expect("Failed to convert path into CString");
-        let mut width = 0;
-        let mut height = 0;
-        let mut channels = 0;
-        let pixels =
-            unsafe { stbi_load(filename.as_ptr(), &mut width, &mut height, &mut channels, 4) };
-
-        if pixels.is_null() {
-            return Err(Error::ImageReadError(path.to_owned()));
+        Self::from_data(resourcemanager.context(), Self::read(path)?)
+    }
+
+    fn dependencies(&self) -> Vec<PathBuf> {
+        self.sources.clone()
+    }
+}
+
+impl AsyncResource for Texture {
+    type Data = TextureData;
+
+    fn read(path: &str) -> Result<TextureData> {
+        // The cooked texture is also stale if the spec changed
+        let spec = TextureSpec::path(path);
+        let cooked = cooked::find(CookedKind::Texture, path)
+            .filter(|cooked| !cooked::is_stale(cooked, &[&spec]));
+
+        let mut data = match cooked {
+            Some(cooked) => {
+                let data: TextureData = cooked::read(&cooked)?;
+                data.validate(&cooked)?;
+                data
+            }
+            None => TextureData::decode(path)?,
+        };
+
+        // Layered textures are described by json files and the images embedded in glTF files
+        // are described by the file, neither having specs of their own
+        if !LayeredTextureSpec::is_layered(path) && !gltf::is_part(path) {
+            data.sources.push(PathBuf::from(spec));
         }
+        Ok(data)
+    }
 
-        // The size of the loaded image with alpha channel
-        // May differ from the vkimage memrequirement size
-        let image_size = width * height * 4;
+    fn upload(
+        resourcemanager: &super::ResourceManager,
+        data: TextureData,
+        upload: &mut Upload,
+    ) -> Result<Self> {
+        Self::record(resourcemanager.context(), upload, data)
+    }
+}
 
-        let format = vk::Format::R8G8B8A8_SRGB;
-        let mut texture = Texture::new(
-            allocator,
-            device,
-            format,
-            vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
-            vk::ImageAspectFlags::COLOR,
-            vk::ImageTiling::OPTIMAL,
-            (width, height).into(),
-        )?;
+impl Texture {
+    /// The format of depth textures created with new_depth
+    pub const DEPTH_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
 
-        // Transition layout for transfer
-        transition_image_layout(
-            device,
-            commandpool,
-            queue,
-            texture.image,
-            vk::ImageAspectFlags::COLOR,
-            vk::ImageLayout::UNDEFINED,
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+    /// Creates a sampled texture from decoded pixels and waits for the upload to complete
+    pub fn from_data(context: &VulkanContext, data: TextureData) -> Result<Texture> {
+        let mut uploader = Uploader::new(
+            &context.device,
+            &context.allocator,
+            context.graphics_queue,
+            context.queue_families.graphics.unwrap(),
         )?;
 
-        // Create and copy image pixel data to stagin buffer
-        let (staging_buffer, staging_memory, staging_info) =
-            buffer::create_staging(allocator, texture.size)?;
-        // Copy the data into the staging buffer
-        let data = staging_info.get_mapped_data();
+        let mut upload = uploader.begin()?;
+        let texture = match Self::record(context, &mut upload, data) {
+            Ok(texture) => texture,
+            Err(e) => {
+                uploader.discard(upload);
+                return Err(e);
+            }
+        };
 
-        unsafe {
-            std::ptr::copy_nonoverlapping(pixels as _, data, image_size as usize);
+        // Dropping the uploader waits for the upload to complete
+        uploader.submit(upload)?;
+        Ok(texture)
+    }
+
+    /// Records the upload of data to a new sampled texture
+    /// Formats the device can't sample are converted to their fallback
+    /// Images with a single mip level get a full mip chain, generated with blits if the device
+    /// supports it and on the CPU otherwise
+    fn record(context: &VulkanContext, upload: &mut Upload, mut data: TextureData) -> Result<Self> {
+        if !context.supports_sampled(data.format) {
+            let format = data.format;
+            data = data.into_fallback()?;
+            log::warn!(
+                "Sampling {:?} is not supported, converted to {:?}",
+                format,
+                data.format
+            );
         }
 
-        // Transfer the staging buffer to the image
-        buffer::copy_to_image(
-            device,
-            queue,
-            commandpool,
-            staging_buffer,
-            texture.image,
-            texture.extent,
-            vk::ImageAspectFlags::COLOR,
+        let full_mip_levels = data.full_mip_levels();
+        let blit = data.mip_levels == 1
+            && full_mip_levels > 1
+            && context.supports_linear_blit(data.format);
+
+        if data.mip_levels == 1 && !blit {
+            data.generate_mips();
+        }
+
+        let mip_levels = if blit {
+            full_mip_levels
+        } else {
+            data.mip_levels
+        };
+
+        let mut texture = Texture::new_sampled(
+            upload.allocator(),
+            &context.device,
+            data.format,
+            data.extent,
+            mip_levels,
+            data.kind,
+            data.layers,
         )?;
 
-        // Transition layout for shader read only optimal
-        transition_image_layout(
-            device,
-            commandpool,
-            queue,
+        let (pixels, regions) = data.staging()?;
+        let staging_buffer = upload.stage(&pixels)?;
+        upload.transition(
             texture.image,
             vk::ImageAspectFlags::COLOR,
+            mip_levels,
+            vk::ImageLayout::UNDEFINED,
             vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
         )?;
+        upload.copy_to_image(staging_buffer, texture.image, &regions);
 
-        texture.layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
-
-        // Free staging buffer
-        allocator
-            .borrow()
-            .destroy_buffer(staging_buffer, &staging_memory)?;
+        if blit {
+            upload.generate_mips(texture.image, data.extent, mip_levels, data.layers);
+        } else {
+            upload.transition(
+                texture.image,
+                vk::ImageAspectFlags::COLOR,
+                mip_levels,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            )?;
+        }
 
-        // Free the pixels
-        unsafe { Box::from_raw(pixels) };
+        texture.layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+        texture.sources = data.sources;
         Ok(texture)
     }
-}
 
-impl Texture {
     // Creates a new empty image and image view with undefined dta
     pub fn new(
         allocator: &VkAllocator,
@@ -129,6 +651,7 @@ impl Texture {
         image_aspect: vk::ImageAspectFlags,
         tiling: vk::ImageTiling,
         extent: Extent2D,
+        mip_levels: u32,
     ) -> Result<Texture> {
         let image_info = vk::ImageCreateInfo::builder()
             .image_type(vk::ImageType::TYPE_2D)
@@ -137,7 +660,7 @@ impl Texture {
                 height: extent.height,
                 depth: 1,
             })
-            .mip_levels(1)
+            .mip_levels(mip_levels)
             .array_layers(1)
             .format(format)
             .tiling(tiling)
@@ -146,19 +669,83 @@ impl Texture {
             .sharing_mode(vk::SharingMode::EXCLUSIVE)
             .samples(vk::SampleCountFlags::TYPE_1);
 
+        Self::create(
+            allocator,
+            device,
+            &image_info,
+            TextureKind::Texture2D,
+            image_aspect,
+        )
+    }
+
+    /// Creates a new empty texture of kind with layers that can be uploaded to and sampled
+    /// Cube maps have six layers
+    pub fn new_sampled(
+        allocator: &VkAllocator,
+        device: &ash::Device,
+        format: vk::Format,
+        extent: Extent2D,
+        mip_levels: u32,
+        kind: TextureKind,
+        layers: u32,
+    ) -> Result<Texture> {
+        let flags = match kind {
+            TextureKind::Cube => vk::ImageCreateFlags::CUBE_COMPATIBLE,
+            _ => vk::ImageCreateFlags::empty(),
+        };
+
+        let image_info = vk::ImageCreateInfo::builder()
+            .flags(flags)
+            .image_type(vk::ImageType::TYPE_2D)
+            .extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            })
+            .mip_levels(mip_levels)
+            .array_layers(layers)
+            .format(format)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .initial_layout(vk::ImageLayout::UNDEFINED)
+            .usage(
+                vk::ImageUsageFlags::TRANSFER_SRC
+                    | vk::ImageUsageFlags::TRANSFER_DST
+                    | vk::ImageUsageFlags::SAMPLED,
+            )
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .samples(vk::SampleCountFlags::TYPE_1);
+
+        Self::create(
+            allocator,
+            device,
+            &image_info,
+            kind,
+            vk::ImageAspectFlags::COLOR,
+        )
+    }
+
+    /// Allocates an image and creates a view of kind over all of its mip levels and layers
+    fn create(
+        allocator: &VkAllocator,
+        device: &ash::Device,
+        image_info: &vk::ImageCreateInfo,
+        kind: TextureKind,
+        image_aspect: vk::ImageAspectFlags,
+    ) -> Result<Texture> {
         let image_allocation_info = &vk_mem::AllocationCreateInfo {
             usage: vk_mem::MemoryUsage::GpuOnly,
             ..Default::default()
         };
 
         let (image, memory, _) = allocator
-            .borrow()
-            .create_image(&image_info, image_allocation_info)?;
+            .read()
+            .unwrap()
+            .create_image(image_info, image_allocation_info)?;
 
         // Create image view
         let view_info = vk::ImageViewCreateInfo::builder()
-            .view_type(vk::ImageViewType::TYPE_2D)
-            .format(format)
+            .view_type(kind.into())
+            .format(image_info.format)
             .components(vk::ComponentMapping {
                 r: vk::ComponentSwizzle::IDENTITY,
                 g: vk::ComponentSwizzle::IDENTITY,
@@ -168,9 +755,9 @@ impl Texture {
             .subresource_range(vk::ImageSubresourceRange {
                 aspect_mask: image_aspect,
                 base_mip_level: 0,
-                level_count: 1,
+                level_count: image_info.mip_levels,
                 base_array_layer: 0,
-                layer_count: 1,
+                layer_count: image_info.array_layers,
             })
             .image(image);
 
@@ -183,11 +770,15 @@ impl Texture {
             image,
             memory: Some(memory),
             view,
-            format,
-            extent,
+            format: image_info.format,
+            extent: Extent2D::new(image_info.extent.width, image_info.extent.height),
+            mip_levels: image_info.mip_levels,
+            kind,
+            layers: image_info.array_layers,
             size,
             owns_image: true,
             layout: vk::ImageLayout::UNDEFINED,
+            sources: Vec::new(),
         })
     }
 
@@ -198,7 +789,7 @@ impl Texture {
         device: &ash::Device,
         extent: Extent2D,
     ) -> Result<Texture> {
-        let format = vk::Format::D32_SFLOAT;
+        let format = Self::DEPTH_FORMAT;
         let texture = Texture::new(
             allocator,
             device,
@@ -207,8 +798,57 @@ impl Texture {
             vk::ImageAspectFlags::DEPTH,
             vk::ImageTiling::OPTIMAL,
             extent,
+            1,
+        )?;
+
+        Ok(texture)
+    }
+
+    /// Creates a new texture that can be rendered to as a depth attachment and later sampled
+    /// The layout is expected to be transitioned to depth read only by the renderpass
+    pub fn new_depth_attachment(
+        allocator: &VkAllocator,
+        device: &ash::Device,
+        extent: Extent2D,
+    ) -> Result<Texture> {
+        let mut texture = Texture::new(
+            allocator,
+            device,
+            Self::DEPTH_FORMAT,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            vk::ImageAspectFlags::DEPTH,
+            vk::ImageTiling::OPTIMAL,
+            extent,
+            1,
+        )?;
+
+        texture.layout = vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL;
+        Ok(texture)
+    }
+
+    /// Creates a new texture that can be rendered to as a color attachment and later sampled or
+    /// copied from
+    /// The layout is expected to be transitioned to shader read only by the renderpass
+    pub fn new_color_attachment(
+        allocator: &VkAllocator,
+        device: &ash::Device,
+        format: vk::Format,
+        extent: Extent2D,
+    ) -> Result<Texture> {
+        let mut texture = Texture::new(
+            allocator,
+            device,
+            format,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::SAMPLED
+                | vk::ImageUsageFlags::TRANSFER_SRC,
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageTiling::OPTIMAL,
+            extent,
+            1,
         )?;
 
+        texture.layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
         Ok(texture)
     }
 
@@ -253,9 +893,13 @@ impl Texture {
             view,
             format,
             extent,
+            mip_levels: 1,
+            kind: TextureKind::Texture2D,
+            layers: 1,
             size,
             owns_image: false,
             layout,
+            sources: Vec::new(),
         })
     }
 
@@ -274,6 +918,22 @@ impl Texture {
     pub fn format(&self) -> vk::Format {
         self.format
     }
+
+    pub fn extent(&self) -> Extent2D {
+        self.extent
+    }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    pub fn kind(&self) -> TextureKind {
+        self.kind
+    }
+
+    pub fn layers(&self) -> u32 {
+        self.layers
+    }
 }
 
 impl Drop for Texture {
@@ -283,7 +943,8 @@ impl Drop for Texture {
                 self.allocator
                     .as_ref()
                     .expect("Missing allocator for owned image")
-                    .borrow()
+                    .read()
+                    .unwrap()
                     .destroy_image(self.image, &self.memory.unwrap())
                     .expect("Failed to free image")
             }
@@ -292,90 +953,132 @@ impl Drop for Texture {
     }
 }
 
-fn transition_image_layout(
-    device: &ash::Device,
-    commandpool: &CommandPool,
-    queue: vk::Queue,
-    image: vk::Image,
-    image_aspect: vk::ImageAspectFlags,
-    old_layout: vk::ImageLayout,
-    new_layout: vk::ImageLayout,
-) -> Result<()> {
-    let commandbuffer = &mut CommandBuffer::new_primary(device, commandpool, 1)?[0];
-
-    commandbuffer.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
-
-    let (src_access_mask, dst_access_mask, src_stage_mask, dst_stage_mask) =
-        match (old_layout, new_layout) {
-            (vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL) => (
-                vk::AccessFlags::default(),
-                vk::AccessFlags::TRANSFER_WRITE,
-                vk::PipelineStageFlags::TOP_OF_PIPE,
-                vk::PipelineStageFlags::TRANSFER,
-            ),
-            (vk::ImageLayout::UNDEFINED, vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL) => (
-                vk::AccessFlags::default(),
-                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
-                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
-                vk::PipelineStageFlags::TOP_OF_PIPE,
-                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
-            ),
-            (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL) => (
-                vk::AccessFlags::TRANSFER_WRITE,
-                vk::AccessFlags::SHADER_READ,
-                vk::PipelineStageFlags::TRANSFER,
-                vk::PipelineStageFlags::FRAGMENT_SHADER,
-            ),
-
-            (src, dst) => return Err(Error::UnsupportedTransition(src, dst)),
-        };
+fn has_stencil_component(format: vk::Format) -> bool {
+    return format == vk::Format::D32_SFLOAT_S8_UINT || format == vk::Format::D24_UNORM_S8_UINT;
+}
 
-    let barrier = vk::ImageMemoryBarrier {
-        s_type: vk::StructureType::IMAGE_MEMORY_BARRIER,
-        new_layout,
-        old_layout,
-        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
-        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
-        image,
-        subresource_range: vk::ImageSubresourceRange {
-            aspect_mask: image_aspect,
-            base_mip_level: 0,
-            level_count: 1,
-            base_array_layer: 0,
-            layer_count: 1,
-        },
-        src_access_mask,
-        dst_access_mask,
-        p_next: std::ptr::null(),
-    };
-
-    unsafe {
-        device.cmd_pipeline_barrier(
-            commandbuffer.vk(),
-            src_stage_mask,
-            dst_stage_mask,
-            vk::DependencyFlags::default(),
-            &[],
-            &[],
-            &[barrier],
-        )
-    }
+/// Halves the size of 8 bit pixels of width and height by averaging 2x2 blocks
+/// The color channels of 4 channel sRGB pixels are averaged in linear space
+/// Odd edges are clamped
+fn downsample(pixels: &[u8], (width, height): (u32, u32), channels: u32, srgb: bool) -> Vec<u8> {
+    let (half_width, half_height) = ((width / 2).max(1), (height / 2).max(1));
+    let mut result = Vec::with_capacity((half_width * half_height * channels) as usize);
 
-    commandbuffer.end()?;
+    for y in 0..half_height {
+        for x in 0..half_width {
+            for channel in 0..channels {
+                // Alpha is stored linearly
+                let linear = !srgb || channel == 3;
+                let sum: f32 = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                    .iter()
+                    .map(|(dx, dy)| {
+                        let px = (x * 2 + dx).min(width - 1);
+                        let py = (y * 2 + dy).min(height - 1);
+                        let value = pixels[((py * width + px) * channels + channel) as usize];
+                        if linear {
+                            value as f32 / 255.0
+                        } else {
+                            formats::srgb_to_linear(value)
+                        }
+                    })
+                    .sum();
 
-    CommandBuffer::submit(
-        device,
-        &[&commandbuffer],
-        queue,
-        &[],
-        &[],
-        &[],
-        vk::Fence::null(),
-    )?;
+                let average = sum / 4.0;
+                result.push(if linear {
+                    (average * 255.0).round() as u8
+                } else {
+                    formats::linear_to_srgb(average)
+                });
+            }
+        }
+    }
 
-    Ok(())
+    result
 }
 
-fn has_stencil_component(format: vk::Format) -> bool {
-    return format == vk::Format::D32_SFLOAT_S8_UINT || format == vk::Format::D24_UNORM_S8_UINT;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_invalid(result: Result<TextureData>) -> bool {
+        matches!(result, Err(Error::InvalidImage(..)))
+    }
+
+    #[test]
+    fn validate_levels_and_layers() {
+        let rgba = vk::Format::R8G8B8A8_UNORM;
+        let data = TextureData::new("test", (4, 2).into(), rgba, 3, vec![0; 48]).unwrap();
+        assert_eq!(data.level_pixels(0, 2).unwrap().len(), 4);
+
+        // Pixels past the last level are dropped
+        let data = TextureData::new("test", (4, 2).into(), rgba, 1, vec![0; 48]).unwrap();
+        assert_eq!(data.pixels.len(), 32);
+
+        assert!(is_invalid(TextureData::new(
+            "test",
+            (4, 2).into(),
+            rgba,
+            3,
+            vec![0; 43]
+        )));
+        assert!(is_invalid(TextureData::new(
+            "test",
+            (4, 2).into(),
+            rgba,
+            4,
+            vec![0; 1024]
+        )));
+        assert!(is_invalid(TextureData::new(
+            "test",
+            (0, 2).into(),
+            rgba,
+            1,
+            vec![0; 8]
+        )));
+        assert!(is_invalid(TextureData::new_layered(
+            "test",
+            (4, 2).into(),
+            rgba,
+            1,
+            TextureKind::Cube,
+            6,
+            vec![0; 192]
+        )));
+    }
+
+    #[test]
+    fn validate_huge_headers() {
+        let rgba = vk::Format::R32G32B32A32_SFLOAT;
+        let extent = (u32::MAX, u32::MAX).into();
+        assert!(is_invalid(TextureData::new(
+            "test",
+            extent,
+            rgba,
+            1,
+            vec![]
+        )));
+        assert!(is_invalid(TextureData::new(
+            "test",
+            extent,
+            rgba,
+            33,
+            vec![]
+        )));
+        assert!(is_invalid(TextureData::new(
+            "test",
+            extent,
+            rgba,
+            u32::MAX,
+            vec![]
+        )));
+        assert!(is_invalid(TextureData::new_layered(
+            "test",
+            extent,
+            rgba,
+            1,
+            TextureKind::Array,
+            u32::MAX,
+            vec![]
+        )));
+    }
 }
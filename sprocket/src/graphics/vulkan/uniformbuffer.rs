@@ -1,13 +1,159 @@
+use super::descriptors::{
+    DescriptorSetLayoutBinding, DescriptorSetLayoutSpec, DescriptorType, ShaderStage,
+};
 use super::Result;
 use super::VkAllocator;
-use crate::math::Mat4;
+use crate::math::{Mat4, Vec4};
 use ash::vk;
 use std::sync::Arc;
 
-pub struct UniformBufferObject {
-    pub model: Mat4,
+/// The maximum number of lights in GlobalUniforms
+/// Must match MAX_LIGHTS in data/shaders/include/global.glsl
+pub const MAX_LIGHTS: usize = 16;
+
+/// The maximum number of views rendered into the shadow atlas
+/// Must match MAX_SHADOW_VIEWS in data/shaders/include/global.glsl
+pub const MAX_SHADOW_VIEWS: usize = 16;
+
+/// The maximum number of joints of all skinned meshes drawn in a frame
+/// Must match MAX_JOINTS in data/shaders/include/skinning.glsl
+pub const MAX_JOINTS: usize = 256;
+
+/// The data of a single light as laid out in the global uniform block
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightData {
+    /// xyz: world position, w: range
+    pub position: Vec4,
+    /// xyz: direction the light is pointing, w: light type
+    pub direction: Vec4,
+    /// rgb: color, a: intensity
+    pub color: Vec4,
+    /// x: cosine of the inner cone angle, y: cosine of the outer cone angle
+    /// z: index of the first shadow view, w: number of shadow views or 0 without shadows
+    pub params: Vec4,
+}
+
+impl Default for LightData {
+    fn default() -> Self {
+        LightData {
+            position: Vec4::zero(),
+            direction: Vec4::zero(),
+            color: Vec4::zero(),
+            params: Vec4::zero(),
+        }
+    }
+}
+
+/// The per frame data bound at set 0, binding 0 for every pipeline
+/// One is written for each camera each frame
+/// The layout follows std140 and must match data/shaders/include/global.glsl
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct GlobalUniforms {
     pub view: Mat4,
     pub proj: Mat4,
+    pub viewproj: Mat4,
+    /// xyz: camera world position
+    pub camera_pos: Vec4,
+    /// x: elapsed seconds, y: delta seconds, z: frame count
+    pub time: Vec4,
+    /// xy: viewport size in pixels, zw: reciprocal of viewport size
+    pub resolution: Vec4,
+    /// rgb: ambient light color
+    pub ambient: Vec4,
+    pub light_count: u32,
+    pub _padding: [u32; 3],
+    pub lights: [LightData; MAX_LIGHTS],
+    /// The view projection of each view in the shadow atlas
+    pub shadow_matrices: [Mat4; MAX_SHADOW_VIEWS],
+    /// The tile of each view in the shadow atlas in texture coordinates
+    /// xy: offset, zw: scale
+    pub shadow_rects: [Vec4; MAX_SHADOW_VIEWS],
+    /// x: reciprocal of the shadow atlas size, y: depth bias, z: normal offset in world units
+    pub shadow_params: Vec4,
+    /// x: intensity of the environment lighting or 0 without an environment, y: mip level of the
+    /// specular environment map for fully rough surfaces
+    pub environment: Vec4,
+}
+
+impl Default for GlobalUniforms {
+    fn default() -> Self {
+        GlobalUniforms {
+            view: Mat4::identity(),
+            proj: Mat4::identity(),
+            viewproj: Mat4::identity(),
+            camera_pos: Vec4::zero(),
+            time: Vec4::zero(),
+            resolution: Vec4::zero(),
+            ambient: Vec4::zero(),
+            light_count: 0,
+            _padding: [0; 3],
+            lights: [LightData::default(); MAX_LIGHTS],
+            shadow_matrices: [Mat4::identity(); MAX_SHADOW_VIEWS],
+            shadow_rects: [Vec4::zero(); MAX_SHADOW_VIEWS],
+            shadow_params: Vec4::zero(),
+            environment: Vec4::zero(),
+        }
+    }
+}
+
+/// The joint matrices of the skinned meshes drawn in a frame, bound at set 0, binding 4
+/// Each skinned mesh reads its joints from the offset it is pushed with
+/// The layout must match data/shaders/include/skinning.glsl
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct JointUniforms {
+    /// The inverse bind matrix of each joint combined with the world matrix of the joint
+    pub joints: [Mat4; MAX_JOINTS],
+}
+
+impl Default for JointUniforms {
+    fn default() -> Self {
+        JointUniforms {
+            joints: [Mat4::identity(); MAX_JOINTS],
+        }
+    }
+}
+
+/// Returns the layout of the global descriptor set bound at set 0 for every pipeline
+/// Binding 0 holds the GlobalUniforms, binding 1 the shadow atlas, bindings 2 and 3 the
+/// irradiance and specular cube maps of the environment and binding 4 the JointUniforms
+pub fn global_layout_spec() -> DescriptorSetLayoutSpec {
+    DescriptorSetLayoutSpec {
+        bindings: vec![
+            DescriptorSetLayoutBinding {
+                slot: 0,
+                ty: DescriptorType::UniformBuffer,
+                count: 1,
+                stages: vec![ShaderStage::Vertex, ShaderStage::Fragment],
+            },
+            DescriptorSetLayoutBinding {
+                slot: 1,
+                ty: DescriptorType::CombinedImageSampler,
+                count: 1,
+                stages: vec![ShaderStage::Fragment],
+            },
+            DescriptorSetLayoutBinding {
+                slot: 2,
+                ty: DescriptorType::CombinedImageSampler,
+                count: 1,
+                stages: vec![ShaderStage::Fragment],
+            },
+            DescriptorSetLayoutBinding {
+                slot: 3,
+                ty: DescriptorType::CombinedImageSampler,
+                count: 1,
+                stages: vec![ShaderStage::Fragment],
+            },
+            DescriptorSetLayoutBinding {
+                slot: 4,
+                ty: DescriptorType::UniformBuffer,
+                count: 1,
+                stages: vec![ShaderStage::Vertex],
+            },
+        ],
+    }
 }
 
 pub struct UniformBuffer {
@@ -18,7 +164,7 @@ pub struct UniformBuffer {
 }
 impl UniformBuffer {
     pub fn new(allocator: &VkAllocator, size: u64) -> Result<UniformBuffer> {
-        let (buffer, memory, _) = allocator.borrow().create_buffer(
+        let (buffer, memory, _) = allocator.read().unwrap().create_buffer(
             &vk::BufferCreateInfo::builder()
                 .size(size)
                 .usage(vk::BufferUsageFlags::UNIFORM_BUFFER)
@@ -45,9 +191,9 @@ impl UniformBuffer {
         let offset = offset.unwrap_or(0);
 
         // Copy the data into the buffer
-        let mapped: *mut u8 = self.allocator.borrow().map_memory(&self.memory)?;
+        let mapped: *mut u8 = self.allocator.read().unwrap().map_memory(&self.memory)?;
         unsafe { std::ptr::copy_nonoverlapping(data as _, mapped.offset(offset as isize), size) }
-        self.allocator.borrow().unmap_memory(&self.memory)?;
+        self.allocator.read().unwrap().unmap_memory(&self.memory)?;
 
         Ok(())
     }
@@ -65,7 +211,8 @@ impl UniformBuffer {
 impl Drop for UniformBuffer {
     fn drop(&mut self) {
         self.allocator
-            .borrow()
+            .read()
+            .unwrap()
             .destroy_buffer(self.buffer, &self.memory)
             .expect("Failed to free vulkan memory");
     }
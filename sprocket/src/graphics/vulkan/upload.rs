@@ -0,0 +1,402 @@
+use super::{buffer, CommandBuffer, CommandPool, Error, Result, VkAllocator};
+use crate::graphics::Extent2D;
+use ash::version::DeviceV1_0;
+use ash::vk;
+use std::sync::Arc;
+
+/// Identifies a submitted upload
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct UploadId(u64);
+
+/// Transfers recorded into a single command buffer together with the staging buffers they read
+/// Submitted with Uploader::submit
+pub struct Upload {
+    device: ash::Device,
+    allocator: VkAllocator,
+    commandbuffer: CommandBuffer,
+    staging: Vec<(vk::Buffer, vk_mem::Allocation)>,
+}
+
+impl Upload {
+    /// Returns the allocator resources created for the upload should be allocated from
+    pub fn allocator(&self) -> &VkAllocator {
+        &self.allocator
+    }
+
+    /// Copies data into a new staging buffer that lives until the upload has completed
+    pub fn stage(&mut self, data: &[u8]) -> Result<vk::Buffer> {
+        let (staging_buffer, staging_memory, staging_info) =
+            buffer::create_staging(&self.allocator, data.len().max(1) as u64)?;
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                staging_info.get_mapped_data(),
+                data.len(),
+            );
+        }
+
+        self.staging.push((staging_buffer, staging_memory));
+        Ok(staging_buffer)
+    }
+
+    /// Records a copy of the first size bytes of src to dst
+    pub fn copy_buffer(&mut self, src: vk::Buffer, dst: vk::Buffer, size: vk::DeviceSize) {
+        let region = vk::BufferCopy::builder()
+            .src_offset(0)
+            .dst_offset(0)
+            .size(size)
+            .build();
+        unsafe {
+            self.device
+                .cmd_copy_buffer(self.commandbuffer.vk(), src, dst, &[region])
+        }
+    }
+
+    /// Records copies of src into regions of an image in transfer dst layout
+    pub fn copy_to_image(
+        &mut self,
+        src: vk::Buffer,
+        image: vk::Image,
+        regions: &[vk::BufferImageCopy],
+    ) {
+        unsafe {
+            self.device.cmd_copy_buffer_to_image(
+                self.commandbuffer.vk(),
+                src,
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                regions,
+            )
+        }
+    }
+
+    /// Records a layout transition of the first mip_levels levels of all layers of an image
+    /// Only the transitions into and out of transfer dst used by uploads are supported
+    pub fn transition(
+        &mut self,
+        image: vk::Image,
+        aspect: vk::ImageAspectFlags,
+        mip_levels: u32,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+    ) -> Result<()> {
+        let (src_access_mask, dst_access_mask, src_stage_mask, dst_stage_mask) =
+            match (old_layout, new_layout) {
+                (vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL) => (
+                    vk::AccessFlags::default(),
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    vk::PipelineStageFlags::TRANSFER,
+                ),
+                (
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                ) => (
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    vk::AccessFlags::SHADER_READ,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::FRAGMENT_SHADER,
+                ),
+                (src, dst) => return Err(Error::UnsupportedTransition(src, dst)),
+            };
+
+        let barrier = vk::ImageMemoryBarrier::builder()
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: aspect,
+                base_mip_level: 0,
+                level_count: mip_levels,
+                base_array_layer: 0,
+                layer_count: vk::REMAINING_ARRAY_LAYERS,
+            })
+            .src_access_mask(src_access_mask)
+            .dst_access_mask(dst_access_mask)
+            .build();
+
+        unsafe {
+            self.device.cmd_pipeline_barrier(
+                self.commandbuffer.vk(),
+                src_stage_mask,
+                dst_stage_mask,
+                vk::DependencyFlags::default(),
+                &[],
+                &[],
+                &[barrier],
+            )
+        }
+
+        Ok(())
+    }
+
+    /// Records blits filling each mip level of the layers of a color image from the previous,
+    /// starting with level 0 in transfer dst layout
+    /// All levels are left in shader read only layout
+    /// The format must support linear blits, see VulkanContext::supports_linear_blit
+    pub fn generate_mips(
+        &mut self,
+        image: vk::Image,
+        extent: Extent2D,
+        mip_levels: u32,
+        layers: u32,
+    ) {
+        let mip_extent = |level: u32| vk::Offset3D {
+            x: (extent.width >> level).max(1) as i32,
+            y: (extent.height >> level).max(1) as i32,
+            z: 1,
+        };
+
+        let subresource = |level: u32| vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: level,
+            base_array_layer: 0,
+            layer_count: layers,
+        };
+
+        for level in 1..mip_levels {
+            // Previous level is now written to
+            self.barrier(
+                image,
+                level - 1,
+                (
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                ),
+                (
+                    vk::AccessFlags::TRANSFER_WRITE,
+                    vk::AccessFlags::TRANSFER_READ,
+                ),
+                (
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::TRANSFER,
+                ),
+            );
+
+            let blit = vk::ImageBlit {
+                src_subresource: subresource(level - 1),
+                src_offsets: [vk::Offset3D::default(), mip_extent(level - 1)],
+                dst_subresource: subresource(level),
+                dst_offsets: [vk::Offset3D::default(), mip_extent(level)],
+            };
+
+            unsafe {
+                self.device.cmd_blit_image(
+                    self.commandbuffer.vk(),
+                    image,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[blit],
+                    vk::Filter::LINEAR,
+                )
+            }
+
+            self.barrier(
+                image,
+                level - 1,
+                (
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                ),
+                (vk::AccessFlags::TRANSFER_READ, vk::AccessFlags::SHADER_READ),
+                (
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::FRAGMENT_SHADER,
+                ),
+            );
+        }
+
+        // The last level is never blitted from
+        self.barrier(
+            image,
+            mip_levels - 1,
+            (
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            ),
+            (
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::SHADER_READ,
+            ),
+            (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+            ),
+        );
+    }
+
+    /// Records a barrier transitioning a single mip level of all layers of a color image
+    fn barrier(
+        &mut self,
+        image: vk::Image,
+        level: u32,
+        (old_layout, new_layout): (vk::ImageLayout, vk::ImageLayout),
+        (src_access_mask, dst_access_mask): (vk::AccessFlags, vk::AccessFlags),
+        (src_stage_mask, dst_stage_mask): (vk::PipelineStageFlags, vk::PipelineStageFlags),
+    ) {
+        let barrier = vk::ImageMemoryBarrier::builder()
+            .old_layout(old_layout)
+            .new_layout(new_layout)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: level,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: vk::REMAINING_ARRAY_LAYERS,
+            })
+            .src_access_mask(src_access_mask)
+            .dst_access_mask(dst_access_mask)
+            .build();
+
+        unsafe {
+            self.device.cmd_pipeline_barrier(
+                self.commandbuffer.vk(),
+                src_stage_mask,
+                dst_stage_mask,
+                vk::DependencyFlags::default(),
+                &[],
+                &[],
+                &[barrier],
+            )
+        }
+    }
+
+    /// Frees the staging buffers
+    fn release(self, pool: &CommandPool) {
+        let allocator = self.allocator.read().unwrap();
+        for (staging_buffer, staging_memory) in &self.staging {
+            if let Err(e) = allocator.destroy_buffer(*staging_buffer, staging_memory) {
+                log::error!("Failed to destroy staging buffer '{}'", e);
+            }
+        }
+
+        unsafe {
+            self.device
+                .free_command_buffers(pool.vk(), &[self.commandbuffer.vk()])
+        };
+    }
+}
+
+/// A submitted upload and the fence signaled when it has completed
+struct Pending {
+    id: UploadId,
+    fence: vk::Fence,
+    upload: Upload,
+}
+
+/// Submits uploads to the upload queue without waiting for them
+/// The staging buffers of an upload are freed once poll sees it has completed
+pub struct Uploader {
+    device: ash::Device,
+    allocator: VkAllocator,
+    queue: vk::Queue,
+    pool: CommandPool,
+    pending: Vec<Pending>,
+    next_id: u64,
+}
+
+impl Uploader {
+    /// Creates an uploader submitting to queue of queue_family
+    pub fn new(
+        device: &ash::Device,
+        allocator: &VkAllocator,
+        queue: vk::Queue,
+        queue_family: u32,
+    ) -> Result<Self> {
+        Ok(Uploader {
+            device: device.clone(),
+            allocator: Arc::clone(allocator),
+            queue,
+            pool: CommandPool::new(device, queue_family, true, false)?,
+            pending: Vec::new(),
+            next_id: 0,
+        })
+    }
+
+    /// Starts recording a new upload
+    pub fn begin(&self) -> Result<Upload> {
+        let mut commandbuffer = CommandBuffer::new_primary(&self.device, &self.pool, 1)?.remove(0);
+        commandbuffer.begin(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)?;
+
+        Ok(Upload {
+            device: self.device.clone(),
+            allocator: Arc::clone(&self.allocator),
+            commandbuffer,
+            staging: Vec::new(),
+        })
+    }
+
+    /// Releases an upload that won't be submitted, E.g; because recording it failed
+    pub fn discard(&self, upload: Upload) {
+        upload.release(&self.pool);
+    }
+
+    /// Submits the recorded transfers of upload
+    /// Returns an id that poll reports once the transfers have completed
+    pub fn submit(&mut self, mut upload: Upload) -> Result<UploadId> {
+        upload.commandbuffer.end()?;
+
+        let fence_info = vk::FenceCreateInfo::builder();
+        let fence = unsafe { self.device.create_fence(&fence_info, None)? };
+
+        if let Err(e) = CommandBuffer::submit(
+            &self.device,
+            &[&upload.commandbuffer],
+            self.queue,
+            &[],
+            &[],
+            &[],
+            fence,
+        ) {
+            unsafe { self.device.destroy_fence(fence, None) };
+            upload.release(&self.pool);
+            return Err(e);
+        }
+
+        let id = UploadId(self.next_id);
+        self.next_id += 1;
+        self.pending.push(Pending { id, fence, upload });
+        Ok(id)
+    }
+
+    /// Releases the uploads that have completed and returns their ids
+    pub fn poll(&mut self) -> Vec<UploadId> {
+        let device = &self.device;
+        let (completed, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|pending| {
+                unsafe { device.get_fence_status(pending.fence) }.unwrap_or(false)
+            });
+        self.pending = pending;
+
+        completed
+            .into_iter()
+            .map(|pending| {
+                unsafe { self.device.destroy_fence(pending.fence, None) };
+                pending.upload.release(&self.pool);
+                pending.id
+            })
+            .collect()
+    }
+}
+
+impl Drop for Uploader {
+    fn drop(&mut self) {
+        let fences: Vec<_> = self.pending.iter().map(|pending| pending.fence).collect();
+        if !fences.is_empty() {
+            super::wait_for_fences(&self.device, &fences, true);
+        }
+
+        for pending in self.pending.drain(..) {
+            unsafe { self.device.destroy_fence(pending.fence, None) };
+            pending.upload.release(&self.pool);
+        }
+    }
+}
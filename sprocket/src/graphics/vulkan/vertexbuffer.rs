@@ -2,25 +2,115 @@ use super::buffer;
 use super::CommandPool;
 use crate::math::*;
 use ash::vk;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
-use super::{Result, VkAllocator};
+use super::{Result, Upload, VkAllocator};
 
-#[derive(Debug)]
+/// A single interleaved vertex containing all supported attributes
+/// Pipelines select which attributes they read with a VertexLayout
+/// Missing fields are defaulted when deserializing
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+#[repr(C)]
 pub struct Vertex {
     pub position: Vec3,
+    pub normal: Vec3,
+    /// The tangent in xyz and the handedness of the bitangent in w
+    pub tangent: Vec4,
+    pub color: Vec4,
     pub uv: Vec2,
+    /// The second uv set, usually used for lightmaps
+    pub uv1: Vec2,
+    /// The indices of the four joints of the skin deforming the vertex
+    pub joints: Vec4,
+    /// The influence of each of the joints, summing to one for skinned vertices
+    pub weights: Vec4,
 }
 
 impl Vertex {
+    /// Creates a new vertex with only a position and uv
+    /// The other attributes are set to their defaults
     pub fn new(position: Vec3, texcoord: Vec2) -> Vertex {
         Vertex {
             position,
             uv: texcoord,
+            ..Default::default()
         }
     }
+}
+
+impl Default for Vertex {
+    fn default() -> Self {
+        Vertex {
+            position: Vec3::zero(),
+            normal: Vec3::zero(),
+            tangent: Vec4::zero(),
+            color: Vec4::one(),
+            uv: Vec2::zero(),
+            uv1: Vec2::zero(),
+            joints: Vec4::zero(),
+            weights: Vec4::zero(),
+        }
+    }
+}
+
+/// A single attribute of a Vertex
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum VertexAttribute {
+    Position,
+    Normal,
+    Tangent,
+    Color,
+    Uv,
+    Uv1,
+    Joints,
+    Weights,
+}
+
+impl VertexAttribute {
+    /// Returns the format of the attribute as read by shaders
+    pub fn format(&self) -> vk::Format {
+        match self {
+            VertexAttribute::Position | VertexAttribute::Normal => vk::Format::R32G32B32_SFLOAT,
+            VertexAttribute::Tangent
+            | VertexAttribute::Color
+            | VertexAttribute::Joints
+            | VertexAttribute::Weights => vk::Format::R32G32B32A32_SFLOAT,
+            VertexAttribute::Uv | VertexAttribute::Uv1 => vk::Format::R32G32_SFLOAT,
+        }
+    }
+
+    /// Returns the offset of the attribute in a Vertex
+    pub fn offset(&self) -> u32 {
+        (match self {
+            VertexAttribute::Position => offsetof!(Vertex, position),
+            VertexAttribute::Normal => offsetof!(Vertex, normal),
+            VertexAttribute::Tangent => offsetof!(Vertex, tangent),
+            VertexAttribute::Color => offsetof!(Vertex, color),
+            VertexAttribute::Uv => offsetof!(Vertex, uv),
+            VertexAttribute::Uv1 => offsetof!(Vertex, uv1),
+            VertexAttribute::Joints => offsetof!(Vertex, joints),
+            VertexAttribute::Weights => offsetof!(Vertex, weights),
+        }) as u32
+    }
+}
+
+/// Describes the vertex attributes a pipeline reads
+/// Each attribute is bound to the shader location of its index
+/// Defaults to position and uv at location 0 and 1
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash, Debug)]
+#[serde(transparent)]
+pub struct VertexLayout {
+    pub attributes: Vec<VertexAttribute>,
+}
+
+impl VertexLayout {
+    pub fn new(attributes: Vec<VertexAttribute>) -> Self {
+        VertexLayout { attributes }
+    }
 
-    pub fn binding_description() -> vk::VertexInputBindingDescription {
+    pub fn binding_description(&self) -> vk::VertexInputBindingDescription {
         vk::VertexInputBindingDescription::builder()
             .binding(0)
             .stride(std::mem::size_of::<Vertex>() as u32)
@@ -28,23 +118,79 @@ impl Vertex {
             .build()
     }
 
-    pub fn attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription> {
-        vec![
-            // Position
-            vk::VertexInputAttributeDescription::builder()
-                .binding(0)
-                .location(0)
-                .format(vk::Format::R32G32B32_SFLOAT)
-                .offset(offsetof!(Vertex, position) as u32)
-                .build(),
-            // Color
-            vk::VertexInputAttributeDescription::builder()
-                .binding(0)
-                .location(1)
-                .format(vk::Format::R32G32_SFLOAT)
-                .offset(offsetof!(Vertex, uv) as u32)
-                .build(),
-        ]
+    pub fn attribute_descriptions(&self) -> Vec<vk::VertexInputAttributeDescription> {
+        self.attributes
+            .iter()
+            .enumerate()
+            .map(|(location, attribute)| {
+                vk::VertexInputAttributeDescription::builder()
+                    .binding(0)
+                    .location(location as u32)
+                    .format(attribute.format())
+                    .offset(attribute.offset())
+                    .build()
+            })
+            .collect()
+    }
+}
+
+impl Default for VertexLayout {
+    fn default() -> Self {
+        VertexLayout::new(vec![VertexAttribute::Position, VertexAttribute::Uv])
+    }
+}
+
+/// Calculates the tangents of vertices from their positions, normals and first uv set
+/// Vertices shared by several triangles get the average tangent
+pub fn calculate_tangents(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut tangents = vec![Vec3::zero(); vertices.len()];
+    let mut bitangents = vec![Vec3::zero(); vertices.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let (a, b, c) = (
+            triangle[0] as usize,
+            triangle[1] as usize,
+            triangle[2] as usize,
+        );
+
+        let edge1 = vertices[b].position - vertices[a].position;
+        let edge2 = vertices[c].position - vertices[a].position;
+        let duv1 = vertices[b].uv - vertices[a].uv;
+        let duv2 = vertices[c].uv - vertices[a].uv;
+
+        let det = duv1.x * duv2.y - duv2.x * duv1.y;
+        // Degenerate uvs
+        if det.abs() < f32::EPSILON {
+            continue;
+        }
+
+        let r = 1.0 / det;
+        let tangent = (edge1 * duv2.y - edge2 * duv1.y) * r;
+        let bitangent = (edge2 * duv1.x - edge1 * duv2.x) * r;
+
+        for index in &[a, b, c] {
+            tangents[*index] += tangent;
+            bitangents[*index] += bitangent;
+        }
+    }
+
+    for (i, vertex) in vertices.iter_mut().enumerate() {
+        let normal = vertex.normal;
+
+        // Gram-Schmidt orthogonalize against the normal
+        let tangent = tangents[i] - normal * Vec3::dot(&normal, &tangents[i]);
+        if tangent.mag() < f32::EPSILON {
+            continue;
+        }
+        let tangent = tangent.norm();
+
+        let handedness = if Vec3::dot(&Vec3::cross(&normal, &tangent), &bitangents[i]) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+
+        vertex.tangent = Vec4::new(tangent.x, tangent.y, tangent.z, handedness);
     }
 }
 
@@ -83,7 +229,7 @@ impl VertexBuffer {
             std::ptr::copy_nonoverlapping(vertices.as_ptr() as _, data, buffer_size as usize);
         }
 
-        let (buffer, memory, _) = allocator.borrow().create_buffer(
+        let (buffer, memory, _) = allocator.read().unwrap().create_buffer(
             &vk::BufferCreateInfo::builder()
                 .size(buffer_size)
                 .usage(vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::VERTEX_BUFFER)
@@ -105,7 +251,8 @@ impl VertexBuffer {
         )?;
 
         allocator
-            .borrow()
+            .read()
+            .unwrap()
             .destroy_buffer(staging_buffer, &staging_memory)?;
 
         Ok(VertexBuffer {
@@ -117,6 +264,49 @@ impl VertexBuffer {
         })
     }
 
+    /// Creates a vertex buffer filled with vertices by a transfer recorded into upload
+    /// The buffer may not be used until the upload has completed
+    pub fn new_staged(upload: &mut Upload, vertices: &[Vertex]) -> Result<VertexBuffer> {
+        let buffer_size = match vertices.len() {
+            0 => DEFAULT_SIZE,
+            n => (n * std::mem::size_of_val(&vertices[0])) as u64,
+        };
+
+        let data = unsafe {
+            std::slice::from_raw_parts(
+                vertices.as_ptr() as *const u8,
+                std::mem::size_of_val(vertices),
+            )
+        };
+        let staging_buffer = upload.stage(data)?;
+
+        let allocator = Arc::clone(upload.allocator());
+        let (buffer, memory, _) = allocator.read().unwrap().create_buffer(
+            &vk::BufferCreateInfo::builder()
+                .size(buffer_size)
+                .usage(vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::VERTEX_BUFFER)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                .build(),
+            &vk_mem::AllocationCreateInfo {
+                usage: vk_mem::MemoryUsage::GpuOnly,
+                ..Default::default()
+            },
+        )?;
+
+        // An empty buffer is left undefined
+        if !data.is_empty() {
+            upload.copy_buffer(staging_buffer, buffer, data.len() as u64);
+        }
+
+        Ok(VertexBuffer {
+            allocator,
+            buffer,
+            memory,
+            size: buffer_size,
+            count: vertices.len() as u32,
+        })
+    }
+
     pub fn buffer(&self) -> vk::Buffer {
         self.buffer
     }
@@ -130,7 +320,8 @@ impl VertexBuffer {
 impl Drop for VertexBuffer {
     fn drop(&mut self) {
         self.allocator
-            .borrow()
+            .read()
+            .unwrap()
             .destroy_buffer(self.buffer, &self.memory)
             .expect("Failed to free vulkan memory");
     }
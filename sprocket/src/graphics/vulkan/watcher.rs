@@ -0,0 +1,72 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Detects changes to files on disk by polling their modification times
+pub struct FileWatcher {
+    interval: Duration,
+    state: Mutex<WatchState>,
+}
+
+struct WatchState {
+    last_poll: Instant,
+    /// The last seen modification time of each file, None if it couldn't be read
+    files: HashMap<PathBuf, Option<SystemTime>>,
+}
+
+impl FileWatcher {
+    /// Creates a watcher that checks the files at most once every interval
+    pub fn new(interval: Duration) -> Self {
+        FileWatcher {
+            interval,
+            state: Mutex::new(WatchState {
+                last_poll: Instant::now(),
+                files: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Starts watching path if it isn't already watched
+    pub fn watch<P: AsRef<Path>>(&self, path: P) {
+        let path = path.as_ref();
+        let mut state = self.state.lock().unwrap();
+        if !state.files.contains_key(path) {
+            state.files.insert(path.to_owned(), modified(path));
+        }
+    }
+
+    /// Returns the watched files that were modified, created or removed since the previous poll
+    /// Returns nothing if less than the interval has passed since the previous poll
+    pub fn poll(&self) -> Vec<PathBuf> {
+        let mut state = self.state.lock().unwrap();
+        if state.last_poll.elapsed() < self.interval {
+            return Vec::new();
+        }
+        state.last_poll = Instant::now();
+
+        state
+            .files
+            .iter_mut()
+            .filter_map(|(path, time)| {
+                let current = modified(path);
+                if current != *time {
+                    *time = current;
+                    Some(path.to_owned())
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// Virtual paths are checked through the file they are currently read from
+fn modified(path: &Path) -> Option<SystemTime> {
+    let path = crate::vfs::source_path(&path.to_string_lossy())?;
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
@@ -0,0 +1,259 @@
+use crate::ecs::{ComponentManager, Entity, EntityManager};
+use crate::event::Event;
+use crate::graphics::vulkan::ResourceManager;
+use crate::physics::Parent;
+use crate::{Scheduler, Time, Window};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Gives layers access to the application state from inside the main loop callbacks
+/// A new context is created each frame
+pub struct Context<'a> {
+    pub time: &'a Time,
+    /// The duration of each fixed update step
+    pub fixed_timestep: Duration,
+    pub component_manager: &'a mut ComponentManager,
+    pub entity_manager: &'a mut EntityManager,
+    pub scheduler: &'a mut Scheduler,
+    /// The resource manager is None if graphics failed or haven't been initialized
+    pub resource_manager: Option<&'a Arc<ResourceManager>>,
+    pub windows: &'a [Window],
+    quit: bool,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(
+        time: &'a Time,
+        fixed_timestep: Duration,
+        component_manager: &'a mut ComponentManager,
+        entity_manager: &'a mut EntityManager,
+        scheduler: &'a mut Scheduler,
+        resource_manager: Option<&'a Arc<ResourceManager>>,
+        windows: &'a [Window],
+    ) -> Self {
+        Context {
+            time,
+            fixed_timestep,
+            component_manager,
+            entity_manager,
+            scheduler,
+            resource_manager,
+            windows,
+            quit: false,
+        }
+    }
+
+    /// Creates a new entity
+    pub fn create_entity(&mut self) -> Entity {
+        self.entity_manager.create_entity()
+    }
+
+    /// Destroys an entity along with all its components and scheduled callbacks
+    /// The entities placed under it with a Parent are destroyed as well, recursively
+    pub fn destroy_entity(&mut self, entity: Entity) {
+        destroy_entity(
+            self.component_manager,
+            self.entity_manager,
+            self.scheduler,
+            entity,
+        );
+    }
+
+    /// Requests the application to exit the main loop after the current frame
+    pub fn quit(&mut self) {
+        self.quit = true;
+    }
+
+    /// Returns true if quit has been requested this frame
+    pub fn quit_requested(&self) -> bool {
+        self.quit
+    }
+}
+
+/// Destroys an entity along with all its components, scheduled callbacks and children
+/// Shared by Context and Application so both destroy hierarchies the same way
+pub(crate) fn destroy_entity(
+    component_manager: &mut ComponentManager,
+    entity_manager: &mut EntityManager,
+    scheduler: &mut Scheduler,
+    entity: Entity,
+) {
+    let mut stack = vec![entity];
+    while let Some(entity) = stack.pop() {
+        // The Parent of a destroyed entity is removed before its children are searched for, so
+        // each entity is only destroyed once even if the hierarchy has cycles
+        stack.extend(
+            component_manager
+                .iter::<Parent>()
+                .filter(|(child, parent)| parent.entity == entity && *child != entity)
+                .map(|(child, _)| child),
+        );
+
+        component_manager.remove_entity(entity);
+        scheduler.remove_entity(entity);
+        entity_manager.destroy_entity(entity);
+    }
+}
+
+/// A layer is a piece of user code hooked into the main loop of the application
+/// All functions have empty default implementations so that a layer only needs to implement what
+/// it uses
+pub trait Layer {
+    /// Returns the name of the layer for debugging
+    fn name(&self) -> &str {
+        "Layer"
+    }
+
+    /// Called once before the first frame
+    fn on_start(&mut self, _ctx: &mut Context) {}
+
+    /// Called once every frame
+    fn on_update(&mut self, _ctx: &mut Context) {}
+
+    /// Called zero or more times every frame at a fixed rate of ctx.fixed_timestep in scaled time
+    /// Use this for physics and other framerate independent logic
+    fn on_fixed_update(&mut self, _ctx: &mut Context) {}
+
+    /// Called for each event received from the windows
+    /// Events are dispatched from the top of the stack to the bottom
+    /// Return true to mark the event as handled and stop it from propagating further down
+    fn on_event(&mut self, _ctx: &mut Context, _event: &Event) -> bool {
+        false
+    }
+
+    /// Called once after the main loop exits
+    fn on_shutdown(&mut self, _ctx: &mut Context) {}
+}
+
+/// An ordered stack of layers
+/// Normal layers are placed below all overlays, and overlays are always on top
+/// Updates run from the bottom to the top, while events propagate from the top to the bottom
+pub struct LayerStack {
+    layers: Vec<Box<dyn Layer>>,
+    /// The index of the first overlay
+    overlay_start: usize,
+}
+
+impl LayerStack {
+    pub fn new() -> Self {
+        LayerStack {
+            layers: Vec::new(),
+            overlay_start: 0,
+        }
+    }
+
+    /// Pushes a layer on top of all other layers but below overlays
+    pub fn push_layer(&mut self, layer: Box<dyn Layer>) {
+        self.layers.insert(self.overlay_start, layer);
+        self.overlay_start += 1;
+    }
+
+    /// Pushes an overlay on top of all layers and overlays
+    pub fn push_overlay(&mut self, overlay: Box<dyn Layer>) {
+        self.layers.push(overlay);
+    }
+
+    /// Removes and returns the layer or overlay with name
+    pub fn remove(&mut self, name: &str) -> Option<Box<dyn Layer>> {
+        let index = self.layers.iter().position(|layer| layer.name() == name)?;
+        if index < self.overlay_start {
+            self.overlay_start -= 1;
+        }
+        Some(self.layers.remove(index))
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Calls on_start for all layers from the bottom to the top
+    pub fn start(&mut self, ctx: &mut Context) {
+        self.layers.iter_mut().for_each(|layer| layer.on_start(ctx));
+    }
+
+    /// Calls on_update for all layers from the bottom to the top
+    pub fn update(&mut self, ctx: &mut Context) {
+        self.layers
+            .iter_mut()
+            .for_each(|layer| layer.on_update(ctx));
+    }
+
+    /// Calls on_fixed_update for all layers from the bottom to the top
+    pub fn fixed_update(&mut self, ctx: &mut Context) {
+        self.layers
+            .iter_mut()
+            .for_each(|layer| layer.on_fixed_update(ctx));
+    }
+
+    /// Dispatches an event from the top to the bottom until a layer handles it
+    /// Returns true if the event was handled
+    pub fn event(&mut self, ctx: &mut Context, event: &Event) -> bool {
+        self.layers
+            .iter_mut()
+            .rev()
+            .any(|layer| layer.on_event(ctx, event))
+    }
+
+    /// Calls on_shutdown for all layers from the top to the bottom
+    pub fn shutdown(&mut self, ctx: &mut Context) {
+        self.layers
+            .iter_mut()
+            .rev()
+            .for_each(|layer| layer.on_shutdown(ctx));
+    }
+}
+
+impl Default for LayerStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn destroy_entity_destroys_children() {
+        let time = Time::new();
+        let mut component_manager = ComponentManager::new();
+        let mut entity_manager = EntityManager::new();
+        let mut scheduler = Scheduler::new();
+        let mut ctx = Context::new(
+            &time,
+            Duration::from_millis(10),
+            &mut component_manager,
+            &mut entity_manager,
+            &mut scheduler,
+            None,
+            &[],
+        );
+
+        let root = ctx.create_entity();
+        let child = ctx.create_entity();
+        let grandchild = ctx.create_entity();
+        let other = ctx.create_entity();
+        let components = &mut ctx.component_manager;
+        components.insert_component(root, 0);
+        components.insert_component(child, Parent::new(root));
+        components.insert_component(grandchild, Parent::new(child));
+        components.insert_component(other, Parent::new(other));
+        components.insert_component(other, 3);
+
+        ctx.destroy_entity(root);
+        assert!(ctx.component_manager.get_component::<i32>(root).is_none());
+        assert_eq!(ctx.component_manager.iter::<Parent>().count(), 1);
+
+        // An entity that is its own parent is only destroyed once
+        ctx.destroy_entity(other);
+        assert_eq!(ctx.component_manager.iter::<Parent>().count(), 0);
+        let mut reused: Vec<usize> = (0..4).map(|_| ctx.create_entity().into()).collect();
+        reused.sort_unstable();
+        assert_eq!(reused, vec![0, 1, 2, 3]);
+        assert_eq!(usize::from(ctx.create_entity()), 4);
+    }
+}
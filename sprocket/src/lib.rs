@@ -6,6 +6,7 @@ pub mod graphics;
 pub mod logger;
 pub mod math;
 pub mod utils;
+pub mod vfs;
 pub use application::Application;
 pub use graphics::window::{Window, WindowMode};
 /// Exports logging macros
@@ -18,6 +19,12 @@ pub use time::Time;
 mod timer;
 pub use timer::Timer;
 
+mod scheduler;
+pub use scheduler::{ScheduleHandle, Scheduler};
+
+pub mod layer;
+pub use layer::{Context, Layer, LayerStack};
+
 // Systems
 pub mod systems;
 
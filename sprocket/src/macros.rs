@@ -49,8 +49,11 @@ macro_rules! iferr {
 /// Returns the offset in bytes of the specified field in the struct
 macro_rules! offsetof {
     ($type:ty, $field:ident) => {{
-        let field = unsafe { &(*(std::ptr::null() as *const $type)).$field as *const _ };
+        // Never read, only used to get the addresses
+        let base = std::mem::MaybeUninit::<$type>::uninit();
+        let base = base.as_ptr();
+        let field = unsafe { std::ptr::addr_of!((*base).$field) };
 
-        field as usize
+        field as usize - base as usize
     }};
 }
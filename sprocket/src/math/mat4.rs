@@ -1,9 +1,16 @@
-use super::Vec3;
+use super::{Quaternion, Vec3};
 use std::ops;
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[repr(C)]
 pub struct Mat4([f32; 16]);
 
 impl Mat4 {
+    /// Creates a matrix from its elements in row major order
+    pub fn from_array(elements: [f32; 16]) -> Self {
+        Mat4(elements)
+    }
+
     pub fn zero() -> Self {
         Mat4([0.0; 16])
     }
@@ -85,6 +92,31 @@ impl Mat4 {
         ])
     }
 
+    /// Creates a rotation matrix from a quaternion
+    /// The matrix is laid out to be used in the same order as translate, I.e; vectors are
+    /// multiplied from the left
+    pub fn rotate(q: Quaternion) -> Self {
+        let (x, y, z, w) = (q.x, q.y, q.z, q.w);
+        Mat4([
+            1.0 - 2.0 * (y * y + z * z),
+            2.0 * (x * y + z * w),
+            2.0 * (x * z - y * w),
+            0.0,
+            2.0 * (x * y - z * w),
+            1.0 - 2.0 * (x * x + z * z),
+            2.0 * (y * z + x * w),
+            0.0,
+            2.0 * (x * z + y * w),
+            2.0 * (y * z - x * w),
+            1.0 - 2.0 * (x * x + y * y),
+            0.0,
+            0.0,
+            0.0,
+            0.0,
+            1.0,
+        ])
+    }
+
     pub fn rotate_x(angle: f32) -> Self {
         let cosa = angle.cos();
         let sina = angle.sin();
@@ -1,9 +1,11 @@
 pub mod mat4;
+pub mod quaternion;
 pub mod vec2;
 pub mod vec3;
 pub mod vec4;
 
 pub use mat4::Mat4;
+pub use quaternion::Quaternion;
 pub use vec2::Vec2;
 pub use vec3::Vec3;
 pub use vec4::Vec4;
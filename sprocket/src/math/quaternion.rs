@@ -0,0 +1,159 @@
+use super::Vec3;
+use serde::{Deserialize, Serialize};
+use std::ops;
+
+/// Representation of a rotation in 3D space
+/// Quaternions are expected to be of unit length when used as rotations
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug)]
+pub struct Quaternion {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Quaternion {
+    /// Creates a quaternion from the raw components
+    /// Does not normalize
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Quaternion { x, y, z, w }
+    }
+
+    /// Creates a quaternion representing no rotation
+    pub fn identity() -> Self {
+        Quaternion {
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            w: 1.0,
+        }
+    }
+
+    /// Creates a rotation of angle radians around axis
+    /// The axis does not need to be normalized
+    pub fn from_axis_angle(axis: Vec3, angle: f32) -> Self {
+        let axis = axis.norm();
+        let (sin, cos) = (angle * 0.5).sin_cos();
+        Quaternion {
+            x: axis.x * sin,
+            y: axis.y * sin,
+            z: axis.z * sin,
+            w: cos,
+        }
+    }
+
+    /// Creates a rotation from euler angles in radians
+    /// The rotations are applied in the order z, x, y, I.e; roll, pitch, yaw
+    pub fn from_euler(x: f32, y: f32, z: f32) -> Self {
+        Self::from_axis_angle(Vec3::up(), y)
+            * Self::from_axis_angle(Vec3::right(), x)
+            * Self::from_axis_angle(Vec3::forward(), z)
+    }
+
+    /// Returns the inverse rotation
+    /// Equivalent to the conjugate for unit quaternions
+    pub fn inverse(&self) -> Self {
+        Quaternion {
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+            w: self.w,
+        }
+    }
+
+    /// Returns the length of the quaternion
+    pub fn mag(&self) -> f32 {
+        (self.x * self.x + self.y * self.y + self.z * self.z + self.w * self.w).sqrt()
+    }
+
+    /// Returns the quaternion scaled to unit length
+    pub fn norm(&self) -> Self {
+        let mag = self.mag();
+        Quaternion {
+            x: self.x / mag,
+            y: self.y / mag,
+            z: self.z / mag,
+            w: self.w / mag,
+        }
+    }
+
+    /// Rotates a vector by the quaternion
+    pub fn rotate(&self, v: Vec3) -> Vec3 {
+        let q = Vec3::new(self.x, self.y, self.z);
+        let t = Vec3::cross(&q, &v) * 2.0;
+        v + t * self.w + Vec3::cross(&q, &t)
+    }
+
+    /// Spherically interpolates between two rotations with t
+    /// Takes the shortest path
+    /// Clamps t between 0, 1
+    pub fn slerp(a: Self, b: Self, t: f32) -> Self {
+        let t = t.clamp(0.0, 1.0);
+        let mut dot = a.x * b.x + a.y * b.y + a.z * b.z + a.w * b.w;
+
+        // Take the shortest path
+        let b = if dot < 0.0 {
+            dot = -dot;
+            Quaternion::new(-b.x, -b.y, -b.z, -b.w)
+        } else {
+            b
+        };
+
+        // Fall back to linear interpolation when the rotations are close to avoid dividing by zero
+        let (wa, wb) = if dot > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let theta = dot.acos();
+            let sin = theta.sin();
+            (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+
+        Quaternion {
+            x: a.x * wa + b.x * wb,
+            y: a.y * wa + b.y * wb,
+            z: a.z * wa + b.z * wb,
+            w: a.w * wa + b.w * wb,
+        }
+        .norm()
+    }
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl std::fmt::Display for Quaternion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({}, {}, {}, {})", self.x, self.y, self.z, self.w)
+    }
+}
+
+/// Combines two rotations
+/// The resulting rotation applies rhs first and then self
+impl ops::Mul for Quaternion {
+    type Output = Quaternion;
+    fn mul(self, rhs: Self) -> Self {
+        Quaternion {
+            x: self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            y: self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            z: self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+            w: self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+        }
+    }
+}
+
+impl ops::MulAssign for Quaternion {
+    fn mul_assign(&mut self, rhs: Self) {
+        *self = *self * rhs;
+    }
+}
+
+/// Rotates a vector
+impl ops::Mul<Vec3> for Quaternion {
+    type Output = Vec3;
+    fn mul(self, rhs: Vec3) -> Vec3 {
+        self.rotate(rhs)
+    }
+}
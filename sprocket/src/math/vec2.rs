@@ -1,5 +1,8 @@
+use serde::{Deserialize, Serialize};
 use std::ops;
 /// Representation of 3D vectors and points
+#[derive(Serialize, Deserialize, PartialEq)]
+#[repr(C)]
 pub struct Vec2 {
     pub x: f32,
     pub y: f32,
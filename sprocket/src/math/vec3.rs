@@ -1,6 +1,9 @@
 use super::vec2::Vec2;
+use serde::{Deserialize, Serialize};
 use std::ops;
 /// Representation of 3D vectors and points
+#[derive(Serialize, Deserialize, PartialEq)]
+#[repr(C)]
 pub struct Vec3 {
     pub x: f32,
     pub y: f32,
@@ -1,6 +1,9 @@
 use super::vec3::Vec3;
+use serde::{Deserialize, Serialize};
 use std::ops;
 /// Representation of 3D vectors and points
+#[derive(Serialize, Deserialize, PartialEq)]
+#[repr(C)]
 pub struct Vec4 {
     pub x: f32,
     pub y: f32,
@@ -0,0 +1,47 @@
+use super::Transform;
+use crate::ecs::Entity;
+
+/// The maximum number of parents followed when resolving a world transform
+/// Guards against cycles in the hierarchy
+const MAX_DEPTH: usize = 64;
+
+/// A component placing the Transform of its entity relative to the Transform of another entity
+/// Entities without a parent are placed in world space
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Parent {
+    pub entity: Entity,
+}
+
+impl Parent {
+    pub fn new(entity: Entity) -> Self {
+        Parent { entity }
+    }
+}
+
+/// Returns the transform of entity in world space by combining it with the transforms of its
+/// parents
+/// transform and parent look up the local Transform and the Parent of an entity
+/// The hierarchy ends at the first parent without a transform
+/// Returns None if entity has no transform
+pub fn world_transform<'a, T, P>(entity: Entity, transform: T, parent: P) -> Option<Transform>
+where
+    T: Fn(Entity) -> Option<&'a Transform>,
+    P: Fn(Entity) -> Option<Entity>,
+{
+    let mut world = transform(entity)?.clone();
+    let mut current = entity;
+    for _ in 0..MAX_DEPTH {
+        let (next, parent_transform) = match parent(current) {
+            Some(next) => match transform(next) {
+                Some(parent_transform) => (next, parent_transform),
+                None => break,
+            },
+            None => break,
+        };
+
+        world = parent_transform.combine(&world);
+        current = next;
+    }
+
+    Some(world)
+}
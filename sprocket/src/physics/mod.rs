@@ -1,2 +1,4 @@
+pub mod hierarchy;
 pub mod transform;
+pub use hierarchy::{world_transform, Parent};
 pub use transform::Transform;
@@ -1,19 +1,67 @@
 use crate::math::*;
 
 /// A component representing a the position, rotation, and scale of an entity
+#[derive(Clone)]
 pub struct Transform {
     pub position: Vec3,
-    // rotation: Quaternion,
-    // scale: Vec3,
+    pub rotation: Quaternion,
+    pub scale: Vec3,
 }
 
 impl Transform {
+    /// Creates a new transform at position with no rotation and a scale of one
     pub fn new(position: Vec3) -> Self {
-        Transform { position }
+        Transform {
+            position,
+            rotation: Quaternion::identity(),
+            scale: Vec3::one(),
+        }
+    }
+
+    /// Creates a new transform with the given position, rotation, and scale
+    pub fn with_rotation_scale(position: Vec3, rotation: Quaternion, scale: Vec3) -> Self {
+        Transform {
+            position,
+            rotation,
+            scale,
+        }
     }
 
     /// Creates a new worldmatrix from the contained position, rotation, and scale
     pub fn create_worldmatrix(&self) -> Mat4 {
-        Mat4::translate(self.position)
+        Mat4::scale(self.scale) * Mat4::rotate(self.rotation) * Mat4::translate(self.position)
+    }
+
+    /// Returns child, which is relative to the transform, relative to the space the transform is in
+    /// Scale is combined per axis, so the shear of non uniformly scaled parents with rotated
+    /// children is lost
+    pub fn combine(&self, child: &Transform) -> Transform {
+        Transform {
+            position: self.position + self.rotation.rotate(self.scale * child.position),
+            rotation: self.rotation * child.rotation,
+            scale: self.scale * child.scale,
+        }
+    }
+
+    /// Creates a view matrix looking from the transform
+    /// The inverse of the worldmatrix without scale
+    pub fn create_viewmatrix(&self) -> Mat4 {
+        Mat4::translate(-self.position) * Mat4::rotate(self.rotation.inverse())
+    }
+
+    /// Returns the direction the transform is facing
+    /// Cameras look down the negative z axis
+    pub fn forward(&self) -> Vec3 {
+        self.rotation.rotate(-Vec3::forward())
+    }
+
+    /// Returns the direction to the right of the transform
+    pub fn right(&self) -> Vec3 {
+        self.rotation.rotate(Vec3::right())
+    }
+
+    /// Returns the up direction of the transform
+    pub fn up(&self) -> Vec3 {
+        self.rotation.rotate(Vec3::up())
     }
 }
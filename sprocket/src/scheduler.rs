@@ -0,0 +1,426 @@
+use crate::ecs::Entity;
+use crate::layer::Context;
+use std::time::Duration;
+
+/// A handle to a scheduled callback
+/// Used to cancel or query the callback after it has been scheduled
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct ScheduleHandle(usize);
+
+/// Describes when a scheduled callback is run
+enum Trigger {
+    /// Runs once when the remaining duration has passed
+    Delay(Duration),
+    /// Runs every interval
+    /// Remaining is the duration left to the next run
+    Interval {
+        interval: Duration,
+        remaining: Duration,
+    },
+    /// Runs once every frame for the remaining number of frames
+    Frames(usize),
+}
+
+/// A scheduled closure, given the context of the frame it runs in
+type Callback = Box<dyn FnMut(&mut Context)>;
+
+struct Scheduled {
+    handle: ScheduleHandle,
+    /// The entity that owns the callback, if any
+    /// The callback is cancelled when the entity is removed
+    owner: Option<Entity>,
+    trigger: Trigger,
+    /// None while the callback is running
+    callback: Option<Callback>,
+}
+
+/// Runs closures after a delay, repeatedly at an interval, or on the next N frames
+/// Unlike Timer which measures real time and needs to be polled, the scheduler is driven by Time
+/// and advances with the scaled delta, I.e; callbacks are paused along with the time scale
+/// Callbacks are run in the order they were scheduled and are given the Context, so they can
+/// access the ECS and schedule, cancel or attach callbacks, including themselves
+pub struct Scheduler {
+    scheduled: Vec<Scheduled>,
+    next_id: usize,
+}
+
+impl Scheduler {
+    /// Creates a new empty scheduler
+    pub fn new() -> Self {
+        Scheduler {
+            scheduled: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Schedules a callback to run once after delay has passed in scaled time
+    pub fn after<F: FnMut(&mut Context) + 'static>(
+        &mut self,
+        delay: Duration,
+        callback: F,
+    ) -> ScheduleHandle {
+        self.schedule(Trigger::Delay(delay), Box::new(callback))
+    }
+
+    /// Schedules a callback to run repeatedly every interval in scaled time
+    /// The first run happens after one interval has passed
+    /// An interval of zero runs the callback every frame
+    pub fn every<F: FnMut(&mut Context) + 'static>(
+        &mut self,
+        interval: Duration,
+        callback: F,
+    ) -> ScheduleHandle {
+        self.schedule(
+            Trigger::Interval {
+                interval,
+                remaining: interval,
+            },
+            Box::new(callback),
+        )
+    }
+
+    /// Schedules a callback to run once every frame for the next count frames
+    /// Frames are counted regardless of time scale
+    pub fn frames<F: FnMut(&mut Context) + 'static>(
+        &mut self,
+        count: usize,
+        callback: F,
+    ) -> ScheduleHandle {
+        self.schedule(Trigger::Frames(count), Box::new(callback))
+    }
+
+    /// Makes entity the owner of a scheduled callback
+    /// The callback will be cancelled when the entity is removed with remove_entity
+    /// Returns false if the handle is no longer scheduled
+    pub fn attach(&mut self, handle: ScheduleHandle, entity: Entity) -> bool {
+        match self.find_mut(handle) {
+            Some(scheduled) => {
+                scheduled.owner = Some(entity);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Cancels a scheduled callback
+    /// Returns false if the callback has already finished or been cancelled
+    pub fn cancel(&mut self, handle: ScheduleHandle) -> bool {
+        let len = self.scheduled.len();
+        self.scheduled
+            .retain(|scheduled| scheduled.handle != handle);
+        self.scheduled.len() != len
+    }
+
+    /// Returns true if the callback is still waiting to be run
+    pub fn is_scheduled(&self, handle: ScheduleHandle) -> bool {
+        self.scheduled
+            .iter()
+            .any(|scheduled| scheduled.handle == handle)
+    }
+
+    /// Cancels all callbacks owned by entity
+    /// Should be called when an entity is destroyed
+    pub fn remove_entity(&mut self, entity: Entity) {
+        self.scheduled
+            .retain(|scheduled| scheduled.owner != Some(entity));
+    }
+
+    /// Cancels all scheduled callbacks
+    pub fn clear(&mut self) {
+        self.scheduled.clear();
+    }
+
+    /// Returns the number of callbacks waiting to be run
+    pub fn len(&self) -> usize {
+        self.scheduled.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scheduled.is_empty()
+    }
+
+    /// Advances the scheduler of ctx by the scaled delta of time and runs all due callbacks with ctx
+    /// Should be called once per frame after time has been updated
+    /// An interval callback is run at most once per update even if several intervals have passed
+    /// Callbacks scheduled by other callbacks are first considered on the next update
+    pub fn update(ctx: &mut Context) {
+        let due = ctx.scheduler.advance(ctx.time.scaled_delta_raw());
+        for (handle, mut callback) in due {
+            callback(ctx);
+            // Repeating callbacks are put back unless they were cancelled while running
+            if let Some(scheduled) = ctx.scheduler.find_mut(handle) {
+                scheduled.callback = Some(callback);
+            }
+        }
+    }
+
+    /// Advances the triggers by delta and takes out the callbacks that are due
+    /// Callbacks that won't run again are removed
+    fn advance(&mut self, delta: Duration) -> Vec<(ScheduleHandle, Callback)> {
+        let mut due = Vec::new();
+        self.scheduled.retain_mut(|scheduled| {
+            let (run, keep) = match &mut scheduled.trigger {
+                Trigger::Delay(remaining) => {
+                    if *remaining > delta {
+                        *remaining -= delta;
+                        (false, true)
+                    } else {
+                        (true, false)
+                    }
+                }
+                Trigger::Interval {
+                    interval,
+                    remaining,
+                } => {
+                    if *remaining > delta {
+                        *remaining -= delta;
+                        (false, true)
+                    } else {
+                        // Carry over the overshoot so the interval doesn't drift
+                        let overshoot = delta - *remaining;
+                        *remaining = match interval.as_nanos() {
+                            0 => Duration::from_secs(0),
+                            interval_ns => {
+                                *interval
+                                    - Duration::from_nanos(
+                                        (overshoot.as_nanos() % interval_ns) as u64,
+                                    )
+                            }
+                        };
+                        (true, true)
+                    }
+                }
+                Trigger::Frames(0) => (false, false),
+                Trigger::Frames(remaining) => {
+                    *remaining -= 1;
+                    (true, *remaining > 0)
+                }
+            };
+
+            if run {
+                if let Some(callback) = scheduled.callback.take() {
+                    due.push((scheduled.handle, callback));
+                }
+            }
+            keep
+        });
+        due
+    }
+
+    fn schedule(&mut self, trigger: Trigger, callback: Callback) -> ScheduleHandle {
+        let handle = ScheduleHandle(self.next_id);
+        self.next_id += 1;
+
+        self.scheduled.push(Scheduled {
+            handle,
+            owner: None,
+            trigger,
+            callback: Some(callback),
+        });
+        handle
+    }
+
+    fn find_mut(&mut self, handle: ScheduleHandle) -> Option<&mut Scheduled> {
+        self.scheduled
+            .iter_mut()
+            .find(|scheduled| scheduled.handle == handle)
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::{ComponentManager, EntityManager};
+    use crate::Time;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// The state a context is created from
+    struct World {
+        time: Time,
+        component_manager: ComponentManager,
+        entity_manager: EntityManager,
+        scheduler: Scheduler,
+    }
+
+    impl World {
+        fn new() -> Self {
+            World {
+                time: Time::new(),
+                component_manager: ComponentManager::new(),
+                entity_manager: EntityManager::new(),
+                scheduler: Scheduler::new(),
+            }
+        }
+
+        /// Advances time by delta and updates the scheduler
+        fn update(&mut self, delta: Duration) {
+            self.time.advance(delta);
+            let mut ctx = Context::new(
+                &self.time,
+                ms(125),
+                &mut self.component_manager,
+                &mut self.entity_manager,
+                &mut self.scheduler,
+                None,
+                &[],
+            );
+            Scheduler::update(&mut ctx);
+        }
+    }
+
+    /// Returns a counter and a callback incrementing it
+    fn counter() -> (Rc<Cell<usize>>, impl FnMut(&mut Context) + 'static) {
+        let count = Rc::new(Cell::new(0));
+        let callback = {
+            let count = Rc::clone(&count);
+            move |_: &mut Context| count.set(count.get() + 1)
+        };
+        (count, callback)
+    }
+
+    /// Durations are multiples of 125ms which are exact when scaled as f32
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn delay_runs_once() {
+        let mut world = World::new();
+        let (count, callback) = counter();
+        let handle = world.scheduler.after(ms(250), callback);
+
+        world.update(ms(125));
+        assert_eq!(count.get(), 0);
+        assert!(world.scheduler.is_scheduled(handle));
+
+        world.update(ms(125));
+        assert_eq!(count.get(), 1);
+        assert!(!world.scheduler.is_scheduled(handle));
+
+        world.update(ms(500));
+        assert_eq!(count.get(), 1);
+    }
+
+    #[test]
+    fn scaled_time_delays_callbacks() {
+        let mut world = World::new();
+        world.time.set_scale(0.5);
+        let (count, callback) = counter();
+        world.scheduler.after(ms(250), callback);
+
+        // 375ms of real time is only 187.5ms of scaled time
+        world.update(ms(375));
+        assert_eq!(count.get(), 0);
+
+        world.update(ms(125));
+        assert_eq!(count.get(), 1);
+    }
+
+    #[test]
+    fn paused_time_holds_callbacks() {
+        let mut world = World::new();
+        world.time.set_scale(0.0);
+        let (delayed, callback) = counter();
+        world.scheduler.after(ms(125), callback);
+        let (repeated, callback) = counter();
+        world.scheduler.every(ms(125), callback);
+        let (framed, callback) = counter();
+        world.scheduler.frames(2, callback);
+
+        for _ in 0..5 {
+            world.update(ms(500));
+        }
+        assert_eq!(delayed.get(), 0);
+        assert_eq!(repeated.get(), 0);
+        // Frames are counted regardless of time scale
+        assert_eq!(framed.get(), 2);
+
+        world.time.set_scale(1.0);
+        world.update(ms(125));
+        assert_eq!(delayed.get(), 1);
+        assert_eq!(repeated.get(), 1);
+    }
+
+    #[test]
+    fn interval_carries_overshoot() {
+        let mut world = World::new();
+        let (count, callback) = counter();
+        world.scheduler.every(ms(500), callback);
+
+        // Runs at 625ms with 125ms overshoot, so the next run is due at 1000ms
+        world.update(ms(625));
+        assert_eq!(count.get(), 1);
+
+        world.update(ms(250));
+        assert_eq!(count.get(), 1);
+
+        world.update(ms(125));
+        assert_eq!(count.get(), 2);
+    }
+
+    #[test]
+    fn cancel_and_remove_entity() {
+        let mut world = World::new();
+        let (count, callback) = counter();
+        let cancelled = world.scheduler.after(ms(125), callback);
+        let (owned, callback) = counter();
+        let handle = world.scheduler.every(ms(125), callback);
+
+        let entity = world.entity_manager.create_entity();
+        assert!(world.scheduler.attach(handle, entity));
+        assert!(world.scheduler.cancel(cancelled));
+        assert!(!world.scheduler.cancel(cancelled));
+        world.scheduler.remove_entity(entity);
+        assert!(world.scheduler.is_empty());
+
+        world.update(ms(500));
+        assert_eq!(count.get(), 0);
+        assert_eq!(owned.get(), 0);
+    }
+
+    #[test]
+    fn callbacks_use_the_context() {
+        let mut world = World::new();
+        let entity = world.entity_manager.create_entity();
+        world.component_manager.insert_component(entity, 0_u32);
+
+        // Counts a component up and cancels itself on the third run
+        let handle = Rc::new(Cell::new(None));
+        let this = Rc::clone(&handle);
+        handle.set(Some(world.scheduler.every(ms(125), move |ctx| {
+            let value = ctx
+                .component_manager
+                .get_component_mut::<u32>(entity)
+                .unwrap();
+            *value += 1;
+            if *value == 3 {
+                assert!(ctx.scheduler.cancel(this.get().unwrap()));
+            }
+        })));
+
+        // Schedules another callback from inside a callback
+        let (count, callback) = counter();
+        let mut callback = Some(callback);
+        world.scheduler.after(ms(125), move |ctx| {
+            ctx.scheduler.after(ms(125), callback.take().unwrap());
+        });
+
+        for _ in 0..5 {
+            world.update(ms(125));
+        }
+        assert_eq!(
+            world.component_manager.get_component::<u32>(entity),
+            Some(&3)
+        );
+        assert!(!world.scheduler.is_scheduled(handle.get().unwrap()));
+        assert_eq!(count.get(), 1);
+        assert!(world.scheduler.is_empty());
+    }
+}
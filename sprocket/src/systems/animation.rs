@@ -0,0 +1,218 @@
+//! Keyframe animation of the transforms of entities
+use crate::ecs::{ComponentManager, Entity};
+use crate::math::{Quaternion, Vec4};
+use crate::physics::Transform;
+
+/// The part of a Transform an animation channel animates
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnimatedProperty {
+    Translation,
+    Rotation,
+    Scale,
+}
+
+/// How the values between two keyframes are interpolated
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// The value of a keyframe is held until the next
+    Step,
+    /// Translation and scale are interpolated linearly and rotations spherically
+    Linear,
+    /// A cubic Hermite spline with an in tangent, value and out tangent for each keyframe
+    CubicSpline,
+}
+
+/// The keyframes of a single property of an entity
+#[derive(Clone, Debug)]
+pub struct AnimationChannel {
+    pub target: Entity,
+    pub property: AnimatedProperty,
+    pub interpolation: Interpolation,
+    /// The time of each keyframe in seconds in ascending order
+    pub times: Vec<f32>,
+    /// The value of each keyframe, or the in tangent, value and out tangent for cubic splines
+    /// Rotations are quaternions in x, y, z, w while translations and scales are stored in xyz
+    pub values: Vec<Vec4>,
+}
+
+impl AnimationChannel {
+    /// Returns the value of the channel at time
+    /// Times outside the keyframes hold the first or last value
+    /// Returns None if the channel has no keyframes
+    pub fn sample(&self, time: f32) -> Option<Vec4> {
+        let last = self.times.len().checked_sub(1)?;
+        if time <= self.times[0] {
+            return self.value(0);
+        }
+        if time >= self.times[last] {
+            return self.value(last);
+        }
+
+        let next = self.times.partition_point(|&key| key <= time);
+        let prev = next - 1;
+        let duration = self.times[next] - self.times[prev];
+        let t = (time - self.times[prev]) / duration;
+
+        let (a, b) = (self.value(prev)?, self.value(next)?);
+        let value = match self.interpolation {
+            Interpolation::Step => a,
+            Interpolation::Linear if self.property == AnimatedProperty::Rotation => {
+                let q = Quaternion::slerp(to_quaternion(a), to_quaternion(b), t);
+                Vec4::new(q.x, q.y, q.z, q.w)
+            }
+            Interpolation::Linear => Vec4::lerp_unclamped(a, b, t),
+            Interpolation::CubicSpline => {
+                let out_tangent = *self.values.get(prev * 3 + 2)? * duration;
+                let in_tangent = *self.values.get(next * 3)? * duration;
+                let (t2, t3) = (t * t, t * t * t);
+                let value = a * (2.0 * t3 - 3.0 * t2 + 1.0)
+                    + out_tangent * (t3 - 2.0 * t2 + t)
+                    + b * (-2.0 * t3 + 3.0 * t2)
+                    + in_tangent * (t3 - t2);
+
+                match self.property {
+                    AnimatedProperty::Rotation => value.norm(),
+                    _ => value,
+                }
+            }
+        };
+
+        Some(value)
+    }
+
+    /// Returns the value of the keyframe at index
+    fn value(&self, index: usize) -> Option<Vec4> {
+        match self.interpolation {
+            Interpolation::CubicSpline => self.values.get(index * 3 + 1).copied(),
+            _ => self.values.get(index).copied(),
+        }
+    }
+}
+
+fn to_quaternion(v: Vec4) -> Quaternion {
+    Quaternion::new(v.x, v.y, v.z, v.w)
+}
+
+/// A named set of channels played together
+#[derive(Clone, Debug)]
+pub struct AnimationClip {
+    pub name: String,
+    pub channels: Vec<AnimationChannel>,
+}
+
+impl AnimationClip {
+    /// Returns the time of the last keyframe of all channels
+    pub fn duration(&self) -> f32 {
+        self.channels
+            .iter()
+            .filter_map(|channel| channel.times.last())
+            .fold(0.0, |duration, &time| duration.max(time))
+    }
+}
+
+/// A component playing animation clips on the Transforms of the entities they target
+/// The targets don't need to be the entity of the animator, E.g; the animator of an imported
+/// scene is placed on its root
+#[derive(Clone, Debug)]
+pub struct Animator {
+    pub clips: Vec<AnimationClip>,
+    /// The index of the clip playing, if any
+    pub playing: Option<usize>,
+    /// The time in seconds into the playing clip
+    pub time: f32,
+    /// Scales how fast the clip plays
+    pub speed: f32,
+    /// Restarts the clip when it ends instead of holding the last keyframes
+    pub looping: bool,
+}
+
+impl Animator {
+    /// Creates a looping animator with clips that doesn't play anything yet
+    pub fn new(clips: Vec<AnimationClip>) -> Self {
+        Animator {
+            clips,
+            playing: None,
+            time: 0.0,
+            speed: 1.0,
+            looping: true,
+        }
+    }
+
+    /// Plays the clip with name from the start
+    /// Returns false if there is no such clip
+    pub fn play(&mut self, name: &str) -> bool {
+        match self.clips.iter().position(|clip| clip.name == name) {
+            Some(index) => {
+                self.playing = Some(index);
+                self.time = 0.0;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stops playing and leaves the targets as they are
+    pub fn stop(&mut self) {
+        self.playing = None;
+    }
+
+    /// Returns the clip playing, if any
+    pub fn clip(&self) -> Option<&AnimationClip> {
+        self.clips.get(self.playing?)
+    }
+
+    /// Advances the playing clip by delta seconds scaled by speed
+    fn advance(&mut self, delta: f32) {
+        let duration = match self.clip() {
+            Some(clip) => clip.duration(),
+            None => return,
+        };
+
+        self.time += delta * self.speed;
+        if self.looping && duration > 0.0 {
+            self.time = self.time.rem_euclid(duration);
+        } else {
+            self.time = self.time.clamp(0.0, duration);
+        }
+    }
+}
+
+/// Advances all animators by delta seconds and writes the sampled keyframes into the Transforms
+/// of their targets
+/// Targets without a Transform are skipped
+pub fn update(component_manager: &mut ComponentManager, delta: f32) {
+    let playing: Vec<Entity> = component_manager
+        .iter::<Animator>()
+        .filter(|(_, animator)| animator.playing.is_some())
+        .map(|(entity, _)| entity)
+        .collect();
+
+    for entity in playing {
+        let samples: Vec<(Entity, AnimatedProperty, Vec4)> =
+            match component_manager.get_component_mut::<Animator>(entity) {
+                Some(animator) => {
+                    animator.advance(delta);
+                    let time = animator.time;
+                    animator.clip().map_or_else(Vec::new, |clip| {
+                        clip.channels
+                            .iter()
+                            .filter_map(|channel| {
+                                Some((channel.target, channel.property, channel.sample(time)?))
+                            })
+                            .collect()
+                    })
+                }
+                None => continue,
+            };
+
+        for (target, property, value) in samples {
+            if let Some(transform) = component_manager.get_component_mut::<Transform>(target) {
+                match property {
+                    AnimatedProperty::Translation => transform.position = value.xyz(),
+                    AnimatedProperty::Rotation => transform.rotation = to_quaternion(value),
+                    AnimatedProperty::Scale => transform.scale = value.xyz(),
+                }
+            }
+        }
+    }
+}
@@ -1 +1,2 @@
 //! This module defines the different systems that will be working together
+pub mod animation;
@@ -11,9 +11,19 @@ pub struct Time {
     framecount: usize,
     delta: Duration,
     elapsed: Duration,
+    /// The factor at which scaled time advances relative to real time
+    scale: f32,
+    /// The delta multiplied by scale
+    scaled_delta: Duration,
+    /// The accumulation of scaled deltas since creation
+    scaled_elapsed: Duration,
 }
 
 impl Time {
+    /// The largest factor scaled time can advance at relative to real time
+    /// Larger scales could overflow the scaled delta of long frames
+    pub const MAX_SCALE: f32 = 1000.0;
+
     /// Creates and initializes a new time struct
     /// Few time instances should be need for a program, usually one per thread
     pub fn new() -> Self {
@@ -24,6 +34,9 @@ impl Time {
             framecount: 0,
             delta: Duration::from_secs(0),
             elapsed: Duration::from_secs(0),
+            scale: 1.0,
+            scaled_delta: Duration::from_secs(0),
+            scaled_elapsed: Duration::from_secs(0),
         }
     }
 
@@ -34,10 +47,61 @@ impl Time {
         self.prev = self.cur;
         self.cur = Instant::now();
 
-        self.delta = self.cur.saturating_duration_since(self.prev);
-
         self.elapsed = self.cur.saturating_duration_since(self.init);
+        self.step(self.cur.saturating_duration_since(self.prev));
+    }
+
+    /// Advances time by delta as if delta had passed since the previous update
+    /// Used by tests to drive time deterministically
+    #[cfg(test)]
+    pub(crate) fn advance(&mut self, delta: Duration) {
+        self.elapsed += delta;
+        self.step(delta);
+    }
+
+    /// Starts the next frame with a delta since the previous one
+    fn step(&mut self, delta: Duration) {
+        self.delta = delta;
         self.framecount += 1;
+
+        self.scaled_delta = self.delta.mul_f32(self.scale);
+        self.scaled_elapsed += self.scaled_delta;
+    }
+
+    /// Sets the factor at which scaled time advances relative to real time
+    /// A scale of 0 pauses scaled time, 0.5 runs at half speed
+    /// Scales are clamped between 0 and MAX_SCALE, NaN keeps the current scale
+    pub fn set_scale(&mut self, scale: f32) {
+        if !scale.is_nan() {
+            self.scale = scale.clamp(0.0, Self::MAX_SCALE);
+        }
+    }
+
+    /// Returns the current time scale
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Returns the delta time multiplied by the time scale in seconds
+    /// Use this for gameplay logic that should respect slow motion and pausing
+    pub fn scaled_delta_f32(&self) -> f32 {
+        self.scaled_delta.as_secs_f32()
+    }
+
+    /// Returns the raw delta time multiplied by the time scale
+    pub fn scaled_delta_raw(&self) -> Duration {
+        self.scaled_delta
+    }
+
+    /// Returns the accumulated scaled time since creation in seconds
+    /// Changing the time scale does not retroactively change the elapsed scaled time
+    pub fn scaled_elapsed_f32(&self) -> f32 {
+        self.scaled_elapsed.as_secs_f32()
+    }
+
+    /// Returns the accumulated scaled time since creation
+    pub fn scaled_elapsed_raw(&self) -> Duration {
+        self.scaled_elapsed
     }
 
     /// Returns the duration between the last frame and start of current frame in seconds
@@ -86,3 +150,48 @@ impl Time {
         1.0 / self.delta_f32()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale_scales_delta() {
+        let mut time = Time::new();
+        time.set_scale(0.5);
+        time.advance(Duration::from_millis(250));
+        assert_eq!(time.scaled_delta_raw(), Duration::from_millis(125));
+        assert_eq!(time.scaled_elapsed_raw(), Duration::from_millis(125));
+        assert_eq!(time.delta_raw(), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn negative_scale_pauses() {
+        let mut time = Time::new();
+        time.set_scale(-2.0);
+        assert_eq!(time.scale(), 0.0);
+        time.advance(Duration::from_millis(100));
+        assert_eq!(time.scaled_delta_raw(), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn non_finite_scale_does_not_panic() {
+        let mut time = Time::new();
+        time.set_scale(2.0);
+        time.set_scale(f32::NAN);
+        assert_eq!(time.scale(), 2.0);
+
+        time.set_scale(f32::INFINITY);
+        assert_eq!(time.scale(), Time::MAX_SCALE);
+        time.advance(Duration::from_secs(1));
+        assert_eq!(
+            time.scaled_delta_raw(),
+            Duration::from_secs(Time::MAX_SCALE as u64)
+        );
+
+        time.set_scale(f32::NEG_INFINITY);
+        assert_eq!(time.scale(), 0.0);
+        time.update();
+        assert_eq!(time.scaled_delta_raw(), Duration::from_secs(0));
+    }
+}
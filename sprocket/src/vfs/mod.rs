@@ -0,0 +1,212 @@
+//! A virtual filesystem resources are read through
+//! Paths of the form <mount>://<path> are looked up in the directories and packs mounted at
+//! <mount>, E.g; assets://textures/grid.png
+//! Several sources can be mounted at the same point where later mounts overlay earlier ones, so
+//! that a mod directory can replace individual files of the base assets
+//! Any other path is read from the OS filesystem as is
+mod pack;
+pub use pack::{Compression, Pack, PackWriter};
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::RwLock;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error, String),
+    NotFound(String),
+    UnknownMount(String),
+    InvalidPack(String, String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e, path) => write!(f, "Failed to read '{}' {:?}", path, e),
+            Error::NotFound(path) => write!(f, "No mounted source contains '{}'", path),
+            Error::UnknownMount(path) => write!(f, "Nothing is mounted for '{}'", path),
+            Error::InvalidPack(path, reason) => write!(f, "Invalid pack '{}': {}", path, reason),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+enum Source {
+    Dir(PathBuf),
+    Pack(Pack),
+}
+
+struct Mount {
+    point: String,
+    source: Source,
+}
+
+/// The mounted sources in the order they were mounted
+static MOUNTS: RwLock<Vec<Mount>> = RwLock::new(Vec::new());
+
+/// Mounts a directory at point so that <point>://<path> reads <dir>/<path>
+/// Overlays anything already mounted at point
+pub fn mount_dir<P: AsRef<Path>>(point: &str, dir: P) {
+    MOUNTS.write().unwrap().push(Mount {
+        point: point.to_owned(),
+        source: Source::Dir(dir.as_ref().to_owned()),
+    });
+}
+
+/// Mounts the files of a pack at point so that <point>://<path> reads the file stored as path
+/// Overlays anything already mounted at point
+pub fn mount_pack<P: AsRef<Path>>(point: &str, path: P) -> Result<()> {
+    let pack = Pack::open(path)?;
+    MOUNTS.write().unwrap().push(Mount {
+        point: point.to_owned(),
+        source: Source::Pack(pack),
+    });
+    Ok(())
+}
+
+/// Removes all sources mounted at point
+pub fn unmount(point: &str) {
+    MOUNTS.write().unwrap().retain(|mount| mount.point != point);
+}
+
+/// Splits a virtual path into its mount point and the path relative to it
+/// Returns None for OS paths
+pub fn split(path: &str) -> Option<(&str, &str)> {
+    let index = path.find("://")?;
+    Some((&path[..index], &path[index + 3..]))
+}
+
+/// Returns true if a path relative to a mount could refer to a file outside of it
+/// Virtual paths may not go up a directory or be absolute
+fn escapes(relative: &str) -> bool {
+    Path::new(relative)
+        .components()
+        .any(|component| match component {
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => true,
+            Component::CurDir | Component::Normal(_) => false,
+        })
+}
+
+/// Reads the whole file at path
+pub fn read(path: &str) -> Result<Vec<u8>> {
+    let (point, relative) = match split(path) {
+        Some(split) => split,
+        None => return std::fs::read(path).map_err(|e| Error::Io(e, path.to_owned())),
+    };
+
+    if escapes(relative) {
+        return Err(Error::NotFound(path.to_owned()));
+    }
+
+    let mounts = MOUNTS.read().unwrap();
+    let mut mounted = false;
+    for mount in mounts.iter().rev().filter(|mount| mount.point == point) {
+        mounted = true;
+        match &mount.source {
+            Source::Dir(dir) => match std::fs::read(dir.join(relative)) {
+                Ok(data) => return Ok(data),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(Error::Io(e, path.to_owned())),
+            },
+            Source::Pack(pack) => {
+                if pack.contains(relative) {
+                    return pack.read(relative);
+                }
+            }
+        }
+    }
+
+    if mounted {
+        Err(Error::NotFound(path.to_owned()))
+    } else {
+        Err(Error::UnknownMount(path.to_owned()))
+    }
+}
+
+/// Reads the whole file at path as UTF-8
+pub fn read_to_string(path: &str) -> Result<String> {
+    String::from_utf8(read(path)?).map_err(|e| {
+        Error::Io(
+            std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+            path.to_owned(),
+        )
+    })
+}
+
+/// Returns true if path can be read
+pub fn exists(path: &str) -> bool {
+    source_path(path).is_some()
+}
+
+/// Returns the OS file path is currently read from
+/// Files in packs return the pack file
+/// Used to watch resources for changes on disk
+pub fn source_path(path: &str) -> Option<PathBuf> {
+    let (point, relative) = match split(path) {
+        Some(split) => split,
+        None => {
+            let path = Path::new(path);
+            return if path.exists() {
+                Some(path.to_owned())
+            } else {
+                None
+            };
+        }
+    };
+
+    if escapes(relative) {
+        return None;
+    }
+
+    MOUNTS
+        .read()
+        .unwrap()
+        .iter()
+        .rev()
+        .filter(|mount| mount.point == point)
+        .find_map(|mount| match &mount.source {
+            Source::Dir(dir) => Some(dir.join(relative)).filter(|path| path.exists()),
+            Source::Pack(pack) => Some(pack.path().to_owned()).filter(|_| pack.contains(relative)),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn virtual_paths_stay_in_their_mount() {
+        let dir = std::env::temp_dir().join("sprocket-vfs-escape");
+        let root = dir.join("root");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("inside.txt"), "inside").unwrap();
+        let secret = dir.join("secret.txt");
+        std::fs::write(&secret, "secret").unwrap();
+        mount_dir("escape", &root);
+
+        assert_eq!(read("escape://inside.txt").unwrap(), b"inside");
+        assert_eq!(read("escape://./inside.txt").unwrap(), b"inside");
+        assert!(exists("escape://inside.txt"));
+
+        let escaping = [
+            "escape://../secret.txt".to_owned(),
+            "escape://sub/../../secret.txt".to_owned(),
+            format!("escape://{}", secret.display()),
+        ];
+        for path in &escaping {
+            assert!(
+                matches!(read(path), Err(Error::NotFound(_))),
+                "{} was read",
+                path
+            );
+            assert!(!exists(path), "{} exists", path);
+            assert!(source_path(path).is_none(), "{} has a source", path);
+        }
+
+        // OS paths are read as is
+        assert_eq!(read(&secret.to_string_lossy()).unwrap(), b"secret");
+        unmount("escape");
+    }
+}
@@ -0,0 +1,291 @@
+use super::{Error, Result};
+use flate2::{read::DeflateDecoder, write::DeflateEncoder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Identifies a pack file
+const MAGIC: &[u8; 4] = b"SPAK";
+const VERSION: u32 = 1;
+/// The magic, version and index offset
+const HEADER_SIZE: u64 = 16;
+/// The most memory reserved up front for a file, larger files grow as they are read
+const MAX_RESERVED: u64 = 64 * 1024 * 1024;
+
+/// How a file is stored in a pack
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Compression {
+    None,
+    Deflate,
+}
+
+/// Describes where a file is stored in a pack
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct PackEntry {
+    path: String,
+    offset: u64,
+    /// The size of the stored, possibly compressed, data
+    size: u64,
+    uncompressed_size: u64,
+    compression: Compression,
+}
+
+/// A single file containing many files
+/// Laid out as a header of the magic, version and offset of the index, followed by the data of
+/// each file and lastly the bincode encoded index
+pub struct Pack {
+    path: PathBuf,
+    entries: HashMap<String, PackEntry>,
+}
+
+impl Pack {
+    /// Opens a pack and reads its index
+    /// The file contents are read when requested
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Pack> {
+        let path = path.as_ref();
+        let name = path.display().to_string();
+        let io_error = |e| Error::Io(e, name.clone());
+
+        let mut file = File::open(path).map_err(io_error)?;
+        let mut header = [0; HEADER_SIZE as usize];
+        file.read_exact(&mut header).map_err(io_error)?;
+
+        if &header[0..4] != MAGIC {
+            return Err(Error::InvalidPack(name, "not a pack file".to_owned()));
+        }
+
+        let version = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if version != VERSION {
+            return Err(Error::InvalidPack(
+                name,
+                format!("unsupported version {}", version),
+            ));
+        }
+
+        let mut index_offset = [0; 8];
+        index_offset.copy_from_slice(&header[8..16]);
+        file.seek(SeekFrom::Start(u64::from_le_bytes(index_offset)))
+            .map_err(io_error)?;
+
+        let entries: Vec<PackEntry> = bincode::deserialize_from(&mut file)
+            .map_err(|e| Error::InvalidPack(name.clone(), e.to_string()))?;
+
+        Ok(Pack {
+            path: path.to_owned(),
+            entries: entries
+                .into_iter()
+                .map(|entry| (entry.path.clone(), entry))
+                .collect(),
+        })
+    }
+
+    /// Returns the path of the pack file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns true if the pack contains a file stored as path
+    pub fn contains(&self, path: &str) -> bool {
+        self.entries.contains_key(path)
+    }
+
+    /// Returns the paths of the files in the pack
+    pub fn files(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(|path| &path[..])
+    }
+
+    /// Reads and decompresses the file stored as path
+    pub fn read(&self, path: &str) -> Result<Vec<u8>> {
+        let entry = self
+            .entries
+            .get(path)
+            .ok_or_else(|| Error::NotFound(path.to_owned()))?;
+        let io_error = |e| Error::Io(e, format!("{}:{}", self.path.display(), path));
+
+        // Opened for each read so that files can be read from several threads at once
+        let mut file = File::open(&self.path).map_err(io_error)?;
+        file.seek(SeekFrom::Start(entry.offset)).map_err(io_error)?;
+        let stored = file.take(entry.size);
+
+        // The sizes in the index are not trusted, at most one byte more than expected is read to
+        // detect files larger than their entry
+        let limit = entry.uncompressed_size.saturating_add(1);
+        let mut data = Vec::with_capacity(entry.uncompressed_size.min(MAX_RESERVED) as usize);
+        match entry.compression {
+            Compression::None => stored.take(limit).read_to_end(&mut data),
+            Compression::Deflate => DeflateDecoder::new(stored)
+                .take(limit)
+                .read_to_end(&mut data),
+        }
+        .map_err(io_error)?;
+
+        if data.len() as u64 != entry.uncompressed_size {
+            return Err(Error::InvalidPack(
+                self.path.display().to_string(),
+                format!(
+                    "'{}' is {} bytes but its entry says {}",
+                    path,
+                    data.len(),
+                    entry.uncompressed_size
+                ),
+            ));
+        }
+
+        Ok(data)
+    }
+}
+
+/// Writes a pack file
+/// The pack is incomplete until finish has been called
+pub struct PackWriter {
+    file: BufWriter<File>,
+    name: String,
+    entries: Vec<PackEntry>,
+    offset: u64,
+}
+
+impl PackWriter {
+    /// Creates or truncates the pack file at path
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<PackWriter> {
+        let name = path.as_ref().display().to_string();
+        let mut file = BufWriter::new(File::create(path).map_err(|e| Error::Io(e, name.clone()))?);
+
+        // The header is written again with the index offset when finished
+        file.write_all(&[0; HEADER_SIZE as usize])
+            .map_err(|e| Error::Io(e, name.clone()))?;
+
+        Ok(PackWriter {
+            file,
+            name,
+            entries: Vec::new(),
+            offset: HEADER_SIZE,
+        })
+    }
+
+    /// Adds a file to the pack stored as path
+    pub fn add(&mut self, path: &str, data: &[u8], compression: Compression) -> Result<()> {
+        let stored = match compression {
+            Compression::None => data.to_vec(),
+            Compression::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder
+                    .write_all(data)
+                    .and_then(|_| encoder.finish())
+                    .map_err(|e| Error::Io(e, path.to_owned()))?
+            }
+        };
+
+        self.file
+            .write_all(&stored)
+            .map_err(|e| Error::Io(e, self.name.clone()))?;
+
+        self.entries.push(PackEntry {
+            path: path.to_owned(),
+            offset: self.offset,
+            size: stored.len() as u64,
+            uncompressed_size: data.len() as u64,
+            compression,
+        });
+        self.offset += stored.len() as u64;
+        Ok(())
+    }
+
+    /// Writes the index and header
+    pub fn finish(mut self) -> Result<()> {
+        let name = self.name;
+        let io_error = |e| Error::Io(e, name.clone());
+
+        bincode::serialize_into(&mut self.file, &self.entries)
+            .map_err(|e| Error::InvalidPack(name.clone(), e.to_string()))?;
+
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.extend_from_slice(&self.offset.to_le_bytes());
+
+        self.file.seek(SeekFrom::Start(0)).map_err(io_error)?;
+        self.file.write_all(&header).map_err(io_error)?;
+        self.file.flush().map_err(io_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pack_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sprocket-{}.pak", name))
+    }
+
+    /// Writes a pack with an uncompressed and a deflated file
+    fn write_pack(name: &str) -> PathBuf {
+        let path = pack_path(name);
+        let mut writer = PackWriter::create(&path).unwrap();
+        writer
+            .add("raw.txt", b"stored as is", Compression::None)
+            .unwrap();
+        writer
+            .add("deflated.txt", &[7; 4096], Compression::Deflate)
+            .unwrap();
+        writer.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn round_trip() {
+        let pack = Pack::open(write_pack("round-trip")).unwrap();
+        let mut files: Vec<_> = pack.files().collect();
+        files.sort_unstable();
+        assert_eq!(files, vec!["deflated.txt", "raw.txt"]);
+        assert_eq!(pack.read("raw.txt").unwrap(), b"stored as is");
+        assert_eq!(pack.read("deflated.txt").unwrap(), vec![7; 4096]);
+        assert!(matches!(pack.read("missing.txt"), Err(Error::NotFound(_))));
+    }
+
+    #[test]
+    fn rejects_corrupt_header() {
+        let path = write_pack("corrupt-header");
+        let valid = std::fs::read(&path).unwrap();
+
+        let corrupt = |offset: usize, bytes: &[u8]| {
+            let mut data = valid.clone();
+            data[offset..offset + bytes.len()].copy_from_slice(bytes);
+            std::fs::write(&path, data).unwrap();
+            Pack::open(&path)
+        };
+
+        assert!(matches!(corrupt(0, b"KAPS"), Err(Error::InvalidPack(..))));
+        assert!(matches!(
+            corrupt(4, &2u32.to_le_bytes()),
+            Err(Error::InvalidPack(..))
+        ));
+        // The index offset is past the end of the file
+        assert!(matches!(
+            corrupt(8, &(valid.len() as u64 + 100).to_le_bytes()),
+            Err(Error::InvalidPack(..))
+        ));
+        assert!(corrupt(8, &u64::MAX.to_le_bytes()).is_err());
+
+        std::fs::write(&path, &valid[..8]).unwrap();
+        assert!(matches!(Pack::open(&path), Err(Error::Io(..))));
+    }
+
+    #[test]
+    fn rejects_wrong_sizes() {
+        let mut pack = Pack::open(write_pack("wrong-sizes")).unwrap();
+        for file in &["raw.txt", "deflated.txt"] {
+            let entry = pack.entries.get_mut(*file).unwrap();
+            let size = entry.uncompressed_size;
+
+            // Not allocated up front
+            entry.uncompressed_size = u64::MAX;
+            assert!(matches!(pack.read(file), Err(Error::InvalidPack(..))));
+
+            let entry = pack.entries.get_mut(*file).unwrap();
+            entry.uncompressed_size = size - 1;
+            assert!(matches!(pack.read(file), Err(Error::InvalidPack(..))));
+        }
+    }
+}